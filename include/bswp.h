@@ -0,0 +1,63 @@
+#ifndef BSWP_H
+#define BSWP_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+#if defined(__unix__) || defined(__APPLE__)
+#define BSWP_UNIX
+#endif
+
+// Rules parsed by `bswp_rules_parse`, opaque to C.
+typedef struct BswpRules BswpRules;
+
+// Parses the NUL-terminated UTF-8 `expressions`, see `dsl::parse_rules`.
+//
+// Returns `NULL` if `expressions` is `NULL`, not UTF-8 or invalid, else rules to release
+// with `bswp_rules_free`.
+//
+// # Safety
+//
+// `expressions` must be `NULL` or point to a NUL-terminated string.
+struct BswpRules *bswp_rules_parse(const char *expressions);
+
+// Releases rules returned by `bswp_rules_parse`, does nothing on `NULL`.
+//
+// # Safety
+//
+// `rules` must be `NULL` or returned by `bswp_rules_parse` and not released yet.
+void bswp_rules_free(struct BswpRules *rules);
+
+// Swaps the `len` bytes at `buffer` in place, `buffer[0]` being at position `start`.
+//
+// Returns `0`, `-1` if `rules` or `buffer` is `NULL`.
+//
+// # Safety
+//
+// `rules` must be `NULL` or valid rules, `buffer` must be `NULL` or point to `len`
+// writable bytes.
+int bswp_apply_buffer(const struct BswpRules *rules, uint8_t *buffer, size_t len, size_t start);
+
+#if defined(BSWP_UNIX)
+// Swaps the bytes read from file descriptor `input` until end of file into file
+// descriptor `output`, see `io::swap_io`. Neither descriptor is closed.
+//
+// Returns the number of bytes written, `-1` if `rules` is `NULL` or on io error.
+//
+// Only available on Unix.
+//
+// # Safety
+//
+// `rules` must be `NULL` or valid rules, `input` and `output` must be open file
+// descriptors not used elsewhere during the call.
+int64_t bswp_apply_fd(const struct BswpRules *rules, int input, int output);
+#endif
+
+// Returns the NUL-terminated description of the last failure of the calling thread,
+// `NULL` if none.
+//
+// The string is valid until the next failure of the thread.
+const char *bswp_last_error(void);
+
+#endif  /* BSWP_H */
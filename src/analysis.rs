@@ -0,0 +1,393 @@
+//! Rule inference and inversion (pure)
+//!
+//! Recovers swap rules from an original and a modified binary, so that a change
+//! made by hand on one file can be replayed on others, computes rules undoing a swap
+//! and learns byte transformations from input/output examples.
+
+use crate::pattern::{group_size, swap_slice_from, Pattern, Predicate};
+use crate::rule::{Op, SwapRule, Target};
+use crate::BUFFER_SIZE;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Returns swaps turning `original` into `modified`.
+///
+/// Changed bytes are grouped by new value and positions in arithmetic progression are
+/// folded into a single periodic predicate with a limit. Each pattern only sets the bits
+/// that changed somewhere in its group, bits left untouched by the edit stay untouched
+/// in other files.
+///
+/// Bytes past the end of the shorter input are ignored.
+///
+/// ```
+/// use bswp::analysis::infer_rules;
+/// use bswp::pattern::{swap_slice, Pattern, Predicate};
+///
+/// let original = [0x00, 0x11, 0x00, 0x11, 0x00, 0x11, 0x7F];
+/// let modified = [0xFF, 0x11, 0xFF, 0x11, 0xFF, 0x91, 0x7F];
+/// let swaps = infer_rules(&original, &modified);
+/// assert_eq!(swaps, vec![
+///     (Pattern::new(0xFF), Predicate::new().with_periodicity(2).with_limit(3)),
+///     (Pattern::new(0x80).with_mask(0x80), Predicate::new().with_offset(5).with_limit(1)),
+/// ]);
+///
+/// let mut buffer = original;
+/// swap_slice(&mut buffer, &swaps);
+/// assert_eq!(buffer, modified);
+/// ```
+pub fn infer_rules(original: &[u8], modified: &[u8]) -> Vec<(Pattern, Predicate)> {
+    // new value -> changed positions, in increasing order
+    let mut changes: BTreeMap<u8, Vec<usize>> = BTreeMap::new();
+    for (position, (&old, &new)) in original.iter().zip(modified).enumerate() {
+        if old != new {
+            changes.entry(new).or_default().push(position);
+        }
+    }
+    let mut swaps: Vec<(Pattern, Predicate)> = Vec::new();
+    for (value, positions) in changes {
+        for (run, predicate) in progressions(&positions) {
+            let mask = run
+                .iter()
+                .fold(0, |mask, &position| mask | (original[position] ^ value));
+            swaps.push((Pattern::new(value & mask).with_mask(mask), predicate));
+        }
+    }
+    swaps.sort_by_key(|(_, predicate)| predicate.offset);
+    swaps
+}
+
+/// Returns rules undoing `swaps` on `original`: applying `swaps` then the returned rules
+/// yields `original` again.
+///
+/// Rules are undone in reverse order. `xor` and `not` rules are their own inverse, the
+/// bytes other rules overwrite are read from `original` and recorded as `set` rules.
+///
+/// ```
+/// use bswp::analysis::invert_rules;
+/// use bswp::pattern::{swap_slice, Pattern, Predicate};
+/// use bswp::rule::{Op, SwapRule, Target};
+///
+/// let swaps: Vec<SwapRule> = vec![
+///     (Op::Xor(0xFF), Target::Range(0..2)),
+///     (Op::Set(Pattern::new(0x00)), Target::Range(2..6)),
+/// ];
+/// let original = [0x10, 0x11, 0x42, 0x42, 0x42, 0x42];
+/// let undo = invert_rules(&swaps, &original[..]).unwrap();
+/// assert_eq!(undo, vec![
+///     (Op::Set(Pattern::new(0x42)), Target::Periodic(Predicate::new().with_offset(2).with_limit(4).with_periodicity(1))),
+///     (Op::Xor(0xFF), Target::Range(0..2)),
+/// ]);
+///
+/// let mut buffer = original;
+/// swap_slice(&mut buffer, &swaps);
+/// swap_slice(&mut buffer, &undo);
+/// assert_eq!(buffer, original);
+/// ```
+pub fn invert_rules<R: Read>(
+    swaps: &[SwapRule],
+    mut original: R,
+) -> std::io::Result<Vec<SwapRule>> {
+    // rule index -> original value -> positions overwritten by the rule
+    let mut overwritten: Vec<BTreeMap<u8, Vec<usize>>> = vec![BTreeMap::new(); swaps.len()];
+    let group = group_size(swaps);
+    let mut buffer = vec![0; BUFFER_SIZE.div_ceil(group) * group];
+    let mut position = 0;
+    loop {
+        let size = read_full(&mut original, &mut buffer)?;
+        if size == 0 {
+            break;
+        }
+        let chunk = &mut buffer[..size];
+        for (index, rule) in swaps.iter().enumerate() {
+            if is_involution(&rule.0) {
+                swap_slice_from(chunk, std::slice::from_ref(rule), position);
+                continue;
+            }
+            let before = chunk.to_vec();
+            swap_slice_from(chunk, std::slice::from_ref(rule), position);
+            for (offset, (&old, &new)) in before.iter().zip(chunk.iter()).enumerate() {
+                if old != new {
+                    overwritten[index]
+                        .entry(old)
+                        .or_default()
+                        .push(position + offset);
+                }
+            }
+        }
+        position += size;
+    }
+    let mut undo: Vec<SwapRule> = Vec::new();
+    for ((op, target), overwritten) in swaps.iter().zip(overwritten).rev() {
+        if is_involution(op) {
+            undo.push((*op, target.clone()));
+            continue;
+        }
+        let mut restore: Vec<SwapRule> = Vec::new();
+        for (value, positions) in overwritten {
+            for (_, predicate) in progressions(&positions) {
+                restore.push((Op::Set(Pattern::new(value)), Target::Periodic(predicate)));
+            }
+        }
+        // restore rules of a rule never overlap, order them by position
+        restore.sort_by_key(|(_, target)| match target {
+            Target::Periodic(predicate) => predicate.offset,
+            _ => 0,
+        });
+        undo.extend(restore);
+    }
+    Ok(undo)
+}
+
+/// Returns the pattern turning every input byte into its output byte.
+///
+/// Bits the examples never change are left out of the mask. Returns `None` if no
+/// pattern is consistent with the examples, e.g. if a bit is inverted. See
+/// `ambiguous_bits` for the bits the examples do not determine.
+///
+/// ```
+/// use bswp::analysis::learn_pattern;
+/// use bswp::pattern::Pattern;
+///
+/// let pairs = [(0x12, 0xF2), (0x3C, 0xFC), (0xA0, 0xF0)];
+/// assert_eq!(learn_pattern(&pairs), Some(Pattern::new(0xF0).with_mask(0xF0)));
+/// assert_eq!(learn_pattern(&[(0x00, 0x01), (0x01, 0x00)]), None);
+/// ```
+pub fn learn_pattern(pairs: &[(u8, u8)]) -> Option<Pattern> {
+    let bits = BitMaps::observe(pairs)?;
+    if bits.ones0 & bits.zeros1 != 0 {
+        return None; // inverted bits
+    }
+    let (seen0, seen1) = (bits.ones0 | bits.zeros0, bits.ones1 | bits.zeros1);
+    let mask = bits.constant() | (bits.ones0 & !seen1) | (bits.zeros1 & !seen0);
+    Some(Pattern::new((bits.ones0 | bits.ones1) & mask).with_mask(mask))
+}
+
+/// Returns the key XORed into every input byte to obtain its output byte.
+///
+/// Returns `None` if no key is consistent with the examples, e.g. if a bit is set.
+///
+/// ```
+/// use bswp::analysis::learn_xor;
+///
+/// assert_eq!(learn_xor(&[(0x00, 0x5A), (0xFF, 0xA5)]), Some(0x5A));
+/// assert_eq!(learn_xor(&[(0x00, 0x01), (0x01, 0x01)]), None);
+/// ```
+pub fn learn_xor(pairs: &[(u8, u8)]) -> Option<u8> {
+    let bits = BitMaps::observe(pairs)?;
+    if bits.constant() != 0 {
+        return None;
+    }
+    Some(bits.inverted())
+}
+
+/// Returns `(and, xor)` such that every output byte is `(input & and) ^ xor`, i.e.
+/// every bit is kept, inverted, cleared or set, as `Op::And` then `Op::Xor` do.
+///
+/// Returns `None` if the examples map equal bits to different values.
+///
+/// ```
+/// use bswp::analysis::learn_affine;
+///
+/// // clear the high nibble, invert the low one
+/// let pairs = [(0x00, 0x0F), (0xFF, 0x00), (0x5A, 0x05)];
+/// assert_eq!(learn_affine(&pairs), Some((0x0F, 0x0F)));
+/// ```
+pub fn learn_affine(pairs: &[(u8, u8)]) -> Option<(u8, u8)> {
+    let bits = BitMaps::observe(pairs)?;
+    let constant = bits.constant();
+    Some((!constant, bits.inverted() | (bits.ones0 & bits.ones1)))
+}
+
+/// Returns the bits the examples do not determine: bits equal in every input byte,
+/// the examples do not tell how the other value is mapped.
+///
+/// ```
+/// use bswp::analysis::ambiguous_bits;
+///
+/// assert_eq!(ambiguous_bits(&[(0x01, 0x01), (0x03, 0x02)]), 0xFD); // only bit 1 varies
+/// ```
+pub fn ambiguous_bits(pairs: &[(u8, u8)]) -> u8 {
+    let ones = pairs.iter().fold(0, |ones, &(input, _)| ones | input);
+    let zeros = pairs.iter().fold(0, |zeros, &(input, _)| zeros | !input);
+    !(ones & zeros)
+}
+
+/// Output bits observed for each input bit value, one bit per bit position.
+struct BitMaps {
+    /// `0` mapped to `1`
+    ones0: u8,
+    /// `0` mapped to `0`
+    zeros0: u8,
+    /// `1` mapped to `1`
+    ones1: u8,
+    /// `1` mapped to `0`
+    zeros1: u8,
+}
+
+impl BitMaps {
+    /// Returns the bit mappings of `pairs`, `None` if a bit value is mapped to both values.
+    fn observe(pairs: &[(u8, u8)]) -> Option<Self> {
+        let mut bits = BitMaps {
+            ones0: 0,
+            zeros0: 0,
+            ones1: 0,
+            zeros1: 0,
+        };
+        for &(input, output) in pairs {
+            bits.ones0 |= !input & output;
+            bits.zeros0 |= !input & !output;
+            bits.ones1 |= input & output;
+            bits.zeros1 |= input & !output;
+        }
+        let conflicts = (bits.ones0 & bits.zeros0) | (bits.ones1 & bits.zeros1);
+        Some(bits).filter(|_| conflicts == 0)
+    }
+
+    /// Returns the bits mapped to a constant value.
+    fn constant(&self) -> u8 {
+        (self.ones0 & self.ones1) | (self.zeros0 & self.zeros1)
+    }
+
+    /// Returns the bits inverted, bits seen with a single value being inverted if it changes.
+    fn inverted(&self) -> u8 {
+        (self.ones0 | self.zeros1) & !self.constant()
+    }
+}
+
+/// Returns `true` if applying `op` twice leaves bytes unchanged.
+fn is_involution(op: &Op) -> bool {
+    matches!(op, Op::Xor(_) | Op::Not)
+}
+
+/// Reads from `reader` until `buffer` is full or the end of `reader`, returns the bytes read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut size = 0;
+    while size < buffer.len() {
+        match reader.read(&mut buffer[size..]) {
+            Ok(0) => break,
+            Ok(read) => size += read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(size)
+}
+
+/// Splits increasing `positions` into arithmetic progressions and the predicates matching them.
+fn progressions(positions: &[usize]) -> Vec<(&[usize], Predicate)> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+    while index < positions.len() {
+        let run = progression(&positions[index..]);
+        let mut predicate = Predicate::new().with_offset(run[0]).with_limit(run.len());
+        if run.len() > 1 {
+            predicate = predicate.with_periodicity(run[1] - run[0]);
+        }
+        runs.push((run, predicate));
+        index += run.len();
+    }
+    runs
+}
+
+/// Returns the longest prefix of `positions` in arithmetic progression.
+fn progression(positions: &[usize]) -> &[usize] {
+    let len = match positions {
+        [first, second, ..] => {
+            let step = second - first;
+            1 + positions
+                .windows(2)
+                .take_while(|pair| pair[1] - pair[0] == step)
+                .count()
+        }
+        _ => positions.len(),
+    };
+    &positions[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{ambiguous_bits, infer_rules, invert_rules};
+    use crate::analysis::{learn_affine, learn_pattern, learn_xor};
+    use crate::pattern::{swap_slice, Pattern, Predicate};
+    use crate::rule::{Op, SwapRule, Target};
+
+    #[test]
+    fn test_infer_round_trip() {
+        let original: Vec<u8> = (0..200u32).map(|i| (i * 37 % 251) as u8).collect();
+        let mut modified = original.clone();
+        for position in (10..100).step_by(3) {
+            modified[position] = 0x00;
+        }
+        modified[150..160].fill(0xAA);
+        modified[7] ^= 0x01;
+        modified[199] = 0x42;
+
+        let swaps = infer_rules(&original, &modified);
+        let mut buffer = original.clone();
+        swap_slice(&mut buffer, &swaps);
+        assert_eq!(buffer, modified);
+        assert!(swaps.len() < 10, "{:?}", swaps);
+        assert!(infer_rules(&original, &original).is_empty());
+        assert!(infer_rules(&original, &modified[..5]).is_empty());
+    }
+
+    #[test]
+    fn test_invert_round_trip() {
+        let original: Vec<u8> = (0..20_000u32).map(|i| (i * 37 % 251) as u8).collect();
+        let swaps: Vec<SwapRule> = vec![
+            (Op::Xor(0x5A), Target::Range(100..15_000)),
+            (
+                Op::Set(Pattern::new(0x00).with_mask(0x0F)),
+                Target::Periodic(Predicate::new().with_periodicity(3)),
+            ),
+            (Op::Reverse(4), Target::Range(7_990..8_010)),
+            (Op::Not, Target::Offsets(vec![1, 8_000, 19_999])),
+            (Op::And(0xF0), Target::Range(12_000..12_010)),
+        ];
+        let undo = invert_rules(&swaps, &original[..]).unwrap();
+        let mut buffer = original.clone();
+        swap_slice(&mut buffer, &swaps);
+        assert!(buffer != original);
+        swap_slice(&mut buffer, &undo);
+        assert!(buffer == original);
+        assert_eq!(undo.last(), Some(&swaps[0]));
+        assert!(invert_rules(&swaps, &[][..]).unwrap().len() == 2);
+    }
+
+    #[test]
+    fn test_learn_transforms() {
+        let inputs: Vec<u8> = (0..=255).step_by(7).collect();
+        let examples = |transform: &dyn Fn(u8) -> u8| -> Vec<(u8, u8)> {
+            inputs
+                .iter()
+                .map(|&input| (input, transform(input)))
+                .collect()
+        };
+
+        let pattern = Pattern::new(0x28).with_mask(0x3C);
+        let pairs = examples(&|input| (input & !0x3C) | 0x28);
+        assert_eq!(learn_pattern(&pairs), Some(pattern));
+        assert_eq!(learn_xor(&pairs), None);
+        assert_eq!(learn_affine(&pairs), Some((!0x3C, 0x28)));
+
+        let pairs = examples(&|input| input ^ 0x81);
+        assert_eq!(learn_xor(&pairs), Some(0x81));
+        assert_eq!(learn_pattern(&pairs), None);
+        assert_eq!(learn_affine(&pairs), Some((0xFF, 0x81)));
+
+        let pairs = examples(&|input| (input & 0xF0) ^ 0x33);
+        assert_eq!(learn_affine(&pairs), Some((0xF0, 0x33)));
+        assert_eq!(ambiguous_bits(&pairs), 0x00);
+
+        // a single example leaves every bit ambiguous
+        let pairs = [(0x0F, 0x1E)];
+        assert_eq!(ambiguous_bits(&pairs), 0xFF);
+        assert_eq!(
+            learn_pattern(&pairs),
+            Some(Pattern::new(0x10).with_mask(0x11))
+        );
+        assert_eq!(learn_xor(&pairs), Some(0x11));
+        assert_eq!(learn_xor(&[]), Some(0x00));
+        assert_eq!(learn_affine(&[(0x01, 0x01), (0x01, 0x00)]), None);
+    }
+}
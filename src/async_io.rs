@@ -0,0 +1,382 @@
+//! Byte swap async IO utils (mut), requires the `tokio` feature.
+
+use crate::pattern::{group_size, swap_slice_from};
+use crate::stream::Swapper;
+use crate::{BytePattern, PositionPredicate, BUFFER_SIZE};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// For each byte in `reader` compute pattern and write result to `writer`.
+///
+/// Returns number of bytes read from `reader` and written to `writer`.
+///
+/// Async counterpart of `io::swap_io`, with the same position semantics: bytes of a group
+/// split across reads (see `BytePattern::group_size`) are held back until the next read.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::async_io::swap_io_async;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let mut reader: &[u8] = &[0x41, 0x42, 0x43, 0x44];
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// let swaps: &[(Pattern, Predicate)] = &[(Pattern::new(0x42).with_mask(0xFF), Predicate::new().with_periodicity(2).with_offset(0))];
+/// let swap = swap_io_async(&mut reader, &mut writer, swaps).await;
+/// assert_eq!(swap.unwrap(), 4); // 4 bytes written
+/// assert_eq!(writer, vec![0x42, 0x42, 0x42, 0x44])
+/// # });
+/// ```
+pub async fn swap_io_async<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+) -> Result<usize, std::io::Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let group = group_size(swaps);
+    let mut position: usize = 0; // position of `buffer[0]`
+    let mut held: usize = 0; // bytes of a partial group at the start of `buffer`
+    let mut buffer = vec![0; BUFFER_SIZE + group]; // on the heap to keep the future small
+
+    loop {
+        let size = reader.read(&mut buffer[held..]).await?;
+        if size == 0 {
+            break; // finished
+        }
+        let end = held + size;
+        let len = end - (position + end) % group;
+        swap_slice_from(&mut buffer[..len], swaps, position); // position relative to reader start
+        writer.write_all(&buffer[..len]).await?;
+        buffer.copy_within(len..end, 0);
+        held = end - len;
+        position += len;
+    }
+    // partial group at the end of the stream
+    swap_slice_from(&mut buffer[..held], swaps, position);
+    writer.write_all(&buffer[..held]).await?;
+    Ok(position + held)
+}
+
+/// Async reader adapter swapping bytes read from the inner reader.
+///
+/// Positions are relative to the first byte read through the adapter. Bytes of groups
+/// moved by patterns (see `BytePattern::group_size`) are held back until their group is
+/// read whole.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::async_io::AsyncSwapReader;
+/// use tokio::io::AsyncReadExt;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let inner: &[u8] = &[0x41, 0x42, 0x43, 0x44];
+/// let swaps = vec![(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let mut reader = AsyncSwapReader::new(inner, swaps);
+///
+/// let mut swapped = Vec::new();
+/// reader.read_to_end(&mut swapped).await.unwrap();
+/// assert_eq!(swapped, vec![0x42, 0x42, 0x42, 0x44]);
+/// # });
+/// ```
+pub struct AsyncSwapReader<R, P, Q> {
+    inner: R,
+    swapper: Swapper<P, Q>,
+    /// bytes read from `inner` not returned yet, the first `ready` ones being swapped
+    pending: Vec<u8>,
+    ready: usize,
+}
+
+impl<R, P: BytePattern, Q: PositionPredicate> AsyncSwapReader<R, P, Q> {
+    /// Creates a new `AsyncSwapReader` wrapping `inner`.
+    pub fn new(inner: R, swaps: Vec<(P, Q)>) -> Self {
+        AsyncSwapReader {
+            inner,
+            swapper: Swapper::new(swaps),
+            pending: Vec::new(),
+            ready: 0,
+        }
+    }
+
+    /// Returns the position of the next byte to read.
+    pub fn position(&self) -> usize {
+        self.swapper.position() - self.ready
+    }
+
+    /// Gets a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this `AsyncSwapReader`, returning the inner reader.
+    ///
+    /// Bytes read from the inner reader but not returned yet are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, P, Q> AsyncRead for AsyncSwapReader<R, P, Q>
+where
+    R: AsyncRead + Unpin,
+    P: BytePattern + Unpin,
+    Q: PositionPredicate + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if this.ready > 0 {
+                let size = this.ready.min(buf.remaining());
+                buf.put_slice(&this.pending[..size]);
+                this.pending.drain(..size);
+                this.ready -= size;
+                return Poll::Ready(Ok(()));
+            }
+            let group = this.swapper.group_size();
+            if group == 1 && this.pending.is_empty() {
+                let before = buf.filled().len();
+                let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+                if let Poll::Ready(Ok(())) = poll {
+                    this.swapper.feed(&mut buf.filled_mut()[before..]);
+                }
+                return poll;
+            }
+            let held = this.pending.len();
+            this.pending.resize(held + buf.remaining().max(group), 0);
+            let mut read = ReadBuf::new(&mut this.pending[held..]);
+            let poll = Pin::new(&mut this.inner).poll_read(cx, &mut read);
+            let size = read.filled().len();
+            this.pending.truncate(held + size);
+            if let Poll::Pending | Poll::Ready(Err(_)) = poll {
+                return poll;
+            }
+            if size == 0 {
+                if held == 0 {
+                    return Poll::Ready(Ok(())); // finished
+                }
+                // partial group at the end of the stream
+                this.swapper.feed(&mut this.pending);
+                this.ready = held;
+            } else {
+                this.ready = this.swapper.feed_groups(&mut this.pending);
+            }
+        }
+    }
+}
+
+/// Async writer adapter swapping bytes before writing them to the inner writer.
+///
+/// Positions are relative to the first byte written through the adapter. Bytes of groups
+/// moved by patterns (see `BytePattern::group_size`) are buffered until their group is
+/// written whole, then written to the inner writer on the next write or flush: call
+/// `shutdown` to write a trailing partial group.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::async_io::AsyncSwapWriter;
+/// use tokio::io::AsyncWriteExt;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let swaps = vec![(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let mut writer = AsyncSwapWriter::new(Vec::new(), swaps);
+///
+/// writer.write_all(&[0x41, 0x42]).await.unwrap();
+/// writer.write_all(&[0x43, 0x44]).await.unwrap();
+/// assert_eq!(writer.into_inner(), vec![0x42, 0x42, 0x42, 0x44]);
+/// # });
+/// ```
+pub struct AsyncSwapWriter<W, P, Q> {
+    inner: W,
+    swaps: Vec<(P, Q)>,
+    group: usize,
+    position: usize,
+    buffer: Vec<u8>,
+    /// bytes accepted but not written to `inner` yet, the first `ready` ones being swapped
+    pending: Vec<u8>,
+    ready: usize,
+}
+
+impl<W, P: BytePattern, Q: PositionPredicate> AsyncSwapWriter<W, P, Q> {
+    /// Creates a new `AsyncSwapWriter` wrapping `inner`.
+    pub fn new(inner: W, swaps: Vec<(P, Q)>) -> Self {
+        AsyncSwapWriter {
+            inner,
+            group: group_size(&swaps),
+            swaps,
+            position: 0,
+            buffer: Vec::new(),
+            pending: Vec::new(),
+            ready: 0,
+        }
+    }
+
+    /// Returns the position of the next byte to write.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Gets a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwraps this `AsyncSwapWriter`, returning the inner writer.
+    ///
+    /// Bytes buffered but not written to the inner writer yet are lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin, P, Q> AsyncSwapWriter<W, P, Q> {
+    /// Writes the swapped bytes of `pending` to the inner writer.
+    fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        while self.ready > 0 {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.pending[..self.ready]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+                }
+                Poll::Ready(Ok(written)) => {
+                    self.pending.drain(..written);
+                    self.ready -= written;
+                }
+                poll => return poll.map_ok(|_| ()),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W, P, Q> AsyncWrite for AsyncSwapWriter<W, P, Q>
+where
+    W: AsyncWrite + Unpin,
+    P: BytePattern + Unpin,
+    Q: PositionPredicate + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = self.get_mut();
+        if this.group == 1 {
+            // swapped again on retry: swapping only depends on position
+            this.buffer.clear();
+            this.buffer.extend_from_slice(buf);
+            swap_slice_from(&mut this.buffer, &this.swaps, this.position);
+            let poll = Pin::new(&mut this.inner).poll_write(cx, &this.buffer);
+            if let Poll::Ready(Ok(written)) = poll {
+                this.position += written;
+            }
+            return poll;
+        }
+        ready!(this.poll_write_ready(cx))?;
+        // only a partial group is pending
+        let start = this.position - this.pending.len();
+        this.pending.extend_from_slice(buf);
+        this.position += buf.len();
+        let len = this.pending.len() - this.position % this.group;
+        swap_slice_from(&mut this.pending[..len], &this.swaps, start);
+        this.ready = len;
+        // errors are reported by the next write or flush
+        let _ = this.poll_write_ready(cx);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_ready(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_ready(cx))?;
+        // partial group at the end of the stream
+        let start = this.position - this.pending.len();
+        swap_slice_from(&mut this.pending, &this.swaps, start);
+        this.ready = this.pending.len();
+        ready!(this.poll_write_ready(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_io::{swap_io_async, AsyncSwapReader, AsyncSwapWriter};
+    use crate::pattern::{PermutePattern, Predicate};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn swaps() -> Vec<(PermutePattern, Predicate)> {
+        vec![(PermutePattern::new(vec![1, 0]).unwrap(), Predicate::new())]
+    }
+
+    #[test]
+    fn test_swap_io_async_unaligned_reads() {
+        let source: Vec<u8> = (0..10).collect();
+        let mut reader = source[..3]
+            .chain(&source[3..6])
+            .chain(&source[6..9])
+            .chain(&source[9..]);
+        let mut writer: Vec<u8> = Vec::new();
+        let written = block_on(swap_io_async(&mut reader, &mut writer, &swaps())).unwrap();
+        assert_eq!(written, 10);
+        assert_eq!(writer, vec![1, 0, 3, 2, 5, 4, 7, 6, 9, 8]);
+    }
+
+    #[test]
+    fn test_async_swap_reader_unaligned_reads() {
+        let source: Vec<u8> = (0..10).collect();
+        let mut reader = AsyncSwapReader::new(&source[..], swaps());
+        let mut swapped = Vec::new();
+        let mut buffer = [0u8; 3];
+        block_on(async {
+            loop {
+                let size = reader.read(&mut buffer).await.unwrap();
+                if size == 0 {
+                    break;
+                }
+                swapped.extend_from_slice(&buffer[..size]);
+                assert_eq!(reader.position(), swapped.len());
+            }
+        });
+        assert_eq!(swapped, vec![1, 0, 3, 2, 5, 4, 7, 6, 9, 8]);
+    }
+
+    #[test]
+    fn test_async_swap_writer_unaligned_writes() {
+        let source: Vec<u8> = (0..11).collect();
+        let mut writer = AsyncSwapWriter::new(Vec::new(), swaps());
+        block_on(async {
+            for chunk in source.chunks(3) {
+                writer.write_all(chunk).await.unwrap();
+            }
+            writer.shutdown().await.unwrap();
+        });
+        assert_eq!(writer.position(), 11);
+        // trailing partial group is left unswapped
+        assert_eq!(writer.into_inner(), vec![1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 10]);
+    }
+}
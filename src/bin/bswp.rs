@@ -0,0 +1,582 @@
+//! `bswp` command line tool, requires the `cli` feature.
+
+use bswp::dsl::{parse_number, parse_rules};
+use bswp::io::{
+    swap_files_par_with, swap_io_dry_run, swap_io_with, SwapFileOptions, SwapIoOptions, SwapOutcome,
+};
+use bswp::pattern::Change;
+use bswp::preview::Preview;
+use bswp::rule::SwapRule;
+use bswp::spec::SwapSpec;
+use bswp::BUFFER_SIZE;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Swap bytes using patterns and masks.
+///
+/// Exit status is 0 on success, 1 if `apply --check` finds bytes to change and 2 on error.
+#[derive(Parser)]
+#[command(name = "bswp", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Applies rules to a file or to stdin
+    Apply(ApplyArgs),
+    /// Shows a hexdump of the bytes rules would change, before and after
+    Preview(PreviewArgs),
+}
+
+/// Where rules come from.
+#[derive(Args)]
+struct RuleArgs {
+    /// Rule expression, e.g. "set 0x42 mask 0xF0 at 2n+1 limit 3" (repeatable)
+    #[arg(short = 'e', long = "rule", value_name = "EXPRESSION")]
+    rules: Vec<String>,
+    /// TOML, JSON or YAML specification file, rules are applied before expressions
+    #[arg(short, long, value_name = "FILE")]
+    spec: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("edit").args(["in_place", "glob"]).multiple(true)))]
+struct ApplyArgs {
+    #[command(flatten)]
+    rules: RuleArgs,
+    /// Input files, stdin if missing, only one without --in-place
+    #[arg(value_name = "FILE")]
+    inputs: Vec<PathBuf>,
+    /// Output file, stdout if missing
+    #[arg(short, long, value_name = "FILE", conflicts_with = "in_place")]
+    output: Option<PathBuf>,
+    /// Edit files in place, each file being atomically replaced
+    #[arg(short, long)]
+    in_place: bool,
+    /// Edit files matching PATTERN in place, e.g. 'dumps/**/*.bin' (repeatable)
+    #[arg(long, value_name = "PATTERN", conflicts_with = "output")]
+    glob: Vec<String>,
+    /// Number of files edited at once
+    #[arg(short, long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+    /// Back each file up to its path followed by SUFFIX before editing it in place
+    #[arg(long, value_name = "SUFFIX", requires = "edit")]
+    backup: Option<String>,
+    /// Only swap bytes in START..END, positions being relative to START
+    #[arg(long, value_name = "START..END", value_parser = parse_range)]
+    range: Option<Range<usize>>,
+    /// Size of the buffer between input and output
+    #[arg(long, value_name = "BYTES", default_value_t = BUFFER_SIZE)]
+    buffer_size: usize,
+    /// Print statistics to stderr
+    #[arg(long)]
+    stats: bool,
+    /// Only report files that would change, exiting with status 1 if any
+    #[arg(long, conflicts_with_all = ["output", "in_place", "backup"])]
+    check: bool,
+}
+
+#[derive(Args)]
+struct PreviewArgs {
+    #[command(flatten)]
+    rules: RuleArgs,
+    /// Input file
+    input: PathBuf,
+    /// Number of unchanged rows shown around changed ones
+    #[arg(short = 'C', long, value_name = "ROWS", default_value_t = 1)]
+    context: usize,
+    /// Show every row of START..END instead of the rows around changes
+    #[arg(long, value_name = "START..END", value_parser = parse_range)]
+    range: Option<Range<usize>>,
+    /// Highlights changed bytes
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    color: Color,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Color {
+    /// when stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+fn parse_range(range: &str) -> Result<Range<usize>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected START..END, found {:?}", range))?;
+    let bound = |bound: &str| {
+        parse_number(bound)
+            .map(|bound| bound as usize)
+            .ok_or_else(|| format!("invalid range bound {:?}", bound))
+    };
+    Ok(bound(start)?..bound(end)?)
+}
+
+/// Loads the specification at `path`, its format being inferred from its extension.
+fn load_spec(path: &Path) -> Result<SwapSpec, Box<dyn Error>> {
+    let spec = fs::read_to_string(path)?;
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let spec = match extension {
+        #[cfg(feature = "json")]
+        Some("json") => SwapSpec::from_json(&spec)?,
+        #[cfg(feature = "yaml")]
+        Some("yaml") | Some("yml") => SwapSpec::from_yaml(&spec)?,
+        _ => SwapSpec::from_toml(&spec)?,
+    };
+    Ok(spec)
+}
+
+/// Returns rules from the specification file then from expressions.
+fn load_rules(args: &RuleArgs) -> Result<Vec<SwapRule>, Box<dyn Error>> {
+    let mut rules: Vec<SwapRule> = Vec::new();
+    if let Some(path) = &args.spec {
+        let spec = load_spec(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        rules.extend(spec.into_swaps());
+    }
+    for expression in &args.rules {
+        rules.extend(parse_rules(expression).map_err(|e| format!("{:?}: {}", expression, e))?);
+    }
+    Ok(rules)
+}
+
+fn print_stats(outcome: &SwapOutcome) {
+    if let Some(stats) = &outcome.stats {
+        eprintln!("bytes read: {}", stats.bytes_read);
+        eprintln!("bytes written: {}", stats.bytes_written);
+        eprintln!("bytes modified: {}", stats.bytes_modified);
+        for (index, hits) in stats.rule_hits.iter().enumerate() {
+            eprintln!("rule {}: {} hits", index, hits);
+        }
+    }
+}
+
+/// Returns `swap_io_with` options from the command line.
+fn swap_options(args: &ApplyArgs) -> SwapIoOptions<'static> {
+    let mut options = SwapIoOptions::new()
+        .with_buffer_size(args.buffer_size)
+        .with_flush(true)
+        .with_stats(args.stats);
+    if let Some(range) = &args.range {
+        options = options.with_range(range.clone());
+    }
+    options
+}
+
+/// Returns input files followed by files matching `--glob` patterns, without duplicates.
+fn edited_files(args: &ApplyArgs) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = args.inputs.clone();
+    for pattern in &args.glob {
+        let entries =
+            glob::glob(pattern).map_err(|e| format!("invalid pattern {:?}: {}", pattern, e))?;
+        let matched = paths.len();
+        for entry in entries {
+            let path = entry?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        if paths.len() == matched {
+            return Err(format!("no file matches {:?}", pattern).into());
+        }
+    }
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    Ok(paths)
+}
+
+/// Writes one row per file: bytes written, bytes modified and status.
+fn render_summary<W: Write, E: std::fmt::Display>(
+    writer: &mut W,
+    results: &[(PathBuf, Result<SwapOutcome, E>)],
+) -> std::io::Result<()> {
+    let width = results
+        .iter()
+        .map(|(path, _)| path.display().to_string().len())
+        .fold(4, usize::max);
+    let row = |file: &str, bytes: &str, modified: &str, status: &str| {
+        format!(
+            "{:<width$}  {:>10}  {:>10}  {}",
+            file,
+            bytes,
+            modified,
+            status,
+            width = width
+        )
+    };
+    writeln!(writer, "{}", row("FILE", "BYTES", "MODIFIED", "STATUS"))?;
+    for (path, result) in results {
+        let path = path.display().to_string();
+        let line = match result {
+            Ok(outcome) => {
+                let modified = outcome
+                    .stats
+                    .as_ref()
+                    .map_or(0, |stats| stats.bytes_modified);
+                row(
+                    &path,
+                    &outcome.bytes_written.to_string(),
+                    &modified.to_string(),
+                    "ok",
+                )
+            }
+            Err(error) => row(&path, "-", "-", &format!("error: {}", error)),
+        };
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Edits input files in place, `--jobs` at once.
+///
+/// Prints a summary table when several files or patterns are given.
+fn apply_in_place(args: &ApplyArgs, rules: &[SwapRule]) -> Result<(), Box<dyn Error>> {
+    if args.jobs == 0 {
+        return Err("jobs must not be 0".into());
+    }
+    let paths = edited_files(args)?;
+    if paths.is_empty() {
+        return Err("--in-place requires at least one file".into());
+    }
+    let options = |_: &Path| {
+        let options = SwapFileOptions::new(swap_options(args).with_stats(true));
+        match &args.backup {
+            Some(suffix) => options.with_backup(suffix),
+            None => options,
+        }
+    };
+    let results = swap_files_par_with(paths, rules, args.jobs, options);
+    if let [(path, result)] = results.as_slice() {
+        if args.glob.is_empty() {
+            let outcome = result
+                .as_ref()
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            if args.stats {
+                print_stats(outcome);
+            }
+            return Ok(());
+        }
+    }
+    let stdout = std::io::stdout();
+    render_summary(&mut stdout.lock(), &results)?;
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} files failed", failed, results.len()).into());
+    }
+    Ok(())
+}
+
+/// Counts bytes `rules` would change in input files, or stdin, reporting changed inputs to stderr.
+///
+/// Returns whether any byte would change.
+fn check(args: &ApplyArgs, rules: &[SwapRule]) -> Result<bool, Box<dyn Error>> {
+    let count = |reader: &mut dyn Read| {
+        let options = swap_options(args).with_flush(false).with_stats(true);
+        swap_io_with(reader, &mut std::io::sink(), rules, options)
+            .map(|outcome| outcome.stats.map_or(0, |stats| stats.bytes_modified))
+    };
+    let paths = edited_files(args)?;
+    if paths.is_empty() {
+        let modified = count(&mut std::io::stdin().lock())?;
+        if modified > 0 {
+            eprintln!("stdin: {} bytes would change", modified);
+        }
+        return Ok(modified > 0);
+    }
+    let mut changed = false;
+    for path in &paths {
+        let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let modified = count(&mut file).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if modified > 0 {
+            eprintln!("{}: {} bytes would change", path.display(), modified);
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+fn apply(args: &ApplyArgs) -> Result<ExitCode, Box<dyn Error>> {
+    if args.buffer_size == 0 {
+        return Err("buffer size must not be 0".into());
+    }
+    let rules = load_rules(&args.rules)?;
+    if args.check {
+        let changed = check(args, &rules)?;
+        return Ok(ExitCode::from(u8::from(changed)));
+    }
+    if args.in_place || !args.glob.is_empty() {
+        apply_in_place(args, &rules)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.inputs.len() > 1 {
+        return Err("several files require --in-place".into());
+    }
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut reader: Box<dyn Read> = match args.inputs.first() {
+        Some(path) => Box::new(File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => Box::new(stdin.lock()),
+    };
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(path) => {
+            Box::new(File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?)
+        }
+        None => Box::new(BufWriter::new(stdout.lock())),
+    };
+    match swap_io_with(&mut reader, &mut writer, &rules, swap_options(args)) {
+        Ok(outcome) => print_stats(&outcome),
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => {} // e.g. piped into head
+        Err(error) => return Err(error.into()),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Writes rows of `source` holding `changes` and `context` rows around them,
+/// or every row of `range`, original bytes on the left and swapped bytes on the right.
+fn render_preview<W: Write>(
+    writer: &mut W,
+    source: &[u8],
+    changes: &[Change],
+    context: usize,
+    range: Option<Range<usize>>,
+    color: bool,
+) -> std::io::Result<()> {
+    let mut preview = Preview::new(source, changes.iter().copied())
+        .with_context(context)
+        .with_color(color);
+    if let Some(range) = range {
+        preview = preview.with_range(range);
+    }
+    write!(writer, "{}", preview)
+}
+
+fn preview(args: &PreviewArgs) -> Result<(), Box<dyn Error>> {
+    let rules = load_rules(&args.rules)?;
+    let source = fs::read(&args.input).map_err(|e| format!("{}: {}", args.input.display(), e))?;
+    let report = swap_io_dry_run(&mut source.as_slice(), &rules)?;
+    let stdout = std::io::stdout();
+    let color = match args.color {
+        Color::Auto => stdout.is_terminal(),
+        Color::Always => true,
+        Color::Never => false,
+    };
+    let mut writer = BufWriter::new(stdout.lock());
+    match render_preview(
+        &mut writer,
+        &source,
+        &report.changes,
+        args.context,
+        args.range.clone(),
+        color,
+    )
+    .and_then(|()| writer.flush())
+    {
+        Err(error) if error.kind() != std::io::ErrorKind::BrokenPipe => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Apply(args) => apply(args),
+        Command::Preview(args) => preview(args).map(|()| ExitCode::SUCCESS),
+    };
+    match result {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("bswp: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        apply, check, load_rules, parse_range, render_preview, render_summary, Cli, Command,
+        RuleArgs,
+    };
+    use bswp::io::SwapOutcome;
+    use bswp::pattern::{diff_iter, Pattern, Predicate};
+    use bswp::rule::{Op, Target};
+    use clap::{CommandFactory, Parser};
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+        assert_eq!(parse_range("0x10..32"), Ok(16..32));
+        assert!(parse_range("16").is_err());
+    }
+
+    #[test]
+    fn test_load_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = dir.path().join("rules.toml");
+        std::fs::write(&spec, "[[rule]]\nvalue = 0x42\nlimit = 1").unwrap();
+        let args = RuleArgs {
+            rules: vec!["not at 1; xor 0xFF in 2..4".to_string()],
+            spec: Some(spec),
+        };
+        let rules = load_rules(&args).unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[0],
+            (
+                Op::Set(Pattern::new(0x42)),
+                Target::Periodic(Predicate::new().with_limit(1))
+            )
+        );
+        assert_eq!(rules[2], (Op::Xor(0xFF), Target::Range(2..4)));
+    }
+
+    #[test]
+    fn test_apply_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [dir.path().join("a.bin"), dir.path().join("b.bin")];
+        for path in &paths {
+            std::fs::write(path, [0x41; 4]).unwrap();
+        }
+        let mut arguments = vec![
+            "bswp",
+            "apply",
+            "-i",
+            "--backup",
+            ".bak",
+            "-e",
+            "xor 0x01 at 0",
+        ];
+        arguments.extend(paths.iter().map(|path| path.to_str().unwrap()));
+        let Command::Apply(args) = Cli::parse_from(arguments).command else {
+            panic!("expected apply")
+        };
+        apply(&args).unwrap();
+        for path in &paths {
+            assert_eq!(std::fs::read(path).unwrap(), [0x40, 0x41, 0x41, 0x41]);
+            let backup = path.with_extension("bin.bak");
+            assert_eq!(std::fs::read(backup).unwrap(), [0x41; 4]);
+        }
+        assert!(Cli::try_parse_from(["bswp", "apply", "--backup", ".bak", "a.bin"]).is_err());
+        assert!(Cli::try_parse_from(["bswp", "apply", "-i", "-o", "out.bin", "a.bin"]).is_err());
+    }
+
+    #[test]
+    fn test_apply_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("dumps")).unwrap();
+        for name in ["dumps/a.bin", "dumps/b.bin", "dumps/c.txt"].iter() {
+            std::fs::write(dir.path().join(name), [0x41; 4]).unwrap();
+        }
+        let pattern = dir.path().join("**/*.bin");
+        let arguments = [
+            "bswp",
+            "apply",
+            "--glob",
+            pattern.to_str().unwrap(),
+            "-j",
+            "2",
+        ];
+        let Command::Apply(args) = Cli::parse_from(arguments.iter().chain(&["-e", "not"])).command
+        else {
+            panic!("expected apply")
+        };
+        apply(&args).unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("dumps/b.bin")).unwrap(),
+            [0xBE; 4]
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join("dumps/c.txt")).unwrap(),
+            [0x41; 4]
+        );
+
+        let pattern = dir.path().join("*.bin");
+        let Command::Apply(args) =
+            Cli::parse_from(["bswp", "apply", "--glob", pattern.to_str().unwrap()]).command
+        else {
+            panic!("expected apply")
+        };
+        assert!(apply(&args).is_err()); // no match
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patched.bin");
+        std::fs::write(&path, [0x42, 0x41]).unwrap();
+        let path = path.to_str().unwrap();
+        let checked = |rule: &str, range: &str| {
+            let arguments = [
+                "bswp", "apply", "--check", "-e", rule, "--range", range, path,
+            ];
+            let Command::Apply(args) = Cli::parse_from(arguments).command else {
+                panic!("expected apply")
+            };
+            check(&args, &load_rules(&args.rules).unwrap()).unwrap()
+        };
+        assert!(!checked("set 0x42 at 0", "0..2")); // already patched
+        assert!(checked("set 0x42 at 1", "0..2"));
+        assert!(!checked("set 0x42 at 1", "0..1"));
+        assert_eq!(std::fs::read(path).unwrap(), [0x42, 0x41]);
+        assert!(Cli::try_parse_from(["bswp", "apply", "--check", "-i", path]).is_err());
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let results = vec![
+            (PathBuf::from("a.bin"), Ok(SwapOutcome::default())),
+            (
+                PathBuf::from("dumps/b.bin"),
+                Err(std::io::Error::new(ErrorKind::NotFound, "not found")),
+            ),
+        ];
+        let mut output: Vec<u8> = Vec::new();
+        render_summary(&mut output, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec![
+                "FILE              BYTES    MODIFIED  STATUS",
+                "a.bin                 0           0  ok",
+                "dumps/b.bin           -           -  error: not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_preview() {
+        let source: Vec<u8> = (0..80).collect();
+        let rules = bswp::dsl::parse_rules("set 0xFF at 17; set 0xFF at 18").unwrap();
+        let changes: Vec<_> = diff_iter(&source, &rules).collect();
+        let mut output: Vec<u8> = Vec::new();
+        render_preview(&mut output, &source[..40], &changes, 0, None, false).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "*");
+        assert!(lines[1].starts_with("00000010  10 11 12 13"));
+        assert!(lines[1].contains("1f  |................|  10 ff ff 13"));
+        assert_eq!(lines[2], "*");
+        assert_eq!(lines[3], "2 of 40 bytes changed");
+
+        let mut output: Vec<u8> = Vec::new();
+        render_preview(&mut output, &source[..40], &changes, 1, None, false).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 4); // 3 rows then summary
+
+        let mut output: Vec<u8> = Vec::new();
+        render_preview(&mut output, &source[..40], &changes, 1, Some(0..8), false).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 3); // first row, "*" then summary
+    }
+}
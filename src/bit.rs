@@ -0,0 +1,169 @@
+//! Bit-level swapping (pure)
+//!
+//! Same model as `pattern` at bit granularity: bits are numbered from the start of the
+//! stream, most significant bit first, i.e. bit `8 * n` is the most significant bit of
+//! byte `n`. Protocol captures and bitstreams pack fields that do not align on bytes.
+
+use crate::pattern::Predicate;
+use crate::PositionPredicate;
+
+/// Operation applied to a single bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitPattern {
+    /// set the bit to `1`
+    Set,
+    /// set the bit to `0`
+    Clear,
+    /// invert the bit
+    Toggle,
+}
+
+impl BitPattern {
+    /// Returns `byte` with the bits under `mask` swapped.
+    fn apply(self, byte: u8, mask: u8) -> u8 {
+        match self {
+            BitPattern::Set => byte | mask,
+            BitPattern::Clear => byte & !mask,
+            BitPattern::Toggle => byte ^ mask,
+        }
+    }
+}
+
+/// Predicate on bit position, see `pattern::Predicate`.
+///
+/// ```
+/// use bswp::bit::BitPredicate;
+/// use bswp::PositionPredicate;
+///
+/// // lowest bit of every 12-bit sample
+/// let parity = BitPredicate::new().with_periodicity(12).with_offset(11);
+/// assert!(parity.eval(11) && parity.eval(23) && !parity.eval(12));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BitPredicate {
+    predicate: Predicate,
+}
+
+impl BitPredicate {
+    /// Creates a new `BitPredicate` matching every bit.
+    pub fn new() -> Self {
+        BitPredicate::default()
+    }
+
+    /// Sets the `periodicity`, in bits.
+    ///
+    /// **Default**: `1`
+    pub fn with_periodicity(mut self, periodicity: usize) -> Self {
+        self.predicate = self.predicate.with_periodicity(periodicity);
+        self
+    }
+
+    /// Sets the `offset`, in bits.
+    ///
+    /// **Default**: `0`
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.predicate = self.predicate.with_offset(offset);
+        self
+    }
+
+    /// Sets the maximum number of bits matched.
+    ///
+    /// **Default**: `None`
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.predicate = self.predicate.with_limit(limit);
+        self
+    }
+}
+
+impl PositionPredicate for BitPredicate {
+    fn eval(&self, position: usize) -> bool {
+        self.predicate.eval(position)
+    }
+
+    fn as_periodic(&self) -> Option<Predicate> {
+        self.predicate.as_periodic()
+    }
+
+    fn next_match(&self, position: usize) -> Option<usize> {
+        self.predicate.next_match(position)
+    }
+}
+
+/// Swaps bits of `buffer` in place, predicates being evaluated on bit positions.
+///
+/// Swaps are applied in order.
+///
+/// ```
+/// use bswp::bit::{swap_bits, BitPattern, BitPredicate};
+///
+/// let mut buffer = [0x00, 0xFF];
+/// let swaps = &[
+///     (BitPattern::Set, BitPredicate::new().with_periodicity(4)),
+///     (BitPattern::Toggle, BitPredicate::new().with_offset(14)),
+/// ];
+/// swap_bits(&mut buffer, swaps);
+/// assert_eq!(buffer, [0b1000_1000, 0b1111_1100]);
+/// ```
+pub fn swap_bits<Q: PositionPredicate>(buffer: &mut [u8], swaps: &[(BitPattern, Q)]) {
+    swap_bits_from(buffer, swaps, 0)
+}
+
+/// Same as `swap_bits`, `buffer[0]` being the byte at `position` in the stream.
+pub fn swap_bits_from<Q: PositionPredicate>(
+    buffer: &mut [u8],
+    swaps: &[(BitPattern, Q)],
+    position: usize,
+) {
+    let (start, end) = (position * 8, (position + buffer.len()) * 8);
+    for (pattern, predicate) in swaps {
+        let mut bit = start;
+        while let Some(matching) = predicate.next_match(bit).filter(|&bit| bit < end) {
+            let index = matching / 8 - position;
+            buffer[index] = pattern.apply(buffer[index], 0x80 >> (matching % 8));
+            bit = matching + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bit::{swap_bits, swap_bits_from, BitPattern};
+    use crate::pattern::Predicate;
+    use crate::rule::Target;
+
+    #[test]
+    fn test_bits_across_chunks() {
+        let swaps = &[
+            (
+                BitPattern::Toggle,
+                Target::Periodic(Predicate::new().with_periodicity(3).with_offset(5)),
+            ),
+            (BitPattern::Clear, Target::Range(20..44)),
+            (BitPattern::Set, Target::offsets(vec![0, 63, 200])),
+        ];
+        let source: Vec<u8> = (0..40).map(|i| (i * 29) as u8).collect();
+        let mut expected = source.clone();
+        swap_bits(&mut expected, swaps);
+        for bit in 0..source.len() * 8 {
+            let (byte, mask) = (bit / 8, 0x80 >> (bit % 8));
+            let mut value = source[byte] & mask != 0;
+            if bit >= 5 && (bit - 5) % 3 == 0 {
+                value = !value;
+            }
+            if (20..44).contains(&bit) {
+                value = false;
+            }
+            if [0, 63, 200].contains(&bit) {
+                value = true;
+            }
+            assert_eq!(expected[byte] & mask != 0, value, "bit: {}", bit);
+        }
+        for chunk_size in [1, 3, 7] {
+            let mut buffer = source.clone();
+            for (index, chunk) in buffer.chunks_mut(chunk_size).enumerate() {
+                swap_bits_from(chunk, swaps, index * chunk_size);
+            }
+            assert_eq!(buffer, expected, "chunk size: {}", chunk_size);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! BPS patches (pure)
+//!
+//! BPS describes the target as copies from the source, from itself and literal bytes,
+//! and carries CRC-32 checksums of the source, the target and the patch itself.
+
+use crate::checksum::crc32;
+use std::fmt;
+
+const HEADER: &[u8] = b"BPS1";
+/// Size of the checksums footer.
+const FOOTER_LEN: usize = 12;
+
+/// Error returned when a BPS patch is invalid or does not apply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BpsError {
+    /// the patch does not start with `BPS1`
+    InvalidHeader,
+    /// the patch ends in the middle of an action
+    Truncated,
+    /// the patch checksum does not match, the patch is corrupted
+    PatchChecksum,
+    /// the source size or checksum does not match, the patch was made for another file
+    SourceMismatch,
+    /// the target checksum does not match
+    TargetChecksum,
+    /// an action reads or writes out of bounds
+    InvalidAction(usize),
+}
+
+impl fmt::Display for BpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BpsError::InvalidHeader => write!(f, "not a BPS patch"),
+            BpsError::Truncated => write!(f, "truncated BPS patch"),
+            BpsError::PatchChecksum => write!(f, "BPS patch checksum mismatch"),
+            BpsError::SourceMismatch => write!(f, "BPS patch does not apply to this source"),
+            BpsError::TargetChecksum => write!(f, "BPS target checksum mismatch"),
+            BpsError::InvalidAction(offset) => {
+                write!(f, "invalid BPS action at patch offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BpsError {}
+
+/// Parsed BPS patch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BpsPatch<'a> {
+    source_len: usize,
+    target_len: usize,
+    metadata: &'a [u8],
+    /// encoded actions
+    actions: &'a [u8],
+    /// offset of `actions` in the patch
+    actions_offset: usize,
+    source_crc: u32,
+    target_crc: u32,
+}
+
+impl<'a> BpsPatch<'a> {
+    /// Parses a BPS patch, verifying the patch checksum.
+    pub fn parse(patch: &'a [u8]) -> Result<Self, BpsError> {
+        let body = patch.strip_prefix(HEADER).ok_or(BpsError::InvalidHeader)?;
+        if body.len() < FOOTER_LEN {
+            return Err(BpsError::Truncated);
+        }
+        let (body, footer) = body.split_at(body.len() - FOOTER_LEN);
+        let crc = |index: usize| {
+            let bytes = [
+                footer[index],
+                footer[index + 1],
+                footer[index + 2],
+                footer[index + 3],
+            ];
+            u32::from_le_bytes(bytes)
+        };
+        if crc32(&patch[..patch.len() - 4]) != crc(8) {
+            return Err(BpsError::PatchChecksum);
+        }
+        let mut reader = Decoder {
+            bytes: body,
+            index: 0,
+        };
+        let source_len = reader.number()?;
+        let target_len = reader.number()?;
+        let metadata_len = reader.number()?;
+        let metadata = reader.take(metadata_len)?;
+        Ok(BpsPatch {
+            source_len,
+            target_len,
+            metadata,
+            actions: &body[reader.index..],
+            actions_offset: HEADER.len() + reader.index,
+            source_crc: crc(0),
+            target_crc: crc(4),
+        })
+    }
+
+    /// Returns the expected source size.
+    pub fn source_len(&self) -> usize {
+        self.source_len
+    }
+
+    /// Returns the size of the target.
+    pub fn target_len(&self) -> usize {
+        self.target_len
+    }
+
+    /// Returns the metadata, usually XML or empty.
+    pub fn metadata(&self) -> &[u8] {
+        self.metadata
+    }
+
+    /// Returns the target built from `source`, verifying source and target checksums.
+    pub fn apply(&self, source: &[u8]) -> Result<Vec<u8>, BpsError> {
+        if source.len() != self.source_len || crc32(source) != self.source_crc {
+            return Err(BpsError::SourceMismatch);
+        }
+        let mut target: Vec<u8> = Vec::with_capacity(self.target_len);
+        let mut reader = Decoder {
+            bytes: self.actions,
+            index: 0,
+        };
+        let (mut source_offset, mut target_offset) = (0usize, 0usize);
+        while reader.index < self.actions.len() {
+            let at = self.actions_offset + reader.index;
+            let invalid = || BpsError::InvalidAction(at);
+            let action = reader.number()?;
+            let len = (action >> 2) + 1;
+            if target.len() + len > self.target_len {
+                return Err(invalid());
+            }
+            match action & 3 {
+                0 => {
+                    // source read
+                    let start = target.len();
+                    let bytes = source.get(start..start + len).ok_or_else(invalid)?;
+                    target.extend_from_slice(bytes);
+                }
+                1 => target.extend_from_slice(reader.take(len)?),
+                2 => {
+                    source_offset =
+                        relative(source_offset, reader.number()?).ok_or_else(invalid)?;
+                    let bytes = source
+                        .get(source_offset..source_offset + len)
+                        .ok_or_else(invalid)?;
+                    target.extend_from_slice(bytes);
+                    source_offset += len;
+                }
+                _ => {
+                    target_offset =
+                        relative(target_offset, reader.number()?).ok_or_else(invalid)?;
+                    if target_offset >= target.len() {
+                        return Err(invalid());
+                    }
+                    // copies may overlap the bytes being written
+                    for index in target_offset..target_offset + len {
+                        target.push(target[index]);
+                    }
+                    target_offset += len;
+                }
+            }
+        }
+        if target.len() != self.target_len || crc32(&target) != self.target_crc {
+            return Err(BpsError::TargetChecksum);
+        }
+        Ok(target)
+    }
+}
+
+/// Returns `offset` moved by the signed `delta` encoded as `magnitude << 1 | sign`.
+fn relative(offset: usize, delta: usize) -> Option<usize> {
+    if delta & 1 == 1 {
+        offset.checked_sub(delta >> 1)
+    } else {
+        offset.checked_add(delta >> 1)
+    }
+}
+
+/// Reads BPS variable length numbers and byte strings.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn number(&mut self) -> Result<usize, BpsError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = *self.bytes.get(self.index).ok_or(BpsError::Truncated)?;
+            self.index += 1;
+            let overflow = || BpsError::InvalidAction(HEADER.len() + self.index);
+            value = usize::from(byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|add| value.checked_add(add))
+                .ok_or_else(overflow)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+            value = value.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BpsError> {
+        let end = self.index.checked_add(len).ok_or(BpsError::Truncated)?;
+        let bytes = self.bytes.get(self.index..end).ok_or(BpsError::Truncated)?;
+        self.index = end;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bps::{BpsError, BpsPatch};
+    use crate::checksum::crc32;
+
+    fn number(mut value: usize, patch: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(byte | 0x80);
+                return;
+            }
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn action(kind: usize, len: usize, patch: &mut Vec<u8>) {
+        number((len - 1) << 2 | kind, patch);
+    }
+
+    #[test]
+    fn test_bps_apply() {
+        let source = b"hello world".to_vec();
+        let target = b"hello hello world!!!!".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        number(source.len(), &mut patch);
+        number(target.len(), &mut patch);
+        number(0, &mut patch);
+        action(0, 6, &mut patch); // source read "hello "
+        action(2, 5, &mut patch); // source copy "hello" from 0
+        number(0, &mut patch);
+        action(2, 6, &mut patch); // source copy " world" from 5
+        number(0, &mut patch);
+        action(1, 1, &mut patch); // target read "!"
+        patch.push(b'!');
+        action(3, 3, &mut patch); // target copy "!!!" overlapping
+        number(17 << 1, &mut patch);
+        patch.extend_from_slice(&crc32(&source).to_le_bytes());
+        patch.extend_from_slice(&crc32(&target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+
+        let parsed = BpsPatch::parse(&patch).unwrap();
+        assert_eq!(parsed.target_len(), target.len());
+        assert_eq!(parsed.apply(&source).unwrap(), target);
+        assert_eq!(parsed.apply(b"hello World"), Err(BpsError::SourceMismatch));
+
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        assert_eq!(BpsPatch::parse(&patch), Err(BpsError::PatchChecksum));
+        assert_eq!(BpsPatch::parse(b"PATCH"), Err(BpsError::InvalidHeader));
+    }
+}
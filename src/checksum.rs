@@ -0,0 +1,226 @@
+//! Checksum fix-ups (pure)
+//!
+//! Firmware and ROM images often store a checksum of some of their bytes, which must
+//! be recomputed once the image is swapped for it to load. A `Fixup` declares where the
+//! checksummed bytes are and where the checksum is stored, a `Fixer` recomputes and
+//! stores checksums while data is swapped chunk by chunk.
+
+use crate::word::Endianness;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Size of a stored CRC-32.
+const CRC32_LEN: usize = 4;
+
+/// CRC-32 (IEEE) of `range`, stored at `offset`.
+///
+/// ```
+/// use bswp::checksum::{apply_fixups, Fixup};
+/// use bswp::word::Endianness;
+///
+/// let mut image = *b"\0\0\0\0123456789";
+/// apply_fixups(&mut image, &[Fixup::crc32(4..13, 0).with_endianness(Endianness::Big)]);
+/// assert_eq!(image[..4], [0xCB, 0xF4, 0x39, 0x26]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fixup {
+    range: Range<usize>,
+    offset: usize,
+    endianness: Endianness,
+}
+
+impl Fixup {
+    /// Creates a fix-up storing the CRC-32 of the bytes in `range` at `offset`, little-endian.
+    pub fn crc32(range: Range<usize>, offset: usize) -> Self {
+        Fixup {
+            range,
+            offset,
+            endianness: Endianness::Little,
+        }
+    }
+
+    /// Sets the byte order of the stored checksum.
+    ///
+    /// **Default**: `Endianness::Little`
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Returns the positions of the checksummed bytes.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Returns the positions the checksum is stored at.
+    pub fn stored(&self) -> Range<usize> {
+        self.offset..self.offset + CRC32_LEN
+    }
+
+    /// Returns the stored bytes of the checksum `crc`.
+    fn encode(&self, crc: u32) -> [u8; CRC32_LEN] {
+        match self.endianness {
+            Endianness::Little => crc.to_le_bytes(),
+            Endianness::Big => crc.to_be_bytes(),
+        }
+    }
+}
+
+/// Recomputes and stores the checksums of `fixups` in `buffer`.
+///
+/// Checksums cover the bytes of `buffer` before any of them is stored, so that the
+/// order of `fixups` does not matter. Fix-ups whose range ends past `buffer` are
+/// skipped, checksum bytes past the end of `buffer` are not stored.
+pub fn apply_fixups(buffer: &mut [u8], fixups: &[Fixup]) {
+    Fixer::new(fixups).feed(buffer);
+}
+
+/// Checksum fix-ups over a stream of chunks.
+///
+/// Each fed chunk is checksummed, then the checksums completed so far are stored in it.
+/// A checksum stored before the end of its range cannot be stored in the chunk holding
+/// it, since it is not known yet: `finish` returns it instead, to be written afterwards.
+///
+/// ```
+/// use bswp::checksum::{Fixer, Fixup};
+///
+/// let fixups = [Fixup::crc32(0..9, 9), Fixup::crc32(4..9, 0)];
+/// let mut fixer = Fixer::new(&fixups);
+/// let mut header = *b"1234";
+/// let mut body = *b"56789\0\0\0\0";
+/// fixer.feed(&mut header);
+/// fixer.feed(&mut body);
+/// assert_eq!(body[5..], [0x26, 0x39, 0xF4, 0xCB]); // stored after its range
+/// assert_eq!(fixer.finish(), vec![(0, vec![0x70, 0xA0, 0x1D, 0x13])]); // stored before
+/// ```
+pub struct Fixer<'a> {
+    fixups: &'a [Fixup],
+    /// running CRC of every fix-up
+    crcs: Vec<u32>,
+    /// position of the chunk where every fix-up completed, if it did
+    completed: Vec<Option<usize>>,
+    /// position of the next byte fed
+    position: usize,
+}
+
+impl<'a> Fixer<'a> {
+    /// Creates a new `Fixer` starting at position `0`.
+    pub fn new(fixups: &'a [Fixup]) -> Self {
+        Fixer {
+            fixups,
+            crcs: vec![!0; fixups.len()],
+            completed: vec![None; fixups.len()],
+            position: 0,
+        }
+    }
+
+    /// Returns the position of the next byte fed.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Checksums `chunk`, then stores in it the checksums completed so far.
+    pub fn feed(&mut self, chunk: &mut [u8]) {
+        let start = self.position;
+        let end = start + chunk.len();
+        for (index, fixup) in self.fixups.iter().enumerate() {
+            let from = fixup.range.start.max(start);
+            let to = fixup.range.end.min(end);
+            if from < to {
+                self.crcs[index] = update(self.crcs[index], &chunk[from - start..to - start]);
+            }
+            if self.completed[index].is_none() && fixup.range.end <= end {
+                self.completed[index] = Some(start);
+            }
+        }
+        for (index, fixup) in self.fixups.iter().enumerate() {
+            if self.completed[index].is_none() {
+                continue;
+            }
+            let stored = fixup.stored();
+            let from = stored.start.max(start);
+            let to = stored.end.min(end);
+            if from < to {
+                let bytes = fixup.encode(!self.crcs[index]);
+                chunk[from - start..to - start]
+                    .copy_from_slice(&bytes[from - stored.start..to - stored.start]);
+            }
+        }
+        self.position = end;
+    }
+
+    /// Returns the checksum bytes that could not be stored in the chunks fed, with the
+    /// position of their first byte.
+    ///
+    /// Fix-ups whose range ends past the bytes fed are skipped.
+    pub fn finish(&self) -> Vec<(usize, Vec<u8>)> {
+        let mut pending = Vec::new();
+        for (index, fixup) in self.fixups.iter().enumerate() {
+            if let Some(completed) = self.completed[index] {
+                let stored = fixup.stored();
+                let to = stored.end.min(completed).min(self.position);
+                if stored.start < to {
+                    let bytes = fixup.encode(!self.crcs[index]);
+                    pending.push((stored.start, bytes[..to - stored.start].to_vec()));
+                }
+            }
+        }
+        pending
+    }
+}
+
+/// Returns the CRC-32 (IEEE) of `bytes`.
+///
+/// ```
+/// use bswp::checksum::crc32;
+///
+/// assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+/// ```
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !update(!0, bytes)
+}
+
+/// Returns the running CRC-32 `crc` updated with `bytes`.
+fn update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{apply_fixups, crc32, Fixer, Fixup};
+    use crate::word::Endianness;
+
+    #[test]
+    fn test_fixups_across_chunks() {
+        let fixups = [
+            Fixup::crc32(100..1_000, 2),
+            Fixup::crc32(0..50, 500).with_endianness(Endianness::Big),
+            Fixup::crc32(900..1_200, 1_198), // past the end
+            Fixup::crc32(0..1_300, 0),       // never completed
+        ];
+        let data: Vec<u8> = (0..1_200u32).map(|i| (i * 7 % 256) as u8).collect();
+        let mut expected = data.clone();
+        apply_fixups(&mut expected, &fixups);
+        assert_eq!(expected[2..6], crc32(&data[100..1_000]).to_le_bytes());
+        assert_eq!(expected[500..504], crc32(&data[..50]).to_be_bytes());
+        assert_eq!(expected[1_198..], crc32(&data[900..]).to_le_bytes()[..2]);
+        assert_eq!(expected[..2], data[..2]);
+
+        for chunk_size in [1, 3, 64, 999] {
+            let mut output = data.clone();
+            let mut fixer = Fixer::new(&fixups);
+            for chunk in output.chunks_mut(chunk_size) {
+                fixer.feed(chunk);
+            }
+            for (offset, bytes) in fixer.finish() {
+                output[offset..offset + bytes.len()].copy_from_slice(&bytes);
+            }
+            assert!(output == expected, "chunk size: {}", chunk_size);
+        }
+    }
+}
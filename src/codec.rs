@@ -0,0 +1,318 @@
+//! Hex and Base64 text streams (pure)
+//!
+//! Decodes hex or Base64 text to bytes and encodes bytes back to text with the formatting
+//! of the original (line width, line endings, case, padding), so that swaps can be applied
+//! to the decoded bytes of an embedded blob, see `io::SwapIoOptions::with_text`.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Base64 alphabet, RFC 4648 standard variant.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Text encoding of bytes.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// two hexadecimal digits per byte
+    Hex,
+    /// standard Base64, four characters per three bytes
+    Base64,
+}
+
+/// Error returned when text cannot be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CodecError {
+    /// the byte at the given index of the text is not part of the encoding
+    InvalidCharacter(usize),
+    /// the text ends in the middle of an encoded byte
+    Truncated,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::InvalidCharacter(index) => write!(f, "invalid character at {}", index),
+            CodecError::Truncated => write!(f, "truncated text"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CodecError {}
+
+/// Encoding and layout of a text stream.
+///
+/// ```
+/// use bswp::codec::{TextEncoding, TextFormat};
+///
+/// let text = b"DEADBE\nEF\n";
+/// let format = TextFormat::detect(TextEncoding::Hex, text);
+/// let expected = TextFormat::new(TextEncoding::Hex)
+///     .with_uppercase(true)
+///     .with_line_width(6)
+///     .with_trailing_newline(true);
+/// assert_eq!(format, expected);
+///
+/// let mut bytes = format.decode(text).unwrap();
+/// assert_eq!(bytes, vec![0xDE, 0xAD, 0xBE, 0xEF]);
+/// bytes[3] = 0xFF;
+/// assert_eq!(format.encode(&bytes), b"DEADBE\nFF\n");
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextFormat {
+    encoding: TextEncoding,
+    uppercase: bool,
+    padding: bool,
+    line_width: Option<usize>,
+    crlf: bool,
+    trailing_newline: bool,
+}
+
+impl TextFormat {
+    /// Creates a format on a single line, lowercase hex and padded Base64.
+    pub fn new(encoding: TextEncoding) -> Self {
+        TextFormat {
+            encoding,
+            uppercase: false,
+            padding: true,
+            line_width: None,
+            crlf: false,
+            trailing_newline: false,
+        }
+    }
+
+    /// Detects the format of `text`, e.g. before swapping its decoded bytes.
+    ///
+    /// The line width is the length of the first line if the text spans several lines.
+    pub fn detect(encoding: TextEncoding, text: &[u8]) -> Self {
+        let first_line = text.iter().position(|&byte| byte == b'\n');
+        let line_width = first_line.filter(|&end| end + 1 < text.len()).map(|end| {
+            text[..end]
+                .strip_suffix(b"\r")
+                .unwrap_or(&text[..end])
+                .len()
+        });
+        let uppercase = match encoding {
+            TextEncoding::Hex => text
+                .iter()
+                .find(|byte| matches!(byte, b'a'..=b'f' | b'A'..=b'F'))
+                .is_some_and(|byte| byte.is_ascii_uppercase()),
+            TextEncoding::Base64 => false,
+        };
+        let characters = text
+            .iter()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .count();
+        TextFormat {
+            encoding,
+            uppercase,
+            padding: text.contains(&b'=') || characters % 4 == 0,
+            line_width,
+            crlf: first_line.is_some_and(|end| end > 0 && text[end - 1] == b'\r'),
+            trailing_newline: text.ends_with(b"\n"),
+        }
+    }
+
+    /// Writes hex digits in uppercase.
+    ///
+    /// **Default**: `false`
+    pub fn with_uppercase(mut self, uppercase: bool) -> Self {
+        self.uppercase = uppercase;
+        self
+    }
+
+    /// Pads Base64 with `=` to a multiple of four characters.
+    ///
+    /// **Default**: `true`
+    pub fn with_padding(mut self, padding: bool) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Breaks lines every `line_width` characters.
+    ///
+    /// **Default**: a single line
+    pub fn with_line_width(mut self, line_width: usize) -> Self {
+        self.line_width = Some(line_width).filter(|&width| width > 0);
+        self
+    }
+
+    /// Ends lines with `\r\n` instead of `\n`.
+    ///
+    /// **Default**: `false`
+    pub fn with_crlf(mut self, crlf: bool) -> Self {
+        self.crlf = crlf;
+        self
+    }
+
+    /// Ends the text with a line ending.
+    ///
+    /// **Default**: `false`
+    pub fn with_trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Returns the encoding.
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Decodes `text`, ignoring whitespace.
+    pub fn decode(&self, text: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let characters = text
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| !byte.is_ascii_whitespace());
+        match self.encoding {
+            TextEncoding::Hex => decode_hex(characters),
+            TextEncoding::Base64 => decode_base64(characters),
+        }
+    }
+
+    /// Encodes `bytes` with this format.
+    pub fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        let characters = match self.encoding {
+            TextEncoding::Hex => {
+                let digits: &[u8; 16] = if self.uppercase {
+                    b"0123456789ABCDEF"
+                } else {
+                    b"0123456789abcdef"
+                };
+                bytes
+                    .iter()
+                    .flat_map(|&byte| {
+                        [digits[(byte >> 4) as usize], digits[(byte & 0x0F) as usize]]
+                    })
+                    .collect()
+            }
+            TextEncoding::Base64 => encode_base64(bytes, self.padding),
+        };
+        let newline: &[u8] = if self.crlf { b"\r\n" } else { b"\n" };
+        let width = self.line_width.unwrap_or(characters.len()).max(1);
+        let mut text = Vec::with_capacity(characters.len() + characters.len() / width * 2 + 2);
+        for (index, line) in characters.chunks(width).enumerate() {
+            if index > 0 {
+                text.extend_from_slice(newline);
+            }
+            text.extend_from_slice(line);
+        }
+        if self.trailing_newline {
+            text.extend_from_slice(newline);
+        }
+        text
+    }
+}
+
+fn decode_hex<'a, I>(characters: I) -> Result<Vec<u8>, CodecError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+{
+    let mut bytes = Vec::new();
+    let mut high: Option<u8> = None;
+    for (index, &character) in characters {
+        let digit = (character as char)
+            .to_digit(16)
+            .ok_or(CodecError::InvalidCharacter(index))? as u8;
+        match high.take() {
+            Some(high) => bytes.push(high << 4 | digit),
+            None => high = Some(digit),
+        }
+    }
+    match high {
+        Some(_) => Err(CodecError::Truncated),
+        None => Ok(bytes),
+    }
+}
+
+fn decode_base64<'a, I>(characters: I) -> Result<Vec<u8>, CodecError>
+where
+    I: Iterator<Item = (usize, &'a u8)>,
+{
+    let mut bytes = Vec::new();
+    let (mut bits, mut count, mut padding) = (0u32, 0, 0);
+    for (index, &character) in characters {
+        if character == b'=' {
+            padding += 1;
+            continue;
+        }
+        let value = BASE64
+            .iter()
+            .position(|&symbol| symbol == character)
+            .filter(|_| padding == 0)
+            .ok_or(CodecError::InvalidCharacter(index))?;
+        bits = bits << 6 | value as u32;
+        count += 1;
+        if count == 4 {
+            bytes.extend_from_slice(&bits.to_be_bytes()[1..]);
+            bits = 0;
+            count = 0;
+        }
+    }
+    match count {
+        0 => {}
+        2 => bytes.push((bits >> 4) as u8),
+        3 => bytes.extend_from_slice(&((bits >> 2) as u16).to_be_bytes()),
+        _ => return Err(CodecError::Truncated),
+    }
+    Ok(bytes)
+}
+
+fn encode_base64(bytes: &[u8], padding: bool) -> Vec<u8> {
+    let mut text = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for index in 0..=chunk.len() {
+            text.push(BASE64[(bits >> (18 - 6 * index) & 0x3F) as usize]);
+        }
+        if padding {
+            text.resize(text.len() + 3 - chunk.len(), b'=');
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::{CodecError, TextEncoding, TextFormat};
+
+    #[test]
+    fn test_base64_round_trip() {
+        let cases: [(&[u8], &[u8]); 4] = [
+            (b"", b""),
+            (b"f", b"Zg=="),
+            (b"fo", b"Zm8="),
+            (b"foobar", b"Zm9vYmFy"),
+        ];
+        let format = TextFormat::new(TextEncoding::Base64);
+        for (bytes, text) in cases {
+            assert_eq!(format.encode(bytes), text);
+            assert_eq!(format.decode(text).unwrap(), bytes);
+        }
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        let text = TextFormat::new(TextEncoding::Base64)
+            .with_line_width(76)
+            .with_crlf(true)
+            .with_trailing_newline(true)
+            .with_padding(false)
+            .encode(&bytes);
+        let format = TextFormat::detect(TextEncoding::Base64, &text);
+        assert_eq!(format.decode(&text).unwrap(), bytes);
+        assert_eq!(format.encode(&bytes), text);
+        assert_eq!(format.decode(b"Zm9v\nY"), Err(CodecError::Truncated));
+        assert_eq!(
+            format.decode(b"Zm=9v"),
+            Err(CodecError::InvalidCharacter(3))
+        );
+        assert_eq!(
+            TextFormat::new(TextEncoding::Hex).decode(b"0g"),
+            Err(CodecError::InvalidCharacter(1))
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Compressed streams (mut), requires the `gzip` or `zstd` feature.
+//!
+//! Decompresses the input, swaps the decompressed bytes and compresses them again on the
+//! way out, positions being positions in the decompressed stream.
+
+use crate::io::{swap_io, Error, Operation};
+use crate::{BytePattern, PositionPredicate};
+use std::io::{Read, Write};
+
+/// Same as `io::swap_io` on gzip streams: `reader` is decompressed, swapped and
+/// recompressed into `writer` with the default compression level.
+///
+/// Concatenated gzip members are read as a single stream.
+/// Returns number of decompressed bytes swapped.
+///
+/// Requires the `gzip` feature.
+///
+/// ```
+/// use std::io::{Read, Write};
+/// use bswp::compress::swap_io_gz;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+///
+/// let mut compressed = GzEncoder::new(Vec::new(), Compression::default());
+/// compressed.write_all(&[0x41; 4]).unwrap();
+/// let compressed = compressed.finish().unwrap();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let mut writer: Vec<u8> = Vec::new();
+/// assert_eq!(swap_io_gz(&mut &compressed[..], &mut writer, swaps).unwrap(), 4);
+///
+/// let mut swapped = Vec::new();
+/// GzDecoder::new(&writer[..]).read_to_end(&mut swapped).unwrap();
+/// assert_eq!(swapped, vec![0x42, 0x41, 0x42, 0x41]);
+/// ```
+#[cfg(feature = "gzip")]
+pub fn swap_io_gz<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+
+    let mut decoder = MultiGzDecoder::new(reader);
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    let written = swap_io(&mut decoder, &mut encoder, swaps)?;
+    encoder
+        .finish()
+        .map_err(|e| Error::new(Operation::Write, written, written, e))?;
+    Ok(written)
+}
+
+/// Same as `io::swap_io` on zstd streams: `reader` is decompressed, swapped and
+/// recompressed into `writer` with the default compression level.
+///
+/// Returns number of decompressed bytes swapped.
+///
+/// Requires the `zstd` feature.
+///
+/// ```
+/// use bswp::compress::swap_io_zstd;
+/// use bswp::pattern::{Pattern, Predicate};
+///
+/// let compressed = zstd::encode_all(&[0x41; 4][..], 0).unwrap();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let mut writer: Vec<u8> = Vec::new();
+/// assert_eq!(swap_io_zstd(&mut &compressed[..], &mut writer, swaps).unwrap(), 4);
+/// assert_eq!(zstd::decode_all(&writer[..]).unwrap(), vec![0x42, 0x41, 0x42, 0x41]);
+/// ```
+#[cfg(feature = "zstd")]
+pub fn swap_io_zstd<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    use zstd::stream::{read::Decoder, write::Encoder};
+
+    let mut decoder = Decoder::new(reader).map_err(|e| Error::new(Operation::Read, 0, 0, e))?;
+    let mut encoder = Encoder::new(writer, 0).map_err(|e| Error::new(Operation::Write, 0, 0, e))?;
+    let written = swap_io(&mut decoder, &mut encoder, swaps)?;
+    encoder
+        .finish()
+        .map_err(|e| Error::new(Operation::Write, written, written, e))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gz_round_trip() {
+        use crate::compress::swap_io_gz;
+        use crate::pattern::swap_slice;
+        use crate::rule::{Op, Target};
+        use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+        use std::io::{Read, Write};
+
+        let source: Vec<u8> = (0..100_000).map(|i| (i % 97) as u8).collect();
+        let mut compressed = Vec::new();
+        for half in source.chunks(50_000) {
+            // concatenated members
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(half).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        let swaps = &[(Op::Xor(0xFF), Target::Range(49_000..51_000))];
+        let mut writer: Vec<u8> = Vec::new();
+        let written = swap_io_gz(&mut &compressed[..], &mut writer, swaps).unwrap();
+        assert_eq!(written, source.len());
+
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        let mut swapped = Vec::new();
+        GzDecoder::new(&writer[..])
+            .read_to_end(&mut swapped)
+            .unwrap();
+        assert_eq!(swapped, expected);
+        assert!(swap_io_gz(&mut &source[..], &mut Vec::new(), swaps).is_err());
+    }
+}
@@ -0,0 +1,549 @@
+//! Textual rule expressions (pure)
+//!
+//! A rule is an operation followed by optional position clauses:
+//!
+//! ```text
+//! set 0x42 mask 0xF0 at 2n+1 limit 3
+//! xor 0x55 in 0x100..0x200
+//! not offsets 4,8,15
+//! reverse 4 in 0..0x400
+//! ```
+//!
+//! Operations: `set VALUE [mask MASK]`, `xor KEY`, `and MASK`, `or MASK`, `not`,
+//! `reverse SIZE` (byte order within groups), `preset NAME` (see `presets::PresetRegistry`).
+//!
+//! Clauses:
+//! * `at Pn+O` positions `O`, `O + P`, `O + 2P`... (`n`, `4n`, `2n+1`), `at POSITION` a single position
+//! * `in START..END` positions in the range
+//! * `limit N` at most `N` positions
+//! * `offsets A,B,C` explicit positions, exclusive with other clauses
+//!
+//! Without clauses every position matches. Numbers are decimal, hexadecimal (`0x`)
+//! or binary (`0b`). Rules are separated by newlines or `;`, `#` starts a comment.
+
+use crate::pattern::{Pattern, Predicate};
+use crate::presets::PresetRegistry;
+use crate::rule::{Op, SwapRule, Target};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Error returned when an expression cannot be parsed.
+///
+/// ```
+/// use bswp::dsl::parse_rule;
+///
+/// let error = parse_rule("set 0x42 mask 0x1FF").unwrap_err();
+/// assert_eq!(error.message(), "mask 0x1FF does not fit in a byte");
+/// assert_eq!(error.suggestion(), Some("use a value up to 0xFF"));
+/// assert_eq!(error.to_string(), "1:15: mask 0x1FF does not fit in a byte (help: use a value up to 0xFF)");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    message: String,
+    suggestion: Option<String>,
+}
+
+impl ParseError {
+    pub(crate) fn new(column: usize, message: impl Into<String>) -> Self {
+        ParseError {
+            line: 1,
+            column,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+
+    /// Suggests the closest of `candidates` if `word` looks like a typo.
+    fn with_closest(self, word: &str, candidates: &[&str]) -> Self {
+        match closest(word, candidates) {
+            Some(candidate) => self.with_suggestion(format!("did you mean {:?}?", candidate)),
+            None => self,
+        }
+    }
+
+    /// Returns the line of the error, starting at `1`.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the column of the error, starting at `1`.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns the error message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns a hint on how to fix the expression, if any.
+    pub fn suggestion(&self) -> Option<&str> {
+        self.suggestion.as_deref()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (help: {})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Returns the candidate closest to `word`, if at most 2 edits away and not a rewrite,
+/// or if `word` abbreviates it.
+pub(crate) fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let word: Vec<char> = word.chars().collect();
+    let distance = |candidate: &str| {
+        // edit distance counting adjacent transpositions as one edit
+        let candidate: Vec<char> = candidate.chars().collect();
+        let mut d = vec![vec![0; candidate.len() + 1]; word.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+        for i in 1..=word.len() {
+            for j in 1..=candidate.len() {
+                let cost = usize::from(word[i - 1] != candidate[j - 1]);
+                d[i][j] = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+                if i > 1
+                    && j > 1
+                    && word[i - 1] == candidate[j - 2]
+                    && word[i - 2] == candidate[j - 1]
+                {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+        d[word.len()][candidate.len()]
+    };
+    let prefix: String = word.iter().collect();
+    candidates
+        .iter()
+        .map(|&candidate| {
+            if word.len() >= 3 && candidate.starts_with(&prefix) {
+                (1, candidate) // abbreviation, e.g. period
+            } else {
+                (distance(candidate), candidate)
+            }
+        })
+        .filter(|&(distance, _)| distance <= 2 && distance < word.len())
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Message and suggestion for number `text` that does not fit in `T`.
+pub(crate) fn out_of_range<T>(text: &str) -> (String, Option<String>) {
+    match std::mem::size_of::<T>() {
+        1 => (
+            format!("{} does not fit in a byte", text),
+            Some("use a value up to 0xFF".to_string()),
+        ),
+        _ => (format!("{} is out of range", text), None),
+    }
+}
+
+/// Word of an expression and its column.
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    column: usize,
+    text: &'a str,
+}
+
+/// Parses a number: decimal, hexadecimal (`0x`) or binary (`0b`), `_` being ignored.
+///
+/// ```
+/// use bswp::dsl::parse_number;
+/// assert_eq!(parse_number("0xF0"), Some(240));
+/// assert_eq!(parse_number("0b1111_0000"), Some(240));
+/// assert_eq!(parse_number("240"), Some(240));
+/// assert_eq!(parse_number("F0"), None);
+/// ```
+pub fn parse_number(text: &str) -> Option<u64> {
+    let text = text.replace('_', "");
+    let (digits, radix) = match text.get(..2) {
+        Some("0x") | Some("0X") => (&text[2..], 16),
+        Some("0b") | Some("0B") => (&text[2..], 2),
+        _ => (&text[..], 10),
+    };
+    u64::from_str_radix(digits, radix).ok()
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    index: usize,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &'a str, start: usize) -> Self {
+        let tokens = expression
+            .split_whitespace()
+            .map(|text| Token {
+                column: start + (text.as_ptr() as usize - expression.as_ptr() as usize) + 1,
+                text,
+            })
+            .collect();
+        Parser {
+            tokens,
+            index: 0,
+            end: start + expression.len() + 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.index).copied();
+        self.index += 1;
+        token
+    }
+
+    fn expect(&mut self, what: &str) -> Result<Token<'a>, ParseError> {
+        let end = self.end;
+        self.next()
+            .ok_or_else(|| ParseError::new(end, format!("expected {}", what)))
+    }
+
+    fn number<T: TryFrom<u64>>(&mut self, what: &str) -> Result<T, ParseError> {
+        let token = self.expect(what)?;
+        number(token, what)
+    }
+}
+
+fn number<T: TryFrom<u64>>(token: Token, what: &str) -> Result<T, ParseError> {
+    let value = parse_number(token.text).ok_or_else(|| {
+        ParseError::new(
+            token.column,
+            format!("expected {}, found {:?}", what, token.text),
+        )
+    })?;
+    T::try_from(value).map_err(|_| {
+        let (message, suggestion) = out_of_range::<T>(token.text);
+        let error = ParseError::new(token.column, format!("{} {}", what, message));
+        match suggestion {
+            Some(suggestion) => error.with_suggestion(suggestion),
+            None => error,
+        }
+    })
+}
+
+const OPERATIONS: [&str; 7] = ["set", "xor", "and", "or", "not", "reverse", "preset"];
+const CLAUSES: [&str; 4] = ["at", "in", "limit", "offsets"];
+
+/// Parses `Pn+O`, `n`, `Pn` or a single position, returns `(periodicity, offset, single)`.
+fn parse_at(token: Token) -> Result<(usize, usize, bool), ParseError> {
+    let invalid = || {
+        ParseError::new(
+            token.column,
+            format!("expected position like 2n+1, found {:?}", token.text),
+        )
+    };
+    match token.text.split_once('n') {
+        Some((periodicity, offset)) => {
+            let periodicity = match periodicity {
+                "" => 1,
+                periodicity => parse_number(periodicity)
+                    .and_then(|value| usize::try_from(value).ok())
+                    .filter(|&value| value > 0)
+                    .ok_or_else(invalid)?,
+            };
+            let offset = match offset {
+                "" => 0,
+                offset => offset
+                    .strip_prefix('+')
+                    .and_then(parse_number)
+                    .and_then(|value| usize::try_from(value).ok())
+                    .ok_or_else(invalid)?,
+            };
+            Ok((periodicity, offset, false))
+        }
+        None => Ok((1, number(token, "position")?, true)),
+    }
+}
+
+/// Parses a single rule expression.
+///
+/// ```
+/// use bswp::dsl::parse_rule;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::rule::{Op, Target};
+///
+/// let rule = parse_rule("set 0x42 mask 0xF0 at 2n+1 limit 3").unwrap();
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1).with_limit(3);
+/// assert_eq!(rule, (Op::Set(Pattern::new(0x42).with_mask(0xF0)), Target::Periodic(predicate)));
+///
+/// assert_eq!(parse_rule("xor 0x55 in 0x100..0x200").unwrap(), (Op::Xor(0x55), Target::Range(0x100..0x200)));
+///
+/// let error = parse_rule("xor 0x155").unwrap_err();
+/// assert_eq!(error.column(), 5);
+/// ```
+pub fn parse_rule(expression: &str) -> Result<SwapRule, ParseError> {
+    parse_rule_with(expression, &PresetRegistry::new())
+}
+
+/// Same as `parse_rule`, `preset NAME` referencing `presets`.
+pub fn parse_rule_with(expression: &str, presets: &PresetRegistry) -> Result<SwapRule, ParseError> {
+    parse_rule_at(expression, 0, presets)
+}
+
+/// Parses `expression`, `start` being the column of its first character minus one.
+fn parse_rule_at(
+    expression: &str,
+    start: usize,
+    presets: &PresetRegistry,
+) -> Result<SwapRule, ParseError> {
+    let mut parser = Parser::new(expression, start);
+    let token = parser.expect("operation")?;
+    let op = match token.text {
+        "set" => {
+            let value = parser.number("value")?;
+            let mut pattern = Pattern::new(value);
+            if parser.tokens.get(parser.index).map(|token| token.text) == Some("mask") {
+                parser.next();
+                pattern = pattern.with_mask(parser.number("mask")?);
+            }
+            Op::Set(pattern)
+        }
+        "xor" => Op::Xor(parser.number("key")?),
+        "and" => Op::And(parser.number("mask")?),
+        "or" => Op::Or(parser.number("mask")?),
+        "not" => Op::Not,
+        "reverse" => Op::Reverse(parser.number("group size")?),
+        "preset" => {
+            let token = parser.expect("preset name")?;
+            presets.get(token.text).ok_or_else(|| {
+                let names: Vec<&str> = presets.names().collect();
+                ParseError::new(token.column, format!("unknown preset {:?}", token.text))
+                    .with_closest(token.text, &names)
+            })?
+        }
+        text => {
+            return Err(ParseError::new(
+                token.column,
+                format!(
+                    "expected operation (set, xor, and, or, not, reverse, preset), found {:?}",
+                    text
+                ),
+            )
+            .with_closest(text, &OPERATIONS))
+        }
+    };
+
+    let mut at: Option<(usize, usize, bool)> = None;
+    let mut range: Option<std::ops::Range<usize>> = None;
+    let mut limit: Option<usize> = None;
+    let mut offsets: Option<Vec<usize>> = None;
+    while let Some(token) = parser.next() {
+        let duplicate = || ParseError::new(token.column, format!("duplicate {:?}", token.text));
+        match token.text {
+            "at" if at.is_none() => at = Some(parse_at(parser.expect("position")?)?),
+            "in" if range.is_none() => {
+                let token = parser.expect("range")?;
+                let (start, end) = token.text.split_once("..").ok_or_else(|| {
+                    ParseError::new(
+                        token.column,
+                        format!("expected range like 0x10..0x20, found {:?}", token.text),
+                    )
+                })?;
+                let start: usize = number(
+                    Token {
+                        text: start,
+                        ..token
+                    },
+                    "range start",
+                )?;
+                let end: usize = number(Token { text: end, ..token }, "range end")?;
+                range = Some(start..end);
+            }
+            "limit" if limit.is_none() => limit = Some(parser.number("limit")?),
+            "offsets" if offsets.is_none() => {
+                let token = parser.expect("offsets")?;
+                let values = token
+                    .text
+                    .split(',')
+                    .map(|text| number(Token { text, ..token }, "offset"))
+                    .collect::<Result<_, _>>()?;
+                offsets = Some(values);
+            }
+            "at" | "in" | "limit" | "offsets" => return Err(duplicate()),
+            text => {
+                return Err(ParseError::new(
+                    token.column,
+                    format!("expected clause (at, in, limit, offsets), found {:?}", text),
+                )
+                .with_closest(text, &CLAUSES))
+            }
+        }
+    }
+
+    let target = match (offsets, at, range, limit) {
+        (Some(offsets), None, None, None) => Target::offsets(offsets),
+        (Some(_), ..) => {
+            return Err(
+                ParseError::new(start + 1, "offsets cannot be combined with other clauses")
+                    .with_suggestion("list every position with offsets or split the rule"),
+            )
+        }
+        (None, None, Some(range), None) => Target::Range(range),
+        (None, at, range, limit) => {
+            let (periodicity, mut offset, single) = at.unwrap_or((1, 0, false));
+            let mut limit = if single { Some(1) } else { limit };
+            if let Some(range) = range {
+                if offset < range.start {
+                    let skipped = (range.start - offset).div_ceil(periodicity);
+                    offset += skipped * periodicity;
+                }
+                let count = range.end.saturating_sub(offset).div_ceil(periodicity);
+                limit = Some(limit.map_or(count, |limit| limit.min(count)));
+            }
+            let mut predicate = Predicate::new()
+                .with_periodicity(periodicity)
+                .with_offset(offset);
+            if let Some(limit) = limit {
+                predicate = predicate.with_limit(limit);
+            }
+            Target::Periodic(predicate)
+        }
+    };
+    Ok((op, target))
+}
+
+/// Parses rules separated by newlines or `;`, ignoring blank lines and `#` comments.
+///
+/// ```
+/// use bswp::dsl::parse_rules;
+/// use bswp::pattern::swap_slice;
+///
+/// let rules = parse_rules("
+///     set 0x00 at 0; not at 2n+1 limit 2 # header
+///     xor 0xFF in 4..6
+/// ").unwrap();
+/// let mut buffer = [0x0F; 6];
+/// swap_slice(&mut buffer, &rules);
+/// assert_eq!(buffer, [0x00, 0xF0, 0x0F, 0xF0, 0xF0, 0xF0]);
+///
+/// let error = parse_rules("not\nnot at").unwrap_err();
+/// assert_eq!((error.line(), error.message()), (2, "expected position"));
+/// ```
+pub fn parse_rules(expressions: &str) -> Result<Vec<SwapRule>, ParseError> {
+    parse_rules_with(expressions, &PresetRegistry::new())
+}
+
+/// Same as `parse_rules`, `preset NAME` referencing `presets`.
+pub fn parse_rules_with(
+    expressions: &str,
+    presets: &PresetRegistry,
+) -> Result<Vec<SwapRule>, ParseError> {
+    let mut rules = Vec::new();
+    for (index, line) in expressions.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut start = 0;
+        for expression in line.split(';') {
+            if !expression.trim().is_empty() {
+                let rule =
+                    parse_rule_at(expression, start, presets).map_err(|error| ParseError {
+                        line: index + 1,
+                        ..error
+                    })?;
+                rules.push(rule);
+            }
+            start += expression.len() + 1;
+        }
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dsl::{closest, parse_rule, parse_rules};
+    use crate::pattern::{Pattern, Predicate};
+    use crate::rule::{Op, Target};
+    use crate::PositionPredicate;
+
+    #[test]
+    fn test_parse_clauses() {
+        let (_, target) = parse_rule("not at 3n+1 in 5..20").unwrap();
+        let matching: Vec<usize> = (0..30).filter(|&p| target.eval(p)).collect();
+        assert_eq!(matching, vec![7, 10, 13, 16, 19]);
+
+        let (_, target) = parse_rule("not at 3n+1 in 5..20 limit 2").unwrap();
+        let matching: Vec<usize> = (0..30).filter(|&p| target.eval(p)).collect();
+        assert_eq!(matching, vec![7, 10]);
+
+        let (_, target) = parse_rule("not at 0x10 in 0..8").unwrap();
+        assert!((0..30).all(|p| !target.eval(p)));
+
+        let (op, target) = parse_rule("or 0b1000_0000 offsets 9,3").unwrap();
+        assert_eq!(op, Op::Or(0x80));
+        assert_eq!(target, Target::Offsets(vec![3, 9]));
+
+        let (op, target) = parse_rule("preset zero-fill in 2..4").unwrap();
+        assert_eq!(op, Op::Set(Pattern::new(0x00)));
+        assert_eq!(target, Target::Range(2..4));
+
+        let (op, target) = parse_rule("reverse 4 in 0..0x400").unwrap();
+        assert_eq!(op, Op::Reverse(4));
+        assert_eq!(target, Target::Range(0..0x400));
+
+        let (op, target) = parse_rule("set 7").unwrap();
+        assert_eq!(op, Op::Set(Pattern::new(7)));
+        assert_eq!(target, Target::Periodic(Predicate::new()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |expression| {
+            let error = parse_rules(expression).unwrap_err();
+            (error.line(), error.column())
+        };
+        assert_eq!(error("rotate 1"), (1, 1));
+        assert_eq!(error("not at 0n"), (1, 8));
+        assert_eq!(error("not; not limit 1 limit 2"), (1, 18));
+        assert_eq!(error("not\nxor 1 in 1..x"), (2, 10));
+        assert_eq!(error("not offsets 1 at 2"), (1, 1));
+        assert_eq!(error("set 1 mask"), (1, 11));
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let suggestion = |expression| {
+            parse_rule(expression)
+                .unwrap_err()
+                .suggestion()
+                .map(String::from)
+        };
+        assert_eq!(
+            suggestion("xro 1"),
+            Some("did you mean \"xor\"?".to_string())
+        );
+        assert_eq!(
+            suggestion("not limt 2"),
+            Some("did you mean \"limit\"?".to_string())
+        );
+        assert_eq!(suggestion("rotate 1"), None);
+        assert_eq!(
+            suggestion("xor 256"),
+            Some("use a value up to 0xFF".to_string())
+        );
+        assert_eq!(closest("offset", &["at", "offsets"]), Some("offsets"));
+        assert_eq!(closest("", &["at", "in"]), None);
+        assert_eq!(
+            suggestion("preset inverse"),
+            Some("did you mean \"invert\"?".to_string())
+        );
+    }
+}
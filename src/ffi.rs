@@ -0,0 +1,201 @@
+//! C bindings (mut), requires the `ffi` feature.
+//!
+//! Exposes rules written in the `dsl` syntax through a stable C ABI, declared in
+//! `include/bswp.h` (generated with `cbindgen --config cbindgen.toml -o include/bswp.h`).
+//! Build the shared library with `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! ```c
+//! BswpRules *rules = bswp_rules_parse("set 0x42 at 2n+1; xor 0xFF in 0x100..0x200");
+//! if (rules == NULL) {
+//!     fprintf(stderr, "%s\n", bswp_last_error());
+//!     return 1;
+//! }
+//! bswp_apply_buffer(rules, buffer, len, 0);
+//! bswp_rules_free(rules);
+//! ```
+//!
+//! Functions report failures by returning `NULL` or a negative value, `bswp_last_error`
+//! then describes the last failure of the calling thread.
+
+use crate::dsl::parse_rules;
+use crate::pattern::swap_slice_from;
+use crate::rule::SwapRule;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: impl ToString) {
+    let error = CString::new(error.to_string().replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+}
+
+/// Rules parsed by `bswp_rules_parse`, opaque to C.
+#[derive(Clone, Debug)]
+pub struct BswpRules {
+    rules: Vec<SwapRule>,
+}
+
+/// Parses the NUL-terminated UTF-8 `expressions`, see `dsl::parse_rules`.
+///
+/// Returns `NULL` if `expressions` is `NULL`, not UTF-8 or invalid, else rules to release
+/// with `bswp_rules_free`.
+///
+/// # Safety
+///
+/// `expressions` must be `NULL` or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn bswp_rules_parse(expressions: *const c_char) -> *mut BswpRules {
+    if expressions.is_null() {
+        set_last_error("expressions is NULL");
+        return ptr::null_mut();
+    }
+    let expressions = match CStr::from_ptr(expressions).to_str() {
+        Ok(expressions) => expressions,
+        Err(error) => {
+            set_last_error(error);
+            return ptr::null_mut();
+        }
+    };
+    match parse_rules(expressions) {
+        Ok(rules) => Box::into_raw(Box::new(BswpRules { rules })),
+        Err(error) => {
+            set_last_error(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases rules returned by `bswp_rules_parse`, does nothing on `NULL`.
+///
+/// # Safety
+///
+/// `rules` must be `NULL` or returned by `bswp_rules_parse` and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn bswp_rules_free(rules: *mut BswpRules) {
+    if !rules.is_null() {
+        drop(Box::from_raw(rules));
+    }
+}
+
+/// Swaps the `len` bytes at `buffer` in place, `buffer[0]` being at position `start`.
+///
+/// Returns `0`, `-1` if `rules` or `buffer` is `NULL`.
+///
+/// # Safety
+///
+/// `rules` must be `NULL` or valid rules, `buffer` must be `NULL` or point to `len`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bswp_apply_buffer(
+    rules: *const BswpRules,
+    buffer: *mut u8,
+    len: usize,
+    start: usize,
+) -> c_int {
+    if rules.is_null() || buffer.is_null() {
+        set_last_error("rules or buffer is NULL");
+        return -1;
+    }
+    let buffer = std::slice::from_raw_parts_mut(buffer, len);
+    swap_slice_from(buffer, &(*rules).rules, start);
+    0
+}
+
+/// Swaps the bytes read from file descriptor `input` until end of file into file
+/// descriptor `output`, see `io::swap_io`. Neither descriptor is closed.
+///
+/// Returns the number of bytes written, `-1` if `rules` is `NULL` or on io error.
+///
+/// Only available on Unix.
+///
+/// # Safety
+///
+/// `rules` must be `NULL` or valid rules, `input` and `output` must be open file
+/// descriptors not used elsewhere during the call.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn bswp_apply_fd(
+    rules: *const BswpRules,
+    input: c_int,
+    output: c_int,
+) -> i64 {
+    use std::fs::File;
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::FromRawFd;
+
+    if rules.is_null() {
+        set_last_error("rules is NULL");
+        return -1;
+    }
+    let mut reader = ManuallyDrop::new(File::from_raw_fd(input));
+    let mut writer = ManuallyDrop::new(File::from_raw_fd(output));
+    match crate::io::swap_io(&mut *reader, &mut *writer, &(*rules).rules) {
+        Ok(written) => written as i64,
+        Err(error) => {
+            set_last_error(error);
+            -1
+        }
+    }
+}
+
+/// Returns the NUL-terminated description of the last failure of the calling thread,
+/// `NULL` if none.
+///
+/// The string is valid until the next failure of the thread.
+#[no_mangle]
+pub extern "C" fn bswp_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ffi::{bswp_apply_buffer, bswp_last_error, bswp_rules_free, bswp_rules_parse};
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    #[test]
+    fn test_ffi_buffer() {
+        unsafe {
+            let expressions = CString::new("not at 0; set 0x42 at 2n+1").unwrap();
+            let rules = bswp_rules_parse(expressions.as_ptr());
+            assert!(!rules.is_null());
+            let mut buffer = [0x0F; 4];
+            assert_eq!(bswp_apply_buffer(rules, buffer.as_mut_ptr(), 3, 1), 0);
+            assert_eq!(buffer, [0x42, 0x0F, 0x42, 0x0F]);
+            assert_eq!(bswp_apply_buffer(rules, ptr::null_mut(), 0, 0), -1);
+
+            #[cfg(unix)]
+            {
+                use crate::ffi::bswp_apply_fd;
+                use std::io::{Read, Seek, SeekFrom, Write};
+                use std::os::unix::io::AsRawFd;
+
+                let mut input = tempfile::tempfile().unwrap();
+                input.write_all(&[0x0F; 10_000]).unwrap();
+                input.seek(SeekFrom::Start(0)).unwrap();
+                let mut output = tempfile::tempfile().unwrap();
+                let written = bswp_apply_fd(rules, input.as_raw_fd(), output.as_raw_fd());
+                assert_eq!(written, 10_000);
+                let mut swapped = Vec::new();
+                output.seek(SeekFrom::Start(0)).unwrap();
+                output.read_to_end(&mut swapped).unwrap();
+                assert_eq!(&swapped[..4], &[0xF0, 0x42, 0x0F, 0x42]);
+            }
+            bswp_rules_free(rules);
+
+            let invalid = CString::new("not at").unwrap();
+            assert!(bswp_rules_parse(invalid.as_ptr()).is_null());
+            let error = CStr::from_ptr(bswp_last_error()).to_str().unwrap();
+            assert!(error.contains("expected position"), "{}", error);
+        }
+    }
+}
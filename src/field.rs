@@ -0,0 +1,169 @@
+//! Typed fields (pure)
+//!
+//! Read-modify-write operations on integers at fixed offsets, e.g. incrementing a
+//! little endian `u32` counter, with carries across bytes that per-byte patterns cannot
+//! express. Arithmetic wraps around at the field size.
+
+use crate::word::Endianness;
+use core::ops::Range;
+
+/// Operation applied to the value of a field.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldOp {
+    /// adds the given value, wrapping around
+    Add(u64),
+    /// subtracts the given value, wrapping around
+    Sub(u64),
+    /// ands with the given mask
+    And(u64),
+    /// ors with the given mask
+    Or(u64),
+    /// xors with the given key
+    Xor(u64),
+    /// sets the given value
+    Set(u64),
+}
+
+/// Integer of 1 to 8 bytes at a fixed offset, modified by a `FieldOp`.
+///
+/// ```
+/// use bswp::field::{apply_fields, Field, FieldOp};
+/// use bswp::word::Endianness;
+///
+/// let fields = [
+///     // increment the u32 LE counter at 0
+///     Field::u32(0, Endianness::Little, FieldOp::Add(1)),
+///     // OR 0x8000 into the u16 BE flags at 4
+///     Field::u16(4, Endianness::Big, FieldOp::Or(0x8000)),
+/// ];
+/// let mut buffer = [0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01];
+/// apply_fields(&mut buffer, &fields, 0);
+/// assert_eq!(buffer, [0x00, 0x00, 0x01, 0x00, 0x80, 0x01]);
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    offset: usize,
+    size: usize,
+    endianness: Endianness,
+    op: FieldOp,
+}
+
+impl Field {
+    /// Creates a field of `size` bytes at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not between `1` and `8`.
+    pub fn new(offset: usize, size: usize, endianness: Endianness, op: FieldOp) -> Self {
+        assert!((1..=8).contains(&size), "field size must be 1 to 8 bytes");
+        Field {
+            offset,
+            size,
+            endianness,
+            op,
+        }
+    }
+
+    /// Creates a `u8` field at `offset`.
+    pub fn u8(offset: usize, op: FieldOp) -> Self {
+        Field::new(offset, 1, Endianness::Little, op)
+    }
+
+    /// Creates a `u16` field at `offset`.
+    pub fn u16(offset: usize, endianness: Endianness, op: FieldOp) -> Self {
+        Field::new(offset, 2, endianness, op)
+    }
+
+    /// Creates a `u32` field at `offset`.
+    pub fn u32(offset: usize, endianness: Endianness, op: FieldOp) -> Self {
+        Field::new(offset, 4, endianness, op)
+    }
+
+    /// Creates a `u64` field at `offset`.
+    pub fn u64(offset: usize, endianness: Endianness, op: FieldOp) -> Self {
+        Field::new(offset, 8, endianness, op)
+    }
+
+    /// Returns the positions of the field bytes.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.size
+    }
+
+    /// Returns `value` with the operation applied, truncated to the field size.
+    pub fn eval(&self, value: u64) -> u64 {
+        let value = match self.op {
+            FieldOp::Add(operand) => value.wrapping_add(operand),
+            FieldOp::Sub(operand) => value.wrapping_sub(operand),
+            FieldOp::And(mask) => value & mask,
+            FieldOp::Or(mask) => value | mask,
+            FieldOp::Xor(key) => value ^ key,
+            FieldOp::Set(operand) => operand,
+        };
+        value & (u64::MAX >> (64 - 8 * self.size))
+    }
+
+    /// Modifies the field in `buffer`, `buffer[0]` being the byte at `position`.
+    ///
+    /// Returns `false`, leaving `buffer` untouched, if the field does not lie wholly
+    /// in `buffer`.
+    pub fn apply(&self, buffer: &mut [u8], position: usize) -> bool {
+        let bytes = match self
+            .offset
+            .checked_sub(position)
+            .and_then(|start| buffer.get_mut(start..start + self.size))
+        {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let value: u64 = self.endianness.read(bytes);
+        self.endianness.write(self.eval(value), bytes);
+        true
+    }
+}
+
+/// Modifies every field lying wholly in `buffer`, in order, `buffer[0]` being the byte at
+/// `position`.
+///
+/// Returns the number of fields modified.
+pub fn apply_fields(buffer: &mut [u8], fields: &[Field], position: usize) -> usize {
+    fields
+        .iter()
+        .filter(|field| field.apply(buffer, position))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::field::{apply_fields, Field, FieldOp};
+    use crate::word::Endianness;
+
+    #[test]
+    fn test_field_carries() {
+        let fields = [
+            Field::u64(0, Endianness::Big, FieldOp::Add(1)),
+            Field::new(8, 3, Endianness::Little, FieldOp::Sub(1)),
+            Field::u16(11, Endianness::Little, FieldOp::Xor(0xFFFF)),
+            Field::u8(13, FieldOp::And(0x0F)),
+            Field::u32(14, Endianness::Big, FieldOp::Set(0x1234_5678_9ABC)),
+            Field::u16(20, Endianness::Little, FieldOp::Add(1)), // past the end
+        ];
+        let mut buffer = [
+            0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x34, 0x12, 0xAB,
+            0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(apply_fields(&mut buffer, &fields, 0), 5);
+        assert_eq!(
+            buffer,
+            [
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xCB, 0xED, 0x0B,
+                0x56, 0x78, 0x9A, 0xBC, 0x00
+            ]
+        );
+        let mut shifted = [0x41, 0x41];
+        assert!(!fields[0].apply(&mut shifted, 1));
+        assert!(fields[3].apply(&mut shifted, 12));
+        assert_eq!(shifted, [0x41, 0x01]);
+    }
+}
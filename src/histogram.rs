@@ -0,0 +1,181 @@
+//! Byte histograms (pure)
+//!
+//! Byte-value histograms and Shannon entropy of the input and output of a swap, overall
+//! or per region, e.g. to check that deobfuscated data is structured: encrypted or
+//! compressed data is close to 8 bits of entropy per byte, text and code much lower.
+
+use std::ops::Range;
+
+/// Number of occurrences of every byte value.
+///
+/// ```
+/// use bswp::histogram::Histogram;
+///
+/// let mut histogram = Histogram::new();
+/// histogram.record(b"aabb");
+/// assert_eq!(histogram.count(b'a'), 2);
+/// assert_eq!(histogram.total(), 4);
+/// assert_eq!(histogram.entropy(), 1.0); // two equally likely values
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; 256],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram { counts: [0; 256] }
+    }
+}
+
+impl Histogram {
+    /// Creates an empty `Histogram`.
+    pub fn new() -> Self {
+        Histogram::default()
+    }
+
+    /// Counts every byte of `bytes`.
+    pub fn record(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.counts[byte as usize] += 1;
+        }
+    }
+
+    /// Returns the number of occurrences of `byte`.
+    pub fn count(&self, byte: u8) -> u64 {
+        self.counts[byte as usize]
+    }
+
+    /// Returns the number of occurrences of every byte value, indexed by value.
+    pub fn counts(&self) -> &[u64; 256] {
+        &self.counts
+    }
+
+    /// Returns the number of bytes counted.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the Shannon entropy in bits per byte, from `0.0` (a single value) to `8.0`
+    /// (every value equally likely), `0.0` if no byte was counted.
+    pub fn entropy(&self) -> f64 {
+        let total = self.total() as f64;
+        self.counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let probability = count as f64 / total;
+                -probability * probability.log2()
+            })
+            .sum::<f64>()
+            .max(0.0)
+    }
+}
+
+/// Histograms of the input and output of a region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionHistogram {
+    /// positions covered
+    pub range: Range<usize>,
+    /// bytes before swap
+    pub input: Histogram,
+    /// bytes after swap
+    pub output: Histogram,
+}
+
+/// Histograms of the input and output of a swap, overall and per region, collected by
+/// `io::SwapIoOptions::with_histograms`.
+///
+/// ```
+/// use bswp::histogram::ByteStats;
+///
+/// let mut stats = ByteStats::new().with_region(0..2);
+/// stats.record(&[0x00, 0x00, 0x01, 0x02], &[0x00, 0x00, 0x00, 0x00], 0);
+/// assert_eq!(stats.input().entropy(), 1.5);
+/// assert_eq!(stats.output().entropy(), 0.0);
+/// assert_eq!(stats.regions()[0].input.total(), 2);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ByteStats {
+    input: Histogram,
+    output: Histogram,
+    regions: Vec<RegionHistogram>,
+}
+
+impl ByteStats {
+    /// Creates empty statistics, without regions.
+    pub fn new() -> Self {
+        ByteStats::default()
+    }
+
+    /// Also collects histograms of the positions in `range`.
+    pub fn with_region(mut self, range: Range<usize>) -> Self {
+        self.regions.push(RegionHistogram {
+            range,
+            input: Histogram::new(),
+            output: Histogram::new(),
+        });
+        self
+    }
+
+    /// Counts the bytes of a chunk before and after swap, `input[0]` being at `position`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` and `output` lengths differ.
+    pub fn record(&mut self, input: &[u8], output: &[u8], position: usize) {
+        assert_eq!(input.len(), output.len(), "chunk lengths differ");
+        self.input.record(input);
+        self.output.record(output);
+        let end = position + input.len();
+        for region in &mut self.regions {
+            let first = region.range.start.clamp(position, end) - position;
+            let last = region.range.end.clamp(position, end) - position;
+            if first < last {
+                region.input.record(&input[first..last]);
+                region.output.record(&output[first..last]);
+            }
+        }
+    }
+
+    /// Returns the histogram of every byte before swap.
+    pub fn input(&self) -> &Histogram {
+        &self.input
+    }
+
+    /// Returns the histogram of every byte after swap.
+    pub fn output(&self) -> &Histogram {
+        &self.output
+    }
+
+    /// Returns the histograms of the regions, in the order they were added.
+    pub fn regions(&self) -> &[RegionHistogram] {
+        &self.regions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::histogram::{ByteStats, Histogram};
+
+    #[test]
+    fn test_entropy_by_region() {
+        let random: Vec<u8> = (0..=255).collect();
+        let text = b"the quick brown fox jumps over the lazy dog";
+        let mut stats = ByteStats::new().with_region(0..256).with_region(250..260);
+        stats.record(&random, &random, 0);
+        stats.record(text, text, 256);
+        let regions = stats.regions();
+        assert_eq!(regions[0].output.entropy(), 8.0);
+        assert_eq!(regions[1].input.total(), 10);
+        let text_entropy = {
+            let mut histogram = Histogram::new();
+            histogram.record(text);
+            histogram.entropy()
+        };
+        assert!(text_entropy > 3.0 && text_entropy < 5.0);
+        assert!(stats.input().entropy() < 8.0);
+        assert_eq!(stats.input().total(), 256 + text.len() as u64);
+        assert_eq!(Histogram::new().entropy(), 0.0);
+    }
+}
@@ -1,7 +1,44 @@
 //! Byte swap IO utils (mut)
 
+use crate::bit::{swap_bits_from, BitPattern};
+use crate::bps::BpsPatch;
+use crate::checksum::Fixer;
+use crate::field::Field;
+use crate::ips::{IpsPatch, IpsRecord};
+use crate::overlay::Overlay;
+use crate::pattern::{
+    fill_slice_from, gcd, group_size, matching_runs, swap_slice_from, Change, ChangeReport,
+    FromEnd, SwapStats,
+};
+use crate::replace::{Replacement, Replacer};
+use crate::signature::{Signature, SignatureScanner};
+use crate::stream::Swapper;
+use crate::xor::XorKey;
+use crate::zip::{zip_slice_from, Combine};
 use crate::{BytePattern, PositionPredicate, BUFFER_SIZE};
-use std::io::{Read, Write};
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, IoSlice, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(any(feature = "sha2", feature = "crc32fast"))]
+mod digest;
+mod error;
+mod options;
+mod sidecar;
+
+#[cfg(any(feature = "sha2", feature = "crc32fast"))]
+pub use self::digest::DigestAlgorithm;
+pub use self::error::{Error, Operation};
+#[cfg(feature = "rayon")]
+pub use self::options::swap_files_par_with;
+pub use self::options::{
+    swap_file_with, swap_io_seek_with, swap_io_with, SwapFileOptions, SwapIoOptions, SwapOutcome,
+};
 
 /// For each byte in `reader` compute pattern and write result to `writer`.
 ///
@@ -9,6 +46,9 @@ use std::io::{Read, Write};
 ///
 /// Internally it relies on a `BUFFER_SIZE` buffer between `reader` and `writer`.
 ///
+/// `reader` and `writer` are generic so that calls are statically dispatched,
+/// trait objects (`&mut dyn Read`, `&mut dyn Write`) are accepted as well.
+///
 /// Please note that `swap_io`:
 /// * resets neither of `reader`/`writer` cursors before reading from/writing to it.
 /// * resets neither of `reader`/`writer` cursors after reading from/writing to it.
@@ -29,29 +69,3269 @@ use std::io::{Read, Write};
 /// assert_eq!(swap.unwrap(), 4); // 4 bytes written
 /// assert_eq!(writer.into_inner(), vec![0x42, 0x42, 0x42, 0x44])
 /// ```
-pub fn swap_io<P: BytePattern, Q: PositionPredicate>(
-    reader: &mut dyn Read,
-    writer: &mut dyn Write,
+pub fn swap_io<R, W, P, Q>(reader: &mut R, writer: &mut W, swaps: &[(P, Q)]) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut buffer = [0; BUFFER_SIZE];
+    swap_io_with_buffer(reader, writer, swaps, &mut buffer)
+}
+
+/// Same as `swap_io` but relying on a user-supplied `buffer` between `reader` and `writer`.
+///
+/// Small buffers suit memory-constrained targets, large buffers improve throughput.
+///
+/// # Panics
+///
+/// Panics if `buffer` is empty.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_io_with_buffer;
+///
+/// let mut reader: Cursor<Vec<u8>> = Cursor::new(vec![0x41, 0x42, 0x43, 0x44]);
+/// let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+///
+/// let swaps: &[(Pattern, Predicate)] = &[(Pattern::new(0x42).with_mask(0xFF), Predicate::new().with_periodicity(2).with_offset(0))];
+/// let mut buffer = [0u8; 3]; // tiny buffer
+/// let swap = swap_io_with_buffer(&mut reader, &mut writer, swaps, &mut buffer);
+/// assert_eq!(swap.unwrap(), 4);
+/// assert_eq!(writer.into_inner(), vec![0x42, 0x42, 0x42, 0x44])
+/// ```
+pub fn swap_io_with_buffer<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+    buffer: &mut [u8],
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    swap_io_hooked(reader, writer, &[swaps], buffer, Hooks::default())
+}
+
+/// Progress of a long running swap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// bytes read, swapped and written so far by this swap, not counting the bytes before a
+    /// resumed checkpoint
+    pub processed: usize,
+    /// position of the next byte to swap, i.e. `processed` plus the resumed checkpoint position
+    pub position: usize,
+    /// total number of bytes to process, if known
+    pub total: Option<usize>,
+}
+
+/// Same as `swap_io` but stops after `limit` bytes.
+///
+/// Returns number of bytes written to `writer`, less than `limit` if `reader` ends first.
+///
+/// No more than `limit` bytes are read: `reader` is left positioned right after them,
+/// ready to be handed to other code.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_io_take;
+///
+/// let mut reader: &[u8] = &[0x41, 0x41, 0x41, 0x41];
+/// let mut header: Vec<u8> = Vec::new();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new())];
+/// assert_eq!(swap_io_take(&mut reader, &mut header, swaps, 2).unwrap(), 2);
+/// assert_eq!(header, vec![0x42, 0x42]);
+/// assert_eq!(reader, &[0x41, 0x41]); // untouched remainder
+/// ```
+pub fn swap_io_take<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+    limit: usize,
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut reader = reader.take(limit as u64);
+    swap_io(&mut reader, writer, swaps)
+}
+
+/// Buffer size used by `swap_stdio`.
+const STDIO_BUFFER_SIZE: usize = 1 << 16;
+
+/// For each byte from stdin compute pattern and write result to stdout.
+///
+/// Returns number of bytes written to stdout.
+///
+/// Both handles are locked for the whole swap. A broken pipe (e.g. when piping
+/// into `head`) is not an error: the swap stops and the bytes written so far are returned.
+///
+/// ```no_run
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_stdio;
+///
+/// // cat firmware.bin | filter > patched.bin
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(4))];
+/// swap_stdio(swaps).unwrap();
+/// ```
+pub fn swap_stdio<P, Q>(swaps: &[(P, Q)]) -> Result<usize, Error>
+where
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut buffer = vec![0; STDIO_BUFFER_SIZE];
+    let mut reader = stdin.lock();
+    let mut writer = stdout.lock();
+    let result =
+        swap_io_with_buffer(&mut reader, &mut writer, swaps, &mut buffer).and_then(|written| {
+            writer
+                .flush()
+                .map(|_| written)
+                .map_err(|e| Error::new(Operation::Flush, written, written, e))
+        });
+    match result {
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => Ok(error.bytes_written()),
+        result => result,
+    }
+}
+
+/// Same as `swap_io` but predicates are evaluated from the end of `reader`.
+///
+/// The length of `reader` is determined by seeking to its end, then `reader` is
+/// rewound to its current position. Each predicate is wrapped in a `FromEnd`, so
+/// `Predicate::new().with_limit(n)` matches the last `n` bytes.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_io_from_end;
+///
+/// let mut reader = Cursor::new(vec![0x41; 6]);
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(2))]; // 2 last bytes
+/// assert_eq!(swap_io_from_end(&mut reader, &mut writer, swaps).unwrap(), 6);
+/// assert_eq!(writer, vec![0x41, 0x41, 0x41, 0x41, 0x42, 0x42]);
+/// ```
+pub fn swap_io_from_end<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+) -> Result<usize, Error>
+where
+    R: Read + Seek + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let start = seek_at(reader, SeekFrom::Current(0), 0, 0)?;
+    let end = seek_at(reader, SeekFrom::End(0), 0, 0)?;
+    seek_at(reader, SeekFrom::Start(start), 0, 0)?;
+    let len = end.saturating_sub(start) as usize;
+    swap_io_from_end_with_len(reader, writer, swaps, len)
+}
+
+/// Same as `swap_io_from_end` for readers that cannot seek, `len` being the length of `reader`.
+///
+/// Bytes past `len` are copied unchanged.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_io_from_end_with_len;
+///
+/// let mut reader: &[u8] = &[0x41; 6];
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(1))]; // last byte
+/// assert_eq!(swap_io_from_end_with_len(&mut reader, &mut writer, swaps, 6).unwrap(), 6);
+/// assert_eq!(writer, vec![0x41, 0x41, 0x41, 0x41, 0x41, 0x42]);
+/// ```
+pub fn swap_io_from_end_with_len<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+    len: usize,
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let swaps: Vec<(&P, FromEnd<&Q>)> = swaps
+        .iter()
+        .map(|(pattern, predicate)| (pattern, FromEnd::new(predicate, len)))
+        .collect();
+    swap_io(reader, writer, &swaps)
+}
+
+/// What to do when a non-blocking reader has no data available (`ErrorKind::WouldBlock`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WouldBlockStrategy {
+    /// return the `WouldBlock` error
+    Fail,
+    /// stop and return the number of bytes processed so far, the swap can be
+    /// continued later with `SwapIoOptions::with_resume`
+    Return,
+    /// sleep for the given duration, then retry
+    Sleep(Duration),
+}
+
+impl Default for WouldBlockStrategy {
+    /// `WouldBlockStrategy::Fail`
+    fn default() -> Self {
+        WouldBlockStrategy::Fail
+    }
+}
+
+/// Same as `swap_io` but works directly on `reader` internal buffer (see `BufRead::fill_buf`)
+/// instead of reading into a buffer of its own.
+///
+/// Each filled buffer is swapped into a copy and written with a single write, bytes of a
+/// group split across fills (see `BytePattern::group_size`) being held back until the next
+/// fill.
+///
+/// ```
+/// use std::io::{BufReader, Cursor};
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_bufread;
+///
+/// let mut reader = BufReader::new(Cursor::new(vec![0x41, 0x42, 0x43, 0x44]));
+/// let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let swap = swap_bufread(&mut reader, &mut writer, swaps);
+/// assert_eq!(swap.unwrap(), 4); // 4 bytes written
+/// assert_eq!(writer.into_inner(), vec![0x42, 0x42, 0x42, 0x44])
+/// ```
+pub fn swap_bufread<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
     swaps: &[(P, Q)],
-) -> Result<usize, std::io::Error> {
+) -> Result<usize, Error>
+where
+    R: BufRead + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let group = group_size(swaps);
+    let mut position: usize = 0; // position of `swapped[0]`
+    let mut total_written: usize = 0;
+    let mut swapped: Vec<u8> = Vec::new(); // copy of the filled buffer, after held bytes
+
+    loop {
+        let available = loop {
+            match reader.fill_buf() {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                result => {
+                    break result.map_err(|e| {
+                        Error::new(Operation::Read, position + swapped.len(), total_written, e)
+                    })?
+                }
+            }
+        };
+        if available.is_empty() {
+            break; // finished
+        }
+        swapped.extend_from_slice(available);
+        let size = available.len();
+        reader.consume(size);
+        let end = position + swapped.len();
+        let len = swapped.len() - end % group;
+        swap_slice_from(&mut swapped[..len], swaps, position);
+        write_at(writer, &swapped[..len], position, &mut total_written)?;
+        swapped.drain(..len);
+        position += len;
+    }
+    // partial group at the end of the stream
+    swap_slice_from(&mut swapped, swaps, position);
+    write_at(writer, &swapped, position, &mut total_written)?;
+    Ok(position + swapped.len())
+}
+
+/// Number of chunks batched in a single vectored write, see `SwapIoOptions::with_vectored`.
+pub const VECTORED_CHUNKS: usize = 8;
+
+/// Writes every slice using `Write::write_vectored`, retrying until all bytes are written.
+///
+/// `offset` is the position of the first byte, `written` is incremented by the number of bytes written.
+fn write_all_vectored<W: Write + ?Sized>(
+    writer: &mut W,
+    mut slices: &mut [IoSlice<'_>],
+    mut offset: usize,
+    written: &mut usize,
+) -> Result<(), Error> {
+    IoSlice::advance_slices(&mut slices, 0); // skip empty slices
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(Error::new(Operation::Write, offset, *written, write_zero())),
+            Ok(size) => {
+                offset += size;
+                *written += size;
+                IoSlice::advance_slices(&mut slices, size)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::new(Operation::Write, offset, *written, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Same as `swap_io` but applies several swap sets in one pass: each stage sees
+/// the output of the previous stage.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_io_pipeline;
+///
+/// let mut reader: Cursor<Vec<u8>> = Cursor::new(vec![0x41, 0x42, 0x43, 0x44]);
+/// let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+///
+/// let clear: &[(Pattern, Predicate)] = &[(Pattern::new(0x00).with_mask(0xF0), Predicate::new())];
+/// let set: &[(Pattern, Predicate)] = &[(Pattern::new(0x50).with_mask(0xF0), Predicate::new().with_offset(2))];
+/// let swap = swap_io_pipeline(&mut reader, &mut writer, &[clear, set]);
+/// assert_eq!(swap.unwrap(), 4); // 4 bytes written
+/// assert_eq!(writer.into_inner(), vec![0x01, 0x02, 0x53, 0x54])
+/// ```
+pub fn swap_io_pipeline<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    stages: &[&[(P, Q)]],
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut buffer = [0; BUFFER_SIZE];
+    swap_io_hooked(reader, writer, stages, &mut buffer, Hooks::default())
+}
+
+/// Reads `reader` to the end and returns the changes `swap_io` would make, without writing anything.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Change, Pattern, Predicate};
+/// use bswp::io::swap_io_dry_run;
+///
+/// let mut reader: Cursor<Vec<u8>> = Cursor::new(vec![0x41, 0x42, 0x43, 0x44]);
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let report = swap_io_dry_run(&mut reader, swaps).unwrap();
+/// assert_eq!(report.bytes_read, 4);
+/// assert_eq!(report.changes, vec![
+///     Change { offset: 0, old: 0x41, new: 0x42 },
+///     Change { offset: 2, old: 0x43, new: 0x42 },
+/// ]);
+/// ```
+pub fn swap_io_dry_run<R, P, Q>(reader: &mut R, swaps: &[(P, Q)]) -> Result<ChangeReport, Error>
+where
+    R: Read + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut changes: Vec<Change> = Vec::new();
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut log = sidecar::ChangeLog(|change| changes.push(change));
+    let hooks = Hooks {
+        observer: Some(&mut log),
+        ..Hooks::default()
+    };
+    let bytes_read = swap_io_hooked(reader, &mut std::io::sink(), &[swaps], &mut buffer, hooks)?;
+    Ok(ChangeReport {
+        bytes_read,
+        changes,
+    })
+}
+
+/// Copies `reader` to `writer`, replacing occurrences of byte sequences on the way
+/// (see `replace::Replacer`).
+///
+/// Occurrences spanning several reads are replaced. Returns the number of occurrences replaced.
+///
+/// ```
+/// use bswp::io::replace_io;
+/// use bswp::replace::Replacement;
+///
+/// let mut reader: &[u8] = b"version=1.0; version=1.0";
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// let replacements = &[Replacement::new(b"1.0", b"2.0").unwrap()];
+/// assert_eq!(replace_io(&mut reader, &mut writer, replacements).unwrap(), 2);
+/// assert_eq!(writer, b"version=2.0; version=2.0");
+/// ```
+pub fn replace_io<R, W, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    replacements: &[Replacement<Q>],
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    Q: PositionPredicate,
+{
+    let mut replacer = Replacer::new(replacements);
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut output: Vec<u8> = Vec::with_capacity(BUFFER_SIZE);
     let mut position: usize = 0;
+    let mut written: usize = 0;
+
+    loop {
+        let size = read_at(reader, &mut buffer, position, written)?;
+        if size == 0 {
+            break; // finished
+        }
+        output.clear();
+        replacer.feed(&buffer[..size], &mut output);
+        write_at(writer, &output, written, &mut written)?;
+        position += size;
+    }
+    output.clear();
+    replacer.finish(&mut output);
+    write_at(writer, &output, written, &mut written)?;
+    Ok(replacer.matches())
+}
+
+/// Reads `reader` to the end and returns the offsets of every match of `signature`.
+///
+/// ```
+/// use bswp::io::find_signature;
+/// use bswp::signature::Signature;
+///
+/// let mut reader: &[u8] = &[0xDE, 0xAD, 0x00, 0xBE, 0xEF, 0xDE, 0xAD, 0x01, 0xBE, 0xEF];
+/// let signature = Signature::parse("DE AD ?? BE EF").unwrap();
+/// assert_eq!(find_signature(&mut reader, &signature).unwrap(), vec![0, 5]);
+/// ```
+pub fn find_signature<R>(reader: &mut R, signature: &Signature) -> Result<Vec<usize>, Error>
+where
+    R: Read + ?Sized,
+{
+    let mut scanner = SignatureScanner::new(signature);
     let mut buffer = [0; BUFFER_SIZE];
+    let mut matches: Vec<usize> = Vec::new();
 
     loop {
-        let size = reader.read(&mut buffer)?;
+        let size = read_at(reader, &mut buffer, scanner.position(), 0)?;
         if size == 0 {
             break; // finished
         }
-        for (position_in_buffer, item) in buffer.iter_mut().enumerate().take(size) {
-            let byte_position = position + position_in_buffer; // position relative to reader start
-            for (pattern, predicate) in swaps {
-                if predicate.eval(byte_position) {
-                    *item = pattern.eval(*item);
-                }
+        scanner.feed(&buffer[..size], &mut matches);
+    }
+    Ok(matches)
+}
+
+/// Copies `reader` to `writer`, applying the IPS `patch` on the way (see `ips::IpsPatch`).
+///
+/// Records past the end of `reader` grow the output, zero filling any gap.
+/// Returns the number of bytes written.
+///
+/// ```
+/// use bswp::io::apply_ips;
+///
+/// let mut reader: &[u8] = &[0x41; 4];
+/// let mut writer: Vec<u8> = Vec::new();
+/// let patch = b"PATCH\x00\x00\x01\x00\x02\x42\x42\x00\x00\x06\x00\x01\x43EOF";
+/// assert_eq!(apply_ips(&mut reader, &mut writer, patch).unwrap(), 7);
+/// assert_eq!(writer, vec![0x41, 0x42, 0x42, 0x41, 0x00, 0x00, 0x43]);
+/// ```
+pub fn apply_ips<R, W>(reader: &mut R, writer: &mut W, patch: &[u8]) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let patch = IpsPatch::parse(patch).map_err(invalid_patch)?;
+    // records by offset, patch order being kept for equal offsets
+    let records = &patch.records;
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    order.sort_by_key(|&index| records[index].offset);
+    let longest = records
+        .iter()
+        .map(|record| record.data.len())
+        .max()
+        .unwrap_or(0);
+    let end = records.iter().map(IpsRecord::end).max().unwrap_or(0);
+    let truncate = patch.truncate.unwrap_or(usize::MAX);
+    let mut position: usize = 0;
+    let mut written: usize = 0;
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut exhausted = false;
+
+    while position < truncate {
+        let size = if exhausted {
+            0
+        } else {
+            read_at(reader, &mut buffer, position, written)?
+        };
+        let size = match size {
+            0 if position >= end => break, // finished
+            0 => {
+                // grow past the end of reader
+                exhausted = true;
+                buffer.fill(0);
+                (end - position).min(BUFFER_SIZE)
             }
+            size => size,
+        };
+        let chunk = position..position + size;
+        let first = order.partition_point(|&index| records[index].offset + longest <= chunk.start);
+        let last = order.partition_point(|&index| records[index].offset < chunk.end);
+        let mut overlapping: Vec<usize> = order[first..last]
+            .iter()
+            .copied()
+            .filter(|&index| records[index].end() > chunk.start)
+            .collect();
+        // later records overwrite earlier ones
+        overlapping.sort_unstable();
+        for record in overlapping.into_iter().map(|index| &records[index]) {
+            let from = record.offset.max(chunk.start);
+            let to = record.end().min(chunk.end);
+            buffer[from - chunk.start..to - chunk.start]
+                .copy_from_slice(&record.data[from - record.offset..to - record.offset]);
         }
+        let size = size.min(truncate - position);
+        write_at(writer, &buffer[..size], position, &mut written)?;
         position += size;
-        writer.write_all(&buffer[..size])?;
     }
-    Ok(position)
+    Ok(written)
+}
+
+/// Reads `reader` to the end and writes the target of the BPS `patch` to `writer`
+/// (see `bps::BpsPatch`).
+///
+/// BPS copies from anywhere in the source, which is held in memory. Source, target and
+/// patch checksums are verified, nothing is written if they do not match.
+/// Returns the number of bytes written.
+pub fn apply_bps<R, W>(reader: &mut R, writer: &mut W, patch: &[u8]) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let patch = BpsPatch::parse(patch).map_err(invalid_patch)?;
+    let mut source: Vec<u8> = Vec::with_capacity(patch.source_len());
+    let mut buffer = [0; BUFFER_SIZE];
+    loop {
+        let size = read_at(reader, &mut buffer, source.len(), 0)?;
+        if size == 0 {
+            break; // finished
+        }
+        source.extend_from_slice(&buffer[..size]);
+    }
+    let target = patch.apply(&source).map_err(invalid_patch)?;
+    let mut written = 0;
+    write_at(writer, &target, 0, &mut written)?;
+    Ok(written)
+}
+
+/// Same as `swap_io` with bit swaps, predicates being evaluated on bit positions
+/// (see `bit::swap_bits`).
+///
+/// Returns number of bytes read from `reader` and written to `writer`.
+///
+/// ```
+/// use bswp::bit::{BitPattern, BitPredicate};
+/// use bswp::io::swap_bits_io;
+///
+/// let mut reader: &[u8] = &[0x00, 0x00, 0x00];
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// // set the first bit of every 12-bit sample
+/// let swaps = &[(BitPattern::Set, BitPredicate::new().with_periodicity(12))];
+/// assert_eq!(swap_bits_io(&mut reader, &mut writer, swaps).unwrap(), 3);
+/// assert_eq!(writer, vec![0x80, 0x08, 0x00]);
+/// ```
+pub fn swap_bits_io<R, W, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(BitPattern, Q)],
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    Q: PositionPredicate,
+{
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut written: usize = 0;
+
+    loop {
+        let size = read_at(reader, &mut buffer, written, written)?;
+        if size == 0 {
+            break; // finished
+        }
+        swap_bits_from(&mut buffer[..size], swaps, written);
+        write_at(writer, &buffer[..size], written, &mut written)?;
+    }
+    Ok(written)
+}
+
+/// Same as `swap_io`, XORing `key` over the bytes from `reader` (see `xor::XorKey`).
+///
+/// Obfuscates and deobfuscates alike. Returns number of bytes read from `reader` and
+/// written to `writer`.
+///
+/// ```
+/// use bswp::io::xor_stream;
+/// use bswp::xor::XorKey;
+///
+/// let mut reader: &[u8] = b"secret";
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// let key = XorKey::new(*b"key").unwrap();
+/// assert_eq!(xor_stream(&mut reader, &mut writer, &key).unwrap(), 6);
+/// assert_eq!(writer, vec![0x18, 0x00, 0x1A, 0x19, 0x00, 0x0D]);
+/// ```
+pub fn xor_stream<R, W>(reader: &mut R, writer: &mut W, key: &XorKey) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    swap_io(reader, writer, &key.rules())
+}
+
+/// Splits the bytes from `reader` into `writers` by lane, groups of `group_size` bytes
+/// going to every writer in turn, e.g. even and odd bytes of a 16-bit ROM dump.
+///
+/// Returns number of bytes read from `reader`, i.e. written to `writers` altogether.
+///
+/// # Panics
+///
+/// Panics if `writers` is empty or if `group_size` is `0`.
+///
+/// ```
+/// use bswp::io::deinterleave;
+///
+/// let mut reader: &[u8] = &[0x00, 0x01, 0x02, 0x03, 0x04];
+/// let mut writers: [Vec<u8>; 2] = Default::default();
+///
+/// assert_eq!(deinterleave(&mut reader, &mut writers, 1).unwrap(), 5);
+/// assert_eq!(writers, [vec![0x00, 0x02, 0x04], vec![0x01, 0x03]]);
+/// ```
+pub fn deinterleave<R, W>(
+    reader: &mut R,
+    writers: &mut [W],
+    group_size: usize,
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write,
+{
+    assert!(!writers.is_empty(), "no writer to deinterleave into");
+    assert!(group_size > 0, "group size must be positive");
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut lanes: Vec<Vec<u8>> = vec![Vec::new(); writers.len()];
+    let mut offsets: Vec<usize> = vec![0; writers.len()];
+    let mut read: usize = 0;
+    let mut written: usize = 0;
+
+    loop {
+        let size = read_at(reader, &mut buffer, read, written)?;
+        if size == 0 {
+            break; // finished
+        }
+        let mut index = 0;
+        while index < size {
+            let position = read + index;
+            let len = (group_size - position % group_size).min(size - index);
+            lanes[(position / group_size) % writers.len()]
+                .extend_from_slice(&buffer[index..index + len]);
+            index += len;
+        }
+        read += size;
+        for ((writer, lane), offset) in writers.iter_mut().zip(&mut lanes).zip(&mut offsets) {
+            let mut lane_written = 0;
+            write_at(writer, lane, *offset, &mut lane_written)?;
+            *offset += lane_written;
+            written += lane_written;
+            lane.clear();
+        }
+    }
+    Ok(written)
+}
+
+/// Merges the bytes from `readers` into `writer`, groups of `group_size` bytes being
+/// taken from every reader in turn, inverse of `deinterleave`.
+///
+/// Stops at the end of the first reader found exhausted, a trailing partial group being
+/// written. Returns number of bytes written to `writer`.
+///
+/// # Panics
+///
+/// Panics if `readers` is empty or if `group_size` is `0`.
+///
+/// ```
+/// use bswp::io::interleave;
+///
+/// let mut readers: [&[u8]; 2] = [&[0x00, 0x02, 0x04], &[0x01, 0x03]];
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// assert_eq!(interleave(&mut readers, &mut writer, 1).unwrap(), 5);
+/// assert_eq!(writer, vec![0x00, 0x01, 0x02, 0x03, 0x04]);
+/// ```
+pub fn interleave<R, W>(
+    readers: &mut [R],
+    writer: &mut W,
+    group_size: usize,
+) -> Result<usize, Error>
+where
+    R: Read,
+    W: Write + ?Sized,
+{
+    assert!(!readers.is_empty(), "no reader to interleave from");
+    assert!(group_size > 0, "group size must be positive");
+    let mut buffer: Vec<u8> = Vec::with_capacity(BUFFER_SIZE + group_size);
+    let mut group = vec![0; group_size];
+    let mut written: usize = 0;
+
+    'merge: loop {
+        for reader in readers.iter_mut() {
+            let offset = written + buffer.len();
+            let mut size = 0;
+            while size < group_size {
+                match read_at(reader, &mut group[size..], offset + size, written)? {
+                    0 => break,
+                    read => size += read,
+                }
+            }
+            buffer.extend_from_slice(&group[..size]);
+            if size < group_size {
+                break 'merge; // exhausted
+            }
+        }
+        if buffer.len() >= BUFFER_SIZE {
+            write_at(writer, &buffer, written, &mut written)?;
+            buffer.clear();
+        }
+    }
+    write_at(writer, &buffer, written, &mut written)?;
+    Ok(written)
+}
+
+/// Same as `swap_io` with a constant pattern, setting every byte at positions matching
+/// `predicate` to `value`, e.g. to wipe or pad sections.
+///
+/// Contiguous matched positions are filled at once (see `pattern::fill_slice_from`).
+/// Returns number of bytes read from `reader` and written to `writer`.
+///
+/// ```
+/// use bswp::io::fill;
+/// use bswp::rule::Target;
+///
+/// let mut reader: &[u8] = &[0x41; 6];
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// assert_eq!(fill(&mut reader, &mut writer, Target::Range(2..5), 0x00).unwrap(), 6);
+/// assert_eq!(writer, vec![0x41, 0x41, 0x00, 0x00, 0x00, 0x41]);
+/// ```
+pub fn fill<R, W, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    predicate: Q,
+    value: u8,
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    Q: PositionPredicate,
+{
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut written: usize = 0;
+
+    loop {
+        let size = read_at(reader, &mut buffer, written, written)?;
+        if size == 0 {
+            break; // finished
+        }
+        fill_slice_from(&mut buffer[..size], &predicate, written, value);
+        write_at(writer, &buffer[..size], written, &mut written)?;
+    }
+    Ok(written)
+}
+
+/// Same as `fill` on `file` in place: matched bytes are overwritten without being read,
+/// unmatched regions being skipped using `Seek`.
+///
+/// Positions are relative to the `file` cursor when called, the cursor is left at the
+/// end of `file`. Returns number of bytes filled.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::io::fill_in_place;
+/// use bswp::pattern::Predicate;
+///
+/// let mut file: Cursor<Vec<u8>> = Cursor::new(vec![0x41; 4]);
+///
+/// let odd = Predicate::new().with_periodicity(2).with_offset(1);
+/// assert_eq!(fill_in_place(&mut file, odd, 0xFF).unwrap(), 2);
+/// assert_eq!(file.into_inner(), vec![0x41, 0xFF, 0x41, 0xFF]);
+/// ```
+pub fn fill_in_place<F, Q>(file: &mut F, predicate: Q, value: u8) -> Result<usize, Error>
+where
+    F: Write + Seek + ?Sized,
+    Q: PositionPredicate,
+{
+    let base = seek_at(file, SeekFrom::Current(0), 0, 0)?;
+    let end = seek_at(file, SeekFrom::End(0), 0, 0)?;
+    let len = end.saturating_sub(base) as usize;
+    let buffer = [value; BUFFER_SIZE];
+    let mut filled: usize = 0;
+
+    for run in matching_runs(&predicate, 0..len) {
+        seek_at(
+            file,
+            SeekFrom::Start(base + run.start as u64),
+            run.start,
+            filled,
+        )?;
+        let mut position = run.start;
+        while position < run.end {
+            let size = (run.end - position).min(BUFFER_SIZE);
+            write_at(file, &buffer[..size], position, &mut filled)?;
+            position += size;
+        }
+    }
+    seek_at(file, SeekFrom::End(0), len, filled)?;
+    Ok(filled)
+}
+
+/// Size of the blocks `SwapFileOptions::with_sparse` skips when they only hold zero bytes.
+pub const SPARSE_BLOCK_SIZE: usize = 4096;
+
+/// Writer seeking over blocks of `SPARSE_BLOCK_SIZE` zero bytes instead of writing them,
+/// see `SwapFileOptions::with_sparse`.
+///
+/// Bytes are held back until their block is complete, `finish` writes the last block.
+/// Zero blocks are written over the bytes `inner` already holds, only blocks past its end
+/// are seeked over. Bytes past the end of the output are left untouched.
+struct SparseWriter<'w, W: ?Sized> {
+    inner: &'w mut W,
+    /// bytes of the incomplete block
+    block: Vec<u8>,
+    /// zero bytes seeked over since the last write
+    hole: usize,
+    /// bytes accepted so far, holes included
+    position: usize,
+    /// bytes `inner` held after its initial position, stale bytes to overwrite
+    stale: usize,
+}
+
+impl<'w, W: Write + Seek + ?Sized> SparseWriter<'w, W> {
+    fn new(inner: &'w mut W) -> std::io::Result<Self> {
+        let start = inner.stream_position()?;
+        let end = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(SparseWriter {
+            inner,
+            block: Vec::with_capacity(SPARSE_BLOCK_SIZE),
+            hole: 0,
+            position: 0,
+            stale: end.saturating_sub(start) as usize,
+        })
+    }
+
+    fn write_block(&mut self, block: &[u8]) -> std::io::Result<()> {
+        let start = self.position;
+        self.position += block.len();
+        let is_zero = block.iter().all(|&byte| byte == 0);
+        if block.len() == SPARSE_BLOCK_SIZE && is_zero && start >= self.stale {
+            self.hole += block.len();
+            return Ok(());
+        }
+        if self.hole > 0 {
+            self.inner.seek(SeekFrom::Current(self.hole as i64))?;
+            self.hole = 0;
+        }
+        self.inner.write_all(block)
+    }
+
+    /// Writes the incomplete block, or a trailing zero byte if the output ends with a
+    /// hole so that the file has its full size.
+    fn finish(&mut self) -> std::io::Result<()> {
+        let block = std::mem::take(&mut self.block);
+        if !block.is_empty() {
+            self.write_block(&block)
+        } else if self.hole > 0 {
+            self.inner.seek(SeekFrom::Current(self.hole as i64 - 1))?;
+            self.hole = 0;
+            self.inner.write_all(&[0])
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<W: Write + Seek + ?Sized> Write for SparseWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.block.is_empty() && buf.len() >= SPARSE_BLOCK_SIZE {
+            self.write_block(&buf[..SPARSE_BLOCK_SIZE])?;
+            return Ok(SPARSE_BLOCK_SIZE);
+        }
+        let size = buf.len().min(SPARSE_BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..size]);
+        if self.block.len() == SPARSE_BLOCK_SIZE {
+            let mut block = std::mem::take(&mut self.block);
+            let result = self.write_block(&block);
+            block.clear();
+            self.block = block;
+            result?;
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Copies `reader` to `writer`, applying the `overlay` sidecar on the way
+/// (see `overlay::Overlay`).
+///
+/// Runs past the end of `reader` are skipped. Returns the number of bytes written.
+pub fn apply_overlay<R, W>(reader: &mut R, writer: &mut W, overlay: &[u8]) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let overlay = Overlay::parse(overlay).map_err(invalid_patch)?;
+    // runs by offset, overlay order being kept for equal offsets
+    let runs = &overlay.runs;
+    let mut order: Vec<usize> = (0..runs.len()).collect();
+    order.sort_by_key(|&index| runs[index].offset);
+    let longest = runs.iter().map(|run| run.data.len()).max().unwrap_or(0);
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut written: usize = 0;
+
+    loop {
+        let size = read_at(reader, &mut buffer, written, written)?;
+        if size == 0 {
+            break; // finished
+        }
+        let chunk = written..written + size;
+        let first = order.partition_point(|&index| runs[index].offset + longest <= chunk.start);
+        let last = order.partition_point(|&index| runs[index].offset < chunk.end);
+        let mut overlapping: Vec<usize> = order[first..last].to_vec();
+        // later runs overwrite earlier ones
+        overlapping.sort_unstable();
+        for run in overlapping.into_iter().map(|index| &runs[index]) {
+            let from = run.offset.max(chunk.start);
+            let to = run.end().min(chunk.end);
+            if from < to {
+                buffer[from - chunk.start..to - chunk.start]
+                    .copy_from_slice(&run.data[from - run.offset..to - run.offset]);
+            }
+        }
+        write_at(writer, &buffer[..size], written, &mut written)?;
+    }
+    Ok(written)
+}
+
+/// First byte of an output differing from the swapped original, see `verify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// byte position
+    pub offset: usize,
+    /// byte predicted by the rules, `None` past the end of the original
+    pub expected: Option<u8>,
+    /// byte read from the output, `None` past the end of the output
+    pub actual: Option<u8>,
+}
+
+/// Re-reads `output` and checks every byte against `original` swapped with `swaps`,
+/// untouched bytes having to match `original`, e.g. to detect silent write corruption
+/// after flashing.
+///
+/// Returns the first mismatch, `None` if `output` is exactly the swapped `original`.
+///
+/// ```
+/// use bswp::io::{verify, Mismatch};
+/// use bswp::pattern::{Pattern, Predicate};
+///
+/// let original: &[u8] = &[0x41, 0x41, 0x41, 0x41];
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let written: &[u8] = &[0x42, 0x41, 0x42, 0x41];
+/// assert_eq!(verify(&mut &original[..], &mut &written[..], swaps).unwrap(), None);
+///
+/// let corrupted: &[u8] = &[0x42, 0x41, 0x41, 0x41];
+/// let mismatch = verify(&mut &original[..], &mut &corrupted[..], swaps).unwrap();
+/// assert_eq!(mismatch, Some(Mismatch { offset: 2, expected: Some(0x42), actual: Some(0x41) }));
+/// ```
+pub fn verify<R, O, P, Q>(
+    original: &mut R,
+    output: &mut O,
+    swaps: &[(P, Q)],
+) -> Result<Option<Mismatch>, Error>
+where
+    R: Read + ?Sized,
+    O: Read + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut expected = [0; BUFFER_SIZE];
+    let mut actual = [0; BUFFER_SIZE];
+    let mut position: usize = 0;
+    let group = group_size(swaps);
+
+    loop {
+        let size = read_groups(original, &mut expected, position, 0, (group, 0))?;
+        let expected = &mut expected[..size];
+        swap_slice_from(expected, swaps, position);
+        // reads as many bytes from output, or one to check output ends as well
+        let wanted = size.max(1);
+        let mut read = 0;
+        while read < wanted {
+            match read_at(output, &mut actual[read..wanted], position + read, 0)? {
+                0 => break,
+                more => read += more,
+            }
+        }
+        let differing = (0..wanted).find(|&index| expected.get(index) != actual[..read].get(index));
+        if let Some(index) = differing {
+            return Ok(Some(Mismatch {
+                offset: position + index,
+                expected: expected.get(index).copied(),
+                actual: actual[..read].get(index).copied(),
+            }));
+        }
+        if size == 0 {
+            return Ok(None); // both finished
+        }
+        position += size;
+    }
+}
+
+/// Copies `reader_a` to `writer`, combining bytes at positions matching `predicate` with
+/// the bytes at the same positions of `reader_b` (see `zip::zip_slice_from`), e.g. to XOR
+/// a keystream file over a dump.
+///
+/// Bytes past the end of `reader_b` are copied untouched. Returns number of bytes read
+/// from `reader_a` and written to `writer`.
+///
+/// ```
+/// use bswp::io::zip_transform;
+/// use bswp::pattern::Predicate;
+/// use bswp::zip::ZipOp;
+///
+/// let mut dump: &[u8] = &[0x41, 0x42, 0x43, 0x44];
+/// let mut keystream: &[u8] = &[0xFF, 0x00, 0xFF];
+/// let mut writer: Vec<u8> = Vec::new();
+///
+/// let written = zip_transform(&mut dump, &mut keystream, &mut writer, Predicate::new(), ZipOp::Xor);
+/// assert_eq!(written.unwrap(), 4);
+/// assert_eq!(writer, vec![0xBE, 0x42, 0xBC, 0x44]);
+/// ```
+pub fn zip_transform<A, B, W, Q, C>(
+    reader_a: &mut A,
+    reader_b: &mut B,
+    writer: &mut W,
+    predicate: Q,
+    mut op: C,
+) -> Result<usize, Error>
+where
+    A: Read + ?Sized,
+    B: Read + ?Sized,
+    W: Write + ?Sized,
+    Q: PositionPredicate,
+    C: Combine,
+{
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut other = [0; BUFFER_SIZE];
+    let mut written: usize = 0;
+    let mut exhausted = false; // end of `reader_b`
+
+    loop {
+        let size = read_at(reader_a, &mut buffer, written, written)?;
+        if size == 0 {
+            break; // finished
+        }
+        let mut len = 0;
+        while !exhausted && len < size {
+            match read_at(reader_b, &mut other[len..size], written + len, written)? {
+                0 => exhausted = true,
+                more => len += more,
+            }
+        }
+        let combine = |value, other| op.combine(value, other);
+        zip_slice_from(
+            &mut buffer[..size],
+            &other[..len],
+            &predicate,
+            written,
+            combine,
+        );
+        write_at(writer, &buffer[..size], written, &mut written)?;
+    }
+    Ok(written)
+}
+
+/// Wraps a patch format error.
+fn invalid_patch<E>(error: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let error = std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+    Error::new(Operation::Read, 0, 0, error)
+}
+
+/// State needed to resume an interrupted `swap_io`.
+///
+/// Rules only depend on byte positions, so the position of the next byte to
+/// swap is all there is to save.
+///
+/// Serialized as `bswp-checkpoint:<position>` using `Display` and `FromStr`.
+///
+/// ```
+/// use bswp::io::SwapCheckpoint;
+/// let checkpoint = SwapCheckpoint { position: 42 };
+/// assert_eq!(checkpoint.to_string(), "bswp-checkpoint:42");
+/// assert_eq!("bswp-checkpoint:42".parse::<SwapCheckpoint>(), Ok(checkpoint));
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapCheckpoint {
+    /// position of the next byte to swap, i.e. number of bytes already written
+    pub position: usize,
+}
+
+const CHECKPOINT_PREFIX: &str = "bswp-checkpoint:";
+
+impl std::fmt::Display for SwapCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", CHECKPOINT_PREFIX, self.position)
+    }
+}
+
+impl std::str::FromStr for SwapCheckpoint {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let position = s.strip_prefix(CHECKPOINT_PREFIX).unwrap_or(s).parse()?;
+        Ok(SwapCheckpoint { position })
+    }
+}
+
+/// What `SwapIoOptions::with_sync` persists to disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncMode {
+    /// file content only, see `File::sync_data`
+    Data,
+    /// file content and metadata, see `File::sync_all`
+    All,
+}
+
+/// Observer of the chunks going through `swap_io_hooked`, e.g. writing a sidecar output.
+///
+/// Errors are reported as `Operation::Write` failures at the position of the chunk.
+pub(crate) trait Observer {
+    /// Called with every chunk read, before it is swapped, `chunk[0]` being at `position`.
+    fn read(&mut self, chunk: &[u8], position: usize) -> std::io::Result<()> {
+        let _ = (chunk, position);
+        Ok(())
+    }
+
+    /// Returns `true` if `change` is to be called, changes are not computed otherwise.
+    fn observes_changes(&self) -> bool {
+        false
+    }
+
+    /// Returns `false` if only `change` matters, so that bytes no rule may modify can be
+    /// skipped rather than read, see `swap_io_seek_with`.
+    fn needs_every_byte(&self) -> bool {
+        true
+    }
+
+    /// Called with every byte modified in a chunk, by increasing offset, once the chunk is swapped.
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        let _ = change;
+        Ok(())
+    }
+
+    /// Called with every chunk once swapped and written, `chunk[0]` being at `position`.
+    fn written(&mut self, chunk: &[u8], position: usize) -> std::io::Result<()> {
+        let _ = (chunk, position);
+        Ok(())
+    }
+
+    /// Called once the swap stops, `position` being the position of the next byte to read.
+    fn finish(&mut self, position: usize) -> std::io::Result<()> {
+        let _ = position;
+        Ok(())
+    }
+}
+
+/// Observers notified in turn.
+impl Observer for Vec<&mut dyn Observer> {
+    fn read(&mut self, chunk: &[u8], position: usize) -> std::io::Result<()> {
+        self.iter_mut()
+            .try_for_each(|observer| observer.read(chunk, position))
+    }
+
+    fn observes_changes(&self) -> bool {
+        self.iter().any(|observer| observer.observes_changes())
+    }
+
+    fn needs_every_byte(&self) -> bool {
+        self.iter().any(|observer| observer.needs_every_byte())
+    }
+
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        self.iter_mut()
+            .filter(|observer| observer.observes_changes())
+            .try_for_each(|observer| observer.change(change))
+    }
+
+    fn written(&mut self, chunk: &[u8], position: usize) -> std::io::Result<()> {
+        self.iter_mut()
+            .try_for_each(|observer| observer.written(chunk, position))
+    }
+
+    fn finish(&mut self, position: usize) -> std::io::Result<()> {
+        self.iter_mut()
+            .try_for_each(|observer| observer.finish(position))
+    }
+}
+
+/// Optional callbacks of `swap_io_hooked`.
+#[derive(Default)]
+struct Hooks<'a, 'f> {
+    progress: Option<&'a mut (dyn FnMut(Progress) + 'f)>,
+    total: Option<usize>,
+    should_continue: Option<&'a mut (dyn FnMut() -> bool + 'f)>,
+    stats: Option<&'a mut SwapStats>,
+    range: Option<Range<usize>>,
+    /// position of the first byte read
+    start: usize,
+    checkpoint: Option<(&'a mut (dyn FnMut(SwapCheckpoint) + 'f), usize)>,
+    /// maximum throughput in bytes per second
+    rate: Option<usize>,
+    would_block: WouldBlockStrategy,
+    observer: Option<&'a mut (dyn Observer + 'a)>,
+    /// batches chunks of `BUFFER_SIZE` bytes of the buffer in vectored writes
+    vectored: bool,
+    /// fields modified once swapped
+    fields: &'a [Field],
+    /// checksums stored once swapped and fields modified
+    fixer: Option<&'a mut Fixer<'a>>,
+}
+
+/// `swap_io` main loop, applying `stages` in turn: each stage sees the output of the
+/// previous stage.
+fn swap_io_hooked<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    stages: &[&[(P, Q)]],
+    buffer: &mut [u8],
+    hooks: Hooks<'_, '_>,
+) -> Result<usize, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut swap = HookedSwap::new(stages, buffer.len(), hooks);
+    swap.run(reader, writer, buffer)?;
+    Ok(swap.written())
+}
+
+/// State of `swap_io_hooked`, advanced one chunk at a time by `step`.
+struct HookedSwap<'s, 'a, 'f, P, Q> {
+    stages: &'s [&'s [(P, Q)]],
+    hooks: Hooks<'a, 'f>,
+    /// groups of `group.0` bytes starting at position `group.1`, read whole
+    group: (usize, usize),
+    /// bytes read at once, a batch holding several chunks in vectored mode
+    chunk_size: usize,
+    /// position of the next byte to write
+    position: usize,
+    /// bytes at the start of the buffer, read and swapped but held back until the
+    /// fields they hold are read whole
+    held: usize,
+    last_checkpoint: usize,
+    started: Instant,
+    /// rules that cannot match anymore, by stage, `next_match` is not called again for them
+    rules_exhausted: Vec<Vec<bool>>,
+    exhausted: bool,
+    /// bytes of the buffer before swap, to count or observe the bytes modified
+    original: Vec<u8>,
+    tracks_changes: bool,
+}
+
+impl<'s, 'a, 'f, P, Q> HookedSwap<'s, 'a, 'f, P, Q>
+where
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    fn new(stages: &'s [&'s [(P, Q)]], buffer_len: usize, mut hooks: Hooks<'a, 'f>) -> Self {
+        assert!(buffer_len > 0, "buffer must not be empty");
+        let group = stages.iter().fold(1, |size, swaps| {
+            let group = group_size(swaps);
+            size / gcd(size, group) * group
+        });
+        // groups are aligned relative to the range start, if any
+        let group_start = hooks.range.as_ref().map_or(0, |range| range.start);
+        let chunk_size = if hooks.vectored {
+            (buffer_len / VECTORED_CHUNKS).max(1)
+        } else {
+            buffer_len
+        };
+        if let Some(stats) = hooks.stats.as_mut() {
+            let rules = stages.iter().map(|swaps| swaps.len()).sum();
+            if stats.rule_hits.len() < rules {
+                stats.rule_hits.resize(rules, 0);
+            }
+        }
+        let observes_changes = hooks
+            .observer
+            .as_ref()
+            .is_some_and(|observer| observer.observes_changes());
+        HookedSwap {
+            stages,
+            group: (group, group_start),
+            chunk_size,
+            position: hooks.start,
+            held: 0,
+            last_checkpoint: hooks.start,
+            started: Instant::now(),
+            rules_exhausted: stages
+                .iter()
+                .map(|swaps| vec![false; swaps.len()])
+                .collect(),
+            exhausted: false,
+            original: Vec::new(),
+            tracks_changes: hooks.stats.is_some() || observes_changes,
+            hooks,
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    fn written(&self) -> usize {
+        self.position - self.hooks.start
+    }
+
+    /// Returns the position of the next byte to write.
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the checksums to write back at their offset, see `Fixer::finish`.
+    fn fixups(&self) -> Vec<(usize, Vec<u8>)> {
+        let fixer = self.hooks.fixer.as_ref();
+        fixer.map_or_else(Vec::new, |fixer| fixer.finish())
+    }
+
+    /// Reads, swaps and writes chunks until the swap is finished, cancelled or would block.
+    fn run<R, W>(&mut self, reader: &mut R, writer: &mut W, buffer: &mut [u8]) -> Result<(), Error>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        while self.step(reader, writer, buffer)? {}
+        Ok(())
+    }
+
+    /// Returns the position of the first byte from the next byte to read that a rule or a
+    /// field may modify, at the start of its group, `None` if none.
+    fn next_match(&self) -> Option<usize> {
+        let from = self.position + self.held;
+        let fields = self.hooks.fields.iter().map(Field::range);
+        let field = fields
+            .filter(|range| range.end > from)
+            .map(|range| range.start.max(from))
+            .min();
+        // positions relative to reader start, or to range start
+        let (base, window) = match &self.hooks.range {
+            Some(range) => (range.start, range.clone()),
+            None => (0, 0..usize::MAX),
+        };
+        let relative = from.max(window.start) - base;
+        let rules = self.stages.iter().zip(self.rules_exhausted.iter());
+        let rule = rules
+            .flat_map(|(swaps, exhausted)| swaps.iter().zip(exhausted.iter()))
+            .filter(|(_, &done)| !done)
+            .filter_map(|((_, predicate), _)| predicate.next_match(relative))
+            .filter_map(|next| base.checked_add(next))
+            .filter(|&next| next < window.end)
+            .min()
+            .map(|next| {
+                let (size, start) = self.group;
+                let aligned = next - next.saturating_sub(start) % size;
+                aligned.max(from)
+            });
+        field.into_iter().chain(rule).min()
+    }
+
+    /// Seeks `reader` and `writer` over the bytes no rule nor field may modify, up to
+    /// `end`, the position of the end of `reader`.
+    ///
+    /// Does nothing while bytes are held back, or if checksums or observers need every byte.
+    fn skip<R, W>(&mut self, reader: &mut R, writer: &mut W, end: usize) -> Result<(), Error>
+    where
+        R: Seek + ?Sized,
+        W: Seek + ?Sized,
+    {
+        let observer = self.hooks.observer.as_ref();
+        if self.held > 0
+            || self.hooks.fixer.is_some()
+            || observer.is_some_and(|observer| observer.needs_every_byte())
+        {
+            return Ok(());
+        }
+        let next = self.next_match().map_or(end, |next| next.min(end));
+        if next > self.position {
+            let skip = SeekFrom::Current((next - self.position) as i64);
+            seek_at(reader, skip, self.position, self.written())?;
+            seek_at(writer, skip, self.position, self.written())?;
+            self.position = next;
+        }
+        Ok(())
+    }
+
+    /// Reads, swaps and writes a chunk.
+    ///
+    /// Returns `false` once the swap is finished, cancelled or would block, held back
+    /// bytes being written.
+    fn step<R, W>(
+        &mut self,
+        reader: &mut R,
+        writer: &mut W,
+        buffer: &mut [u8],
+    ) -> Result<bool, Error>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        let held = self.held;
+        let position = self.position;
+        let written = self.written();
+        let cancelled = match self.hooks.should_continue.as_mut() {
+            Some(should_continue) => !should_continue(),
+            None => false,
+        };
+        let read = if cancelled {
+            0
+        } else {
+            let chunk = &mut buffer[held..self.chunk_size];
+            match read_groups(reader, chunk, position + held, written, self.group) {
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                    match self.hooks.would_block {
+                        WouldBlockStrategy::Fail => return Err(error),
+                        WouldBlockStrategy::Return => 0,
+                        WouldBlockStrategy::Sleep(duration) => {
+                            thread::sleep(duration);
+                            return Ok(true);
+                        }
+                    }
+                }
+                read => read?,
+            }
+        };
+        let last = read == 0;
+        if last && held == 0 {
+            return Ok(false); // finished
+        }
+        let mut size = held + read;
+        // batches chunks as long as reads fill them, data trickling in is not delayed
+        let mut full = size == self.chunk_size;
+        while self.hooks.vectored && full && size < buffer.len() {
+            let end = (size + self.chunk_size).min(buffer.len());
+            let chunk = &mut buffer[size..end];
+            let len = chunk.len();
+            match read_groups(reader, chunk, position + size, written, self.group) {
+                Ok(more) => {
+                    full = more == len;
+                    size += more;
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+        let observed = |e| Error::new(Operation::Write, position, written, e);
+        if let Some(stats) = self.hooks.stats.as_mut() {
+            stats.bytes_read += size - held; // whole chunk, even outside range
+        }
+        if let Some(observer) = self.hooks.observer.as_mut() {
+            let chunk = &buffer[held..size];
+            observer.read(chunk, position + held).map_err(observed)?;
+        }
+        if self.tracks_changes {
+            self.original.extend_from_slice(&buffer[held..size]);
+        }
+        self.swap(&mut buffer[..size], held);
+
+        let end = position + size;
+        // holds back fields not read wholly
+        let fields = self.hooks.fields;
+        for field in fields
+            .iter()
+            .filter(|field| field.range().end > position + held)
+        {
+            if field.range().end <= end {
+                field.apply(&mut buffer[..size], position);
+            }
+        }
+        let cut = fields
+            .iter()
+            .map(Field::range)
+            .filter(|range| !last && range.start < end && range.end > end)
+            .map(|range| range.start.max(position))
+            .min()
+            .unwrap_or(end);
+        let ready = cut - position;
+        let chunk = &mut buffer[..ready];
+        if let Some(fixer) = self.hooks.fixer.as_mut() {
+            fixer.feed(chunk);
+        }
+        if self.tracks_changes {
+            let original = &self.original[..ready];
+            if let Some(stats) = self.hooks.stats.as_mut() {
+                stats.record_modified(original, chunk);
+            }
+            if let Some(observer) = self.hooks.observer.as_mut() {
+                let changes = original.iter().zip(chunk.iter()).enumerate();
+                for (index, (&old, &new)) in changes.filter(|(_, (old, new))| old != new) {
+                    let change = Change {
+                        offset: position + index,
+                        old,
+                        new,
+                    };
+                    observer.change(change).map_err(observed)?;
+                }
+            }
+            self.original.drain(..ready);
+        }
+
+        let mut written = written;
+        if self.hooks.vectored {
+            let chunks = chunk.chunks(self.chunk_size);
+            let mut slices: Vec<IoSlice> = chunks.map(IoSlice::new).collect();
+            write_all_vectored(writer, &mut slices, position, &mut written)?;
+        } else {
+            write_at(writer, chunk, position, &mut written)?;
+        }
+        if let Some(stats) = self.hooks.stats.as_mut() {
+            stats.bytes_written += ready;
+        }
+        if let Some(observer) = self.hooks.observer.as_mut() {
+            observer.written(chunk, position).map_err(observed)?;
+        }
+        buffer.copy_within(ready..size, 0);
+        self.held = size - ready;
+        self.position += ready;
+        self.report();
+        Ok(!last)
+    }
+
+    /// Swaps `chunk[held..]`, `chunk[0]` being at the position of the next byte to write.
+    fn swap(&mut self, chunk: &mut [u8], held: usize) {
+        let first = self.position + held;
+        let last = self.position + chunk.len();
+        // position relative to reader start, or to range start
+        let (window, start) = match &self.hooks.range {
+            Some(range) => {
+                let from = range.start.clamp(first, last);
+                let to = range.end.clamp(from, last);
+                (from..to, from.saturating_sub(range.start))
+            }
+            None => (first..last, first),
+        };
+        // once every rule is exhausted, bytes are copied without evaluating predicates
+        if !self.exhausted {
+            for (swaps, exhausted) in self.stages.iter().zip(self.rules_exhausted.iter_mut()) {
+                for ((_, predicate), done) in swaps.iter().zip(exhausted.iter_mut()) {
+                    *done = *done || predicate.next_match(start).is_none();
+                }
+            }
+            self.exhausted = self.rules_exhausted.iter().flatten().all(|&done| done);
+        }
+        if self.exhausted {
+            return;
+        }
+        let chunk = &mut chunk[window.start - self.position..window.end - self.position];
+        let mut first = 0;
+        for (swaps, exhausted) in self.stages.iter().zip(self.rules_exhausted.iter()) {
+            if let Some(stats) = self.hooks.stats.as_mut() {
+                stats.record_hits(swaps, first, start..start + chunk.len());
+            }
+            if !exhausted.iter().all(|&done| done) {
+                swap_slice_from(chunk, swaps, start);
+            }
+            first += swaps.len();
+        }
+    }
+
+    /// Reports progress and checkpoints, then sleeps if ahead of the rate.
+    fn report(&mut self) {
+        let (position, processed) = (self.position, self.written());
+        if let Some(progress) = self.hooks.progress.as_mut() {
+            progress(Progress {
+                processed,
+                position,
+                total: self.hooks.total,
+            });
+        }
+        if let Some((on_checkpoint, every)) = self.hooks.checkpoint.as_mut() {
+            if position - self.last_checkpoint >= *every {
+                on_checkpoint(SwapCheckpoint { position });
+                self.last_checkpoint = position;
+            }
+        }
+        if let Some(rate) = self.hooks.rate {
+            let expected = Duration::from_secs_f64(self.written() as f64 / rate as f64);
+            if let Some(ahead) = expected.checked_sub(self.started.elapsed()) {
+                thread::sleep(ahead);
+            }
+        }
+    }
+}
+
+/// For each byte in `file` compute pattern and write result back to `file`.
+///
+/// Returns number of bytes processed from `file`.
+///
+/// Positions are relative to the `file` cursor when called, the cursor is left at the end of `file`.
+/// Only the modified part of each `BUFFER_SIZE` chunk is written back, and regions
+/// no rule can match (see `PositionPredicate::next_match`) are skipped using `Seek`:
+/// sparse patches on large files only read the bytes around the matching positions.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_in_place;
+///
+/// let mut file: Cursor<Vec<u8>> = Cursor::new(vec![0x41, 0x42, 0x43, 0x44]);
+///
+/// let swaps: &[(Pattern, Predicate)] = &[(Pattern::new(0x42).with_mask(0xFF), Predicate::new().with_periodicity(2).with_offset(0))];
+/// let swap = swap_in_place(&mut file, swaps);
+/// assert_eq!(swap.unwrap(), 4); // 4 bytes read
+/// assert_eq!(file.into_inner(), vec![0x42, 0x42, 0x42, 0x44])
+/// ```
+pub fn swap_in_place<F, P, Q>(file: &mut F, swaps: &[(P, Q)]) -> Result<usize, Error>
+where
+    F: Read + Write + Seek + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    swap_in_place_window(file, swaps, None)
+}
+
+/// Same as `swap_in_place` but only swaps bytes in `range` (relative to the `file` cursor
+/// when called), seeking directly to `range.start`.
+///
+/// Returns number of bytes processed from `file`, the cursor is left at the end of the range
+/// (or at the end of `file` if the range goes beyond).
+///
+/// Positions are relative to `range.start`: rules behave as if the range were the whole stream.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_in_place_range;
+///
+/// let mut file: Cursor<Vec<u8>> = Cursor::new(vec![0x41; 8]);
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let swap = swap_in_place_range(&mut file, swaps, 3..6);
+/// assert_eq!(swap.unwrap(), 3); // 3 bytes read
+/// assert_eq!(file.into_inner(), vec![0x41, 0x41, 0x41, 0x42, 0x41, 0x42, 0x41, 0x41])
+/// ```
+pub fn swap_in_place_range<F, P, Q>(
+    file: &mut F,
+    swaps: &[(P, Q)],
+    range: Range<usize>,
+) -> Result<usize, Error>
+where
+    F: Read + Write + Seek + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    file.seek(SeekFrom::Current(range.start as i64))
+        .map_err(|e| Error::new(Operation::Seek, range.start, 0, e))?;
+    swap_in_place_window(file, swaps, Some(range.end.saturating_sub(range.start)))
+}
+
+/// `swap_in_place` main loop, processing at most `len` bytes if provided.
+fn swap_in_place_window<F, P, Q>(
+    file: &mut F,
+    swaps: &[(P, Q)],
+    len: Option<usize>,
+) -> Result<usize, Error>
+where
+    F: Read + Write + Seek + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let base = seek_at(file, SeekFrom::Current(0), 0, 0)?;
+    let end = len.unwrap_or(usize::MAX);
+    let mut position: usize = 0;
+    let mut written: usize = 0;
+    let mut original = [0; BUFFER_SIZE];
+    let mut buffer = [0; BUFFER_SIZE];
+    let group = group_size(swaps);
+
+    loop {
+        let next_match = swaps
+            .iter()
+            .filter_map(|(_, predicate)| predicate.next_match(position))
+            .min();
+        match next_match {
+            Some(next) if next < end => {
+                // groups moved by patterns are read whole
+                let next = next - next % group;
+                if next > position {
+                    // skip unmatched bytes
+                    seek_at(file, SeekFrom::Start(base + next as u64), next, written)?;
+                    position = next;
+                }
+            }
+            _ => break, // no more matches
+        }
+        let remaining = (end - position).min(BUFFER_SIZE);
+        let size = read_groups(
+            file,
+            &mut original[..remaining],
+            position,
+            written,
+            (group, 0),
+        )?;
+        if size == 0 {
+            break; // finished
+        }
+        buffer[..size].copy_from_slice(&original[..size]);
+        swap_slice_from(&mut buffer[..size], swaps, position);
+        let changed = |&index: &usize| original[index] != buffer[index];
+        if let Some(first) = (0..size).find(changed) {
+            let last = (0..size).rev().find(changed).unwrap_or(first);
+            let offset = position + first;
+            seek_at(file, SeekFrom::Start(base + offset as u64), offset, written)?;
+            write_at(file, &buffer[first..=last], offset, &mut written)?;
+            let offset = position + size;
+            seek_at(file, SeekFrom::Start(base + offset as u64), offset, written)?;
+        }
+        position += size;
+    }
+    let file_end = seek_at(file, SeekFrom::End(0), position, written)?;
+    let processed = (file_end.saturating_sub(base) as usize).min(end);
+    seek_at(
+        file,
+        SeekFrom::Start(base + processed as u64),
+        processed,
+        written,
+    )?;
+    Ok(processed)
+}
+
+/// Reads from `reader` into `buffer`, `offset` being the position of the first byte to read.
+///
+/// Interrupted reads are retried.
+pub(crate) fn read_at<R: Read + ?Sized>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    offset: usize,
+    written: usize,
+) -> Result<usize, Error> {
+    loop {
+        match reader.read(buffer) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            result => return result.map_err(|e| Error::new(Operation::Read, offset, written, e)),
+        }
+    }
+}
+
+/// Reads from `reader` into `buffer` like `read_at`, reading again until the bytes read
+/// end on a group boundary, groups of `group.0` bytes starting at position `group.1`.
+///
+/// Stops early at the end of `reader`, when `buffer` is smaller than a group or when
+/// `reader` would block after some bytes were read.
+fn read_groups<R: Read + ?Sized>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    offset: usize,
+    written: usize,
+    (size, start): (usize, usize),
+) -> Result<usize, Error> {
+    let is_aligned = |end: usize| (end + size - start % size) % size == 0;
+    let len = (1..=buffer.len())
+        .rev()
+        .take(size)
+        .find(|&len| is_aligned(offset + len))
+        .unwrap_or(buffer.len());
+    let buffer = &mut buffer[..len];
+    let mut read = read_at(reader, buffer, offset, written)?;
+    while read > 0 && read < len && !is_aligned(offset + read) {
+        match read_at(reader, &mut buffer[read..], offset + read, written) {
+            Ok(0) => break,
+            Ok(more) => read += more,
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(read)
+}
+
+/// Writes all of `buffer` to `writer`, `offset` being the position of `buffer[0]`.
+///
+/// `written` is incremented by the number of bytes written.
+pub(crate) fn write_at<W: Write + ?Sized>(
+    writer: &mut W,
+    mut buffer: &[u8],
+    mut offset: usize,
+    written: &mut usize,
+) -> Result<(), Error> {
+    while !buffer.is_empty() {
+        match writer.write(buffer) {
+            Ok(0) => return Err(Error::new(Operation::Write, offset, *written, write_zero())),
+            Ok(size) => {
+                buffer = &buffer[size..];
+                offset += size;
+                *written += size;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::new(Operation::Write, offset, *written, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Seeks `file` to `to`, `offset` being the corresponding stream position.
+fn seek_at<F: Seek + ?Sized>(
+    file: &mut F,
+    to: SeekFrom,
+    offset: usize,
+    written: usize,
+) -> Result<u64, Error> {
+    file.seek(to)
+        .map_err(|e| Error::new(Operation::Seek, offset, written, e))
+}
+
+fn write_zero() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::WriteZero,
+        "failed to write whole buffer",
+    )
+}
+
+/// Swaps file at `path_in` into file at `path_out`, processing ranges of the input in parallel.
+///
+/// Returns number of bytes read from `path_in` and written to `path_out`.
+///
+/// The input is split into one range per thread of the rayon pool, each range is
+/// read, swapped and written at the same offset in the pre-allocated output.
+/// Predicates are evaluated on positions in the whole file.
+///
+/// Requires the `rayon` feature.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_file_par;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path_in = dir.path().join("firmware.bin");
+/// # let path_out = dir.path().join("patched.bin");
+/// std::fs::write(&path_in, vec![0x41; 100_000]).unwrap();
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2).with_offset(1))];
+/// assert_eq!(swap_file_par(&path_in, &path_out, swaps).unwrap(), 100_000);
+/// assert!(std::fs::read(&path_out).unwrap().chunks(2).all(|c| c == [0x41, 0x42]));
+/// ```
+#[cfg(feature = "rayon")]
+pub fn swap_file_par<T, U, P, Q>(path_in: T, path_out: U, swaps: &[(P, Q)]) -> Result<usize, Error>
+where
+    T: AsRef<Path>,
+    U: AsRef<Path>,
+    P: BytePattern + Sync,
+    Q: PositionPredicate + Sync,
+{
+    use rayon::prelude::*;
+    let (path_in, path_out) = (path_in.as_ref(), path_out.as_ref());
+    let len = fs::metadata(path_in).map_err(open_error)?.len() as usize;
+    File::create(path_out)
+        .and_then(|file| file.set_len(len as u64)) // pre-allocate
+        .map_err(open_error)?;
+    // ranges end on group boundaries
+    let group = group_size(swaps);
+    let range_len = len.div_ceil(rayon::current_num_threads()).max(BUFFER_SIZE);
+    let range_len = range_len.div_ceil(group) * group;
+    (0..len.div_ceil(range_len))
+        .into_par_iter()
+        .map(|index| {
+            let start = index * range_len;
+            let mut reader = File::open(path_in).map_err(open_error)?;
+            seek_at(&mut reader, SeekFrom::Start(start as u64), start, 0)?;
+            let mut writer = OpenOptions::new()
+                .write(true)
+                .open(path_out)
+                .map_err(open_error)?;
+            seek_at(&mut writer, SeekFrom::Start(start as u64), start, 0)?;
+            let checkpoint = SwapCheckpoint { position: start };
+            let mut reader = reader.take(range_len as u64);
+            let options = SwapIoOptions::new().with_resume(checkpoint);
+            let outcome = swap_io_with(&mut reader, &mut writer, swaps, options)?;
+            Ok(outcome.bytes_written)
+        })
+        .sum()
+}
+
+/// Reader adapter swapping bytes read from the inner reader.
+///
+/// Positions are relative to the first byte read through the adapter. Bytes of groups
+/// moved by patterns (see `BytePattern::group_size`) are held back until their group is
+/// read whole.
+///
+/// ```
+/// use std::io::{Cursor, Read};
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::SwapReader;
+///
+/// let inner: Cursor<Vec<u8>> = Cursor::new(vec![0x41, 0x42, 0x43, 0x44]);
+/// let swaps = vec![(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let mut reader = SwapReader::new(inner, swaps);
+///
+/// let mut swapped = Vec::new();
+/// reader.read_to_end(&mut swapped).unwrap();
+/// assert_eq!(swapped, vec![0x42, 0x42, 0x42, 0x44]);
+/// assert_eq!(reader.position(), 4);
+/// ```
+pub struct SwapReader<R, P, Q> {
+    inner: R,
+    swapper: Swapper<P, Q>,
+    /// bytes read from `inner` not returned yet, the first `ready` ones being swapped
+    pending: Vec<u8>,
+    ready: usize,
+}
+
+impl<R: Read, P: BytePattern, Q: PositionPredicate> SwapReader<R, P, Q> {
+    /// Creates a new `SwapReader` wrapping `inner`.
+    pub fn new(inner: R, swaps: Vec<(P, Q)>) -> Self {
+        SwapReader {
+            inner,
+            swapper: Swapper::new(swaps),
+            pending: Vec::new(),
+            ready: 0,
+        }
+    }
+
+    /// Returns the position of the next byte to read.
+    pub fn position(&self) -> usize {
+        self.swapper.position() - self.ready
+    }
+
+    /// Gets a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner reader.
+    ///
+    /// Reading directly from the inner reader desynchronizes positions.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `SwapReader`, returning the inner reader.
+    ///
+    /// Bytes read from the inner reader but not returned yet are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, P: BytePattern, Q: PositionPredicate> Read for SwapReader<R, P, Q> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.ready > 0 {
+                let size = self.ready.min(buf.len());
+                buf[..size].copy_from_slice(&self.pending[..size]);
+                self.pending.drain(..size);
+                self.ready -= size;
+                return Ok(size);
+            }
+            let group = self.swapper.group_size();
+            if group == 1 && self.pending.is_empty() {
+                let size = self.inner.read(buf)?;
+                self.swapper.feed(&mut buf[..size]);
+                return Ok(size);
+            }
+            let held = self.pending.len();
+            self.pending.resize(held + buf.len().max(group), 0);
+            let size = match self.inner.read(&mut self.pending[held..]) {
+                Ok(size) => size,
+                Err(error) => {
+                    self.pending.truncate(held);
+                    return Err(error);
+                }
+            };
+            self.pending.truncate(held + size);
+            if size == 0 {
+                if held == 0 {
+                    return Ok(0); // finished
+                }
+                // partial group at the end of the stream
+                self.swapper.feed(&mut self.pending);
+                self.ready = held;
+            } else {
+                self.ready = self.swapper.feed_groups(&mut self.pending);
+            }
+        }
+    }
+}
+
+/// Returns a path for a new temporary file next to `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.bswp-{}-{}.tmp",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Wraps an error opening, creating or inspecting a file, before any byte is read.
+fn open_error(error: std::io::Error) -> Error {
+    Error::new(Operation::Open, 0, 0, error)
+}
+
+/// Calls `write` on a temporary file in the same directory as `path`, then
+/// atomically renames it over `path`, preserving `path` permissions.
+///
+/// The temporary file is removed if `write` fails.
+pub(crate) fn replace_atomically<T, F>(path: &Path, write: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut File) -> Result<T, Error>,
+{
+    let permissions = fs::metadata(path).map_err(open_error)?.permissions();
+    let temp = temp_path(path);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .map_err(open_error)?;
+    let result = write(&mut file).and_then(|value| {
+        // every byte is written by now
+        let written = file.stream_position().unwrap_or(0) as usize;
+        let error = |operation| move |e| Error::new(operation, written, written, e);
+        file.set_permissions(permissions)
+            .map_err(error(Operation::Permissions))?;
+        file.sync_all().map_err(error(Operation::Sync))?;
+        fs::rename(&temp, path).map_err(error(Operation::Rename))?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp); // best effort
+    }
+    result
+}
+
+/// For each byte in file at `path` compute pattern and replace the file with the result.
+///
+/// Returns number of bytes read from and written to the file.
+///
+/// The result is streamed to a temporary file in the same directory which is then
+/// atomically renamed over the original, preserving its permissions: the original
+/// file is left untouched on failure. Zero blocks are not written, keeping sparse files
+/// sparse (see `SwapFileOptions::with_sparse`).
+///
+/// ```no_run
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_file;
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(4))];
+/// let written = swap_file("firmware.bin", swaps).unwrap();
+/// ```
+pub fn swap_file<T, P, Q>(path: T, swaps: &[(P, Q)]) -> Result<usize, Error>
+where
+    T: AsRef<Path>,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let options = SwapFileOptions::new(SwapIoOptions::new()).with_sparse(true);
+    swap_file_with(path, swaps, options).map(|outcome| outcome.bytes_written)
+}
+
+/// Returns the backup path of `path`, i.e. `path` followed by `suffix`.
+fn backup_path(path: &Path, suffix: &OsStr) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(suffix);
+    PathBuf::from(backup)
+}
+
+/// Copies `path` to its backup, if `suffix` is given, then calls `replace`.
+///
+/// The backup is moved back over `path` if `replace` fails.
+pub(crate) fn replace_with_backup<T, F>(
+    path: &Path,
+    suffix: Option<&OsStr>,
+    replace: F,
+) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    let backup = match suffix {
+        Some(suffix) => {
+            let backup = backup_path(path, suffix);
+            fs::copy(path, &backup).map_err(|e| Error::new(Operation::Copy, 0, 0, e))?;
+            Some(backup)
+        }
+        None => None,
+    };
+    let result = replace();
+    if let (Err(_), Some(backup)) = (&result, backup) {
+        let _ = fs::rename(backup, path); // best effort
+    }
+    result
+}
+
+/// Same as `swap_file`, first copying the file to `path` followed by `suffix`, like `sed -i.bak`.
+///
+/// An existing backup is overwritten. The backup is restored if the swap fails.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_file_with_backup;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("firmware.bin");
+/// std::fs::write(&path, [0x41; 4]).unwrap();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(2))];
+/// assert_eq!(swap_file_with_backup(&path, swaps, ".bak").unwrap(), 4);
+/// assert_eq!(std::fs::read(&path).unwrap(), [0x42, 0x42, 0x41, 0x41]);
+/// assert_eq!(std::fs::read(dir.path().join("firmware.bin.bak")).unwrap(), [0x41; 4]);
+/// ```
+pub fn swap_file_with_backup<T, S, P, Q>(
+    path: T,
+    swaps: &[(P, Q)],
+    suffix: S,
+) -> Result<usize, Error>
+where
+    T: AsRef<Path>,
+    S: AsRef<OsStr>,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let path = path.as_ref();
+    replace_with_backup(path, Some(suffix.as_ref()), || swap_file(path, swaps))
+}
+
+/// Swaps file at `path` like `swap_file`, returning statistics about the swap.
+fn swap_file_with_stats<P, Q>(path: &Path, swaps: &[(P, Q)]) -> Result<SwapStats, Error>
+where
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let options = SwapFileOptions::new(SwapIoOptions::new().with_stats(true)).with_sparse(true);
+    let outcome = swap_file_with(path, swaps, options)?;
+    Ok(outcome.stats.unwrap_or_default())
+}
+
+/// Swaps every file from `paths` like `swap_file`, one after the other.
+///
+/// Returns each path along with statistics about its swap, or the error that
+/// prevented it, in order. A failure does not stop the batch.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_files;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let paths = vec![dir.path().join("a.rom"), dir.path().join("missing.rom")];
+/// std::fs::write(&paths[0], [0x41; 4]).unwrap();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let results = swap_files(paths, swaps);
+/// assert_eq!(results[0].1.as_ref().unwrap().bytes_modified, 2);
+/// assert!(results[1].1.is_err());
+/// ```
+pub fn swap_files<I, P, Q>(paths: I, swaps: &[(P, Q)]) -> Vec<(PathBuf, Result<SwapStats, Error>)>
+where
+    I: IntoIterator<Item = PathBuf>,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    paths
+        .into_iter()
+        .map(|path| {
+            let result = swap_file_with_stats(&path, swaps);
+            (path, result)
+        })
+        .collect()
+}
+
+/// Same as `swap_files` but swaps up to `parallelism` files at once.
+///
+/// Results are returned in the order of `paths`.
+///
+/// Requires the `rayon` feature.
+///
+/// # Panics
+///
+/// Panics if the thread pool cannot be built.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::swap_files_par;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let paths: Vec<_> = (0..8).map(|index| dir.path().join(format!("{}.rom", index))).collect();
+/// for path in &paths {
+///     std::fs::write(path, [0x41; 4]).unwrap();
+/// }
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let results = swap_files_par(paths, swaps, 4);
+/// assert!(results.iter().all(|(_, result)| result.as_ref().unwrap().bytes_modified == 2));
+/// ```
+#[cfg(feature = "rayon")]
+pub fn swap_files_par<I, P, Q>(
+    paths: I,
+    swaps: &[(P, Q)],
+    parallelism: usize,
+) -> Vec<(PathBuf, Result<SwapStats, Error>)>
+where
+    I: IntoIterator<Item = PathBuf>,
+    P: BytePattern + Sync,
+    Q: PositionPredicate + Sync,
+{
+    use rayon::prelude::*;
+    let paths: Vec<PathBuf> = paths.into_iter().collect();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallelism)
+        .build()
+        .expect("failed to build thread pool");
+    pool.install(|| {
+        paths
+            .into_par_iter()
+            .map(|path| {
+                let result = swap_file_with_stats(&path, swaps);
+                (path, result)
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{apply_fixups, Fixup};
+    use crate::field::{apply_fields, Field, FieldOp};
+    use crate::io::{
+        apply_ips, apply_overlay, deinterleave, fill, fill_in_place, interleave,
+        replace_with_backup, swap_bufread, swap_file, swap_file_with, swap_in_place,
+        swap_in_place_range, swap_io, swap_io_dry_run, swap_io_seek_with, swap_io_with, verify,
+        Error, Operation, SparseWriter, SwapCheckpoint, SwapFileOptions, SwapIoOptions, SyncMode,
+        WouldBlockStrategy, SPARSE_BLOCK_SIZE, VECTORED_CHUNKS,
+    };
+    use crate::pattern::{swap_slice, swap_slice_from, Change, Pattern, Predicate};
+    use crate::rule::Target;
+    use crate::word::Endianness;
+    use crate::{PositionPredicate, BUFFER_SIZE};
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::io::{Cursor, Read, Write};
+
+    #[test]
+    fn test_swap_io_dyn() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(1))];
+        let mut reader = Cursor::new(vec![0x41, 0x41]);
+        let mut writer: Vec<u8> = Vec::new();
+        {
+            let reader: &mut dyn Read = &mut reader;
+            let writer: &mut dyn Write = &mut writer;
+            assert_eq!(swap_io(reader, writer, swaps).unwrap(), 2);
+        }
+        assert_eq!(writer, vec![0x41, 0x42]);
+    }
+
+    #[test]
+    fn test_swap_in_place_cursor() {
+        let swaps = &[(
+            Pattern::new(0x42),
+            Predicate::new().with_offset(9_000).with_limit(2),
+        )];
+        let mut file = Cursor::new(vec![0x41; 20_000]);
+        file.set_position(1_000);
+        assert_eq!(swap_in_place(&mut file, swaps).unwrap(), 19_000);
+        assert_eq!(file.position(), 20_000);
+        let data = file.into_inner();
+        assert_eq!(data[10_000..10_002], [0x42, 0x42]);
+        assert_eq!(data.iter().filter(|&&b| b == 0x42).count(), 2);
+    }
+
+    #[test]
+    fn test_swap_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, [0x41; 4]).unwrap();
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(2))];
+        assert_eq!(swap_file(&path, swaps).unwrap(), 4);
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0x41, 0x41, 0x42, 0x42]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1); // no leftover
+        let error = swap_file(dir.path().join("missing.bin"), swaps).unwrap_err();
+        assert_eq!(error.operation(), Operation::Open);
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_swap_file_with_backup_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, [0x41; 4]).unwrap();
+        let swaps = &[(Pattern::new(0x42), Predicate::new())];
+        let result = replace_with_backup(&path, Some(OsStr::new("~")), || {
+            swap_file(&path, swaps)?;
+            let error = std::io::Error::other("failure after swap");
+            Err::<(), _>(Error::new(Operation::Rename, 4, 4, error))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0x41; 4]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1); // backup moved back
+    }
+
+    #[test]
+    fn test_swap_io_cancelled_after_first_chunk() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new())];
+        let mut reader = Cursor::new(vec![0x41; 20_000]);
+        let mut writer: Vec<u8> = Vec::new();
+        let mut calls = 0;
+        let options = SwapIoOptions::new().with_cancellation(|| {
+            calls += 1;
+            calls == 1
+        });
+        let outcome = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, crate::BUFFER_SIZE);
+        assert_eq!(writer, vec![0x42; crate::BUFFER_SIZE]);
+    }
+
+    #[test]
+    fn test_swap_range_across_chunks() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(3))];
+        let source = vec![0x41; 20_000];
+        let range = 7_000..17_000;
+        let mut expected = source.clone();
+        crate::pattern::swap_slice(&mut expected[range.clone()], swaps);
+
+        let mut writer: Vec<u8> = Vec::new();
+        let options = SwapIoOptions::new().with_range(range.clone());
+        let outcome = swap_io_with(&mut Cursor::new(&source), &mut writer, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, source.len());
+        assert_eq!(writer, expected);
+
+        let mut file = Cursor::new(source.clone());
+        let read = swap_in_place_range(&mut file, swaps, range.clone()).unwrap();
+        assert_eq!(read, range.len());
+        assert_eq!(file.position(), range.end as u64);
+        assert_eq!(file.into_inner(), expected);
+
+        // bytes copied outside the range are counted too
+        let options = SwapIoOptions::new()
+            .with_range(range.clone())
+            .with_stats(true);
+        let outcome = swap_io_with(&mut Cursor::new(&source), &mut Vec::new(), swaps, options);
+        let stats = outcome.unwrap().stats.unwrap();
+        assert_eq!(stats.bytes_read, source.len());
+        assert_eq!(stats.bytes_written, source.len());
+        assert_eq!(stats.bytes_modified, 3_334);
+
+        // bytes before and after the range are skipped
+        let (mut reader, mut writer) = (Cursor::new(&source), Cursor::new(source.clone()));
+        let options = SwapIoOptions::new()
+            .with_range(range.clone())
+            .with_stats(true);
+        let outcome = swap_io_seek_with(&mut reader, &mut writer, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, source.len());
+        assert!(outcome.stats.unwrap().bytes_read <= range.len() + BUFFER_SIZE);
+        assert_eq!(writer.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_swap_io_groups_across_reads() {
+        let swaps = &[crate::presets::swap32()];
+        let source: Vec<u8> = (0..22).collect();
+        let mut expected = source.clone();
+        crate::pattern::swap_slice(&mut expected, swaps);
+
+        // reads stop after 3 bytes, in the middle of the first group
+        let mut reader = source[..3].chain(&source[3..]);
+        let mut writer: Vec<u8> = Vec::new();
+        assert_eq!(swap_io(&mut reader, &mut writer, swaps).unwrap(), 22);
+        assert_eq!(writer, expected);
+
+        let range = 1..19;
+        let mut expected = source.clone();
+        crate::pattern::swap_slice(&mut expected[range.clone()], swaps);
+        let mut reader = source[..6].chain(&source[6..]);
+        let mut writer: Vec<u8> = Vec::new();
+        let options = SwapIoOptions::new().with_range(range);
+        swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+        assert_eq!(writer, expected);
+        assert_eq!(&writer[..6], &[0, 4, 3, 2, 1, 8]);
+
+        // skipping unmatched bytes stops on a group boundary
+        let swaps = &[(
+            crate::pattern::PermutePattern::new(vec![1, 0, 3, 2]).unwrap(),
+            Predicate::new().with_offset(6),
+        )];
+        let mut expected = source.clone();
+        crate::pattern::swap_slice(&mut expected, swaps);
+        assert_eq!(&expected[4..10], &[4, 5, 7, 6, 9, 8]);
+        let mut file = Cursor::new(source.clone());
+        swap_in_place(&mut file, swaps).unwrap();
+        assert_eq!(file.into_inner(), expected);
+        let (mut reader, mut writer) = (Cursor::new(&source), Cursor::new(source.clone()));
+        swap_io_seek_with(&mut reader, &mut writer, swaps, SwapIoOptions::new()).unwrap();
+        assert_eq!(writer.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_apply_ips_across_chunks() {
+        let source: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        let mut patch = b"PATCH".to_vec();
+        for (offset, data) in [
+            (7_990usize, vec![0xAA; 20]),
+            (7_995, vec![0xBB; 2]), // overwrites the previous record
+            (0, vec![0x01, 0x02]),
+            (20_005, vec![0xCC; 3]), // grows the output
+        ] {
+            patch.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            patch.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            patch.extend_from_slice(&data);
+        }
+        patch.extend_from_slice(b"EOF");
+        let mut expected = source.clone();
+        crate::ips::IpsPatch::parse(&patch)
+            .unwrap()
+            .apply(&mut expected);
+        assert_eq!(expected.len(), 20_008);
+        assert_eq!(&expected[7_994..7_998], &[0xAA, 0xBB, 0xBB, 0xAA]);
+
+        let mut writer: Vec<u8> = Vec::new();
+        let written = apply_ips(&mut Cursor::new(&source), &mut writer, &patch).unwrap();
+        assert_eq!(written, expected.len());
+        assert_eq!(writer, expected);
+
+        // truncated output
+        patch.extend_from_slice(&[0x00, 0x00, 0x10]);
+        let mut writer: Vec<u8> = Vec::new();
+        apply_ips(&mut Cursor::new(&source), &mut writer, &patch).unwrap();
+        assert_eq!(writer, &expected[..0x10]);
+
+        let error = apply_ips(&mut Cursor::new(&source), &mut writer, b"PATCH\x00").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Cursor counting bytes read.
+    struct Counting {
+        inner: Cursor<Vec<u8>>,
+        read: usize,
+    }
+
+    impl Read for Counting {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let size = self.inner.read(buf)?;
+            self.read += size;
+            Ok(size)
+        }
+    }
+
+    impl Write for Counting {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl std::io::Seek for Counting {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_swap_in_place_sparse() {
+        let swaps = &[(
+            Pattern::new(0x42),
+            Predicate::new()
+                .with_periodicity(100_000)
+                .with_offset(10)
+                .with_limit(3),
+        )];
+        let mut file = Counting {
+            inner: Cursor::new(vec![0x41; 1_000_000]),
+            read: 0,
+        };
+        assert_eq!(swap_in_place(&mut file, swaps).unwrap(), 1_000_000);
+        assert_eq!(file.inner.position(), 1_000_000);
+        assert_eq!(file.read, 3 * crate::BUFFER_SIZE);
+        let data = file.inner.into_inner();
+        assert_eq!(data[100_010], 0x42);
+        assert_eq!(data.iter().filter(|&&b| b == 0x42).count(), 3);
+    }
+
+    #[test]
+    fn test_swap_bufread() {
+        let swaps = &[
+            (Pattern::new(0x42), Predicate::new().with_periodicity(7)),
+            (
+                Pattern::new(0x43),
+                Predicate::new().with_offset(20).with_limit(30),
+            ),
+            (Pattern::new(0x41), Predicate::new().with_periodicity(5)), // sometimes no-op
+        ];
+        let source: Vec<u8> = (0..100).map(|i| 0x41 + (i % 3) as u8).collect();
+        let expected: Vec<u8> = crate::pattern::swap_iter(&source, swaps).collect();
+        for capacity in &[1, 3, 16, 1024] {
+            let mut reader = std::io::BufReader::with_capacity(*capacity, Cursor::new(&source));
+            let mut writer: Vec<u8> = Vec::new();
+            assert_eq!(swap_bufread(&mut reader, &mut writer, swaps).unwrap(), 100);
+            assert_eq!(writer, expected, "capacity: {}", capacity);
+        }
+    }
+
+    #[test]
+    fn test_swap_reader_unaligned_reads() {
+        let swaps = vec![(
+            crate::pattern::PermutePattern::new(vec![1, 0]).unwrap(),
+            Predicate::new(),
+        )];
+        let source: Vec<u8> = (0..10).collect();
+        let mut reader = crate::io::SwapReader::new(Cursor::new(&source), swaps);
+        let mut swapped = Vec::new();
+        let mut buffer = [0u8; 3];
+        loop {
+            let size = reader.read(&mut buffer).unwrap();
+            if size == 0 {
+                break;
+            }
+            swapped.extend_from_slice(&buffer[..size]);
+            assert_eq!(reader.position(), swapped.len());
+        }
+        assert_eq!(swapped, vec![1, 0, 3, 2, 5, 4, 7, 6, 9, 8]);
+
+        // trailing partial group is left unswapped
+        let swaps = vec![(
+            crate::pattern::PermutePattern::new(vec![2, 1, 0]).unwrap(),
+            Predicate::new(),
+        )];
+        let mut reader = crate::io::SwapReader::new(Cursor::new(&source), swaps);
+        let mut swapped = Vec::new();
+        reader.read_to_end(&mut swapped).unwrap();
+        assert_eq!(swapped, vec![2, 1, 0, 5, 4, 3, 8, 7, 6, 9]);
+    }
+
+    #[test]
+    fn test_swap_bufread_single_write_per_fill() {
+        /// Writer counting write calls.
+        struct Calls(Vec<u8>, usize);
+
+        impl Write for Calls {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.extend_from_slice(buf);
+                self.1 += 1;
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // every other byte modified
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+        let source: Vec<u8> = vec![0x41; 64];
+        let mut reader = std::io::BufReader::with_capacity(16, Cursor::new(&source));
+        let mut writer = Calls(Vec::new(), 0);
+        assert_eq!(swap_bufread(&mut reader, &mut writer, swaps).unwrap(), 64);
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        assert_eq!(writer.0, expected);
+        assert_eq!(writer.1, 4);
+
+        // groups split across fills are held back
+        let swaps = &[(
+            crate::pattern::PermutePattern::new(vec![1, 0]).unwrap(),
+            Predicate::new(),
+        )];
+        let source: Vec<u8> = (0..10).collect();
+        let mut reader = std::io::BufReader::with_capacity(3, Cursor::new(&source));
+        let mut writer: Vec<u8> = Vec::new();
+        assert_eq!(swap_bufread(&mut reader, &mut writer, swaps).unwrap(), 10);
+        assert_eq!(writer, vec![1, 0, 3, 2, 5, 4, 7, 6, 9, 8]);
+    }
+
+    /// Writer accepting at most 3 bytes per call.
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let size = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..size]);
+            Ok(size)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_swap_io_vectored() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(3))];
+        let source: Vec<u8> = vec![0x41; 100_000];
+        let expected: Vec<u8> = crate::pattern::swap_iter(&source, swaps).collect();
+        let mut writer: Vec<u8> = Vec::new();
+        let vectored = || SwapIoOptions::new().with_vectored(true);
+        let outcome = swap_io_with(&mut Cursor::new(&source), &mut writer, swaps, vectored());
+        assert_eq!(outcome.unwrap().bytes_written, source.len());
+        assert_eq!(writer, expected);
+
+        let mut trickle = Trickle(Vec::new());
+        swap_io_with(
+            &mut Cursor::new(&source[..20]),
+            &mut trickle,
+            swaps,
+            vectored(),
+        )
+        .unwrap();
+        assert_eq!(trickle.0, expected[..20].to_vec());
+
+        // rules are not evaluated anymore once exhausted
+        let swaps = &[(Pattern::new(0x42), Counted::new(4))];
+        let mut reader = Cursor::new(vec![0x41; 20 * BUFFER_SIZE]);
+        swap_io_with(&mut reader, &mut Vec::new(), swaps, vectored()).unwrap();
+        assert_eq!(swaps[0].1.evals.get(), VECTORED_CHUNKS * BUFFER_SIZE); // first batch only
+    }
+
+    /// Accepts `capacity` bytes, then fails.
+    struct Full {
+        capacity: usize,
+    }
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.capacity == 0 {
+                return Err(std::io::ErrorKind::Other.into());
+            }
+            let size = buf.len().min(self.capacity);
+            self.capacity -= size;
+            Ok(size)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_swap_io_error_offset() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new())];
+        let mut reader = Cursor::new(vec![0x41; 10_000]);
+        let mut writer = Full { capacity: 9_000 };
+        let error = swap_io(&mut reader, &mut writer, swaps).unwrap_err();
+        assert_eq!(error.operation(), Operation::Write);
+        assert_eq!(error.offset(), 9_000);
+        assert_eq!(error.bytes_written(), 9_000);
+        assert_eq!(error.kind(), std::io::ErrorKind::Other);
+
+        let mut reader = Cursor::new(vec![0x41; 10_000]);
+        let mut writer = Full { capacity: 9_000 };
+        let options = SwapIoOptions::new().with_vectored(true);
+        let error = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap_err();
+        assert_eq!(error.operation(), Operation::Write);
+        assert_eq!(error.offset(), 9_000);
+        assert_eq!(error.bytes_written(), 9_000);
+    }
+
+    #[test]
+    fn test_swap_io_synced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("patched.bin");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(1))];
+        for mode in [SyncMode::Data, SyncMode::All] {
+            let mut reader: &[u8] = &[0x41, 0x41];
+            let options = SwapIoOptions::new().with_sync(file.try_clone().unwrap(), mode);
+            let outcome = swap_io_with(&mut reader, &mut file, swaps, options).unwrap();
+            assert_eq!(outcome.bytes_written, 2);
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0x41, 0x42, 0x41, 0x42]);
+    }
+
+    #[test]
+    fn test_swap_io_throttled() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new())];
+        let mut reader: &[u8] = &[0x41; 10];
+        let mut writer: Vec<u8> = Vec::new();
+        let started = std::time::Instant::now();
+        let options = SwapIoOptions::new().with_rate(1_000);
+        let outcome = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, 10);
+        // 10 bytes at 1000 bytes per second, minus rounding
+        assert!(started.elapsed() >= std::time::Duration::from_millis(9));
+        assert_eq!(writer, vec![0x42; 10]);
+    }
+
+    /// Fails every other read with `kind`.
+    struct Flaky {
+        inner: Cursor<Vec<u8>>,
+        kind: std::io::ErrorKind,
+        fail: bool,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.fail = !self.fail;
+            if self.fail {
+                return Err(self.kind.into());
+            }
+            self.inner.read(&mut buf[..1])
+        }
+    }
+
+    #[test]
+    fn test_swap_io_interrupted_and_would_block() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(1))];
+        let flaky = |kind| Flaky {
+            inner: Cursor::new(vec![0x41; 3]),
+            kind,
+            fail: false,
+        };
+
+        let mut writer: Vec<u8> = Vec::new();
+        let mut reader = flaky(std::io::ErrorKind::Interrupted);
+        assert_eq!(swap_io(&mut reader, &mut writer, swaps).unwrap(), 3);
+        assert_eq!(writer, vec![0x41, 0x42, 0x42]);
+
+        let mut writer: Vec<u8> = Vec::new();
+        let mut reader = flaky(std::io::ErrorKind::WouldBlock);
+        let error = swap_io(&mut reader, &mut writer, swaps).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::WouldBlock);
+
+        let mut writer: Vec<u8> = Vec::new();
+        let mut reader = flaky(std::io::ErrorKind::WouldBlock);
+        let strategy = WouldBlockStrategy::Sleep(std::time::Duration::from_millis(1));
+        let options = SwapIoOptions::new().with_would_block(strategy);
+        let outcome = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, 3);
+        assert_eq!(writer, vec![0x41, 0x42, 0x42]);
+    }
+
+    /// Matches the first `limit` positions, counting evaluations and `next_match` calls.
+    struct Counted {
+        limit: usize,
+        evals: std::cell::Cell<usize>,
+        next_matches: std::cell::Cell<usize>,
+    }
+
+    impl Counted {
+        fn new(limit: usize) -> Self {
+            Counted {
+                limit,
+                evals: std::cell::Cell::new(0),
+                next_matches: std::cell::Cell::new(0),
+            }
+        }
+    }
+
+    impl crate::PositionPredicate for Counted {
+        fn eval(&self, position: usize) -> bool {
+            self.evals.set(self.evals.get() + 1);
+            position < self.limit
+        }
+
+        fn next_match(&self, position: usize) -> Option<usize> {
+            self.next_matches.set(self.next_matches.get() + 1);
+            Some(position).filter(|&position| position < self.limit)
+        }
+    }
+
+    #[test]
+    fn test_swap_io_exhausted_rules() {
+        let swaps = &[(Pattern::new(0x42), Counted::new(4))];
+        let mut reader = Cursor::new(vec![0x41; 10 * crate::BUFFER_SIZE]);
+        let mut writer: Vec<u8> = Vec::new();
+        swap_io(&mut reader, &mut writer, swaps).unwrap();
+        assert_eq!(&writer[..5], &[0x42, 0x42, 0x42, 0x42, 0x41]);
+        assert!(writer[4..].iter().all(|&byte| byte == 0x41));
+        assert_eq!(swaps[0].1.evals.get(), crate::BUFFER_SIZE); // first chunk only
+
+        // exhausted rules are not asked again while others still match
+        let swaps = &[
+            (Pattern::new(0x42), Counted::new(4)),
+            (Pattern::new(0x43), Counted::new(5 * crate::BUFFER_SIZE)),
+        ];
+        let mut reader = Cursor::new(vec![0x41; 10 * crate::BUFFER_SIZE]);
+        swap_io(&mut reader, &mut Vec::new(), swaps).unwrap();
+        assert_eq!(swaps[0].1.next_matches.get(), 2);
+        assert_eq!(swaps[1].1.next_matches.get(), 6);
+    }
+
+    #[test]
+    fn test_swap_io_observed() {
+        let swaps = &[(Pattern::new(0x42), Counted::new(4))];
+        let source = vec![0x41; 10 * BUFFER_SIZE];
+        let report = swap_io_dry_run(&mut &source[..], swaps).unwrap();
+        assert_eq!(report.bytes_read, source.len());
+        assert_eq!(report.changes.len(), 4);
+        assert_eq!(swaps[0].1.evals.get(), BUFFER_SIZE); // first chunk only
+
+        // groups moved by patterns are observed whole across reads
+        let swaps = &[crate::presets::swap32()];
+        let source: Vec<u8> = (0..22).collect();
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        let (mut writer, mut original) = (Vec::new(), Vec::new());
+        let mut reader = source[..3].chain(&source[3..]);
+        let options = SwapIoOptions::new().with_tee(&mut original);
+        swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+        assert_eq!((writer, original), (expected, source.clone()));
+        let mut log = Vec::new();
+        let mut reader = source[..3].chain(&source[3..]);
+        let options = SwapIoOptions::new().with_change_log(|change| log.push(change));
+        swap_io_with(&mut reader, &mut Vec::new(), swaps, options).unwrap();
+        let changes: Vec<_> = crate::pattern::diff_iter_from(&source, swaps, 0).collect();
+        assert_eq!(log, changes);
+    }
+
+    #[test]
+    fn test_interleave_round_trip() {
+        let source: Vec<u8> = (0..20_000).map(|i| (i * 7) as u8).collect();
+        for (lanes, group_size) in [(2, 1), (3, 2), (4, 4), (5, 3)] {
+            let mut writers: Vec<Vec<u8>> = vec![Vec::new(); lanes];
+            let read = deinterleave(&mut &source[..], &mut writers, group_size).unwrap();
+            assert_eq!(read, source.len());
+            for (lane, writer) in writers.iter().enumerate() {
+                let expected: Vec<u8> = source
+                    .chunks(group_size)
+                    .skip(lane)
+                    .step_by(lanes)
+                    .flatten()
+                    .copied()
+                    .collect();
+                assert_eq!(writer, &expected, "lane: {}", lane);
+            }
+            let mut readers: Vec<&[u8]> = writers.iter().map(Vec::as_slice).collect();
+            let mut merged: Vec<u8> = Vec::new();
+            let written = interleave(&mut readers, &mut merged, group_size).unwrap();
+            assert_eq!(written, source.len());
+            assert_eq!(merged, source, "lanes: {}, group: {}", lanes, group_size);
+        }
+    }
+
+    #[test]
+    fn test_fill_matches_swap() {
+        let source: Vec<u8> = (0..50_000).map(|i| (i % 253) as u8).collect();
+        let predicates = [
+            Target::Range(1_000..45_000),
+            Target::Periodic(Predicate::new().with_periodicity(7).with_offset(3)),
+            Target::offsets(vec![0, 1, 2, 9, 30_000, 49_999, 60_000]),
+        ];
+        for predicate in predicates {
+            let mut expected = source.clone();
+            swap_slice(&mut expected, &[(Pattern::new(0xEE), &predicate)]);
+            let mut writer: Vec<u8> = Vec::new();
+            let written = fill(&mut &source[..], &mut writer, &predicate, 0xEE).unwrap();
+            assert_eq!(written, source.len());
+            assert_eq!(writer, expected, "{:?}", predicate);
+
+            let mut file = Cursor::new(source.clone());
+            let filled = fill_in_place(&mut file, &predicate, 0xEE).unwrap();
+            assert_eq!(
+                filled,
+                (0..source.len()).filter(|&p| predicate.eval(p)).count()
+            );
+            assert_eq!(file.position(), source.len() as u64);
+            assert_eq!(file.into_inner(), expected);
+        }
+    }
+
+    /// Cursor filling the holes seeked over with `0xAA` bytes.
+    struct Holes(Cursor<Vec<u8>>);
+
+    impl Write for Holes {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let position = self.0.position() as usize;
+            let data = self.0.get_mut();
+            if data.len() < position {
+                data.resize(position, 0xAA);
+            }
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl std::io::Seek for Holes {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_sparse_output() {
+        let mut source = vec![0u8; 3 * BUFFER_SIZE + 100];
+        source[10] = 0x01;
+        source[BUFFER_SIZE + 5000] = 0x02;
+        let swaps = &[
+            (
+                Pattern::new(0x00),
+                Predicate::new().with_offset(10).with_limit(1),
+            ),
+            (
+                Pattern::new(0x03),
+                Predicate::new().with_offset(2 * BUFFER_SIZE).with_limit(1),
+            ),
+        ];
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+
+        let mut writer = Holes(Cursor::new(Vec::new()));
+        let mut sparse = SparseWriter::new(&mut writer).unwrap();
+        let written = swap_io(&mut &source[..], &mut sparse, swaps).unwrap();
+        sparse.finish().unwrap();
+        assert_eq!(written, source.len());
+        assert_eq!(writer.0.position(), source.len() as u64);
+        let output = writer.0.into_inner();
+        assert_eq!(output.len(), source.len());
+        for (block, (output, expected)) in output
+            .chunks(SPARSE_BLOCK_SIZE)
+            .zip(expected.chunks(SPARSE_BLOCK_SIZE))
+            .enumerate()
+        {
+            let is_hole = output.iter().all(|&byte| byte == 0xAA);
+            let is_zero = expected.iter().all(|&byte| byte == 0);
+            let is_full = expected.len() == SPARSE_BLOCK_SIZE;
+            if is_zero && is_full {
+                assert!(is_hole, "block: {}", block);
+            } else {
+                assert_eq!(output, expected, "block: {}", block);
+            }
+        }
+
+        // blocks split across reads are skipped alike
+        let mut reader = source[..1000].chain(&source[1000..]);
+        let mut writer = Holes(Cursor::new(Vec::new()));
+        let mut sparse = SparseWriter::new(&mut writer).unwrap();
+        swap_io(&mut reader, &mut sparse, swaps).unwrap();
+        sparse.finish().unwrap();
+        assert_eq!(writer.0.into_inner(), output);
+
+        // zero blocks are written over stale bytes
+        let mut writer = Cursor::new(vec![0xAA; source.len() - 1]);
+        let mut sparse = SparseWriter::new(&mut writer).unwrap();
+        swap_io(&mut &source[..], &mut sparse, swaps).unwrap();
+        sparse.finish().unwrap();
+        assert_eq!(writer.into_inner(), expected);
+
+        // past the end of a file already holding data
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.bin");
+        fs::write(&path, vec![0xAA; 2 * SPARSE_BLOCK_SIZE]).unwrap();
+        let mut file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        let mut sparse = SparseWriter::new(&mut file).unwrap();
+        swap_io(&mut &source[..], &mut sparse, swaps).unwrap();
+        sparse.finish().unwrap();
+        drop(file);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.bin");
+        fs::write(&path, &source).unwrap();
+        swap_file(&path, swaps).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_overlay_io() {
+        let source: Vec<u8> = (0..30_000).map(|i| (i % 13) as u8).collect();
+        let swaps = &[
+            (Pattern::new(0x00), Predicate::new().with_periodicity(4_001)),
+            (
+                Pattern::new(0xAB),
+                Predicate::new().with_offset(7_990).with_limit(20),
+            ),
+        ];
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        let mut overlay: Vec<u8> = Vec::new();
+        let options = SwapIoOptions::new().with_overlay(&mut overlay);
+        let outcome = swap_io_with(&mut &source[..], &mut std::io::sink(), swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, source.len());
+        assert!(overlay.len() < 200);
+
+        let mut writer: Vec<u8> = Vec::new();
+        let written = apply_overlay(&mut &source[..], &mut writer, &overlay).unwrap();
+        assert_eq!(written, source.len());
+        assert_eq!(writer, expected);
+        let error = apply_overlay(&mut &source[..], &mut writer, b"PATCH").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_verify_output() {
+        let original: Vec<u8> = (0..30_000).map(|i| (i % 199) as u8).collect();
+        let swaps = &[(Pattern::new(0x00), Predicate::new().with_periodicity(5))];
+        let mut output: Vec<u8> = Vec::new();
+        swap_io(&mut &original[..], &mut output, swaps).unwrap();
+        let check = |output: &[u8]| verify(&mut &original[..], &mut &output[..], swaps).unwrap();
+        assert_eq!(check(&output), None);
+
+        let mut corrupted = output.clone();
+        corrupted[20_001] ^= 0x01;
+        let mismatch = check(&corrupted).unwrap();
+        assert_eq!(mismatch.offset, 20_001);
+        assert_eq!(mismatch.expected, Some(output[20_001]));
+
+        let mismatch = check(&output[..25_000]).unwrap();
+        assert_eq!((mismatch.offset, mismatch.actual), (25_000, None));
+        let mut longer = output.clone();
+        longer.push(0xFF);
+        let mismatch = check(&longer).unwrap();
+        assert_eq!(mismatch.offset, 30_000);
+        assert_eq!((mismatch.expected, mismatch.actual), (None, Some(0xFF)));
+        assert_eq!(check(&original).unwrap().offset, 5);
+    }
+
+    #[test]
+    fn test_fields_across_chunks() {
+        let source: Vec<u8> = (0..3 * BUFFER_SIZE).map(|i| (i % 256) as u8).collect();
+        let swaps = &[(Pattern::new(0xFF), Predicate::new().with_periodicity(3))];
+        let fields: Vec<Field> = [0, BUFFER_SIZE - 3, 2 * BUFFER_SIZE - 1, 3 * BUFFER_SIZE - 2]
+            .iter()
+            .map(|&offset| Field::u64(offset, Endianness::Big, FieldOp::Add(0x0101)))
+            .collect();
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        assert_eq!(apply_fields(&mut expected, &fields, 0), 3);
+        let mut writer: Vec<u8> = Vec::new();
+        let options = SwapIoOptions::new().with_fields(fields);
+        let outcome = swap_io_with(&mut &source[..], &mut writer, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, source.len());
+        assert_eq!(writer, expected);
+    }
+
+    #[test]
+    fn test_swap_io_seek_with() {
+        let source: Vec<u8> = (0..20 * BUFFER_SIZE).map(|i| (i % 251) as u8).collect();
+        let rule_sets: Vec<Vec<(Pattern, Target)>> = vec![
+            vec![(
+                Pattern::new(0xFF),
+                Target::offsets(vec![3, 70_000, 150_000]),
+            )],
+            vec![
+                (Pattern::new(0x00), Target::Range(50_000..50_010)),
+                (
+                    Pattern::new(0x11),
+                    Target::Periodic(Predicate::new().with_periodicity(40_000).with_offset(7)),
+                ),
+            ],
+            vec![],
+        ];
+        for swaps in &rule_sets {
+            let fields = vec![Field::u16(100_000, Endianness::Little, FieldOp::Add(1))];
+            let mut expected = source.clone();
+            swap_slice_from(&mut expected[1_000..], swaps, 1_000);
+            apply_fields(&mut expected, &fields, 0);
+
+            let mut changes = Vec::new();
+            let mut reader = Cursor::new(source.clone());
+            let mut writer = Cursor::new(source.clone());
+            reader.set_position(1_000);
+            writer.set_position(1_000);
+            let options = SwapIoOptions::new()
+                .with_resume(SwapCheckpoint { position: 1_000 })
+                .with_fields(fields.clone())
+                .with_change_log(|change| changes.push(change))
+                .with_stats(true);
+            let outcome = swap_io_seek_with(&mut reader, &mut writer, swaps, options).unwrap();
+            assert_eq!(outcome.bytes_written, source.len() - 1_000);
+            assert!(outcome.stats.unwrap().bytes_read <= 5 * BUFFER_SIZE);
+            assert_eq!(writer.position(), source.len() as u64);
+            assert_eq!(writer.into_inner(), expected, "{:?}", swaps);
+            let diff: Vec<_> = source
+                .iter()
+                .zip(expected.iter())
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(offset, (&old, &new))| Change { offset, old, new })
+                .collect();
+            assert_eq!(changes, diff);
+
+            // bytes are not skipped when a copy of the input is written
+            let (mut reader, mut original) = (Cursor::new(source.clone()), Vec::new());
+            let mut writer = Cursor::new(vec![0; source.len()]);
+            let options = SwapIoOptions::new()
+                .with_fields(fields.clone())
+                .with_tee(&mut original)
+                .with_stats(true);
+            let outcome = swap_io_seek_with(&mut reader, &mut writer, swaps, options).unwrap();
+            assert_eq!(outcome.stats.unwrap().bytes_read, source.len());
+            let mut expected = source.clone();
+            swap_slice(&mut expected, swaps);
+            apply_fields(&mut expected, &fields, 0);
+            assert_eq!((writer.into_inner(), original), (expected, source.clone()));
+        }
+    }
+
+    #[test]
+    fn test_file_with_fixups() {
+        let mut source = vec![0u8; 3 * SPARSE_BLOCK_SIZE];
+        source[SPARSE_BLOCK_SIZE..]
+            .iter_mut()
+            .for_each(|byte| *byte = 0x41);
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(3))];
+        let fields = vec![Field::u32(
+            2 * SPARSE_BLOCK_SIZE - 2,
+            Endianness::Big,
+            FieldOp::Add(1),
+        )];
+        // checksums stored before and after their range
+        let fixups = vec![
+            Fixup::crc32(SPARSE_BLOCK_SIZE..3 * SPARSE_BLOCK_SIZE - 4, 0),
+            Fixup::crc32(0..SPARSE_BLOCK_SIZE, 3 * SPARSE_BLOCK_SIZE - 4),
+        ];
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        apply_fields(&mut expected, &fields, 0);
+        apply_fixups(&mut expected, &fixups);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.bin");
+        fs::write(&path, &source).unwrap();
+        let options = SwapIoOptions::new()
+            .with_buffer_size(1_000)
+            .with_fields(fields)
+            .with_fixups(fixups);
+        let options = SwapFileOptions::new(options).with_sparse(true);
+        let outcome = swap_file_with(&path, swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, source.len());
+        assert!(outcome.fixups.is_empty());
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_swap_file_par_group_boundaries() {
+        let swaps = &[(
+            crate::pattern::PermutePattern::new(vec![1, 0]).unwrap(),
+            Predicate::new(),
+        )];
+        let source: Vec<u8> = (0..100_003u32).map(|i| i as u8).collect();
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (path_in, path_out) = (dir.path().join("in.bin"), dir.path().join("out.bin"));
+        fs::write(&path_in, &source).unwrap();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+        let read = pool.install(|| crate::io::swap_file_par(&path_in, &path_out, swaps));
+        assert_eq!(read.unwrap(), source.len());
+        assert_eq!(fs::read(&path_out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_fixups_in_any_order() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(3))];
+        let source: Vec<u8> = (0..32).collect();
+        let ascending = vec![Fixup::crc32(12..32, 0), Fixup::crc32(12..32, 6)];
+        let descending = vec![Fixup::crc32(12..32, 6), Fixup::crc32(12..32, 0)];
+        for fixups in [ascending, descending] {
+            let mut expected = source.clone();
+            swap_slice(&mut expected, swaps);
+            apply_fixups(&mut expected, &fixups);
+
+            let (mut reader, mut writer) = (Cursor::new(&source), Cursor::new(Vec::new()));
+            let options = SwapIoOptions::new()
+                .with_buffer_size(8)
+                .with_fixups(fixups.clone());
+            let outcome = swap_io_seek_with(&mut reader, &mut writer, swaps, options).unwrap();
+            assert!(outcome.fixups.is_empty());
+            assert_eq!(writer.position(), 32);
+            assert_eq!(writer.into_inner(), expected);
+
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("image.bin");
+            fs::write(&path, &source).unwrap();
+            let options = SwapIoOptions::new().with_buffer_size(8).with_fixups(fixups);
+            swap_file_with(&path, swaps, options).unwrap();
+            assert_eq!(fs::read(&path).unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "crc32fast")]
+    #[test]
+    fn test_digest_with_fixups_rejected() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new())];
+        let options = SwapIoOptions::new()
+            .with_digest(crate::io::DigestAlgorithm::Crc32)
+            .with_fixups(vec![Fixup::crc32(4..8, 0)]);
+        let mut writer: Vec<u8> = Vec::new();
+        let error = swap_io_with(&mut &[0u8; 8][..], &mut writer, swaps, options).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(writer.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_swap_files_sparse() {
+        use std::os::unix::fs::MetadataExt;
+        let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(1))];
+        let mut source = vec![0u8; 256 * SPARSE_BLOCK_SIZE];
+        source[1] = 0x41;
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.img");
+        fs::write(&path, &source).unwrap();
+        let results = crate::io::swap_files(vec![path.clone()], swaps);
+        assert_eq!(results[0].1.as_ref().unwrap().bytes_modified, 1);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+        let allocated = fs::metadata(&path).unwrap().blocks() * 512;
+        assert!(
+            allocated < source.len() as u64 / 2,
+            "allocated: {}",
+            allocated
+        );
+    }
+
+    #[test]
+    fn test_progress_on_resume() {
+        let swaps = &[(Pattern::new(0x42), Predicate::new())];
+        let source = vec![0x41; 20_000];
+        let mut reader = Cursor::new(&source[5_000..]);
+        let mut reports = Vec::new();
+        let options = SwapIoOptions::new()
+            .with_resume(SwapCheckpoint { position: 5_000 })
+            .with_progress(Some(20_000), |progress| {
+                reports.push((progress.processed, progress.position))
+            });
+        let outcome = swap_io_with(&mut reader, &mut Vec::new(), swaps, options).unwrap();
+        assert_eq!(outcome.bytes_written, 15_000);
+        assert_eq!(reports, vec![(BUFFER_SIZE, 13_000), (15_000, 20_000)]);
+    }
 }
@@ -0,0 +1,57 @@
+//! Digest of the swapped bytes, requires the `sha2` or `crc32fast` feature.
+
+/// Digest algorithm of `SwapIoOptions::with_digest`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DigestAlgorithm {
+    /// SHA-256, 32 bytes digest (feature `sha2`)
+    #[cfg(feature = "sha2")]
+    Sha256,
+    /// CRC-32 (IEEE), 4 bytes big-endian digest (feature `crc32fast`)
+    #[cfg(feature = "crc32fast")]
+    Crc32,
+}
+
+/// Running digest.
+pub(crate) enum Hasher {
+    #[cfg(feature = "sha2")]
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "crc32fast")]
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            #[cfg(feature = "sha2")]
+            DigestAlgorithm::Sha256 => Hasher::Sha256(<sha2::Sha256 as sha2::Digest>::new()),
+            #[cfg(feature = "crc32fast")]
+            DigestAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        match self {
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(hasher) => sha2::Digest::update(hasher, bytes),
+            #[cfg(feature = "crc32fast")]
+            Hasher::Crc32(hasher) => hasher.update(bytes),
+        }
+    }
+
+    pub(crate) fn finalize(self) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(hasher) => sha2::Digest::finalize(hasher).to_vec(),
+            #[cfg(feature = "crc32fast")]
+            Hasher::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl super::Observer for Hasher {
+    fn written(&mut self, chunk: &[u8], _: usize) -> std::io::Result<()> {
+        self.update(chunk);
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! IO error carrying the stream offset of the failure.
+
+use std::fmt;
+
+/// IO operation that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// reading from the reader
+    Read,
+    /// writing to the writer
+    Write,
+    /// seeking in the file
+    Seek,
+    /// flushing the writer
+    Flush,
+    /// syncing the file to disk
+    Sync,
+    /// opening, creating or inspecting a file
+    Open,
+    /// copying a file to its backup
+    Copy,
+    /// setting file permissions
+    Permissions,
+    /// renaming a file over another
+    Rename,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation = match self {
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Seek => "seek",
+            Operation::Flush => "flush",
+            Operation::Sync => "sync",
+            Operation::Open => "open",
+            Operation::Copy => "copy",
+            Operation::Permissions => "set permissions",
+            Operation::Rename => "rename",
+        };
+        f.write_str(operation)
+    }
+}
+
+/// Error returned by `io` functions.
+///
+/// Records which operation failed, at which byte offset, and how many bytes were
+/// successfully written before the failure.
+///
+/// Converts into a `std::io::Error` with the same kind.
+#[derive(Debug)]
+pub struct Error {
+    operation: Operation,
+    offset: usize,
+    written: usize,
+    source: std::io::Error,
+}
+
+impl Error {
+    pub(crate) fn new(
+        operation: Operation,
+        offset: usize,
+        written: usize,
+        source: std::io::Error,
+    ) -> Self {
+        Error {
+            operation,
+            offset,
+            written,
+            source,
+        }
+    }
+
+    /// Returns the operation that failed.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// Returns the absolute position of the byte being read or written when the failure happened.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of bytes successfully written before the failure.
+    pub fn bytes_written(&self) -> usize {
+        self.written
+    }
+
+    /// Returns the kind of the underlying `std::io::Error`.
+    pub fn kind(&self) -> std::io::ErrorKind {
+        self.source.kind()
+    }
+
+    /// Returns the underlying `std::io::Error`.
+    pub fn into_inner(self) -> std::io::Error {
+        self.source
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} failed at byte offset {} ({} bytes written): {}",
+            self.operation, self.offset, self.written, self.source
+        )
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        std::io::Error::new(error.kind(), error)
+    }
+}
@@ -0,0 +1,1001 @@
+//! `swap_io_with` options.
+
+#[cfg(any(feature = "sha2", feature = "crc32fast"))]
+use super::digest::{DigestAlgorithm, Hasher};
+use super::sidecar::{ChangeLog, Histograms, Ips, Journaled, OverlaySidecar, Tee, Vcdiff};
+use super::{
+    invalid_patch, open_error, replace_atomically, replace_with_backup, seek_at, write_at, Error,
+    HookedSwap, Hooks, Observer, Operation, Progress, SparseWriter, SwapCheckpoint, SyncMode,
+    WouldBlockStrategy, VECTORED_CHUNKS,
+};
+use crate::checksum::{Fixer, Fixup};
+use crate::codec::{TextEncoding, TextFormat};
+use crate::field::Field;
+use crate::histogram::ByteStats;
+use crate::journal::JournalSink;
+use crate::pattern::{Change, SwapStats};
+use crate::{BytePattern, PositionPredicate, BUFFER_SIZE};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+#[cfg(feature = "rayon")]
+use std::path::PathBuf;
+
+/// Options of `swap_io_with`.
+///
+/// Every option defaults to the `swap_io` behavior.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::{swap_io_with, SwapIoOptions};
+///
+/// let mut reader: Cursor<Vec<u8>> = Cursor::new(vec![0x41; 20_000]);
+/// let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// let mut chunks = 0;
+/// let options = SwapIoOptions::new()
+///     .with_buffer_size(1_000)
+///     .with_range(10_000..20_000)
+///     .with_progress(None, |_| chunks += 1)
+///     .with_flush(true)
+///     .with_stats(true);
+/// let outcome = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+/// assert_eq!(outcome.bytes_written, 20_000);
+/// assert_eq!(outcome.stats.unwrap().bytes_modified, 5_000);
+/// assert_eq!(chunks, 20);
+/// ```
+pub struct SwapIoOptions<'a> {
+    buffer_size: usize,
+    range: Option<Range<usize>>,
+    total: Option<usize>,
+    progress: Option<Box<dyn FnMut(Progress) + 'a>>,
+    should_continue: Option<Box<dyn FnMut() -> bool + 'a>>,
+    checkpoint: Option<Box<dyn FnMut(SwapCheckpoint) + 'a>>,
+    checkpoint_every: usize,
+    resume: SwapCheckpoint,
+    rate: Option<usize>,
+    would_block: WouldBlockStrategy,
+    flush: bool,
+    sync: Option<(File, SyncMode)>,
+    stats: bool,
+    vectored: bool,
+    #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+    digest: Option<DigestAlgorithm>,
+    sidecars: Vec<Box<dyn Observer + 'a>>,
+    fields: Vec<Field>,
+    fixups: Vec<Fixup>,
+    text: Option<TextEncoding>,
+}
+
+impl Default for SwapIoOptions<'_> {
+    fn default() -> Self {
+        SwapIoOptions {
+            buffer_size: BUFFER_SIZE,
+            range: None,
+            total: None,
+            progress: None,
+            should_continue: None,
+            checkpoint: None,
+            checkpoint_every: 0,
+            resume: SwapCheckpoint::default(),
+            rate: None,
+            would_block: WouldBlockStrategy::default(),
+            flush: false,
+            sync: None,
+            stats: false,
+            vectored: false,
+            #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+            digest: None,
+            sidecars: Vec::new(),
+            fields: Vec::new(),
+            fixups: Vec::new(),
+            text: None,
+        }
+    }
+}
+
+impl<'a> SwapIoOptions<'a> {
+    /// Creates default options, equivalent to `swap_io`.
+    pub fn new() -> Self {
+        SwapIoOptions::default()
+    }
+
+    /// Sets the size of the buffer between reader and writer, see `swap_io_with_buffer`.
+    ///
+    /// **Default**: `BUFFER_SIZE`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer_size` is `0`.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        assert!(buffer_size > 0, "buffer size must not be 0");
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Only swaps bytes in `range`, bytes outside `range` are copied verbatim.
+    /// `swap_io_seek_with` skips them instead of copying them.
+    ///
+    /// Positions are relative to `range.start`: rules behave as if the range were the whole stream.
+    ///
+    /// **Default**: every byte
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Calls `progress` after each chunk is written, `total` being forwarded to `progress`,
+    /// e.g. the file length.
+    ///
+    /// **Default**: no progress reporting
+    pub fn with_progress<F>(mut self, total: Option<usize>, progress: F) -> Self
+    where
+        F: FnMut(Progress) + 'a,
+    {
+        self.total = total;
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stops cleanly before reading the next chunk once `should_continue` returns `false`,
+    /// `SwapOutcome::bytes_written` being the number of bytes processed before cancellation.
+    ///
+    /// **Default**: never cancelled
+    pub fn with_cancellation<F>(mut self, should_continue: F) -> Self
+    where
+        F: FnMut() -> bool + 'a,
+    {
+        self.should_continue = Some(Box::new(should_continue));
+        self
+    }
+
+    /// Calls `on_checkpoint` every `every` bytes written (rounded up to a chunk), see
+    /// `with_resume` to resume from a checkpoint.
+    ///
+    /// **Default**: no checkpoints
+    pub fn with_checkpoints<F>(mut self, every: usize, on_checkpoint: F) -> Self
+    where
+        F: FnMut(SwapCheckpoint) + 'a,
+    {
+        self.checkpoint = Some(Box::new(on_checkpoint));
+        self.checkpoint_every = every;
+        self
+    }
+
+    /// Resumes from `checkpoint`: the first byte read from the reader is at position
+    /// `checkpoint.position`, reader and writer must already be positioned accordingly,
+    /// e.g. using `Seek`. `SwapOutcome::bytes_written` only counts the bytes of this call.
+    ///
+    /// **Default**: `SwapCheckpoint::default()`, i.e. start of the stream
+    pub fn with_resume(mut self, checkpoint: SwapCheckpoint) -> Self {
+        self.resume = checkpoint;
+        self
+    }
+
+    /// Caps throughput to `bytes_per_second`: chunks of at most `bytes_per_second` bytes
+    /// are written, sleeping between chunks whenever the writer is ahead of schedule, so
+    /// slow targets do not receive bursts.
+    ///
+    /// **Default**: no limit
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is `0`.
+    pub fn with_rate(mut self, bytes_per_second: usize) -> Self {
+        assert!(bytes_per_second > 0, "bytes_per_second must not be 0");
+        self.rate = Some(bytes_per_second);
+        self
+    }
+
+    /// Sets what to do on `ErrorKind::WouldBlock` reads. `WouldBlock` errors from the
+    /// writer are returned.
+    ///
+    /// **Default**: `WouldBlockStrategy::Fail`
+    pub fn with_would_block(mut self, strategy: WouldBlockStrategy) -> Self {
+        self.would_block = strategy;
+        self
+    }
+
+    /// Flushes the writer once every byte is written.
+    ///
+    /// **Default**: `false`
+    pub fn with_flush(mut self, flush: bool) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Syncs `file` to disk once every byte is written, according to `mode`.
+    ///
+    /// `file` is a handle to the written file, e.g. obtained with `File::try_clone`.
+    /// Implies `with_flush(true)`.
+    ///
+    /// **Default**: no sync
+    pub fn with_sync(mut self, file: File, mode: SyncMode) -> Self {
+        self.flush = true;
+        self.sync = Some((file, mode));
+        self
+    }
+
+    /// Collects `SwapStats`, returned in `SwapOutcome::stats`.
+    ///
+    /// **Default**: `false`
+    pub fn with_stats(mut self, stats: bool) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Batches up to `VECTORED_CHUNKS` swapped chunks of the buffer size per
+    /// `Write::write_vectored` call.
+    ///
+    /// A batch is written as soon as it is full, or as soon as a read returns less than a
+    /// full chunk so that data trickling in from pipes or sockets is not delayed.
+    ///
+    /// **Default**: `false`
+    pub fn with_vectored(mut self, vectored: bool) -> Self {
+        self.vectored = vectored;
+        self
+    }
+
+    /// Computes a digest of the bytes written, returned in `SwapOutcome::digest`.
+    ///
+    /// Cannot be combined with `with_fixups`, swapping fails with an `InvalidInput` error
+    /// before reading anything: checksums stored before their range are written back once
+    /// the bytes holding them are already hashed, the digest would not match the output.
+    ///
+    /// Requires the `sha2` or `crc32fast` feature.
+    ///
+    /// **Default**: no digest
+    ///
+    /// ```
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::io::{swap_io_with, DigestAlgorithm, SwapIoOptions};
+    ///
+    /// let mut reader: &[u8] = b"123456788";
+    /// let mut writer: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(b'9'), Predicate::new().with_offset(8))];
+    /// # #[cfg(feature = "crc32fast")] {
+    /// let options = SwapIoOptions::new().with_digest(DigestAlgorithm::Crc32);
+    /// let outcome = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+    /// assert_eq!(writer, b"123456789");
+    /// assert_eq!(outcome.digest.unwrap(), vec![0xCB, 0xF4, 0x39, 0x26]); // CRC-32 check value
+    /// # }
+    /// # #[cfg(feature = "sha2")] {
+    /// # let mut reader: &[u8] = b"123456788";
+    /// # let options = SwapIoOptions::new().with_digest(DigestAlgorithm::Sha256);
+    /// # let outcome = swap_io_with(&mut reader, &mut Vec::new(), swaps, options).unwrap();
+    /// # assert_eq!(outcome.digest.unwrap()[..4], [0x15, 0xE2, 0xB0, 0xD3]);
+    /// # }
+    /// ```
+    #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+    pub fn with_digest(mut self, algorithm: DigestAlgorithm) -> Self {
+        self.digest = Some(algorithm);
+        self
+    }
+
+    /// Writes the bytes read, before swap, to `original` as well, in the same pass.
+    ///
+    /// **Default**: no copy
+    ///
+    /// ```
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    ///
+    /// let mut reader: &[u8] = &[0x41, 0x42, 0x43, 0x44];
+    /// let mut writer: Vec<u8> = Vec::new();
+    /// let mut backup: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+    /// let options = SwapIoOptions::new().with_tee(&mut backup);
+    /// assert_eq!(swap_io_with(&mut reader, &mut writer, swaps, options).unwrap().bytes_written, 4);
+    /// assert_eq!(writer, vec![0x42, 0x42, 0x42, 0x44]);
+    /// assert_eq!(backup, vec![0x41, 0x42, 0x43, 0x44]);
+    /// ```
+    pub fn with_tee<O: Write + 'a>(mut self, original: O) -> Self {
+        self.sidecars.push(Box::new(Tee(original)));
+        self
+    }
+
+    /// Calls `on_change` for every byte modified, in the same pass.
+    ///
+    /// Suited for audit logs: unlike `swap_io_dry_run` changes are not accumulated in memory.
+    ///
+    /// **Default**: no change log
+    ///
+    /// ```
+    /// use bswp::pattern::{Change, Pattern, Predicate};
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    ///
+    /// let mut reader: &[u8] = &[0x41, 0x42, 0x43, 0x44];
+    /// let mut writer: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+    /// let mut log = Vec::new();
+    /// let options = SwapIoOptions::new().with_change_log(|change| log.push(change));
+    /// swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+    /// assert_eq!(writer, vec![0x42, 0x42, 0x42, 0x44]);
+    /// assert_eq!(log, vec![
+    ///     Change { offset: 0, old: 0x41, new: 0x42 },
+    ///     Change { offset: 2, old: 0x43, new: 0x42 },
+    /// ]);
+    /// ```
+    pub fn with_change_log<F>(mut self, on_change: F) -> Self
+    where
+        F: FnMut(Change) + 'a,
+    {
+        self.sidecars.push(Box::new(ChangeLog(on_change)));
+        self
+    }
+
+    /// Records every byte modified to `journal`, e.g. a `journal::Journal` or a
+    /// `journal::JournalWriter`, for an audit trail or to undo changes.
+    ///
+    /// Changes of a chunk are recorded before the chunk is written.
+    ///
+    /// **Default**: no journal
+    ///
+    /// ```
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::journal::JournalWriter;
+    /// use bswp::pattern::{Pattern, Predicate};
+    ///
+    /// let mut reader: &[u8] = &[0x41, 0x42, 0x43, 0x44];
+    /// let mut writer: Vec<u8> = Vec::new();
+    /// let mut journal = JournalWriter::new(Vec::new());
+    ///
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+    /// let options = SwapIoOptions::new().with_journal(&mut journal);
+    /// swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+    /// assert_eq!(writer, vec![0x42, 0x42, 0x42, 0x44]);
+    /// assert_eq!(journal.into_inner(), b"0x00000000 41 42\n0x00000002 43 42\n");
+    /// ```
+    pub fn with_journal<J: JournalSink + 'a>(mut self, journal: J) -> Self {
+        self.sidecars.push(Box::new(Journaled(journal)));
+        self
+    }
+
+    /// Writes the bytes modified to `overlay`, as an overlay sidecar (see
+    /// `overlay::Overlay`), e.g. instead of a full copy of the output to `std::io::sink()`.
+    ///
+    /// **Default**: no overlay
+    ///
+    /// ```
+    /// use bswp::io::{apply_overlay, swap_io_with, SwapIoOptions};
+    /// use bswp::pattern::{Pattern, Predicate};
+    ///
+    /// let source = [0x41; 1_000];
+    /// let mut overlay: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(500).with_limit(2))];
+    /// let options = SwapIoOptions::new().with_overlay(&mut overlay);
+    /// swap_io_with(&mut &source[..], &mut std::io::sink(), swaps, options).unwrap();
+    /// assert_eq!(overlay.len(), 8 + 12 + 2); // magic, one run of 2 bytes
+    ///
+    /// let mut writer: Vec<u8> = Vec::new();
+    /// assert_eq!(apply_overlay(&mut &source[..], &mut writer, &overlay).unwrap(), 1_000);
+    /// assert_eq!(&writer[499..503], &[0x41, 0x42, 0x42, 0x41]);
+    /// ```
+    pub fn with_overlay<W: Write + 'a>(mut self, overlay: W) -> Self {
+        self.sidecars.push(Box::new(OverlaySidecar::new(overlay)));
+        self
+    }
+
+    /// Writes an IPS patch making the changes of the swap to `patch` once every byte is
+    /// written, see `ips::to_ips`.
+    ///
+    /// **Default**: no patch
+    ///
+    /// ```
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::pattern::{Pattern, Predicate};
+    ///
+    /// let mut reader: &[u8] = &[0x00; 4];
+    /// let mut patch: Vec<u8> = Vec::new();
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(3))];
+    /// let options = SwapIoOptions::new().with_ips(&mut patch);
+    /// swap_io_with(&mut reader, &mut std::io::sink(), swaps, options).unwrap();
+    /// assert_eq!(patch, b"PATCH\x00\x00\x03\x00\x01\x42EOF");
+    /// ```
+    pub fn with_ips<W: Write + 'a>(mut self, patch: W) -> Self {
+        self.sidecars.push(Box::new(Ips::new(patch)));
+        self
+    }
+
+    /// Writes a VCDIFF delta between the bytes read and the bytes written to `delta`,
+    /// one window per chunk (see `vcdiff`).
+    ///
+    /// **Default**: no delta
+    ///
+    /// ```
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::vcdiff::HEADER;
+    ///
+    /// let mut reader: &[u8] = &[0x41; 1_000];
+    /// let mut delta: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(500).with_limit(2))];
+    /// let options = SwapIoOptions::new().with_vcdiff(&mut delta);
+    /// swap_io_with(&mut reader, &mut std::io::sink(), swaps, options).unwrap();
+    /// assert!(delta.starts_with(HEADER));
+    /// ```
+    pub fn with_vcdiff<W: Write + 'a>(mut self, delta: W) -> Self {
+        self.sidecars.push(Box::new(Vcdiff::new(delta)));
+        self
+    }
+
+    /// Counts bytes before and after swap into `stats`, overall and per region (see
+    /// `histogram::ByteStats`).
+    ///
+    /// **Default**: no histograms
+    ///
+    /// ```
+    /// use bswp::histogram::ByteStats;
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::rule::{Op, Target};
+    ///
+    /// let mut reader: &[u8] = &[0x55, 0xAA, 0x55, 0xAA];
+    /// let mut writer: Vec<u8> = Vec::new();
+    ///
+    /// let mut stats = ByteStats::new();
+    /// let swaps = &[(Op::Xor(0xFF), Target::offsets(vec![1, 3]))];
+    /// let options = SwapIoOptions::new().with_histograms(&mut stats);
+    /// swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+    /// assert_eq!(stats.input().entropy(), 1.0);
+    /// assert_eq!(stats.output().entropy(), 0.0);
+    /// ```
+    pub fn with_histograms(mut self, stats: &'a mut ByteStats) -> Self {
+        self.sidecars.push(Box::new(Histograms::new(stats)));
+        self
+    }
+
+    /// Modifies typed `fields` of the swapped bytes, e.g. incrementing a counter (see
+    /// `field::Field`), in the same pass.
+    ///
+    /// Fields straddling chunks are held back until their last byte is read, fields past
+    /// the end of the reader are skipped. The buffer is grown to hold the longest field.
+    ///
+    /// **Default**: no fields
+    ///
+    /// ```
+    /// use bswp::field::{Field, FieldOp};
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::word::Endianness;
+    ///
+    /// let mut reader: &[u8] = &[0x41, 0xFF, 0x00, 0x00, 0x00];
+    /// let mut writer: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(0x42), Predicate::new().with_limit(1))];
+    /// let fields = vec![Field::u32(1, Endianness::Little, FieldOp::Add(1))];
+    /// let options = SwapIoOptions::new().with_fields(fields);
+    /// swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+    /// assert_eq!(writer, vec![0x42, 0x00, 0x01, 0x00, 0x00]);
+    /// ```
+    pub fn with_fields<I: IntoIterator<Item = Field>>(mut self, fields: I) -> Self {
+        self.fields.extend(fields);
+        self
+    }
+
+    /// Recomputes and stores the checksums of `fixups` over the swapped bytes, once
+    /// fields are modified, in the same pass (see `checksum::Fixer`).
+    ///
+    /// Fixup positions count from the first byte written. Checksums stored before the end
+    /// of their range are already written when computed: `swap_io_with` returns them in
+    /// `SwapOutcome::fixups` to write back at their offset, `swap_file_with` writes
+    /// them back itself. Cannot be combined with `with_digest`.
+    ///
+    /// **Default**: no fixups
+    ///
+    /// ```
+    /// use bswp::checksum::Fixup;
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::pattern::{Pattern, Predicate};
+    ///
+    /// let mut reader: &[u8] = b"\0\0\0\0123456788";
+    /// let mut writer: Vec<u8> = Vec::new();
+    ///
+    /// let swaps = &[(Pattern::new(b'9'), Predicate::new().with_offset(12))];
+    /// let options = SwapIoOptions::new().with_fixups(vec![Fixup::crc32(4..13, 0)]);
+    /// let outcome = swap_io_with(&mut reader, &mut writer, swaps, options).unwrap();
+    /// for (offset, checksum) in outcome.fixups {
+    ///     writer[offset..offset + checksum.len()].copy_from_slice(&checksum);
+    /// }
+    /// assert_eq!(writer, b"\x26\x39\xF4\xCB123456789");
+    /// ```
+    pub fn with_fixups<I: IntoIterator<Item = Fixup>>(mut self, fixups: I) -> Self {
+        self.fixups.extend(fixups);
+        self
+    }
+
+    /// Swaps hex or Base64 text: the reader is decoded, swapped and encoded again with
+    /// the formatting detected on the input (see `codec::TextFormat::detect`).
+    ///
+    /// The whole text is held in memory, `SwapOutcome::bytes_written` is the number of
+    /// decoded bytes swapped.
+    ///
+    /// **Default**: binary
+    ///
+    /// ```
+    /// use bswp::codec::TextEncoding;
+    /// use bswp::io::{swap_io_with, SwapIoOptions};
+    /// use bswp::pattern::{Pattern, Predicate};
+    ///
+    /// let text = b"41414141\n41414141\n";
+    /// let swaps = &[(Pattern::new(0xAB), Predicate::new().with_periodicity(4))];
+    /// let mut writer: Vec<u8> = Vec::new();
+    /// let options = SwapIoOptions::new().with_text(TextEncoding::Hex);
+    /// let outcome = swap_io_with(&mut &text[..], &mut writer, swaps, options).unwrap();
+    /// assert_eq!(outcome.bytes_written, 8);
+    /// assert_eq!(writer, b"ab414141\nab414141\n");
+    /// ```
+    pub fn with_text(mut self, encoding: TextEncoding) -> Self {
+        self.text = Some(encoding);
+        self
+    }
+}
+
+/// Options of `swap_file_with`: `SwapIoOptions` with the options only applying to files.
+///
+/// Converted from `SwapIoOptions`, without backup nor sparse writes.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::{swap_file_with, SwapFileOptions, SwapIoOptions};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("disk.img");
+/// std::fs::write(&path, [0x41; 4]).unwrap();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new())];
+/// let options = SwapFileOptions::new(SwapIoOptions::new().with_stats(true))
+///     .with_backup(".orig")
+///     .with_sparse(true);
+/// let outcome = swap_file_with(&path, swaps, options).unwrap();
+/// assert_eq!(outcome.stats.unwrap().bytes_modified, 4);
+/// assert_eq!(std::fs::read(dir.path().join("disk.img.orig")).unwrap(), [0x41; 4]);
+/// ```
+pub struct SwapFileOptions<'a> {
+    io: SwapIoOptions<'a>,
+    backup: Option<OsString>,
+    sparse: bool,
+}
+
+impl<'a> SwapFileOptions<'a> {
+    /// Creates options swapping files with `io`.
+    pub fn new(io: SwapIoOptions<'a>) -> Self {
+        SwapFileOptions {
+            io,
+            backup: None,
+            sparse: false,
+        }
+    }
+
+    /// Backs the file up to its path followed by `suffix` before swapping it,
+    /// see `swap_file_with_backup`.
+    ///
+    /// **Default**: no backup
+    pub fn with_backup<S: Into<OsString>>(mut self, suffix: S) -> Self {
+        self.backup = Some(suffix.into());
+        self
+    }
+
+    /// Seeks over swapped blocks of `SPARSE_BLOCK_SIZE` bytes only holding zero bytes
+    /// instead of writing them, preserving the sparseness of disk images. `swap_file`
+    /// always does it. Ignored with `SwapIoOptions::with_text`.
+    ///
+    /// Blocks are aligned on positions multiple of `SPARSE_BLOCK_SIZE`. A trailing zero
+    /// byte is written if the file ends with a hole, so that it has its full size.
+    ///
+    /// **Default**: `false`
+    pub fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+}
+
+impl<'a> From<SwapIoOptions<'a>> for SwapFileOptions<'a> {
+    fn from(io: SwapIoOptions<'a>) -> Self {
+        SwapFileOptions::new(io)
+    }
+}
+
+/// Result of `swap_io_with`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SwapOutcome {
+    /// bytes read from the reader and written to the writer
+    pub bytes_written: usize,
+    /// statistics about the swap, if requested with `SwapIoOptions::with_stats`
+    pub stats: Option<SwapStats>,
+    /// digest of the bytes written, if requested with `SwapIoOptions::with_digest`
+    pub digest: Option<Vec<u8>>,
+    /// checksums to write back at their offset, see `SwapIoOptions::with_fixups`, empty
+    /// once written back
+    pub fixups: Vec<(usize, Vec<u8>)>,
+}
+
+/// Same as `swap_io`, behavior being configured with `options`.
+///
+/// See `SwapIoOptions`.
+pub fn swap_io_with<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+    mut options: SwapIoOptions,
+) -> Result<SwapOutcome, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let outcome = match options.text {
+        Some(encoding) => swap_text(reader, writer, swaps, &mut options, encoding)?,
+        None => swap_hooked(swaps, &mut options, |swap, buffer| {
+            swap.run(reader, writer, buffer)
+        })?,
+    };
+    flush(writer, &options, outcome.bytes_written)?;
+    Ok(outcome)
+}
+
+/// Same as `swap_io_with` but seeks `reader` and `writer` over the bytes no rule may
+/// modify (see `PositionPredicate::next_match`) instead of copying them: patching a few
+/// bytes of a large image only reads the bytes around the matching positions.
+///
+/// Skipped bytes are not written, `writer` must already hold the bytes of `reader`, e.g.
+/// a copy of the file or the file itself opened twice. `SwapOutcome::bytes_written` counts
+/// skipped bytes as well, `SwapStats::bytes_read` does not. Bytes outside the range of
+/// `SwapIoOptions::with_range` and past the last match are skipped, up to the end of
+/// `reader`, where both are left.
+///
+/// Every byte is read and written with the options needing it: `with_tee`, `with_vcdiff`,
+/// `with_histograms`, `with_digest`, `with_fixups` and `with_text`.
+///
+/// ```
+/// use std::io::Cursor;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::{swap_io_seek_with, SwapIoOptions};
+///
+/// let image = vec![0x41; 1_000_000];
+/// let mut reader = Cursor::new(image.clone());
+/// let mut writer = Cursor::new(image);
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(100_000))];
+/// let options = SwapIoOptions::new().with_stats(true);
+/// let outcome = swap_io_seek_with(&mut reader, &mut writer, swaps, options).unwrap();
+/// assert_eq!(outcome.bytes_written, 1_000_000);
+/// assert!(outcome.stats.unwrap().bytes_read < 100_000); // a chunk per match
+/// assert_eq!(writer.position(), 1_000_000);
+/// let output = writer.into_inner();
+/// assert_eq!((output[0], output[1], output[100_000]), (0x42, 0x41, 0x42));
+/// ```
+pub fn swap_io_seek_with<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+    mut options: SwapIoOptions,
+) -> Result<SwapOutcome, Error>
+where
+    R: Read + Seek + ?Sized,
+    W: Write + Seek + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    if options.text.is_some() {
+        return swap_io_with(reader, writer, swaps, options);
+    }
+    let start = seek_at(reader, SeekFrom::Current(0), 0, 0)?;
+    let len = seek_at(reader, SeekFrom::End(0), 0, 0)?.saturating_sub(start) as usize;
+    seek_at(reader, SeekFrom::Start(start), 0, 0)?;
+    let mut outcome = swap_hooked(swaps, &mut options, |swap, buffer| {
+        let end = swap.position() + len;
+        loop {
+            swap.skip(reader, writer, end)?;
+            if !swap.step(reader, writer, buffer)? {
+                return Ok(());
+            }
+        }
+    })?;
+    write_fixups(writer, &mut outcome)?;
+    flush(writer, &options, outcome.bytes_written)?;
+    Ok(outcome)
+}
+
+/// Flushes and syncs `writer` as requested by `options`, once `written` bytes are written.
+fn flush<W>(writer: &mut W, options: &SwapIoOptions, written: usize) -> Result<(), Error>
+where
+    W: Write + ?Sized,
+{
+    let position = options.resume.position + written;
+    if options.flush {
+        writer
+            .flush()
+            .map_err(|e| Error::new(Operation::Flush, position, written, e))?;
+    }
+    if let Some((file, mode)) = &options.sync {
+        match mode {
+            SyncMode::Data => file.sync_data(),
+            SyncMode::All => file.sync_all(),
+        }
+        .map_err(|e| Error::new(Operation::Sync, position, written, e))?;
+    }
+    Ok(())
+}
+
+/// Runs the swap configured by `options` with `drive`, reading, swapping and writing
+/// through the given `HookedSwap` and buffer.
+fn swap_hooked<P, Q, F>(
+    swaps: &[(P, Q)],
+    options: &mut SwapIoOptions,
+    drive: F,
+) -> Result<SwapOutcome, Error>
+where
+    P: BytePattern,
+    Q: PositionPredicate,
+    F: FnOnce(&mut HookedSwap<'_, '_, '_, P, Q>, &mut [u8]) -> Result<(), Error>,
+{
+    let buffer_size = match options.rate {
+        Some(rate) => options.buffer_size.min(rate),
+        None => options.buffer_size,
+    };
+    // fields held back must fit in the buffer
+    let longest = options.fields.iter().map(|field| field.range().len());
+    let buffer_size = longest.fold(buffer_size, usize::max);
+    let buffer_size = if options.vectored {
+        buffer_size * VECTORED_CHUNKS
+    } else {
+        buffer_size
+    };
+    let mut buffer = vec![0; buffer_size];
+    let mut stats = SwapStats::default();
+    let every = options.checkpoint_every;
+    #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+    if options.digest.is_some() && !options.fixups.is_empty() {
+        let message = "digest cannot be combined with fixups written back after hashing";
+        let error = std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+        return Err(Error::new(
+            Operation::Write,
+            options.resume.position,
+            0,
+            error,
+        ));
+    }
+    #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+    let mut hasher = options.digest.map(Hasher::new);
+    let mut observers: Vec<&mut dyn Observer> = options
+        .sidecars
+        .iter_mut()
+        .map(|sidecar| sidecar.as_mut() as &mut dyn Observer)
+        .collect();
+    #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+    observers.extend(hasher.as_mut().map(|hasher| hasher as &mut dyn Observer));
+    let mut fixer = Fixer::new(&options.fixups);
+    let hooks = Hooks {
+        progress: options.progress.as_deref_mut(),
+        total: options.total,
+        should_continue: options.should_continue.as_deref_mut(),
+        stats: if options.stats {
+            Some(&mut stats)
+        } else {
+            None
+        },
+        range: options.range.clone(),
+        start: options.resume.position,
+        checkpoint: options
+            .checkpoint
+            .as_deref_mut()
+            .map(|on_checkpoint| (on_checkpoint, every)),
+        rate: options.rate,
+        would_block: options.would_block,
+        observer: if observers.is_empty() {
+            None
+        } else {
+            Some(&mut observers)
+        },
+        vectored: options.vectored,
+        fields: &options.fields,
+        fixer: if options.fixups.is_empty() {
+            None
+        } else {
+            Some(&mut fixer)
+        },
+    };
+    let stages = [swaps];
+    let mut swap = HookedSwap::new(&stages, buffer.len(), hooks);
+    drive(&mut swap, &mut buffer)?;
+    let (position, written, fixups) = (swap.position(), swap.written(), swap.fixups());
+    drop(swap);
+    observers
+        .finish(position)
+        .map_err(|e| Error::new(Operation::Write, position, written, e))?;
+    drop(observers);
+    Ok(SwapOutcome {
+        bytes_written: written,
+        stats: if options.stats { Some(stats) } else { None },
+        #[cfg(any(feature = "sha2", feature = "crc32fast"))]
+        digest: hasher.map(Hasher::finalize),
+        #[cfg(not(any(feature = "sha2", feature = "crc32fast")))]
+        digest: None,
+        fixups,
+    })
+}
+
+/// Swaps the bytes decoded from the `encoding` text of `reader` and writes them encoded
+/// again to `writer`, see `SwapIoOptions::with_text`. Fixups are written back before
+/// encoding.
+fn swap_text<R, W, P, Q>(
+    reader: &mut R,
+    writer: &mut W,
+    swaps: &[(P, Q)],
+    options: &mut SwapIoOptions,
+    encoding: TextEncoding,
+) -> Result<SwapOutcome, Error>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut text = Vec::new();
+    reader
+        .read_to_end(&mut text)
+        .map_err(|e| Error::new(Operation::Read, 0, 0, e))?;
+    let format = TextFormat::detect(encoding, &text);
+    let bytes = format.decode(&text).map_err(invalid_patch)?;
+    let mut swapped = Vec::with_capacity(bytes.len());
+    let mut outcome = swap_hooked(swaps, options, |swap, buffer| {
+        swap.run(&mut &bytes[..], &mut swapped, buffer)
+    })?;
+    for (offset, checksum) in outcome.fixups.drain(..) {
+        swapped[offset..offset + checksum.len()].copy_from_slice(&checksum);
+    }
+    write_at(writer, &format.encode(&swapped), 0, &mut 0)?;
+    Ok(outcome)
+}
+
+/// Writes the checksums of `outcome` back at their offset in `writer`, positioned at the
+/// end of the bytes written, which is left there.
+fn write_fixups<W>(writer: &mut W, outcome: &mut SwapOutcome) -> Result<(), Error>
+where
+    W: Write + Seek + ?Sized,
+{
+    let written = outcome.bytes_written;
+    let mut position = written;
+    // fix-ups are in declaration order, their offsets being before or after each other
+    for (offset, checksum) in outcome.fixups.drain(..) {
+        let relative = offset as i64 - position as i64;
+        seek_at(writer, SeekFrom::Current(relative), offset, written)?;
+        write_at(writer, &checksum, offset, &mut 0)?;
+        position = offset + checksum.len();
+    }
+    if position != written {
+        let relative = SeekFrom::Current(written as i64 - position as i64);
+        seek_at(writer, relative, position, written)?;
+    }
+    Ok(())
+}
+
+/// Same as `swap_file`, behavior being configured with `options`, either `SwapFileOptions`
+/// or `SwapIoOptions`.
+///
+/// Checksums of `SwapIoOptions::with_fixups` are written back in the file.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::{swap_file_with, SwapIoOptions};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("firmware.bin");
+/// std::fs::write(&path, [0x41; 4]).unwrap();
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new())];
+/// let options = SwapIoOptions::new().with_range(2..4).with_stats(true);
+/// let outcome = swap_file_with(&path, swaps, options).unwrap();
+/// assert_eq!(outcome.stats.unwrap().bytes_modified, 2);
+/// assert_eq!(std::fs::read(&path).unwrap(), [0x41, 0x41, 0x42, 0x42]);
+/// ```
+pub fn swap_file_with<'a, T, P, Q, O>(
+    path: T,
+    swaps: &[(P, Q)],
+    options: O,
+) -> Result<SwapOutcome, Error>
+where
+    T: AsRef<Path>,
+    P: BytePattern,
+    Q: PositionPredicate,
+    O: Into<SwapFileOptions<'a>>,
+{
+    let path = path.as_ref();
+    let SwapFileOptions {
+        io: options,
+        backup,
+        sparse,
+    } = options.into();
+    replace_with_backup(path, backup.as_deref(), || {
+        let mut reader = File::open(path).map_err(open_error)?;
+        replace_atomically(path, |file| {
+            let mut outcome = if sparse && options.text.is_none() {
+                let mut writer = SparseWriter::new(&mut *file)
+                    .map_err(|e| Error::new(Operation::Seek, 0, 0, e))?;
+                let outcome = swap_io_with(&mut reader, &mut writer, swaps, options)?;
+                let written = outcome.bytes_written;
+                writer
+                    .finish()
+                    .map_err(|e| Error::new(Operation::Write, written, written, e))?;
+                outcome
+            } else {
+                swap_io_with(&mut reader, file, swaps, options)?
+            };
+            write_fixups(file, &mut outcome)?;
+            Ok(outcome)
+        })
+    })
+}
+
+/// Same as `swap_files_par`, each file being swapped like `swap_file_with` with
+/// the options returned by `options` for its path.
+///
+/// Results are returned in the order of `paths`.
+///
+/// Requires the `rayon` feature.
+///
+/// # Panics
+///
+/// Panics if the thread pool cannot be built.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::io::{swap_files_par_with, SwapFileOptions, SwapIoOptions};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let paths: Vec<_> = (0..4).map(|index| dir.path().join(format!("{}.rom", index))).collect();
+/// for path in &paths {
+///     std::fs::write(path, [0x41; 4]).unwrap();
+/// }
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new())];
+/// let options = |_: &_| {
+///     SwapFileOptions::new(SwapIoOptions::new().with_range(0..2).with_stats(true)).with_backup(".bak")
+/// };
+/// let results = swap_files_par_with(paths, swaps, 2, options);
+/// for (path, result) in results {
+///     assert_eq!(result.unwrap().stats.unwrap().bytes_modified, 2);
+///     assert_eq!(std::fs::read(path).unwrap(), [0x42, 0x42, 0x41, 0x41]);
+/// }
+/// ```
+#[cfg(feature = "rayon")]
+pub fn swap_files_par_with<I, P, Q, F, O>(
+    paths: I,
+    swaps: &[(P, Q)],
+    parallelism: usize,
+    options: F,
+) -> Vec<(PathBuf, Result<SwapOutcome, Error>)>
+where
+    I: IntoIterator<Item = PathBuf>,
+    P: BytePattern + Sync,
+    Q: PositionPredicate + Sync,
+    F: Fn(&Path) -> O + Sync,
+    O: Into<SwapFileOptions<'static>>,
+{
+    use rayon::prelude::*;
+    let paths: Vec<PathBuf> = paths.into_iter().collect();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallelism)
+        .build()
+        .expect("failed to build thread pool");
+    pool.install(|| {
+        paths
+            .into_par_iter()
+            .map(|path| {
+                let result = swap_file_with(&path, swaps, options(&path));
+                (path, result)
+            })
+            .collect()
+    })
+}
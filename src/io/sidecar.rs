@@ -0,0 +1,220 @@
+//! Sidecar outputs written in the same pass as the swap, see `SwapIoOptions`.
+
+use super::Observer;
+use crate::histogram::ByteStats;
+use crate::ips::to_ips;
+use crate::journal::JournalSink;
+use crate::overlay::OverlayWriter;
+use crate::pattern::Change;
+use crate::vcdiff::{encode_window, HEADER};
+use std::io::Write;
+
+/// Calls `F` for every byte modified, see `SwapIoOptions::with_change_log`.
+pub(super) struct ChangeLog<F>(pub(super) F);
+
+impl<F: FnMut(Change)> Observer for ChangeLog<F> {
+    fn observes_changes(&self) -> bool {
+        true
+    }
+
+    fn needs_every_byte(&self) -> bool {
+        false
+    }
+
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        (self.0)(change);
+        Ok(())
+    }
+}
+
+/// Writes the bytes read, before swap, see `SwapIoOptions::with_tee`.
+pub(super) struct Tee<W>(pub(super) W);
+
+impl<W: Write> Observer for Tee<W> {
+    fn read(&mut self, chunk: &[u8], _: usize) -> std::io::Result<()> {
+        self.0.write_all(chunk)
+    }
+}
+
+/// Records every byte modified, see `SwapIoOptions::with_journal`.
+pub(super) struct Journaled<J>(pub(super) J);
+
+impl<J: JournalSink> Observer for Journaled<J> {
+    fn observes_changes(&self) -> bool {
+        true
+    }
+
+    fn needs_every_byte(&self) -> bool {
+        false
+    }
+
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        self.0.record(&[change])
+    }
+}
+
+/// Writes an overlay of the bytes modified, see `SwapIoOptions::with_overlay`.
+pub(super) struct OverlaySidecar<W> {
+    /// writer until the magic bytes are written
+    writer: Option<W>,
+    overlay: Option<OverlayWriter<W>>,
+}
+
+impl<W: Write> OverlaySidecar<W> {
+    pub(super) fn new(writer: W) -> Self {
+        OverlaySidecar {
+            writer: Some(writer),
+            overlay: None,
+        }
+    }
+
+    /// Returns the overlay, writing the magic bytes first if needed.
+    fn overlay(&mut self) -> std::io::Result<&mut OverlayWriter<W>> {
+        if let Some(writer) = self.writer.take() {
+            self.overlay = Some(OverlayWriter::new(writer)?);
+        }
+        Ok(self.overlay.as_mut().expect("overlay already finished"))
+    }
+}
+
+impl<W: Write> Observer for OverlaySidecar<W> {
+    fn observes_changes(&self) -> bool {
+        true
+    }
+
+    fn needs_every_byte(&self) -> bool {
+        false
+    }
+
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        self.overlay()?.record(&[change])
+    }
+
+    fn finish(&mut self, _: usize) -> std::io::Result<()> {
+        self.overlay()?;
+        let overlay = self.overlay.take().expect("overlay already finished");
+        overlay.finish().map(drop)
+    }
+}
+
+/// Writes an IPS patch once every change is known, see `SwapIoOptions::with_ips`.
+pub(super) struct Ips<W> {
+    writer: W,
+    changes: Vec<Change>,
+}
+
+impl<W: Write> Ips<W> {
+    pub(super) fn new(writer: W) -> Self {
+        Ips {
+            writer,
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Observer for Ips<W> {
+    fn observes_changes(&self) -> bool {
+        true
+    }
+
+    fn needs_every_byte(&self) -> bool {
+        false
+    }
+
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        self.changes.push(change);
+        Ok(())
+    }
+
+    fn finish(&mut self, _: usize) -> std::io::Result<()> {
+        let patch = to_ips(&self.changes)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+        self.writer.write_all(&patch)
+    }
+}
+
+/// Writes a VCDIFF delta, one window per chunk written, see `SwapIoOptions::with_vcdiff`.
+pub(super) struct Vcdiff<W> {
+    writer: W,
+    started: bool,
+    /// bytes read, not yet written
+    source: Vec<u8>,
+    changes: Vec<Change>,
+}
+
+impl<W: Write> Vcdiff<W> {
+    pub(super) fn new(writer: W) -> Self {
+        Vcdiff {
+            writer,
+            started: false,
+            source: Vec::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    fn start(&mut self) -> std::io::Result<()> {
+        if !self.started {
+            self.writer.write_all(HEADER)?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Observer for Vcdiff<W> {
+    fn read(&mut self, chunk: &[u8], _: usize) -> std::io::Result<()> {
+        self.start()?;
+        self.source.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    fn observes_changes(&self) -> bool {
+        true
+    }
+
+    fn change(&mut self, change: Change) -> std::io::Result<()> {
+        self.changes.push(change);
+        Ok(())
+    }
+
+    fn written(&mut self, chunk: &[u8], position: usize) -> std::io::Result<()> {
+        let window = encode_window(&self.source[..chunk.len()], position, &self.changes);
+        self.source.drain(..chunk.len());
+        self.changes.clear();
+        self.writer.write_all(&window)
+    }
+
+    fn finish(&mut self, _: usize) -> std::io::Result<()> {
+        self.start()
+    }
+}
+
+/// Counts bytes before and after swap, see `SwapIoOptions::with_histograms`.
+pub(super) struct Histograms<'s> {
+    stats: &'s mut ByteStats,
+    /// bytes read, not yet written
+    input: Vec<u8>,
+}
+
+impl<'s> Histograms<'s> {
+    pub(super) fn new(stats: &'s mut ByteStats) -> Self {
+        Histograms {
+            stats,
+            input: Vec::new(),
+        }
+    }
+}
+
+impl Observer for Histograms<'_> {
+    fn read(&mut self, chunk: &[u8], _: usize) -> std::io::Result<()> {
+        self.input.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    fn written(&mut self, chunk: &[u8], position: usize) -> std::io::Result<()> {
+        self.stats
+            .record(&self.input[..chunk.len()], chunk, position);
+        self.input.drain(..chunk.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! IPS patches (pure)
+//!
+//! IPS is the patch format understood by most ROM patching tools: a `PATCH` header,
+//! records replacing bytes at 24-bit offsets (literal or run-length encoded) and an
+//! `EOF` footer, optionally followed by the 24-bit size to truncate the output to.
+
+use crate::pattern::Change;
+use std::fmt;
+
+/// Largest offset an IPS record can start at.
+pub const MAX_OFFSET: usize = 0xFF_FFFF;
+
+const HEADER: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+/// Offset reading as the footer, no record can start there.
+const EOF_OFFSET: usize = 0x45_4F46;
+/// Largest number of bytes in a record.
+const MAX_RECORD: usize = 0xFFFF;
+/// Shortest run of identical bytes encoded as a run-length record.
+const MIN_RUN: usize = 8;
+
+/// Error returned when changes cannot be described by an IPS patch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IpsError {
+    /// a change lies past `MAX_OFFSET`
+    OffsetOutOfRange(usize),
+    /// a record would start at offset `0x454F46`, which reads as the footer
+    ReservedOffset,
+    /// the patch does not start with `PATCH`
+    InvalidHeader,
+    /// the patch ends in the middle of a record or misses the footer
+    Truncated,
+}
+
+impl fmt::Display for IpsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpsError::OffsetOutOfRange(offset) => write!(
+                f,
+                "offset {:#X} is past the IPS limit of {:#X}",
+                offset, MAX_OFFSET
+            ),
+            IpsError::ReservedOffset => {
+                write!(f, "an IPS record cannot start at offset {:#X}", EOF_OFFSET)
+            }
+            IpsError::InvalidHeader => write!(f, "not an IPS patch"),
+            IpsError::Truncated => write!(f, "truncated IPS patch"),
+        }
+    }
+}
+
+impl std::error::Error for IpsError {}
+
+/// Bytes written by an IPS patch, run-length records being expanded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpsRecord {
+    /// position of the first byte written
+    pub offset: usize,
+    /// bytes written
+    pub data: Vec<u8>,
+}
+
+impl IpsRecord {
+    /// Returns the position following the last byte written.
+    pub fn end(&self) -> usize {
+        self.offset + self.data.len()
+    }
+}
+
+/// Parsed IPS patch.
+///
+/// ```
+/// use bswp::ips::IpsPatch;
+///
+/// let patch = IpsPatch::parse(b"PATCH\x00\x00\x01\x00\x02\x42\x42\x00\x00\x04\x00\x00\x00\x03\x00EOF").unwrap();
+/// assert_eq!(patch.records.len(), 2);
+/// assert_eq!(patch.records[1].data, vec![0x00; 3]); // run-length record
+///
+/// let mut buffer = vec![0x41; 4];
+/// patch.apply(&mut buffer);
+/// assert_eq!(buffer, vec![0x41, 0x42, 0x42, 0x41, 0x00, 0x00, 0x00]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IpsPatch {
+    /// records, in patch order: later records overwrite earlier ones
+    pub records: Vec<IpsRecord>,
+    /// size to truncate the output to, if any
+    pub truncate: Option<usize>,
+}
+
+impl IpsPatch {
+    /// Parses an IPS patch.
+    pub fn parse(patch: &[u8]) -> Result<Self, IpsError> {
+        let mut records = patch.strip_prefix(HEADER).ok_or(IpsError::InvalidHeader)?;
+        let mut parsed = IpsPatch::default();
+        loop {
+            if let Some(rest) = records.strip_prefix(FOOTER) {
+                parsed.truncate = match rest {
+                    [a, b, c, ..] => Some(u24(*a, *b, *c)),
+                    _ => None,
+                };
+                return Ok(parsed);
+            }
+            let (offset, size, rest) = match records {
+                [a, b, c, d, e, rest @ ..] => (u24(*a, *b, *c), u16::from_be_bytes([*d, *e]), rest),
+                _ => return Err(IpsError::Truncated),
+            };
+            let (data, rest) = match (usize::from(size), rest) {
+                (0, [d, e, value, rest @ ..]) => (
+                    vec![*value; usize::from(u16::from_be_bytes([*d, *e]))],
+                    rest,
+                ),
+                (size, rest) if size > 0 && rest.len() >= size => {
+                    (rest[..size].to_vec(), &rest[size..])
+                }
+                _ => return Err(IpsError::Truncated),
+            };
+            parsed.records.push(IpsRecord { offset, data });
+            records = rest;
+        }
+    }
+
+    /// Returns the size of the output for an input of `len` bytes.
+    pub fn output_len(&self, len: usize) -> usize {
+        let end = self
+            .records
+            .iter()
+            .map(IpsRecord::end)
+            .fold(len, usize::max);
+        self.truncate.unwrap_or(end)
+    }
+
+    /// Applies the patch to `buffer`, growing or truncating it as needed.
+    pub fn apply(&self, buffer: &mut Vec<u8>) {
+        let end = self.records.iter().map(IpsRecord::end).max().unwrap_or(0);
+        if end > buffer.len() {
+            buffer.resize(end, 0);
+        }
+        for record in &self.records {
+            buffer[record.offset..record.end()].copy_from_slice(&record.data);
+        }
+        if let Some(len) = self.truncate {
+            buffer.truncate(len);
+        }
+    }
+}
+
+fn u24(a: u8, b: u8, c: u8) -> usize {
+    usize::from(a) << 16 | usize::from(b) << 8 | usize::from(c)
+}
+
+/// Returns an IPS patch making `changes`, sorted by increasing offset (see `pattern::ChangeReport`).
+///
+/// Runs of at least 8 identical bytes are run-length encoded.
+///
+/// ```
+/// use bswp::ips::to_ips;
+/// use bswp::pattern::{diff_iter, Pattern, Predicate};
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(1).with_limit(2))];
+/// let changes: Vec<_> = diff_iter(&[0x00; 4], swaps).collect();
+/// let patch = to_ips(&changes).unwrap();
+/// assert_eq!(patch, b"PATCH\x00\x00\x01\x00\x02\x42\x42EOF");
+/// ```
+pub fn to_ips(changes: &[Change]) -> Result<Vec<u8>, IpsError> {
+    let mut patch = HEADER.to_vec();
+    let mut index = 0;
+    while index < changes.len() {
+        // contiguous changes
+        let start = changes[index].offset;
+        let len = changes[index..]
+            .iter()
+            .enumerate()
+            .take_while(|(i, change)| change.offset == start + i)
+            .count();
+        let data: Vec<u8> = changes[index..index + len]
+            .iter()
+            .map(|change| change.new)
+            .collect();
+        write_block(&mut patch, start, &data)?;
+        index += len;
+    }
+    patch.extend_from_slice(FOOTER);
+    Ok(patch)
+}
+
+/// Appends records replacing bytes from `start` with `data`.
+fn write_block(patch: &mut Vec<u8>, start: usize, data: &[u8]) -> Result<(), IpsError> {
+    let end = start + data.len();
+    let mut offset = start;
+    while offset < end {
+        if offset > MAX_OFFSET {
+            return Err(IpsError::OffsetOutOfRange(offset));
+        }
+        if offset == EOF_OFFSET {
+            return Err(IpsError::ReservedOffset);
+        }
+        let rest = &data[offset - start..];
+        let run = rest.iter().take_while(|&&byte| byte == rest[0]).count();
+        let (mut next, rle) = if run >= MIN_RUN {
+            (offset + run.min(MAX_RECORD), true)
+        } else {
+            // literal bytes up to the next run
+            let literal = (0..rest.len())
+                .find(|&i| {
+                    rest[i..].len() >= MIN_RUN
+                        && rest[i..i + MIN_RUN].iter().all(|&byte| byte == rest[i])
+                })
+                .unwrap_or(rest.len());
+            (offset + literal.min(MAX_RECORD), false)
+        };
+        if next == EOF_OFFSET && next < end {
+            // the next record cannot start there
+            next = if rle || next - offset == MAX_RECORD {
+                next - 1
+            } else {
+                next + 1
+            };
+        }
+        let len = next - offset;
+        patch.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+        if rle {
+            patch.extend_from_slice(&[0, 0]);
+            patch.extend_from_slice(&(len as u16).to_be_bytes());
+            patch.push(rest[0]);
+        } else {
+            patch.extend_from_slice(&(len as u16).to_be_bytes());
+            patch.extend_from_slice(&rest[..len]);
+        }
+        offset = next;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ips::{to_ips, IpsError, IpsPatch, EOF_OFFSET};
+    use crate::pattern::Change;
+
+    fn changes(offsets: std::ops::Range<usize>, new: impl Fn(usize) -> u8) -> Vec<Change> {
+        offsets
+            .map(|offset| Change {
+                offset,
+                old: 0,
+                new: new(offset),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ips_round_trip() {
+        let mut expected = vec![0u8; 0x46_0000];
+        let mut all: Vec<Change> = Vec::new();
+        all.extend(changes(3..10, |offset| offset as u8));
+        all.extend(changes(20..100_020, |offset| {
+            if offset < 30 {
+                offset as u8
+            } else {
+                0xAA
+            }
+        }));
+        // a run would start at the reserved offset
+        all.extend(changes(EOF_OFFSET - 3..EOF_OFFSET + 10, |offset| {
+            if offset < EOF_OFFSET {
+                offset as u8 | 0x80
+            } else {
+                0x55
+            }
+        }));
+        all.extend(changes(EOF_OFFSET + 10..EOF_OFFSET + 20, |offset| {
+            offset as u8 | 1
+        }));
+        for change in &all {
+            expected[change.offset] = change.new;
+        }
+        let patch = IpsPatch::parse(&to_ips(&all).unwrap()).unwrap();
+        assert!(patch
+            .records
+            .iter()
+            .all(|record| record.offset != EOF_OFFSET));
+        let mut buffer = vec![0u8; expected.len()];
+        patch.apply(&mut buffer);
+        assert!(buffer == expected);
+        assert_eq!(
+            IpsPatch::parse(b"PATCH\x00\x00\x01\x00\x02\x42"),
+            Err(IpsError::Truncated)
+        );
+        assert_eq!(
+            IpsPatch::parse(b"PATCHEOF\x00\x00\x02").unwrap().truncate,
+            Some(2)
+        );
+        assert_eq!(IpsPatch::parse(b"BPS1"), Err(IpsError::InvalidHeader));
+
+        let reserved = changes(EOF_OFFSET..EOF_OFFSET + 1, |_| 1);
+        assert_eq!(to_ips(&reserved), Err(IpsError::ReservedOffset));
+        let far = changes(0x100_0000..0x100_0001, |_| 1);
+        assert_eq!(to_ips(&far), Err(IpsError::OffsetOutOfRange(0x100_0000)));
+    }
+}
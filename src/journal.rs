@@ -0,0 +1,234 @@
+//! Change journals (pure)
+//!
+//! A journal records every byte a swap modifies as `(offset, old, new)`, for an audit
+//! trail of what was changed and to undo it afterwards.
+//!
+//! Journals written by `JournalWriter` hold one change per line:
+//!
+//! ```text
+//! 0x00000010 41 42
+//! 0x00000012 43 42
+//! ```
+
+use crate::pattern::{diff_iter_from, Change, Pattern};
+use crate::rule::{Op, SwapRule, Target};
+use crate::{BytePattern, PositionPredicate};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+/// Destination of the changes recorded while swapping.
+pub trait JournalSink {
+    /// Records `changes`, in increasing offset order.
+    fn record(&mut self, changes: &[Change]) -> std::io::Result<()>;
+}
+
+impl<J: JournalSink + ?Sized> JournalSink for &mut J {
+    fn record(&mut self, changes: &[Change]) -> std::io::Result<()> {
+        (**self).record(changes)
+    }
+}
+
+/// In-memory journal.
+///
+/// ```
+/// use bswp::journal::{swap_slice_journaled, Journal};
+/// use bswp::pattern::{Pattern, Predicate};
+///
+/// let mut buffer = [0x41, 0x42, 0x43, 0x44];
+/// let mut journal = Journal::new();
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// swap_slice_journaled(&mut buffer, swaps, 0, &mut journal).unwrap();
+/// assert_eq!(buffer, [0x42, 0x42, 0x42, 0x44]);
+/// assert_eq!(journal.changes().len(), 2);
+///
+/// journal.undo(&mut buffer, 0);
+/// assert_eq!(buffer, [0x41, 0x42, 0x43, 0x44]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    changes: Vec<Change>,
+}
+
+impl Journal {
+    /// Creates an empty `Journal`.
+    pub fn new() -> Self {
+        Journal::default()
+    }
+
+    /// Reads a journal written by `JournalWriter`.
+    ///
+    /// Returns an `InvalidData` error naming the line if a line is malformed.
+    pub fn read_from<R: BufRead>(reader: R) -> std::io::Result<Self> {
+        let mut journal = Journal::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let change = parse_line(&line).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid journal line {}: {:?}", index + 1, line),
+                )
+            })?;
+            journal.changes.push(change);
+        }
+        Ok(journal)
+    }
+
+    /// Returns the changes recorded, in order.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Restores the bytes of `buffer` changed since the journal started, `buffer[0]`
+    /// being the byte at `position`.
+    ///
+    /// Changes are undone in reverse order. Changes outside `buffer` are skipped.
+    pub fn undo(&self, buffer: &mut [u8], position: usize) {
+        for change in self.changes.iter().rev() {
+            if let Some(byte) = change
+                .offset
+                .checked_sub(position)
+                .and_then(|index| buffer.get_mut(index))
+            {
+                *byte = change.old;
+            }
+        }
+    }
+
+    /// Returns swap rules restoring the bytes changed since the journal started,
+    /// e.g. to undo changes to a file with `io::swap_io`.
+    pub fn undo_rules(&self) -> Vec<SwapRule> {
+        // earliest original value of every changed position
+        let mut original: BTreeMap<usize, u8> = BTreeMap::new();
+        for change in self.changes.iter().rev() {
+            original.insert(change.offset, change.old);
+        }
+        let mut positions: BTreeMap<u8, Vec<usize>> = BTreeMap::new();
+        for (offset, old) in original {
+            positions.entry(old).or_default().push(offset);
+        }
+        positions
+            .into_iter()
+            .map(|(old, positions)| (Op::Set(Pattern::new(old)), Target::Offsets(positions)))
+            .collect()
+    }
+}
+
+impl JournalSink for Journal {
+    fn record(&mut self, changes: &[Change]) -> std::io::Result<()> {
+        self.changes.extend_from_slice(changes);
+        Ok(())
+    }
+}
+
+/// Journal written to `writer`, one line per change.
+pub struct JournalWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> JournalWriter<W> {
+    /// Creates a journal writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        JournalWriter { writer }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> JournalSink for JournalWriter<W> {
+    fn record(&mut self, changes: &[Change]) -> std::io::Result<()> {
+        for change in changes {
+            writeln!(
+                self.writer,
+                "{:#010x} {:02x} {:02x}",
+                change.offset, change.old, change.new
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `0xOFFSET OLD NEW`.
+fn parse_line(line: &str) -> Option<Change> {
+    let mut fields = line.split_whitespace();
+    let offset = fields.next()?.strip_prefix("0x")?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let old = u8::from_str_radix(fields.next()?, 16).ok()?;
+    let new = u8::from_str_radix(fields.next()?, 16).ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Change { offset, old, new })
+}
+
+/// Same as `pattern::swap_slice_from`, recording changes to `journal`.
+///
+/// `buffer` is left untouched if `journal` fails to record.
+pub fn swap_slice_journaled<P, Q, J>(
+    buffer: &mut [u8],
+    swaps: &[(P, Q)],
+    position: usize,
+    mut journal: J,
+) -> std::io::Result<()>
+where
+    P: BytePattern,
+    Q: PositionPredicate,
+    J: JournalSink,
+{
+    let changes: Vec<Change> = diff_iter_from(buffer, swaps, position).collect();
+    journal.record(&changes)?;
+    for change in changes {
+        buffer[change.offset - position] = change.new;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::{swap_slice_journaled, Journal, JournalWriter};
+    use crate::pattern::{swap_slice, Change, Pattern, Predicate};
+    use crate::rule::{Op, Target};
+
+    #[test]
+    fn test_journal_undo() {
+        let source: Vec<u8> = (0..100).collect();
+        let first = &[(Op::Xor(0xFF), Target::Range(10..30))];
+        let second = &[(
+            Pattern::new(0x00),
+            Predicate::new().with_offset(20).with_periodicity(5),
+        )];
+        let mut buffer = source.clone();
+        let mut journal = Journal::new();
+        let mut writer = JournalWriter::new(Vec::new());
+        for (index, chunk) in buffer.chunks_mut(16).enumerate() {
+            swap_slice_journaled(chunk, first, index * 16, &mut journal).unwrap();
+            swap_slice_journaled(chunk, first, index * 16, &mut writer).unwrap();
+            swap_slice_journaled(chunk, first, index * 16, &mut writer).unwrap();
+        }
+        swap_slice_journaled(&mut buffer, second, 0, &mut journal).unwrap();
+        assert_eq!(journal.changes().len(), 20 + 16);
+
+        let mut undone = buffer.clone();
+        swap_slice(&mut undone, &journal.undo_rules());
+        assert_eq!(undone, source);
+        journal.undo(&mut buffer[5..], 5);
+        assert_eq!(buffer, source);
+
+        let text = writer.into_inner();
+        assert!(text.starts_with(b"0x0000000a f5 0a\n"));
+        let replayed = Journal::read_from(&text[..]).unwrap();
+        assert_eq!(replayed.changes().len(), 40);
+        let first_change = Change {
+            offset: 10,
+            old: 0xf5,
+            new: 0x0a,
+        };
+        assert_eq!(replayed.changes()[0], first_change);
+        assert!(Journal::read_from(&b"0x10 41\n"[..]).is_err());
+    }
+}
@@ -20,6 +20,7 @@
 //! ## Mutating File-like Data
 //!
 //! ```
+//! # #[cfg(feature = "std")] {
 //! use std::io::Cursor;
 //! use bswp::pattern::{Pattern, Predicate};
 //! use bswp::io::swap_io;
@@ -34,7 +35,18 @@
 //! assert!(swap.is_ok());
 //! assert_eq!(swap.unwrap(), 4); // 4 bytes written
 //! assert_eq!(writer.into_inner(), vec![0x42, 0x42, 0x42, 0x44])
+//! # }
 //! ```
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`:
+//! `pattern`, `rule`, `set` and the other slice modules remain, `io` and the modules
+//! depending on it are gated behind `std`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 /// default buffer size for io: 8KB
 pub const BUFFER_SIZE: usize = 8000; // 8KB
@@ -43,13 +55,172 @@ pub const BUFFER_SIZE: usize = 8000; // 8KB
 pub trait PositionPredicate {
     /// Returns `true` if `position` matches predicate else `false`.
     fn eval(&self, position: usize) -> bool;
+
+    /// Returns the equivalent periodic `Predicate` if matching positions form
+    /// an arithmetic progression, enabling strided fast paths.
+    ///
+    /// **Default**: `None`, positions are evaluated one by one.
+    fn as_periodic(&self) -> Option<pattern::Predicate> {
+        None
+    }
+
+    /// Returns the first position `>= position` that may match, `None` if no such position matches.
+    ///
+    /// Used to skip non-matching regions, e.g. seeking in `io::swap_in_place`.
+    ///
+    /// **Default**: `Some(position)`, i.e. any position may match.
+    fn next_match(&self, position: usize) -> Option<usize> {
+        Some(position)
+    }
 }
 
 /// Pattern on byte.
 pub trait BytePattern {
     /// Returns the value with current pattern applied.
     fn eval(&self, value: u8) -> u8;
+
+    /// Returns `Some(byte)` if the pattern ignores its input and always yields `byte`.
+    ///
+    /// **Default**: `None`.
+    fn constant(&self) -> Option<u8> {
+        None
+    }
+
+    /// Returns the size of the groups the pattern moves bytes within, groups being
+    /// aligned on positions multiple of the size.
+    ///
+    /// Only whole groups are swapped, bytes of a trailing partial group are left untouched.
+    ///
+    /// **Default**: `1`, the pattern does not move bytes.
+    fn group_size(&self) -> usize {
+        1
+    }
+
+    /// Returns the index, within its group, of the byte moved to index `lane`.
+    ///
+    /// Only called when `group_size() > 1`, `eval` is then applied to the moved byte.
+    ///
+    /// **Default**: `lane`.
+    fn source_lane(&self, lane: usize) -> usize {
+        lane
+    }
+
+    /// Returns `value` with current pattern applied, `value` being at `position`.
+    ///
+    /// This is what swaps call, patterns depending on the position override it.
+    ///
+    /// **Default**: `eval(value)`.
+    fn eval_at(&self, position: usize, value: u8) -> u8 {
+        let _ = position;
+        self.eval(value)
+    }
+
+    /// Returns `true` if `eval_at` depends on the position, e.g. `rule::Callback`.
+    ///
+    /// **Default**: `false`.
+    fn is_positional(&self) -> bool {
+        false
+    }
+}
+
+impl<T: PositionPredicate + ?Sized> PositionPredicate for &T {
+    fn eval(&self, position: usize) -> bool {
+        (**self).eval(position)
+    }
+
+    fn as_periodic(&self) -> Option<pattern::Predicate> {
+        (**self).as_periodic()
+    }
+
+    fn next_match(&self, position: usize) -> Option<usize> {
+        (**self).next_match(position)
+    }
+}
+
+impl<T: BytePattern + ?Sized> BytePattern for &T {
+    fn eval(&self, value: u8) -> u8 {
+        (**self).eval(value)
+    }
+
+    fn constant(&self) -> Option<u8> {
+        (**self).constant()
+    }
+
+    fn group_size(&self) -> usize {
+        (**self).group_size()
+    }
+
+    fn source_lane(&self, lane: usize) -> usize {
+        (**self).source_lane(lane)
+    }
+
+    fn eval_at(&self, position: usize, value: u8) -> u8 {
+        (**self).eval_at(position, value)
+    }
+
+    fn is_positional(&self) -> bool {
+        (**self).is_positional()
+    }
 }
 
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod bit;
+#[cfg(feature = "std")]
+pub mod bps;
+pub mod checksum;
+pub mod codec;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod dsl;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod field;
+#[cfg(feature = "std")]
+pub mod histogram;
+#[cfg(feature = "std")]
 pub mod io;
+#[cfg(feature = "std")]
+pub mod ips;
+#[cfg(feature = "std")]
+pub mod journal;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "std")]
+pub mod overlay;
+#[cfg(feature = "std")]
+pub mod patch;
 pub mod pattern;
+#[cfg(feature = "std")]
+pub mod plan;
+#[cfg(feature = "std")]
+pub mod presets;
+#[cfg(feature = "std")]
+pub mod preview;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod record;
+#[cfg(feature = "std")]
+pub mod region;
+#[cfg(feature = "std")]
+pub mod replace;
+pub mod rule;
+#[cfg(feature = "object")]
+pub mod section;
+pub mod set;
+#[cfg(feature = "std")]
+pub mod signature;
+#[cfg(any(feature = "toml", feature = "json", feature = "yaml"))]
+pub mod spec;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod vcdiff;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod word;
+pub mod xor;
+pub mod zip;
@@ -0,0 +1,109 @@
+//! Memory-mapped swapping (mut), requires the `mmap` feature.
+//!
+//! Files are mapped and swapped in place, without any copy between a reader and a writer.
+
+use crate::pattern::{fill_slice_from, swap_slice, SwapStats};
+use crate::{BytePattern, PositionPredicate, BUFFER_SIZE};
+use memmap2::{Mmap, MmapMut};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+/// Maps file at `path` and swaps its bytes in place.
+///
+/// Returns number of bytes in the file.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::mmap::swap_mmap;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path = dir.path().join("data.bin");
+/// std::fs::write(&path, [0x41; 4]).unwrap();
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+/// assert_eq!(swap_mmap(&path, swaps).unwrap(), 4);
+/// assert_eq!(std::fs::read(&path).unwrap(), vec![0x42, 0x41, 0x42, 0x41]);
+/// ```
+pub fn swap_mmap<T, P, Q>(path: T, swaps: &[(P, Q)]) -> Result<usize, std::io::Error>
+where
+    T: AsRef<Path>,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(0); // empty files cannot be mapped
+    }
+    // safety: the file must not be modified by another process while mapped
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    swap_slice(&mut map, swaps);
+    map.flush()?;
+    Ok(map.len())
+}
+
+/// Maps file at `path` and sets every byte at positions matching `predicate` to `value`
+/// (see `io::fill`).
+///
+/// Returns number of bytes in the file.
+///
+/// ```
+/// use bswp::mmap::fill_mmap;
+/// use bswp::rule::Target;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path = dir.path().join("data.bin");
+/// std::fs::write(&path, [0x41; 4]).unwrap();
+/// assert_eq!(fill_mmap(&path, Target::Range(1..3), 0x00).unwrap(), 4);
+/// assert_eq!(std::fs::read(&path).unwrap(), vec![0x41, 0x00, 0x00, 0x41]);
+/// ```
+pub fn fill_mmap<T, Q>(path: T, predicate: Q, value: u8) -> Result<usize, std::io::Error>
+where
+    T: AsRef<Path>,
+    Q: PositionPredicate,
+{
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(0); // empty files cannot be mapped
+    }
+    // safety: the file must not be modified by another process while mapped
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    fill_slice_from(&mut map, predicate, 0, value);
+    map.flush()?;
+    Ok(map.len())
+}
+
+/// Maps file at `path` read-only and returns statistics about the swap, without modifying the file.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::mmap::swap_mmap_dry_run;
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// # let path = dir.path().join("data.bin");
+/// std::fs::write(&path, [0x41, 0x42, 0x41, 0x42]).unwrap();
+/// let swaps = &[(Pattern::new(0x42), Predicate::new())];
+/// let stats = swap_mmap_dry_run(&path, swaps).unwrap();
+/// assert_eq!(stats.bytes_modified, 2);
+/// assert_eq!(std::fs::read(&path).unwrap(), vec![0x41, 0x42, 0x41, 0x42]); // untouched
+/// ```
+pub fn swap_mmap_dry_run<T, P, Q>(path: T, swaps: &[(P, Q)]) -> Result<SwapStats, std::io::Error>
+where
+    T: AsRef<Path>,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let file = File::open(path)?;
+    let mut stats = SwapStats::default();
+    if file.metadata()?.len() == 0 {
+        return Ok(stats); // empty files cannot be mapped
+    }
+    // safety: the file must not be modified by another process while mapped
+    let map = unsafe { Mmap::map(&file)? };
+    let mut buffer = [0; BUFFER_SIZE];
+    for (index, chunk) in map.chunks(BUFFER_SIZE).enumerate() {
+        let buffer = &mut buffer[..chunk.len()];
+        buffer.copy_from_slice(chunk);
+        stats.record_swap(buffer, swaps, index * BUFFER_SIZE);
+    }
+    stats.bytes_read = map.len(); // nothing written, dry run
+    Ok(stats)
+}
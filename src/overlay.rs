@@ -0,0 +1,255 @@
+//! Overlay sidecars (pure)
+//!
+//! An overlay only holds the bytes a swap changed, as runs of new bytes at 64-bit offsets,
+//! so that tiny patches to huge files do not duplicate the whole file. Applying the
+//! overlay onto the original stream yields the swapped stream.
+//!
+//! Overlays start with the `BSWPOVL1` magic, followed by runs encoded as a little-endian
+//! `u64` offset, a little-endian `u32` length and the new bytes.
+
+use crate::journal::JournalSink;
+use crate::pattern::Change;
+use std::fmt;
+use std::io::Write;
+
+/// Magic bytes every overlay starts with.
+pub const MAGIC: &[u8] = b"BSWPOVL1";
+
+/// Largest number of bytes in a run.
+const MAX_RUN: usize = u32::MAX as usize;
+/// Size of a run header, offset and length.
+const RUN_HEADER: usize = 12;
+
+/// Error returned when an overlay cannot be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OverlayError {
+    /// the overlay does not start with `MAGIC`
+    InvalidHeader,
+    /// the overlay ends in the middle of a run
+    Truncated,
+}
+
+impl fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlayError::InvalidHeader => write!(f, "not a bswp overlay"),
+            OverlayError::Truncated => write!(f, "truncated overlay"),
+        }
+    }
+}
+
+impl std::error::Error for OverlayError {}
+
+/// Contiguous bytes changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverlayRun {
+    /// position of the first byte changed
+    pub offset: usize,
+    /// new bytes
+    pub data: Vec<u8>,
+}
+
+impl OverlayRun {
+    /// Returns the position following the last byte changed.
+    pub fn end(&self) -> usize {
+        self.offset + self.data.len()
+    }
+}
+
+/// Parsed overlay.
+///
+/// ```
+/// use bswp::overlay::Overlay;
+/// use bswp::pattern::{diff_iter, Pattern, Predicate};
+///
+/// let source = [0x41; 8];
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(2).with_limit(3))];
+/// let overlay = Overlay::from_changes(diff_iter(&source, swaps));
+/// assert_eq!(overlay.runs.len(), 1);
+///
+/// let overlay = Overlay::parse(&overlay.to_bytes()).unwrap();
+/// let mut buffer = source;
+/// overlay.apply(&mut buffer, 0);
+/// assert_eq!(buffer, [0x41, 0x41, 0x42, 0x42, 0x42, 0x41, 0x41, 0x41]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Overlay {
+    /// runs, in order
+    pub runs: Vec<OverlayRun>,
+}
+
+impl Overlay {
+    /// Returns an overlay of `changes`, contiguous changes being merged into runs.
+    pub fn from_changes<I: IntoIterator<Item = Change>>(changes: I) -> Self {
+        let mut runs: Vec<OverlayRun> = Vec::new();
+        for change in changes {
+            match runs.last_mut() {
+                Some(run) if run.end() == change.offset && run.data.len() < MAX_RUN => {
+                    run.data.push(change.new)
+                }
+                _ => runs.push(OverlayRun {
+                    offset: change.offset,
+                    data: vec![change.new],
+                }),
+            }
+        }
+        Overlay { runs }
+    }
+
+    /// Parses an overlay.
+    pub fn parse(overlay: &[u8]) -> Result<Self, OverlayError> {
+        let mut rest = overlay
+            .strip_prefix(MAGIC)
+            .ok_or(OverlayError::InvalidHeader)?;
+        let mut runs = Vec::new();
+        while !rest.is_empty() {
+            if rest.len() < RUN_HEADER {
+                return Err(OverlayError::Truncated);
+            }
+            let (header, data) = rest.split_at(RUN_HEADER);
+            let mut offset = [0; 8];
+            offset.copy_from_slice(&header[..8]);
+            let mut len = [0; 4];
+            len.copy_from_slice(&header[8..]);
+            let offset = u64::from_le_bytes(offset) as usize;
+            let len = u32::from_le_bytes(len) as usize;
+            if data.len() < len {
+                return Err(OverlayError::Truncated);
+            }
+            runs.push(OverlayRun {
+                offset,
+                data: data[..len].to_vec(),
+            });
+            rest = &data[len..];
+        }
+        Ok(Overlay { runs })
+    }
+
+    /// Returns the encoded overlay.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut overlay = MAGIC.to_vec();
+        for run in &self.runs {
+            encode_run(&mut overlay, run.offset, &run.data);
+        }
+        overlay
+    }
+
+    /// Writes the runs onto `buffer`, `buffer[0]` being the byte at `position`.
+    ///
+    /// Later runs overwrite earlier ones, bytes outside `buffer` are skipped.
+    pub fn apply(&self, buffer: &mut [u8], position: usize) {
+        let end = position + buffer.len();
+        for run in &self.runs {
+            let from = run.offset.max(position);
+            let to = run.end().min(end);
+            if from < to {
+                buffer[from - position..to - position]
+                    .copy_from_slice(&run.data[from - run.offset..to - run.offset]);
+            }
+        }
+    }
+}
+
+/// Appends a run to `overlay`.
+fn encode_run(overlay: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    overlay.extend_from_slice(&(offset as u64).to_le_bytes());
+    overlay.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    overlay.extend_from_slice(data);
+}
+
+/// Overlay written to `writer` as changes are recorded, e.g. by `io::SwapIoOptions::with_overlay`.
+///
+/// Changes must be recorded in increasing offset order, the last run is written by
+/// `finish`.
+pub struct OverlayWriter<W> {
+    writer: W,
+    /// run not yet written
+    run: Option<OverlayRun>,
+}
+
+impl<W: Write> OverlayWriter<W> {
+    /// Creates an overlay writing to `writer`, writing the magic bytes.
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(OverlayWriter { writer, run: None })
+    }
+
+    /// Writes the last run and returns the underlying writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        if let Some(run) = self.run.take() {
+            self.write_run(&run)?;
+        }
+        Ok(self.writer)
+    }
+
+    fn write_run(&mut self, run: &OverlayRun) -> std::io::Result<()> {
+        let mut encoded = Vec::with_capacity(RUN_HEADER + run.data.len());
+        encode_run(&mut encoded, run.offset, &run.data);
+        self.writer.write_all(&encoded)
+    }
+}
+
+impl<W: Write> JournalSink for OverlayWriter<W> {
+    fn record(&mut self, changes: &[Change]) -> std::io::Result<()> {
+        for change in changes {
+            match &mut self.run {
+                Some(run) if run.end() == change.offset && run.data.len() < MAX_RUN => {
+                    run.data.push(change.new)
+                }
+                run => {
+                    let previous = run.replace(OverlayRun {
+                        offset: change.offset,
+                        data: vec![change.new],
+                    });
+                    if let Some(previous) = previous {
+                        self.write_run(&previous)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::JournalSink;
+    use crate::overlay::{Overlay, OverlayError, OverlayWriter, MAGIC};
+    use crate::pattern::{diff_iter, swap_slice, Change, Pattern, Predicate};
+
+    #[test]
+    fn test_overlay_round_trip() {
+        let source: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+        let swaps = &[
+            (Pattern::new(0x00), Predicate::new().with_periodicity(3)),
+            (Pattern::new(0xFF), Predicate::new().with_offset(9_990)),
+        ];
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        let changes: Vec<Change> = diff_iter(&source, swaps).collect();
+
+        let overlay = Overlay::from_changes(changes.iter().copied());
+        let mut writer = OverlayWriter::new(Vec::new()).unwrap();
+        for chunk in changes.chunks(100) {
+            writer.record(chunk).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, overlay.to_bytes());
+        assert_eq!(overlay.runs.last().unwrap().data, vec![0xFF; 10]);
+
+        let parsed = Overlay::parse(&bytes).unwrap();
+        let mut buffer = source.clone();
+        for (index, chunk) in buffer.chunks_mut(333).enumerate() {
+            parsed.apply(chunk, index * 333);
+        }
+        assert_eq!(buffer, expected);
+
+        assert_eq!(Overlay::parse(b"PATCH"), Err(OverlayError::InvalidHeader));
+        assert_eq!(
+            Overlay::parse(&bytes[..bytes.len() - 1]),
+            Err(OverlayError::Truncated)
+        );
+        assert_eq!(Overlay::parse(MAGIC), Ok(Overlay::default()));
+    }
+}
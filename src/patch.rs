@@ -0,0 +1,145 @@
+//! Typed patch templates (pure)
+//!
+//! A `Template` declares typed writes at fixed offsets (integers in a given byte order,
+//! strings) and compiles them into swap rules, instead of decomposing values into
+//! per-byte patterns by hand.
+
+use crate::pattern::Pattern;
+use crate::rule::{Op, SwapRule, Target};
+use std::collections::BTreeMap;
+
+/// Typed writes compiled into swap rules.
+///
+/// Later writes overwrite earlier ones where they overlap.
+///
+/// ```
+/// use bswp::patch::Template;
+/// use bswp::pattern::swap_slice;
+///
+/// let template = Template::new()
+///     .u16_le(0, 0xBEEF)
+///     .u32_be(2, 0x0102_0304)
+///     .cstr(6, "hi");
+/// let mut buffer = [0xAA; 10];
+/// swap_slice(&mut buffer, &template.compile());
+/// assert_eq!(buffer, [0xEF, 0xBE, 0x01, 0x02, 0x03, 0x04, b'h', b'i', 0x00, 0xAA]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Template {
+    /// byte written at every position
+    bytes: BTreeMap<usize, u8>,
+}
+
+impl Template {
+    /// Creates an empty `Template`.
+    pub fn new() -> Self {
+        Template::default()
+    }
+
+    /// Writes `bytes` from `offset`.
+    pub fn bytes(mut self, offset: usize, bytes: impl AsRef<[u8]>) -> Self {
+        for (index, &byte) in bytes.as_ref().iter().enumerate() {
+            self.bytes.insert(offset + index, byte);
+        }
+        self
+    }
+
+    /// Writes `value` at `offset`.
+    pub fn u8(self, offset: usize, value: u8) -> Self {
+        self.bytes(offset, [value])
+    }
+
+    /// Writes `value` little-endian from `offset`.
+    pub fn u16_le(self, offset: usize, value: u16) -> Self {
+        self.bytes(offset, value.to_le_bytes())
+    }
+
+    /// Writes `value` big-endian from `offset`.
+    pub fn u16_be(self, offset: usize, value: u16) -> Self {
+        self.bytes(offset, value.to_be_bytes())
+    }
+
+    /// Writes `value` little-endian from `offset`.
+    pub fn u32_le(self, offset: usize, value: u32) -> Self {
+        self.bytes(offset, value.to_le_bytes())
+    }
+
+    /// Writes `value` big-endian from `offset`.
+    pub fn u32_be(self, offset: usize, value: u32) -> Self {
+        self.bytes(offset, value.to_be_bytes())
+    }
+
+    /// Writes `value` little-endian from `offset`.
+    pub fn u64_le(self, offset: usize, value: u64) -> Self {
+        self.bytes(offset, value.to_le_bytes())
+    }
+
+    /// Writes `value` big-endian from `offset`.
+    pub fn u64_be(self, offset: usize, value: u64) -> Self {
+        self.bytes(offset, value.to_be_bytes())
+    }
+
+    /// Writes the UTF-8 bytes of `text` followed by a NUL byte from `offset`.
+    pub fn cstr(self, offset: usize, text: &str) -> Self {
+        let len = text.len();
+        self.bytes(offset, text).u8(offset + len, 0)
+    }
+
+    /// Returns the number of bytes written.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns `true` if the template writes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns swap rules making the writes, one per distinct byte value.
+    pub fn compile(&self) -> Vec<SwapRule> {
+        let mut positions: BTreeMap<u8, Vec<usize>> = BTreeMap::new();
+        for (&position, &value) in &self.bytes {
+            positions.entry(value).or_default().push(position);
+        }
+        positions
+            .into_iter()
+            .map(|(value, positions)| (Op::Set(Pattern::new(value)), Target::Offsets(positions)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::swap_io;
+    use crate::patch::Template;
+    use crate::pattern::swap_slice;
+
+    #[test]
+    fn test_template_writes() {
+        let template = Template::new()
+            .u64_le(0, 0x0807_0605_0403_0201)
+            .u64_be(8, 0x0102_0304_0506_0708)
+            .u16_be(16, 0xFFFF)
+            .u32_le(18, 0)
+            .cstr(22, "")
+            .bytes(6, [0xAA, 0xBB]) // overwrites
+            .u8(40_000, 0x42);
+        assert_eq!(template.len(), 24);
+        let rules = template.compile();
+        assert_eq!(rules.len(), 13);
+
+        let mut expected = vec![0x55; 40_001];
+        expected[..23].copy_from_slice(&[
+            1, 2, 3, 4, 5, 6, 0xAA, 0xBB, 1, 2, 3, 4, 5, 6, 7, 8, 0xFF, 0xFF, 0, 0, 0, 0, 0,
+        ]);
+        expected[40_000] = 0x42;
+        let mut buffer = vec![0x55; 40_001];
+        swap_slice(&mut buffer, &rules);
+        assert_eq!(buffer, expected);
+
+        let mut writer: Vec<u8> = Vec::new();
+        swap_io(&mut &vec![0x55; 40_001][..], &mut writer, &rules).unwrap();
+        assert_eq!(writer, expected);
+        assert!(Template::new().is_empty());
+    }
+}
@@ -1,11 +1,22 @@
 //! Pattern, Predicate and iterator utils (pure)
 
+#[cfg(feature = "rayon")]
+use crate::BUFFER_SIZE;
 use crate::{BytePattern, PositionPredicate};
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
 
 /// Stores a predicate on byte position.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub struct Predicate {
     /// only match every `periodicity` bytes once the `offset` is reached.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "non_zero_periodicity"))]
     pub periodicity: usize,
     /// skip `offset` first bytes
     /// only bytes with position >= offset will match
@@ -21,12 +32,36 @@ pub struct Predicate {
 /// BytePattern
 /// Given a target byte, the pattern will set bits to corresponding bits from `value`
 /// according to `mask`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub struct Pattern {
     pub value: u8,
+    #[cfg_attr(feature = "serde", serde(default = "full_mask"))]
     pub mask: u8,
 }
 
+#[cfg(feature = "serde")]
+fn full_mask() -> u8 {
+    0xFF
+}
+
+/// Rejects a periodicity of `0`, which would divide by zero in `Predicate::eval`.
+#[cfg(feature = "serde")]
+fn non_zero_periodicity<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{Deserialize, Error, Unexpected};
+    match usize::deserialize(deserializer)? {
+        0 => Err(D::Error::invalid_value(
+            Unexpected::Unsigned(0),
+            &"a periodicity of at least 1",
+        )),
+        periodicity => Ok(periodicity),
+    }
+}
+
 impl Pattern {
     /// Creates a new `BytePattern` with provided `value`.
     ///
@@ -60,6 +95,15 @@ impl BytePattern for Pattern {
     fn eval(&self, value: u8) -> u8 {
         (self.mask & self.value) | (!self.mask & value)
     }
+
+    /// Returns `Some(value)` if `mask` is `0xFF`.
+    fn constant(&self) -> Option<u8> {
+        if self.mask == 0xFF {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for Predicate {
@@ -123,6 +167,38 @@ impl Predicate {
         self.limit = None;
         self
     }
+
+    /// Returns matching positions in `start..end`, in increasing order.
+    ///
+    /// Computed in closed form, no position is evaluated.
+    ///
+    /// ```
+    /// # use bswp::pattern::Predicate;
+    /// let odd_bytes = Predicate::new().with_periodicity(2).with_offset(1).with_limit(3);
+    /// let positions: Vec<usize> = odd_bytes.positions(2..100).collect();
+    /// assert_eq!(positions, vec![3, 5]);
+    /// ```
+    pub fn positions(
+        &self,
+        range: core::ops::Range<usize>,
+    ) -> core::iter::StepBy<core::ops::Range<usize>> {
+        let periodicity = self.periodicity.max(1);
+        let first = if range.start <= self.offset {
+            self.offset
+        } else {
+            let skipped = (range.start - self.offset).div_ceil(periodicity);
+            self.offset
+                .saturating_add(skipped.saturating_mul(periodicity))
+        };
+        let last = match self.limit {
+            Some(limit) => self
+                .offset
+                .saturating_add(limit.saturating_mul(periodicity))
+                .min(range.end),
+            None => range.end,
+        };
+        (first..last.max(first)).step_by(periodicity)
+    }
 }
 
 impl PositionPredicate for Predicate {
@@ -148,6 +224,282 @@ impl PositionPredicate for Predicate {
             })
             && ((position - self.offset) % self.periodicity) == 0
     }
+
+    /// ```
+    /// # use bswp::pattern::Predicate;
+    /// # use bswp::PositionPredicate;
+    /// let predicate = Predicate::new().with_periodicity(4).with_offset(1).with_limit(2);
+    /// assert_eq!(predicate.next_match(0), Some(1));
+    /// assert_eq!(predicate.next_match(2), Some(5));
+    /// assert_eq!(predicate.next_match(6), None);
+    /// ```
+    fn next_match(&self, position: usize) -> Option<usize> {
+        self.positions(position..usize::MAX).next()
+    }
+
+    fn as_periodic(&self) -> Option<Predicate> {
+        if self.periodicity > 0 {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+}
+
+/// Predicate on the distance to the end of a stream of `len` bytes.
+///
+/// The inner predicate is evaluated on `len - 1 - position`: the last byte is at
+/// distance `0`. Positions `>= len` never match.
+///
+/// ```
+/// use bswp::pattern::{FromEnd, Predicate};
+/// use bswp::PositionPredicate;
+///
+/// let last_two_bytes = FromEnd::new(Predicate::new().with_limit(2), 10);
+/// let matching: Vec<usize> = (0..12).filter(|&position| last_two_bytes.eval(position)).collect();
+/// assert_eq!(matching, vec![8, 9]);
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FromEnd<Q> {
+    predicate: Q,
+    len: usize,
+}
+
+impl<Q: PositionPredicate> FromEnd<Q> {
+    /// Creates a new `FromEnd` evaluating `predicate` from the end of a stream of `len` bytes.
+    pub fn new(predicate: Q, len: usize) -> Self {
+        FromEnd { predicate, len }
+    }
+
+    /// Returns the stream length.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the stream is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<Q: PositionPredicate> PositionPredicate for FromEnd<Q> {
+    fn eval(&self, position: usize) -> bool {
+        position < self.len && self.predicate.eval(self.len - 1 - position)
+    }
+
+    /// Computed in closed form from the last match of the inner predicate at a distance
+    /// `<= len - 1 - position`, if the inner predicate is periodic (see `as_periodic`).
+    ///
+    /// ```
+    /// use bswp::pattern::{FromEnd, Predicate};
+    /// use bswp::PositionPredicate;
+    ///
+    /// let last_two_bytes = FromEnd::new(Predicate::new().with_limit(2), 10);
+    /// assert_eq!(last_two_bytes.next_match(0), Some(8));
+    /// assert_eq!(last_two_bytes.next_match(9), Some(9));
+    /// assert_eq!(last_two_bytes.next_match(10), None);
+    /// ```
+    fn next_match(&self, position: usize) -> Option<usize> {
+        if position >= self.len {
+            return None;
+        }
+        let distance = self.len - 1 - position;
+        let periodic = match self.predicate.as_periodic() {
+            Some(periodic) => periodic,
+            None => return Some(position), // any position may match
+        };
+        if distance < periodic.offset || periodic.limit == Some(0) {
+            return None;
+        }
+        let mut index = (distance - periodic.offset) / periodic.periodicity;
+        if let Some(limit) = periodic.limit {
+            index = index.min(limit - 1);
+        }
+        Some(self.len - 1 - (periodic.offset + index * periodic.periodicity))
+    }
+}
+
+/// Permutes bytes within every group of `lanes.len()` bytes: the byte at index `lane`
+/// of a group is taken from index `lanes[lane]` of the same group.
+///
+/// Groups are aligned on positions multiple of their size and only moved if wholly
+/// available, see `BytePattern::group_size`.
+///
+/// ```
+/// use bswp::pattern::{swap_iter, PermutePattern, Predicate};
+///
+/// // middle-endian 32-bit words: 0123 -> 2301
+/// let swaps = &[(PermutePattern::new(vec![2, 3, 0, 1]).unwrap(), Predicate::new())];
+/// let source: [u8; 9] = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+/// let swapped: Vec<u8> = swap_iter(&source, swaps).collect();
+/// assert_eq!(swapped, vec![2, 3, 0, 1, 6, 7, 4, 5, 8]);
+///
+/// assert!(PermutePattern::new(vec![0, 0]).is_none()); // not a permutation
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PermutePattern {
+    lanes: Vec<usize>,
+}
+
+impl PermutePattern {
+    /// Creates a `PermutePattern`, returns `None` if `lanes` is empty or not a permutation
+    /// of `0..lanes.len()`.
+    pub fn new(lanes: impl Into<Vec<usize>>) -> Option<Self> {
+        let lanes = lanes.into();
+        let mut seen = vec![false; lanes.len()];
+        for &lane in &lanes {
+            if core::mem::replace(seen.get_mut(lane)?, true) {
+                return None;
+            }
+        }
+        Some(PermutePattern { lanes }).filter(|permute| !permute.lanes.is_empty())
+    }
+
+    /// Returns the source index of every byte of a group.
+    pub fn lanes(&self) -> &[usize] {
+        &self.lanes
+    }
+
+    /// Returns the permutation undoing this one.
+    ///
+    /// ```
+    /// use bswp::pattern::PermutePattern;
+    ///
+    /// let rotate = PermutePattern::new(vec![1, 2, 0]).unwrap();
+    /// assert_eq!(rotate.inverse().lanes(), &[2, 0, 1]);
+    /// ```
+    pub fn inverse(&self) -> Self {
+        let mut lanes = vec![0; self.lanes.len()];
+        for (lane, &source) in self.lanes.iter().enumerate() {
+            lanes[source] = lane;
+        }
+        PermutePattern { lanes }
+    }
+}
+
+impl BytePattern for PermutePattern {
+    /// Returns `value`: bytes are moved, not modified.
+    fn eval(&self, value: u8) -> u8 {
+        value
+    }
+
+    fn group_size(&self) -> usize {
+        self.lanes.len()
+    }
+
+    fn source_lane(&self, lane: usize) -> usize {
+        self.lanes[lane]
+    }
+}
+
+/// Returns the least common multiple of the group sizes of `swaps` (see `BytePattern::group_size`).
+///
+/// Chunks of a stream starting and ending on a multiple of the group size can be swapped
+/// independently.
+pub(crate) fn group_size<P: BytePattern, Q>(swaps: &[(P, Q)]) -> usize {
+    swaps.iter().fold(1, |size, (pattern, _)| {
+        let group = pattern.group_size();
+        size / gcd(size, group) * group
+    })
+}
+
+pub(crate) fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Iterator on swapped bytes, swapping windows of whole groups when patterns move bytes.
+struct Swapped<'a, I, P, Q> {
+    bytes: I,
+    swaps: &'a [(P, Q)],
+    /// position of the next byte from `bytes`
+    position: usize,
+    /// multiple of the group size, `1` if no pattern moves bytes
+    window_size: usize,
+    window: Vec<u8>,
+    /// index of the next byte from `window`
+    index: usize,
+}
+
+impl<'a, I, P, Q> Swapped<'a, I, P, Q>
+where
+    P: BytePattern,
+{
+    fn new(bytes: I, swaps: &'a [(P, Q)], start: usize) -> Self {
+        let size = group_size(swaps);
+        let window_size = if size == 1 {
+            1
+        } else {
+            64usize.div_ceil(size) * size
+        };
+        Swapped {
+            bytes,
+            swaps,
+            position: start,
+            window_size,
+            window: Vec::new(),
+            index: 0,
+        }
+    }
+}
+
+impl<I, P, Q> Iterator for Swapped<'_, I, P, Q>
+where
+    I: Iterator<Item = u8>,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.window_size == 1 {
+            let value = swap_byte(self.bytes.next()?, self.position, self.swaps);
+            self.position += 1;
+            return Some(value);
+        }
+        if self.index == self.window.len() {
+            // windows end on group boundaries
+            let len = self.window_size - self.position % self.window_size;
+            self.window.clear();
+            self.window.extend(self.bytes.by_ref().take(len));
+            swap_slice_from(&mut self.window, self.swaps, self.position);
+            self.position += self.window.len();
+            self.index = 0;
+        }
+        let value = self.window.get(self.index).copied()?;
+        self.index += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = self.window.len() - self.index;
+        let (lower, upper) = self.bytes.size_hint();
+        (
+            lower.saturating_add(pending),
+            upper.and_then(|upper| upper.checked_add(pending)),
+        )
+    }
+}
+
+/// Returns `value` with every matching swap applied, `value` being at `position`.
+///
+/// Patterns moving bytes (see `BytePattern::group_size`) leave `value` in place.
+fn swap_byte<P: BytePattern, Q: PositionPredicate>(
+    value: u8,
+    position: usize,
+    swaps: &[(P, Q)],
+) -> u8 {
+    swaps.iter().fold(value, |value, (pattern, predicate)| {
+        if predicate.eval(position) {
+            pattern.eval_at(position, value)
+        } else {
+            value
+        }
+    })
 }
 
 /// Returns an iterator on swapped bytes from `source`.
@@ -167,20 +519,590 @@ pub fn swap_iter<'a, P: BytePattern, Q: PositionPredicate>(
     source: &'a [u8],
     swaps: &'a [(P, Q)],
 ) -> impl Iterator<Item = u8> + 'a {
-    source.iter().enumerate().map(move |(position, &e)| {
-        swaps.iter().fold(e, |value, (pattern, predicate)| {
-            if predicate.eval(position) {
-                pattern.eval(value)
+    swap_iter_from(source, swaps, 0)
+}
+
+/// Returns an iterator on swapped bytes from `source`, `source[0]` being at position `start`.
+///
+/// Useful for processing data in chunks: predicates are evaluated against the position
+/// in the whole stream, not in the chunk.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_iter_from};
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1);
+/// let pattern = Pattern::new(0x42).with_mask(0xFF);
+/// let swaps = &[(pattern, predicate)];
+///
+/// let source: [u8; 4] = [0x41, 0x41, 0x41, 0x41];
+/// let swapped: Vec<u8> = swap_iter_from(&source, swaps, 3).collect(); // positions 3, 4, 5, 6
+/// assert_eq!(swapped, vec!(0x42, 0x41, 0x42, 0x41));
+/// ```
+pub fn swap_iter_from<'a, P: BytePattern, Q: PositionPredicate>(
+    source: &'a [u8],
+    swaps: &'a [(P, Q)],
+    start: usize,
+) -> impl Iterator<Item = u8> + 'a {
+    Swapped::new(source.iter().copied(), swaps, start)
+}
+
+/// Returns an iterator on swapped bytes from `sources` as if they were concatenated.
+///
+/// Predicates are evaluated against the position in the concatenated stream.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_iter_chained};
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1);
+/// let pattern = Pattern::new(0x42).with_mask(0xFF);
+/// let swaps = &[(pattern, predicate)];
+///
+/// let segments: [&[u8]; 3] = [&[0x41], &[0x41, 0x41], &[0x41]];
+/// let swapped: Vec<u8> = swap_iter_chained(&segments, swaps).collect();
+/// assert_eq!(swapped, vec!(0x41, 0x42, 0x41, 0x42));
+///
+/// let segments: Vec<Vec<u8>> = vec![vec![0x41, 0x41, 0x41], vec![0x41]];
+/// let swapped: Vec<u8> = swap_iter_chained(&segments, swaps).collect();
+/// assert_eq!(swapped, vec!(0x41, 0x42, 0x41, 0x42));
+/// ```
+pub fn swap_iter_chained<'a, I, S, P, Q>(
+    sources: I,
+    swaps: &'a [(P, Q)],
+) -> impl Iterator<Item = u8> + 'a
+where
+    I: IntoIterator<Item = &'a S>,
+    I::IntoIter: 'a,
+    S: AsRef<[u8]> + ?Sized + 'a,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let bytes = sources
+        .into_iter()
+        .flat_map(|source| source.as_ref().iter().copied());
+    Swapped::new(bytes, swaps, 0)
+}
+
+/// Returns an iterator on swapped bytes from a fallible `source`, e.g. `Read::bytes()`.
+///
+/// Patterns are applied to `Ok` items, errors are forwarded untouched.
+/// Errors do not count as bytes: position is only incremented on `Ok` items.
+/// Bytes are swapped one at a time: patterns moving bytes (see `BytePattern::group_size`)
+/// leave them in place.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_try_iter};
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1);
+/// let pattern = Pattern::new(0x42).with_mask(0xFF);
+/// let swaps = &[(pattern, predicate)];
+///
+/// let source = vec![Ok(0x41), Err("oops"), Ok(0x41), Ok(0x41)];
+/// let swapped: Vec<Result<u8, &str>> = swap_try_iter(source, swaps).collect();
+/// assert_eq!(swapped, vec!(Ok(0x41), Err("oops"), Ok(0x42), Ok(0x41)));
+///
+/// let bytes = std::io::Cursor::new(vec![0x41, 0x41]);
+/// # use std::io::Read;
+/// let swapped: Result<Vec<u8>, std::io::Error> = swap_try_iter(bytes.bytes(), swaps).collect();
+/// assert_eq!(swapped.unwrap(), vec!(0x41, 0x42));
+/// ```
+pub fn swap_try_iter<'a, I, E, P, Q>(
+    source: I,
+    swaps: &'a [(P, Q)],
+) -> impl Iterator<Item = Result<u8, E>> + 'a
+where
+    I: IntoIterator<Item = Result<u8, E>>,
+    I::IntoIter: 'a,
+    P: BytePattern,
+    Q: PositionPredicate,
+{
+    let mut position: usize = 0;
+    source.into_iter().map(move |item| {
+        item.map(|value| {
+            let swapped = swap_byte(value, position, swaps);
+            position += 1;
+            swapped
+        })
+    })
+}
+
+/// Swaps bytes from `buffer` in place.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_slice};
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1);
+/// let pattern = Pattern::new(0x42).with_mask(0xFF);
+/// let swaps = &[(pattern, predicate)];
+///
+/// let mut buffer: [u8; 4] = [0x41, 0x41, 0x41, 0x41];
+/// swap_slice(&mut buffer, swaps);
+/// assert_eq!(buffer, [0x41, 0x42, 0x41, 0x42]);
+/// ```
+pub fn swap_slice<P: BytePattern, Q: PositionPredicate>(buffer: &mut [u8], swaps: &[(P, Q)]) {
+    swap_slice_from(buffer, swaps, 0)
+}
+
+/// Swaps bytes from `buffer` in place, `buffer[0]` being at position `start`.
+///
+/// Patterns moving bytes (see `BytePattern::group_size`) only swap groups lying wholly
+/// in `buffer`: chunks of a stream must start and end on group boundaries.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_slice_from};
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1);
+/// let pattern = Pattern::new(0x42).with_mask(0xFF);
+/// let swaps = &[(pattern, predicate)];
+///
+/// let mut buffer: [u8; 4] = [0x41, 0x41, 0x41, 0x41];
+/// swap_slice_from(&mut buffer, swaps, 3); // positions 3, 4, 5, 6
+/// assert_eq!(buffer, [0x42, 0x41, 0x42, 0x41]);
+/// ```
+pub fn swap_slice_from<P: BytePattern, Q: PositionPredicate>(
+    buffer: &mut [u8],
+    swaps: &[(P, Q)],
+    start: usize,
+) {
+    // rules are applied one after the other on the whole buffer, which yields
+    // the same result as applying every rule byte per byte.
+    for (pattern, predicate) in swaps {
+        let size = pattern.group_size();
+        if size > 1 {
+            swap_groups(buffer, pattern, predicate, start, size);
+            continue;
+        }
+        match predicate.as_periodic() {
+            Some(periodic) => swap_periodic(buffer, pattern, &periodic, start),
+            None => {
+                for (index, item) in buffer.iter_mut().enumerate() {
+                    if predicate.eval(start + index) {
+                        *item = pattern.eval_at(start + index, *item);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves bytes within every whole group of `size` bytes, at positions matching `predicate`.
+fn swap_groups<P: BytePattern, Q: PositionPredicate>(
+    buffer: &mut [u8],
+    pattern: &P,
+    predicate: &Q,
+    start: usize,
+    size: usize,
+) {
+    let head = (size - start % size) % size;
+    if head >= buffer.len() {
+        return;
+    }
+    let mut group = vec![0u8; size];
+    for (index, chunk) in buffer[head..].chunks_exact_mut(size).enumerate() {
+        let first = start + head + index * size;
+        group.copy_from_slice(chunk);
+        for (lane, item) in chunk.iter_mut().enumerate() {
+            if predicate.eval(first + lane) {
+                *item = pattern.eval_at(first + lane, group[pattern.source_lane(lane)]);
+            }
+        }
+    }
+}
+
+/// Strided fast path: only visits positions matching `periodic`.
+fn swap_periodic<P: BytePattern>(
+    buffer: &mut [u8],
+    pattern: &P,
+    periodic: &Predicate,
+    start: usize,
+) {
+    let mut positions = periodic.positions(start..start.saturating_add(buffer.len()));
+    let (first, last) = match (positions.next(), positions.next_back()) {
+        (Some(first), None) => (first - start, first - start),
+        (Some(first), Some(last)) => (first - start, last - start),
+        _ => return,
+    };
+    let matched = &mut buffer[first..=last];
+    match (pattern.constant(), periodic.periodicity) {
+        (Some(value), 1) => {
+            matched.fill(value); // memset
+        }
+        (Some(value), periodicity) => {
+            for item in matched.iter_mut().step_by(periodicity) {
+                *item = value;
+            }
+        }
+        (None, periodicity) => {
+            let first = start + first;
+            for (index, item) in matched.iter_mut().step_by(periodicity).enumerate() {
+                *item = pattern.eval_at(first + index * periodicity, *item);
+            }
+        }
+    }
+}
+
+/// Returns swapped bytes from `source`, borrowing `source` if no byte changed.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use bswp::pattern::{Predicate, Pattern, swap_cow};
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(2))];
+///
+/// let untouched: [u8; 4] = [0x41, 0x41, 0x42, 0x42];
+/// assert!(matches!(swap_cow(&untouched, swaps), Cow::Borrowed(_)));
+///
+/// let source: [u8; 4] = [0x41, 0x41, 0x41, 0x41];
+/// let swapped = swap_cow(&source, swaps);
+/// assert!(matches!(swapped, Cow::Owned(_)));
+/// assert_eq!(&*swapped, &[0x41, 0x41, 0x42, 0x42]);
+/// ```
+pub fn swap_cow<'a, P: BytePattern, Q: PositionPredicate>(
+    source: &'a [u8],
+    swaps: &[(P, Q)],
+) -> Cow<'a, [u8]> {
+    let changed = swap_iter(source, swaps)
+        .zip(source)
+        .any(|(swapped, &value)| swapped != value);
+    if changed {
+        let mut swapped = source.to_vec();
+        swap_slice(&mut swapped, swaps);
+        Cow::Owned(swapped)
+    } else {
+        Cow::Borrowed(source)
+    }
+}
+
+/// Sets every byte of `buffer` at positions matching `predicate` to `value`,
+/// `buffer[0]` being at position `start`.
+///
+/// Same as swapping with a constant `Pattern`, contiguous matched positions being
+/// filled at once.
+///
+/// ```
+/// use bswp::pattern::fill_slice_from;
+/// use bswp::rule::Target;
+///
+/// let mut buffer = [0x41; 6];
+/// fill_slice_from(&mut buffer, Target::Range(4..7), 2, 0x00); // positions 2 to 7
+/// assert_eq!(buffer, [0x41, 0x41, 0x00, 0x00, 0x00, 0x41]);
+/// ```
+pub fn fill_slice_from<Q: PositionPredicate>(
+    buffer: &mut [u8],
+    predicate: Q,
+    start: usize,
+    value: u8,
+) {
+    for run in matching_runs(&predicate, start..start + buffer.len()) {
+        buffer[run.start - start..run.end - start].fill(value); // memset
+    }
+}
+
+/// Returns the runs of contiguous positions in `range` matching `predicate`,
+/// in increasing order.
+pub(crate) fn matching_runs<'a, Q: PositionPredicate>(
+    predicate: &'a Q,
+    range: Range<usize>,
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    let mut position = range.start;
+    core::iter::from_fn(move || {
+        let first = loop {
+            let first = predicate
+                .next_match(position)
+                .filter(|&first| first < range.end)?;
+            if predicate.eval(first) {
+                break first;
+            }
+            position = first + 1; // may match only
+        };
+        let mut last = first + 1;
+        while last < range.end && predicate.eval(last) {
+            last += 1;
+        }
+        position = last;
+        Some(first..last)
+    })
+}
+
+/// Byte modified by a swap.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// byte position
+    pub offset: usize,
+    /// value before swap
+    pub old: u8,
+    /// value after swap
+    pub new: u8,
+}
+
+/// Returns an iterator on bytes from `source` modified by `swaps`.
+///
+/// ```
+/// use bswp::pattern::{Change, Predicate, Pattern, diff_iter};
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))];
+///
+/// let source: [u8; 4] = [0x41, 0x41, 0x42, 0x41];
+/// let changes: Vec<Change> = diff_iter(&source, swaps).collect();
+/// assert_eq!(changes, vec![Change { offset: 0, old: 0x41, new: 0x42 }]);
+/// ```
+pub fn diff_iter<'a, P: BytePattern, Q: PositionPredicate>(
+    source: &'a [u8],
+    swaps: &'a [(P, Q)],
+) -> impl Iterator<Item = Change> + 'a {
+    diff_iter_from(source, swaps, 0)
+}
+
+/// Returns an iterator on bytes from `source` modified by `swaps`, `source[0]` being at position `start`.
+pub fn diff_iter_from<'a, P: BytePattern, Q: PositionPredicate>(
+    source: &'a [u8],
+    swaps: &'a [(P, Q)],
+    start: usize,
+) -> impl Iterator<Item = Change> + 'a {
+    source
+        .iter()
+        .zip(swap_iter_from(source, swaps, start))
+        .enumerate()
+        .filter_map(move |(index, (&old, new))| {
+            if new != old {
+                Some(Change {
+                    offset: start + index,
+                    old,
+                    new,
+                })
             } else {
-                value
+                None
             }
         })
+}
+
+/// Changes a swap would make.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangeReport {
+    /// number of bytes read
+    pub bytes_read: usize,
+    /// modified bytes, by increasing offset
+    pub changes: Vec<Change>,
+}
+
+/// Statistics about a swap.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SwapStats {
+    /// number of bytes read
+    pub bytes_read: usize,
+    /// number of bytes written
+    pub bytes_written: usize,
+    /// number of bytes whose value changed
+    pub bytes_modified: usize,
+    /// `rule_hits[i]` is the number of positions matched by rule `i`
+    pub rule_hits: Vec<usize>,
+}
+
+impl SwapStats {
+    /// Swaps `buffer` in place, `buffer[0]` being at position `start`, and records
+    /// the bytes modified and the rule hits in `self`.
+    ///
+    /// `bytes_read` and `bytes_written` are left to the caller, which may only swap
+    /// part of the bytes it copies.
+    pub(crate) fn record_swap<P: BytePattern, Q: PositionPredicate>(
+        &mut self,
+        buffer: &mut [u8],
+        swaps: &[(P, Q)],
+        start: usize,
+    ) {
+        self.record_hits(swaps, 0, start..start.saturating_add(buffer.len()));
+        let original = buffer.to_vec();
+        swap_slice_from(buffer, swaps, start);
+        self.record_modified(&original, buffer);
+    }
+
+    /// Records the positions of `range` matched by each rule of `swaps`, `swaps[0]`
+    /// being rule `first`.
+    pub(crate) fn record_hits<P, Q: PositionPredicate>(
+        &mut self,
+        swaps: &[(P, Q)],
+        first: usize,
+        range: Range<usize>,
+    ) {
+        if self.rule_hits.len() < first + swaps.len() {
+            self.rule_hits.resize(first + swaps.len(), 0);
+        }
+        for ((_, predicate), hits) in swaps.iter().zip(self.rule_hits[first..].iter_mut()) {
+            *hits += match predicate.as_periodic() {
+                Some(periodic) => periodic.positions(range.clone()).len(),
+                None => range.clone().filter(|&p| predicate.eval(p)).count(),
+            };
+        }
+    }
+
+    /// Records the bytes of `swapped` differing from `original`.
+    pub(crate) fn record_modified(&mut self, original: &[u8], swapped: &[u8]) {
+        self.bytes_modified += original
+            .iter()
+            .zip(swapped.iter())
+            .filter(|(before, after)| before != after)
+            .count();
+    }
+}
+
+/// Swaps bytes from `buffer` in place and returns statistics about the swap.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_slice_with_stats};
+/// let swaps = &[
+///     (Pattern::new(0x42), Predicate::new().with_periodicity(2)),
+///     (Pattern::new(0x43), Predicate::new().with_offset(100)),
+/// ];
+///
+/// let mut buffer: [u8; 4] = [0x41, 0x41, 0x42, 0x41];
+/// let stats = swap_slice_with_stats(&mut buffer, swaps);
+/// assert_eq!(buffer, [0x42, 0x41, 0x42, 0x41]);
+/// assert_eq!(stats.bytes_modified, 1);
+/// assert_eq!(stats.rule_hits, vec![2, 0]); // second rule never matched
+/// ```
+pub fn swap_slice_with_stats<P: BytePattern, Q: PositionPredicate>(
+    buffer: &mut [u8],
+    swaps: &[(P, Q)],
+) -> SwapStats {
+    let mut stats = SwapStats {
+        bytes_read: buffer.len(),
+        bytes_written: buffer.len(),
+        ..SwapStats::default()
+    };
+    stats.record_swap(buffer, swaps, 0);
+    stats
+}
+
+/// Returns an iterator on positions in `0..len` matched by any of `predicates`, in increasing order.
+///
+/// No data is read: periodic predicates (see `PositionPredicate::as_periodic`) are
+/// computed in closed form, others are evaluated position by position.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate, positions_changed};
+/// let swaps = &[
+///     (Pattern::new(0x42), Predicate::new().with_periodicity(4).with_offset(1)),
+///     (Pattern::new(0x42), Predicate::new().with_offset(6).with_limit(3)),
+/// ];
+/// let positions: Vec<usize> = positions_changed(swaps.iter().map(|(_, predicate)| predicate), 12).collect();
+/// assert_eq!(positions, vec![1, 5, 6, 7, 8, 9]);
+/// ```
+pub fn positions_changed<'a, I, Q>(predicates: I, len: usize) -> impl Iterator<Item = usize> + 'a
+where
+    I: IntoIterator<Item = &'a Q>,
+    Q: PositionPredicate + 'a,
+{
+    let mut matches: Vec<core::iter::Peekable<Box<dyn Iterator<Item = usize> + 'a>>> = predicates
+        .into_iter()
+        .map(|predicate| {
+            let positions: Box<dyn Iterator<Item = usize> + 'a> = match predicate.as_periodic() {
+                Some(periodic) => Box::new(periodic.positions(0..len)),
+                None => Box::new((0..len).filter(move |&position| predicate.eval(position))),
+            };
+            positions.peekable()
+        })
+        .collect();
+    core::iter::from_fn(move || {
+        let next = matches
+            .iter_mut()
+            .filter_map(|positions| positions.peek().copied())
+            .min()?;
+        for positions in matches.iter_mut() {
+            positions.next_if_eq(&next);
+        }
+        Some(next)
     })
 }
 
+/// Swaps bytes from `buffer` in place, processing `BUFFER_SIZE` chunks (rounded up to whole groups) in parallel.
+///
+/// Requires the `rayon` feature.
+///
+/// ```
+/// use bswp::pattern::{Predicate, Pattern, swap_slice_par};
+/// let predicate = Predicate::new().with_periodicity(2).with_offset(1);
+/// let pattern = Pattern::new(0x42).with_mask(0xFF);
+/// let swaps = &[(pattern, predicate)];
+///
+/// let mut buffer: Vec<u8> = vec![0x41; 100_000];
+/// swap_slice_par(&mut buffer, swaps);
+/// assert!(buffer.chunks(2).all(|c| c == [0x41, 0x42]));
+/// ```
+#[cfg(feature = "rayon")]
+pub fn swap_slice_par<P, Q>(buffer: &mut [u8], swaps: &[(P, Q)])
+where
+    P: BytePattern + Sync,
+    Q: PositionPredicate + Sync,
+{
+    use rayon::prelude::*;
+    // chunks end on group boundaries
+    let size = group_size(swaps);
+    let chunk_size = BUFFER_SIZE.div_ceil(size) * size;
+    buffer
+        .par_chunks_mut(chunk_size)
+        .enumerate()
+        .for_each(|(index, chunk)| swap_slice_from(chunk, swaps, index * chunk_size));
+}
+
+/// Swaps `buffer` in place, `buffer[0]` being at position `start`.
+///
+/// Requires the `bytes` feature.
+///
+/// ```
+/// use bswp::pattern::{swap_bytes_mut, Pattern, Predicate};
+/// use bytes::BytesMut;
+///
+/// let mut payload = BytesMut::from(&[0x41; 4][..]);
+/// swap_bytes_mut(&mut payload, &[(Pattern::new(0x42), Predicate::new().with_periodicity(2))], 0);
+/// assert_eq!(&payload[..], &[0x42, 0x41, 0x42, 0x41]);
+/// ```
+#[cfg(feature = "bytes")]
+pub fn swap_bytes_mut<P: BytePattern, Q: PositionPredicate>(
+    buffer: &mut bytes::BytesMut,
+    swaps: &[(P, Q)],
+    start: usize,
+) {
+    swap_slice_from(buffer, swaps, start);
+}
+
+/// Returns `source` swapped, `source[0]` being at position `start`, with copy-on-write
+/// semantics: `source` itself if no byte changed, swapped in place if `source` is the only
+/// handle on its buffer, else a swapped copy.
+///
+/// Requires the `bytes` feature.
+///
+/// ```
+/// use bswp::pattern::{swap_bytes, Pattern, Predicate};
+/// use bytes::Bytes;
+///
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(2))];
+/// let payload = Bytes::from_static(&[0x41, 0x41, 0x42, 0x42]);
+/// let untouched = swap_bytes(payload.clone(), swaps, 0);
+/// assert_eq!(untouched.as_ptr(), payload.as_ptr()); // no copy
+///
+/// let swapped = swap_bytes(Bytes::from(vec![0x41; 4]), swaps, 0);
+/// assert_eq!(&swapped[..], &[0x41, 0x41, 0x42, 0x42]);
+/// ```
+#[cfg(feature = "bytes")]
+pub fn swap_bytes<P: BytePattern, Q: PositionPredicate>(
+    source: bytes::Bytes,
+    swaps: &[(P, Q)],
+    start: usize,
+) -> bytes::Bytes {
+    let changed = swap_iter_from(&source, swaps, start)
+        .zip(source.iter())
+        .any(|(swapped, &value)| swapped != value);
+    if !changed {
+        return source;
+    }
+    let mut buffer = source
+        .try_into_mut()
+        .unwrap_or_else(|shared| bytes::BytesMut::from(&shared[..]));
+    swap_slice_from(&mut buffer, swaps, start);
+    buffer.freeze()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::pattern::{swap_iter, Pattern, Predicate};
+    use crate::pattern::{
+        positions_changed, swap_iter, swap_iter_from, swap_slice, swap_slice_from, FromEnd,
+        Pattern, PermutePattern, Predicate,
+    };
     use crate::{BytePattern, PositionPredicate};
 
     #[test]
@@ -233,6 +1155,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_permute_chunks() {
+        // stereo 16-bit samples: swap left and right channels
+        let permute = PermutePattern::new(vec![2, 3, 0, 1]).unwrap();
+        let swaps = &[(permute.clone(), Predicate::new().with_offset(4))];
+        let source: Vec<u8> = (0..19).collect();
+        let expected: Vec<u8> = swap_iter(&source, swaps).collect();
+        assert_eq!(&expected[..8], &[0, 1, 2, 3, 6, 7, 4, 5]);
+        for chunk_size in (4..source.len()).step_by(4) {
+            let mut buffered = source.clone();
+            for (index, chunk) in buffered.chunks_mut(chunk_size).enumerate() {
+                swap_slice_from(chunk, swaps, index * chunk_size);
+            }
+            assert_eq!(buffered, expected, "chunk size: {}", chunk_size);
+        }
+        let undo = &[(permute.inverse(), Predicate::new().with_offset(4))];
+        assert_eq!(swap_iter(&expected, undo).collect::<Vec<u8>>(), source);
+        assert_eq!(PermutePattern::new(vec![0, 2]), None);
+        assert_eq!(PermutePattern::new(Vec::new()), None);
+    }
+
     #[test]
     fn test_iter_swap() {
         let predicate = Predicate::new().with_periodicity(2).with_offset(1);
@@ -244,4 +1187,132 @@ mod tests {
         let swapped: Vec<u8> = swapped.collect();
         assert_eq!(swapped, vec!(0x41, 0x42, 0x41, 0x42));
     }
+
+    #[test]
+    fn test_from_end_next_match() {
+        for (periodicity, offset, limit) in [(1, 0, None), (3, 2, Some(4)), (2, 1, Some(0))] {
+            let predicate = Predicate::new()
+                .with_periodicity(periodicity)
+                .with_offset(offset);
+            let predicate = match limit {
+                Some(limit) => predicate.with_limit(limit),
+                None => predicate,
+            };
+            let from_end = FromEnd::new(predicate, 20);
+            for position in 0..25 {
+                let expected = (position..20).find(|&position| from_end.eval(position));
+                assert_eq!(from_end.next_match(position), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_swap_chunks() {
+        let predicate = Predicate::new()
+            .with_periodicity(3)
+            .with_offset(1)
+            .with_limit(4);
+        let pattern = Pattern::new(0x42).with_mask(0xFF);
+        let swaps = &[(pattern, predicate)];
+
+        let source: Vec<u8> = (0..20).collect();
+        let expected: Vec<u8> = swap_iter(&source, swaps).collect();
+        for chunk_size in 1..source.len() {
+            let mut chunked: Vec<u8> = Vec::new();
+            let mut buffered: Vec<u8> = source.clone();
+            for (index, chunk) in source.chunks(chunk_size).enumerate() {
+                chunked.extend(swap_iter_from(chunk, swaps, index * chunk_size));
+            }
+            for (index, chunk) in buffered.chunks_mut(chunk_size).enumerate() {
+                swap_slice_from(chunk, swaps, index * chunk_size);
+            }
+            assert_eq!(chunked, expected, "chunk size: {}", chunk_size);
+            assert_eq!(buffered, expected, "chunk size: {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_swap_slice_fast_path() {
+        let swaps = &[
+            (
+                Pattern::new(0x42),
+                Predicate::new().with_offset(3).with_limit(5),
+            ),
+            (
+                Pattern::new(0xF0).with_mask(0xF0),
+                Predicate::new().with_periodicity(3).with_offset(1),
+            ),
+            (
+                Pattern::new(0x00),
+                Predicate::new().with_periodicity(4).with_limit(2),
+            ),
+            (Pattern::new(0x0F).with_mask(0x0F), Predicate::new()),
+        ];
+        let source: Vec<u8> = (0..64).collect();
+        for start in 0..10 {
+            let expected: Vec<u8> = swap_iter_from(&source, swaps, start).collect();
+            let mut buffer = source.clone();
+            swap_slice_from(&mut buffer, swaps, start);
+            assert_eq!(buffer, expected, "start: {}", start);
+        }
+        let positions: Vec<usize> =
+            positions_changed(swaps.iter().map(|(_, predicate)| predicate), 64).collect();
+        let expected: Vec<usize> = (0..64)
+            .filter(|&position| swaps.iter().any(|(_, predicate)| predicate.eval(position)))
+            .collect();
+        assert_eq!(positions, expected);
+        let mut empty: [u8; 0] = [];
+        swap_slice(&mut empty, swaps);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_swap_bytes_copy_on_write() {
+        use crate::pattern::swap_bytes;
+        use bytes::Bytes;
+
+        let swaps = &[(
+            PermutePattern::new(vec![1, 0]).unwrap(),
+            Predicate::new().with_offset(4),
+        )];
+        let source: Vec<u8> = (0..16).collect();
+        let expected: Vec<u8> = swap_iter_from(&source, swaps, 2).collect();
+
+        let unique = Bytes::from(source.clone());
+        let pointer = unique.as_ptr();
+        let swapped = swap_bytes(unique, swaps, 2);
+        assert_eq!(swapped, expected);
+        assert_eq!(swapped.as_ptr(), pointer); // swapped in place
+
+        let shared = Bytes::from(source.clone());
+        let swapped = swap_bytes(shared.clone(), swaps, 2);
+        assert_eq!(swapped, expected);
+        assert_eq!(shared, source); // other handles untouched
+        assert_ne!(swapped.as_ptr(), shared.as_ptr());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let swaps = vec![
+            (
+                Pattern::new(0x42),
+                Predicate::new().with_periodicity(2).with_limit(4),
+            ),
+            (Pattern::new(0xF0).with_mask(0xF0), Predicate::new()),
+        ];
+        let json = serde_json::to_string(&swaps).unwrap();
+        let deserialized: Vec<(Pattern, Predicate)> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, swaps);
+
+        let minimal: (Pattern, Predicate) =
+            serde_json::from_str(r#"[{"value": 66}, {"offset": 1}]"#).unwrap();
+        assert_eq!(
+            minimal,
+            (Pattern::new(0x42), Predicate::new().with_offset(1))
+        );
+
+        let error = serde_json::from_str::<Predicate>(r#"{"periodicity": 0}"#).unwrap_err();
+        assert!(error.to_string().contains("at least 1"), "{}", error);
+    }
 }
@@ -0,0 +1,311 @@
+//! Precompiled swap rules (pure)
+//!
+//! A `SwapPlan` folds every rule into lookup tables so that swapping a byte
+//! costs one table lookup instead of evaluating every rule.
+
+use crate::io::{self, read_at, write_at};
+use crate::pattern::gcd;
+use crate::{BytePattern, PositionPredicate, BUFFER_SIZE};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Maximum number of phases per segment (least common multiple of periodicities).
+pub const MAX_PHASES: usize = 1 << 16;
+
+/// Byte lookup table: `lut[value]` is the swapped value.
+type Lut = [u8; 256];
+
+/// Positions `start..` (until next segment) sharing the same set of active rules.
+struct Segment {
+    start: usize,
+    /// `phases[position % phases.len()]` is the index of the lookup table to apply.
+    phases: Vec<usize>,
+}
+
+impl Segment {
+    fn is_identity(&self) -> bool {
+        self.phases.iter().all(|&lut| lut == IDENTITY)
+    }
+}
+
+/// Index of the identity lookup table.
+const IDENTITY: usize = 0;
+
+/// Precompiled swap rules.
+///
+/// Positions are split into segments delimited by rule offsets and limits,
+/// inside a segment the action only depends on `position % lcm(periodicities)`.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate, swap_iter};
+/// use bswp::plan::SwapPlan;
+///
+/// let swaps = &[
+///     (Pattern::new(0x42), Predicate::new().with_periodicity(2).with_offset(1)),
+///     (Pattern::new(0x0F).with_mask(0x0F), Predicate::new().with_periodicity(3).with_limit(2)),
+/// ];
+/// let plan = SwapPlan::compile(swaps).unwrap();
+///
+/// let source: [u8; 6] = [0x41; 6];
+/// let swapped: Vec<u8> = plan.apply_iter(&source).collect();
+/// assert_eq!(swapped, vec![0x4F, 0x42, 0x41, 0x4F, 0x41, 0x42]);
+/// assert_eq!(swapped, swap_iter(&source, swaps).collect::<Vec<u8>>());
+/// ```
+pub struct SwapPlan {
+    luts: Vec<Lut>,
+    segments: Vec<Segment>,
+}
+
+impl SwapPlan {
+    /// Compiles `swaps` into a `SwapPlan`.
+    ///
+    /// Returns `None` if a pattern moves bytes (see `BytePattern::group_size`) or depends
+    /// on the position (see `BytePattern::is_positional`), if a predicate is not periodic (see `PositionPredicate::as_periodic`) or if the
+    /// periodicities least common multiple exceeds `MAX_PHASES`.
+    pub fn compile<P: BytePattern, Q: PositionPredicate>(swaps: &[(P, Q)]) -> Option<SwapPlan> {
+        let mut rules = Vec::with_capacity(swaps.len());
+        for (pattern, predicate) in swaps {
+            if pattern.group_size() > 1 || pattern.is_positional() {
+                return None;
+            }
+            let periodic = predicate.as_periodic()?;
+            let mut lut = [0u8; 256];
+            for (value, swapped) in lut.iter_mut().enumerate() {
+                *swapped = pattern.eval(value as u8);
+            }
+            let end = periodic.limit.map(|limit| {
+                periodic
+                    .offset
+                    .saturating_add(limit.saturating_mul(periodic.periodicity))
+            });
+            rules.push((lut, periodic, end));
+        }
+
+        let mut bounds: Vec<usize> = vec![0];
+        for (_, periodic, end) in &rules {
+            bounds.push(periodic.offset);
+            bounds.extend(end);
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut luts: Vec<Lut> = Vec::new();
+        let mut lut_indices: HashMap<Lut, usize> = HashMap::new();
+        let identity = {
+            let mut lut = [0u8; 256];
+            for (value, swapped) in lut.iter_mut().enumerate() {
+                *swapped = value as u8;
+            }
+            lut
+        };
+        luts.push(identity);
+        lut_indices.insert(identity, IDENTITY);
+
+        let mut segments: Vec<Segment> = Vec::with_capacity(bounds.len());
+        for start in bounds {
+            let active: Vec<&(Lut, _, _)> = rules
+                .iter()
+                .filter(|(_, periodic, end)| {
+                    periodic.offset <= start && end.map_or(true, |end| start < end)
+                })
+                .collect();
+            let mut len = 1;
+            for (_, periodic, _) in &active {
+                len = len / gcd(len, periodic.periodicity) * periodic.periodicity;
+                if len > MAX_PHASES {
+                    return None;
+                }
+            }
+            let mut phases = Vec::with_capacity(len);
+            for phase in 0..len {
+                let mut lut = identity;
+                for (rule_lut, periodic, _) in &active {
+                    if phase % periodic.periodicity == periodic.offset % periodic.periodicity {
+                        for swapped in lut.iter_mut() {
+                            *swapped = rule_lut[*swapped as usize];
+                        }
+                    }
+                }
+                let index = *lut_indices.entry(lut).or_insert_with(|| {
+                    luts.push(lut);
+                    luts.len() - 1
+                });
+                phases.push(index);
+            }
+            segments.push(Segment { start, phases });
+        }
+        Some(SwapPlan { luts, segments })
+    }
+
+    /// Returns `value` swapped, `value` being at `position`.
+    pub fn eval(&self, position: usize, value: u8) -> u8 {
+        let segment = &self.segments[self.segments.partition_point(|s| s.start <= position) - 1];
+        self.luts[segment.phases[position % segment.phases.len()]][value as usize]
+    }
+
+    /// Swaps bytes from `buffer` in place.
+    pub fn apply_slice(&self, buffer: &mut [u8]) {
+        self.apply_slice_from(buffer, 0)
+    }
+
+    /// Swaps bytes from `buffer` in place, `buffer[0]` being at position `start`.
+    pub fn apply_slice_from(&self, buffer: &mut [u8], start: usize) {
+        let end = start.saturating_add(buffer.len());
+        for (index, segment) in self.segments.iter().enumerate() {
+            let segment_end = self.segments.get(index + 1).map_or(usize::MAX, |s| s.start);
+            if segment_end <= start || segment.is_identity() {
+                continue;
+            }
+            if segment.start >= end {
+                break;
+            }
+            let first = segment.start.max(start);
+            let last = segment_end.min(end);
+            let mut phase = first % segment.phases.len();
+            for item in buffer[first - start..last - start].iter_mut() {
+                *item = self.luts[segment.phases[phase]][*item as usize];
+                phase += 1;
+                if phase == segment.phases.len() {
+                    phase = 0;
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator on swapped bytes from `source`.
+    pub fn apply_iter<'a>(&'a self, source: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+        self.apply_iter_from(source, 0)
+    }
+
+    /// Returns an iterator on swapped bytes from `source`, `source[0]` being at position `start`.
+    pub fn apply_iter_from<'a>(
+        &'a self,
+        source: &'a [u8],
+        start: usize,
+    ) -> impl Iterator<Item = u8> + 'a {
+        source
+            .iter()
+            .enumerate()
+            .map(move |(index, &value)| self.eval(start + index, value))
+    }
+
+    /// For each byte in `reader` apply plan and write result to `writer`.
+    ///
+    /// Same semantics as `io::swap_io`, interrupted reads and writes being retried.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::plan::SwapPlan;
+    ///
+    /// let mut reader: Cursor<Vec<u8>> = Cursor::new(vec![0x41, 0x42, 0x43, 0x44]);
+    /// let mut writer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    ///
+    /// let plan = SwapPlan::compile(&[(Pattern::new(0x42), Predicate::new().with_periodicity(2))]).unwrap();
+    /// assert_eq!(plan.apply_io(&mut reader, &mut writer).unwrap(), 4);
+    /// assert_eq!(writer.into_inner(), vec![0x42, 0x42, 0x42, 0x44])
+    /// ```
+    pub fn apply_io<R: Read + ?Sized, W: Write + ?Sized>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<usize, io::Error> {
+        let mut written: usize = 0;
+        let mut buffer = [0; BUFFER_SIZE];
+
+        loop {
+            let size = read_at(reader, &mut buffer, written, written)?;
+            if size == 0 {
+                break; // finished
+            }
+            self.apply_slice_from(&mut buffer[..size], written);
+            write_at(writer, &buffer[..size], written, &mut written)?;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{swap_iter_from, Pattern, Predicate};
+    use crate::plan::SwapPlan;
+    use crate::PositionPredicate;
+
+    struct Odd;
+
+    impl PositionPredicate for Odd {
+        fn eval(&self, position: usize) -> bool {
+            position % 2 == 1
+        }
+    }
+
+    #[test]
+    fn test_plan_matches_swap_iter() {
+        let swaps = &[
+            (
+                Pattern::new(0x42),
+                Predicate::new().with_offset(3).with_limit(5),
+            ),
+            (
+                Pattern::new(0xF0).with_mask(0xF0),
+                Predicate::new().with_periodicity(3).with_offset(1),
+            ),
+            (
+                Pattern::new(0x00),
+                Predicate::new().with_periodicity(4).with_limit(2),
+            ),
+            (
+                Pattern::new(0x0F).with_mask(0x0F),
+                Predicate::new()
+                    .with_periodicity(5)
+                    .with_offset(7)
+                    .with_limit(3),
+            ),
+        ];
+        let plan = SwapPlan::compile(swaps).unwrap();
+        let source: Vec<u8> = (0..64).collect();
+        for start in 0..24 {
+            let expected: Vec<u8> = swap_iter_from(&source, swaps, start).collect();
+            let iterated: Vec<u8> = plan.apply_iter_from(&source, start).collect();
+            let mut buffer = source.clone();
+            plan.apply_slice_from(&mut buffer, start);
+            assert_eq!(iterated, expected, "start: {}", start);
+            assert_eq!(buffer, expected, "start: {}", start);
+        }
+    }
+
+    #[test]
+    fn test_plan_not_compiled() {
+        assert!(SwapPlan::compile(&[(Pattern::new(0x42), Odd)]).is_none());
+        let coprimes = &[
+            (Pattern::new(0x42), Predicate::new().with_periodicity(251)),
+            (Pattern::new(0x42), Predicate::new().with_periodicity(257)),
+            (Pattern::new(0x42), Predicate::new().with_periodicity(263)),
+        ];
+        assert!(SwapPlan::compile(coprimes).is_none());
+    }
+
+    #[test]
+    fn test_plan_apply_io_interrupted() {
+        /// Fails every other read with `ErrorKind::Interrupted`.
+        struct Interrupted<'a>(&'a [u8], bool);
+
+        impl std::io::Read for Interrupted<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1 = !self.1;
+                if self.1 {
+                    return Err(std::io::ErrorKind::Interrupted.into());
+                }
+                self.0.read(buf)
+            }
+        }
+
+        let plan = SwapPlan::compile(&[(Pattern::new(0x42), Predicate::new().with_offset(1))]);
+        let mut writer: Vec<u8> = Vec::new();
+        let written = plan
+            .unwrap()
+            .apply_io(&mut Interrupted(&[0x41; 3], false), &mut writer);
+        assert_eq!(written.unwrap(), 3);
+        assert_eq!(writer, vec![0x41, 0x42, 0x42]);
+    }
+}
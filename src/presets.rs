@@ -0,0 +1,153 @@
+//! Named operations (pure)
+//!
+//! A preset names a common `rule::Op` so that rule expressions and specifications
+//! can reference it instead of spelling out values and masks:
+//!
+//! ```text
+//! preset zero-fill in 0x100..0x200
+//! preset invert at 2n
+//! preset swap32 in 0..0x400
+//! ```
+
+use crate::pattern::{Pattern, Predicate};
+use crate::rule::{Op, SwapRule, Target};
+use std::collections::BTreeMap;
+
+/// Sets every bit to `0`, preset `zero-fill`.
+pub fn zero_fill() -> Op {
+    Op::Set(Pattern::new(0x00))
+}
+
+/// Inverts every bit, preset `invert`.
+pub fn invert() -> Op {
+    Op::Not
+}
+
+/// Reverses the byte order of every 16-bit word, op of preset `swap16`.
+///
+/// ```
+/// use bswp::pattern::swap_slice;
+/// use bswp::presets::swap16;
+///
+/// let mut buffer = [0x01, 0x02, 0x03, 0x04, 0x05];
+/// swap_slice(&mut buffer, &[swap16()]);
+/// assert_eq!(buffer, [0x02, 0x01, 0x04, 0x03, 0x05]); // trailing byte untouched
+/// ```
+pub fn swap16() -> SwapRule {
+    every_position(Op::Reverse(2))
+}
+
+/// Reverses the byte order of every 32-bit word, op of preset `swap32`.
+///
+/// ```
+/// use bswp::pattern::swap_iter;
+/// use bswp::presets::swap32;
+///
+/// let source = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+/// let swapped: Vec<u8> = swap_iter(&source, &[swap32()]).collect();
+/// assert_eq!(swapped, vec![0x04, 0x03, 0x02, 0x01, 0x05, 0x06]);
+/// ```
+pub fn swap32() -> SwapRule {
+    every_position(Op::Reverse(4))
+}
+
+/// Reverses the byte order of every 64-bit word, op of preset `swap64`.
+///
+/// ```
+/// use bswp::pattern::swap_slice;
+/// use bswp::presets::swap64;
+///
+/// let mut buffer: Vec<u8> = (0..8).collect();
+/// swap_slice(&mut buffer, &[swap64()]);
+/// assert_eq!(buffer, vec![7, 6, 5, 4, 3, 2, 1, 0]);
+/// ```
+pub fn swap64() -> SwapRule {
+    every_position(Op::Reverse(8))
+}
+
+fn every_position(op: Op) -> SwapRule {
+    (op, Target::Periodic(Predicate::new()))
+}
+
+/// Presets referencable by name, built-in presets included.
+///
+/// ```
+/// use bswp::dsl::parse_rules_with;
+/// use bswp::pattern::swap_slice;
+/// use bswp::presets::PresetRegistry;
+/// use bswp::rule::Op;
+///
+/// let presets = PresetRegistry::new().with_preset("clear-high", Op::And(0x0F));
+/// let rules = parse_rules_with("preset clear-high at 0; preset invert at 1", &presets).unwrap();
+/// let mut buffer = [0xAB, 0x0F];
+/// swap_slice(&mut buffer, &rules);
+/// assert_eq!(buffer, [0x0B, 0xF0]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PresetRegistry {
+    presets: BTreeMap<String, Op>,
+}
+
+impl Default for PresetRegistry {
+    fn default() -> Self {
+        PresetRegistry::empty()
+            .with_preset("zero-fill", zero_fill())
+            .with_preset("invert", invert())
+            .with_preset("swap16", swap16().0)
+            .with_preset("swap32", swap32().0)
+            .with_preset("swap64", swap64().0)
+    }
+}
+
+impl PresetRegistry {
+    /// Creates a registry holding the built-in presets.
+    pub fn new() -> Self {
+        PresetRegistry::default()
+    }
+
+    /// Creates a registry without presets.
+    pub fn empty() -> Self {
+        PresetRegistry {
+            presets: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `op` as `name`, replacing any preset with the same name.
+    pub fn with_preset(mut self, name: impl Into<String>, op: Op) -> Self {
+        self.register(name, op);
+        self
+    }
+
+    /// Registers `op` as `name`, returning the replaced preset, if any.
+    pub fn register(&mut self, name: impl Into<String>, op: Op) -> Option<Op> {
+        self.presets.insert(name.into(), op)
+    }
+
+    /// Returns the preset named `name`.
+    pub fn get(&self, name: &str) -> Option<Op> {
+        self.presets.get(name).copied()
+    }
+
+    /// Returns preset names, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::presets::{invert, PresetRegistry};
+    use crate::rule::Op;
+
+    #[test]
+    fn test_registry() {
+        let mut presets = PresetRegistry::new();
+        assert_eq!(
+            presets.names().collect::<Vec<_>>(),
+            vec!["invert", "swap16", "swap32", "swap64", "zero-fill"]
+        );
+        assert_eq!(presets.register("invert", Op::Xor(0x0F)), Some(invert()));
+        assert_eq!(presets.get("invert"), Some(Op::Xor(0x0F)));
+        assert_eq!(PresetRegistry::empty().get("invert"), None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Hexdump previews (pure)
+//!
+//! Renders classic offset/hex/ASCII dumps of the bytes swaps change, original bytes on
+//! the left and swapped bytes on the right, so that changes can be reviewed before
+//! they are written.
+
+use crate::pattern::Change;
+use std::fmt;
+use std::ops::Range;
+
+/// Bytes per row.
+pub const ROW_SIZE: usize = 16;
+
+/// Hexdump of `source` before and after `changes` (see `pattern::diff_iter`).
+///
+/// Only rows holding changes are shown, with context rows around them, skipped rows
+/// being replaced by `*`. The last line counts changed bytes.
+///
+/// ```
+/// use bswp::pattern::{diff_iter, Pattern, Predicate};
+/// use bswp::preview::Preview;
+///
+/// let source = b"hello world, hello bswp";
+/// let swaps = &[(Pattern::new(b'H'), Predicate::new().with_offset(13).with_limit(1))];
+/// let preview = Preview::new(source, diff_iter(source, swaps)).with_context(0);
+/// let output = preview.to_string();
+/// let lines: Vec<&str> = output.lines().collect();
+/// assert_eq!(lines.len(), 3);
+/// assert!(lines[0].starts_with("00000000  68 65 6c 6c 6f 20 77 6f"));
+/// assert!(lines[0].ends_with("|hello world, hel|  68 65 6c 6c 6f 20 77 6f 72 6c 64 2c 20 48 65 6c  |hello world, Hel|"));
+/// assert_eq!(lines[1], "*"); // unchanged rows
+/// assert_eq!(lines[2], "1 of 23 bytes changed");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preview<'a> {
+    source: &'a [u8],
+    /// changes, in increasing offset order
+    changes: Vec<Change>,
+    context: usize,
+    range: Option<Range<usize>>,
+    color: bool,
+    ascii: bool,
+}
+
+impl<'a> Preview<'a> {
+    /// Creates a preview of `changes` made to `source`, in increasing offset order.
+    pub fn new<I: IntoIterator<Item = Change>>(source: &'a [u8], changes: I) -> Self {
+        Preview {
+            source,
+            changes: changes.into_iter().collect(),
+            context: 1,
+            range: None,
+            color: false,
+            ascii: true,
+        }
+    }
+
+    /// Sets the number of unchanged rows shown around changed ones.
+    ///
+    /// **Default**: `1`
+    pub fn with_context(mut self, rows: usize) -> Self {
+        self.context = rows;
+        self
+    }
+
+    /// Shows every row holding bytes in `range` instead of the rows around changes.
+    ///
+    /// **Default**: rows around changes
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Highlights changed bytes with ANSI colors, red before and green after.
+    ///
+    /// **Default**: `false`
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Shows ASCII columns next to the hexadecimal ones.
+    ///
+    /// **Default**: `true`
+    pub fn with_ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    /// Returns the changes previewed.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns the spans of rows shown, in increasing order and disjoint.
+    fn spans(&self) -> Vec<Range<usize>> {
+        let rows = self.source.len().div_ceil(ROW_SIZE);
+        if let Some(range) = &self.range {
+            let end = range.end.div_ceil(ROW_SIZE).min(rows);
+            let start = (range.start / ROW_SIZE).min(end);
+            return std::iter::once(start..end)
+                .filter(|span| !span.is_empty())
+                .collect();
+        }
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        for change in &self.changes {
+            let row = change.offset / ROW_SIZE;
+            let span = row.saturating_sub(self.context)..(row + self.context + 1).min(rows);
+            match spans.last_mut() {
+                Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+                _ if span.start < span.end => spans.push(span),
+                _ => {}
+            }
+        }
+        spans
+    }
+
+    /// Writes row `row`, before and after.
+    fn write_row(&self, f: &mut fmt::Formatter<'_>, row: usize) -> fmt::Result {
+        let (old_style, new_style, reset) = if self.color {
+            ("\x1b[31m", "\x1b[32m", "\x1b[0m")
+        } else {
+            ("", "", "")
+        };
+        let start = row * ROW_SIZE;
+        let mut index = self.changes.partition_point(|change| change.offset < start);
+        let (mut old, mut new) = (String::new(), String::new());
+        let (mut old_ascii, mut new_ascii) = (String::new(), String::new());
+        for offset in start..start + ROW_SIZE {
+            let value = match self.source.get(offset) {
+                Some(&value) => value,
+                None => {
+                    old += "   ";
+                    new += "   ";
+                    old_ascii.push(' ');
+                    new_ascii.push(' ');
+                    continue;
+                }
+            };
+            match self.changes.get(index) {
+                Some(change) if change.offset == offset => {
+                    old += &format!(" {}{:02x}{}", old_style, change.old, reset);
+                    new += &format!(" {}{:02x}{}", new_style, change.new, reset);
+                    old_ascii += &format!("{}{}{}", old_style, printable(change.old), reset);
+                    new_ascii += &format!("{}{}{}", new_style, printable(change.new), reset);
+                    index += 1;
+                }
+                _ => {
+                    old += &format!(" {:02x}", value);
+                    new += &format!(" {:02x}", value);
+                    old_ascii.push(printable(value));
+                    new_ascii.push(printable(value));
+                }
+            }
+        }
+        if self.ascii {
+            writeln!(
+                f,
+                "{:08x} {}  |{}| {}  |{}|",
+                start, old, old_ascii, new, new_ascii
+            )
+        } else {
+            writeln!(f, "{:08x} {}  |{}", start, old, new)
+        }
+    }
+}
+
+impl fmt::Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.source.len().div_ceil(ROW_SIZE);
+        let spans = self.spans();
+        let mut next_row = 0; // first row not yet written
+        for span in &spans {
+            if span.start > next_row {
+                writeln!(f, "*")?;
+            }
+            for row in span.clone() {
+                self.write_row(f, row)?;
+            }
+            next_row = span.end;
+        }
+        if next_row < rows && !spans.is_empty() {
+            writeln!(f, "*")?;
+        }
+        writeln!(
+            f,
+            "{} of {} bytes changed",
+            self.changes.len(),
+            self.source.len()
+        )
+    }
+}
+
+/// Returns `byte` as an ASCII character, `.` if not printable.
+fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        char::from(byte)
+    } else {
+        '.'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{diff_iter, Pattern, Predicate};
+    use crate::preview::Preview;
+
+    #[test]
+    fn test_preview_rows() {
+        let source: Vec<u8> = (0..100).collect();
+        let swaps = &[
+            (
+                Pattern::new(b'A'),
+                Predicate::new().with_offset(2).with_limit(1),
+            ),
+            (
+                Pattern::new(0xFF),
+                Predicate::new().with_offset(70).with_limit(1),
+            ),
+        ];
+        let changes: Vec<_> = diff_iter(&source, swaps).collect();
+        let output = Preview::new(&source, changes.clone()).to_string();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 8, "{}", output);
+        assert!(lines[0].starts_with("00000000  00 01 02 03"));
+        assert!(lines[0].ends_with("|................|  00 01 41 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f  |..A.............|"));
+        assert!(lines[1].starts_with("00000010"));
+        assert_eq!(lines[2], "*");
+        assert!(lines[3].starts_with("00000030") && lines[5].starts_with("00000050"));
+        assert!(lines[4].ends_with("|@ABCDE.GHIJKLMNO|"));
+        assert_eq!(lines[6], "*");
+        assert_eq!(lines[7], "2 of 100 bytes changed");
+
+        let output = Preview::new(&source, changes.clone())
+            .with_range(30..40)
+            .with_ascii(false)
+            .to_string();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 5, "{}", output);
+        assert_eq!(lines[0], "*");
+        assert!(
+            lines[1].starts_with("00000010")
+                && lines[1].ends_with("1e 1f  | 10 11 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f")
+        );
+        assert!(lines[2].starts_with("00000020"));
+        assert_eq!(lines[3], "*");
+
+        let colored = Preview::new(&source, changes)
+            .with_context(0)
+            .with_color(true);
+        assert!(colored.to_string().contains("\x1b[32mff\x1b[0m"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Python bindings (mut), requires the `python` feature.
+//!
+//! Exposes `Pattern`, `Predicate`, `swap_bytes` and `swap_file` as the `bswp` Python
+//! module through pyo3, swapping with the slice fast paths and releasing the GIL meanwhile.
+//! Build the extension with `maturin build --features python`, or with
+//! `cargo rustc --release --features python --crate-type cdylib` and rename the library
+//! to `bswp.so` (`bswp.pyd` on Windows).
+//!
+//! ```text
+//! import bswp
+//!
+//! rules = [(bswp.Pattern(0x42), bswp.Predicate(periodicity=2, offset=1))]
+//! assert bswp.swap_bytes(b"AAAA", rules) == b"ABAB"
+//! bswp.swap_file("firmware.bin", rules)
+//! ```
+
+// false positive in the code generated by `#[pyfunction]` for fallible functions
+#![allow(clippy::useless_conversion)]
+
+use crate::io;
+use crate::pattern::{swap_slice, Pattern, Predicate};
+use pyo3::exceptions::PyOSError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::path::PathBuf;
+
+/// `Pattern(value, mask=0xFF)` in Python, see `pattern::Pattern`.
+#[pyclass(name = "Pattern", module = "bswp", frozen, eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PyPattern(pub Pattern);
+
+#[pymethods]
+impl PyPattern {
+    #[new]
+    #[pyo3(signature = (value, mask = 0xFF))]
+    fn new(value: u8, mask: u8) -> Self {
+        PyPattern(Pattern::new(value).with_mask(mask))
+    }
+
+    #[getter]
+    fn value(&self) -> u8 {
+        self.0.value
+    }
+
+    #[getter]
+    fn mask(&self) -> u8 {
+        self.0.mask
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Pattern(0x{:02X}, mask=0x{:02X})",
+            self.0.value, self.0.mask
+        )
+    }
+}
+
+/// `Predicate(periodicity=1, offset=0, limit=None)` in Python, see `pattern::Predicate`.
+#[pyclass(name = "Predicate", module = "bswp", frozen, eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PyPredicate(pub Predicate);
+
+#[pymethods]
+impl PyPredicate {
+    #[new]
+    #[pyo3(signature = (periodicity = 1, offset = 0, limit = None))]
+    fn new(periodicity: usize, offset: usize, limit: Option<usize>) -> Self {
+        let predicate = Predicate::new()
+            .with_periodicity(periodicity)
+            .with_offset(offset);
+        PyPredicate(match limit {
+            Some(limit) => predicate.with_limit(limit),
+            None => predicate,
+        })
+    }
+
+    #[getter]
+    fn periodicity(&self) -> usize {
+        self.0.periodicity
+    }
+
+    #[getter]
+    fn offset(&self) -> usize {
+        self.0.offset
+    }
+
+    #[getter]
+    fn limit(&self) -> Option<usize> {
+        self.0.limit
+    }
+
+    fn __repr__(&self) -> String {
+        let limit = self
+            .0
+            .limit
+            .map_or("None".to_string(), |limit| limit.to_string());
+        format!(
+            "Predicate(periodicity={}, offset={}, limit={})",
+            self.0.periodicity, self.0.offset, limit
+        )
+    }
+}
+
+fn to_swaps(rules: Vec<(PyPattern, PyPredicate)>) -> Vec<(Pattern, Predicate)> {
+    rules
+        .into_iter()
+        .map(|(pattern, predicate)| (pattern.0, predicate.0))
+        .collect()
+}
+
+/// Returns `data` swapped by `rules`, a list of `(Pattern, Predicate)` tuples.
+#[pyfunction]
+pub fn swap_bytes<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    rules: Vec<(PyPattern, PyPredicate)>,
+) -> Bound<'py, PyBytes> {
+    let swaps = to_swaps(rules);
+    let mut swapped = data.to_vec();
+    py.allow_threads(|| swap_slice(&mut swapped, &swaps));
+    PyBytes::new_bound(py, &swapped)
+}
+
+/// Swaps the file at `path` in place by `rules`, see `io::swap_file`.
+///
+/// Returns the number of bytes swapped, raises `OSError` on io error.
+#[pyfunction]
+pub fn swap_file(
+    py: Python<'_>,
+    path: PathBuf,
+    rules: Vec<(PyPattern, PyPredicate)>,
+) -> PyResult<usize> {
+    let swaps = to_swaps(rules);
+    py.allow_threads(|| io::swap_file(&path, &swaps))
+        .map_err(|error| PyOSError::new_err(error.to_string()))
+}
+
+/// `bswp` Python module.
+#[pymodule]
+pub fn bswp(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyPattern>()?;
+    module.add_class::<PyPredicate>()?;
+    module.add_function(wrap_pyfunction!(swap_bytes, module)?)?;
+    module.add_function(wrap_pyfunction!(swap_file, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::python::bswp;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_python_module() {
+        pyo3::append_to_inittab!(bswp);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let path = tempfile::NamedTempFile::new().unwrap();
+            std::fs::write(path.path(), [0x41; 6]).unwrap();
+            let locals = PyDict::new_bound(py);
+            locals.set_item("path", path.path()).unwrap();
+            py.run_bound(
+                r#"
+import bswp
+rules = [(bswp.Pattern(0x42), bswp.Predicate(periodicity=2, offset=1, limit=2))]
+assert bswp.swap_bytes(b"AAAAAA", rules) == b"ABABAA"
+assert bswp.swap_bytes(b"\x0f", [(bswp.Pattern(0xFF, mask=0xF0), bswp.Predicate())]) == b"\xff"
+assert bswp.swap_file(path, rules) == 6
+assert open(path, "rb").read() == b"ABABAA"
+assert repr(bswp.Predicate(limit=3)) == "Predicate(periodicity=1, offset=0, limit=3)"
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,208 @@
+//! Fixed-size records (pure)
+//!
+//! Many formats are a header followed by fixed-size records. A `RecordTarget` addresses
+//! bytes as a field of the records whose index matches a predicate instead of raw stream
+//! positions, and is usable wherever swaps are.
+
+use crate::pattern::Predicate;
+use crate::PositionPredicate;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Header length and record size of a stream.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordLayout {
+    header: usize,
+    size: usize,
+}
+
+impl RecordLayout {
+    /// Creates a layout of records of `size` bytes, without header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is `0`.
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "record size must be positive");
+        RecordLayout { header: 0, size }
+    }
+
+    /// Sets the length of the header preceding the first record.
+    ///
+    /// **Default**: `0`
+    pub fn with_header(mut self, header: usize) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Returns the record size.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the index of the record holding `position` and the position within the
+    /// record, `None` for header positions.
+    pub fn locate(&self, position: usize) -> Option<(usize, usize)> {
+        let position = position.checked_sub(self.header)?;
+        Some((position / self.size, position % self.size))
+    }
+
+    /// Returns a target matching the bytes in `field` of records whose index matches
+    /// `records`.
+    pub fn target<Q: PositionPredicate>(&self, records: Q, field: Range<usize>) -> RecordTarget<Q> {
+        let field = field.start.min(self.size)..field.end.min(self.size);
+        RecordTarget {
+            layout: *self,
+            records,
+            field,
+        }
+    }
+
+    /// Returns swaps made of rules addressed as a field of records: every rule is a
+    /// pattern, a predicate on the record index and the range of the field within
+    /// records (see `target`).
+    ///
+    /// ```
+    /// use bswp::pattern::{swap_slice, Pattern, Predicate};
+    /// use bswp::record::RecordLayout;
+    ///
+    /// // 2-byte header then 4-byte records: set bytes 2 and 3 of every record
+    /// let layout = RecordLayout::new(4).with_header(2);
+    /// let rules = &[(Pattern::new(0xFF), Predicate::new(), 2..4)];
+    /// let mut buffer = [0x00; 2 + 3 * 4];
+    /// swap_slice(&mut buffer, &layout.swaps(rules));
+    /// assert_eq!(buffer[..8], [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00]);
+    /// ```
+    pub fn swaps<'r, P, Q>(
+        &self,
+        rules: &'r [(P, Q, Range<usize>)],
+    ) -> Vec<(&'r P, RecordTarget<&'r Q>)>
+    where
+        Q: PositionPredicate,
+    {
+        rules
+            .iter()
+            .map(|(pattern, records, field)| (pattern, self.target(records, field.clone())))
+            .collect()
+    }
+}
+
+/// Predicate matching a field of records, see `RecordLayout::target`.
+///
+/// ```
+/// use bswp::pattern::{swap_slice, Pattern, Predicate};
+/// use bswp::record::RecordLayout;
+/// use bswp::PositionPredicate;
+///
+/// // 2-byte header, 4-byte records: set byte 1 of every odd record
+/// let layout = RecordLayout::new(4).with_header(2);
+/// let target = layout.target(Predicate::new().with_periodicity(2).with_offset(1), 1..2);
+/// assert!(target.eval(2 + 4 + 1) && !target.eval(2 + 1));
+///
+/// let mut buffer = [0x00; 2 + 4 * 4];
+/// swap_slice(&mut buffer, &[(Pattern::new(0xFF), target)]);
+/// assert_eq!(buffer[7], 0xFF);
+/// assert_eq!(buffer[15], 0xFF);
+/// assert_eq!(buffer.iter().filter(|&&byte| byte == 0xFF).count(), 2);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordTarget<Q> {
+    layout: RecordLayout,
+    records: Q,
+    field: Range<usize>,
+}
+
+impl<Q: PositionPredicate> PositionPredicate for RecordTarget<Q> {
+    fn eval(&self, position: usize) -> bool {
+        match self.layout.locate(position) {
+            Some((index, offset)) => self.field.contains(&offset) && self.records.eval(index),
+            None => false,
+        }
+    }
+
+    fn as_periodic(&self) -> Option<Predicate> {
+        if self.field.len() != 1 {
+            return None;
+        }
+        let records = self.records.as_periodic()?;
+        let offset = records
+            .offset
+            .checked_mul(self.layout.size)?
+            .checked_add(self.layout.header + self.field.start)?;
+        let predicate = Predicate::new()
+            .with_periodicity(records.periodicity.checked_mul(self.layout.size)?)
+            .with_offset(offset);
+        Some(match records.limit {
+            Some(limit) => predicate.with_limit(limit),
+            None => predicate,
+        })
+    }
+
+    fn next_match(&self, position: usize) -> Option<usize> {
+        if self.field.is_empty() {
+            return None;
+        }
+        let (mut index, offset) = self
+            .layout
+            .locate(position)
+            .unwrap_or((0, self.field.start));
+        let mut offset = offset.max(self.field.start);
+        if offset >= self.field.end {
+            index = index.checked_add(1)?;
+            offset = self.field.start;
+        }
+        let next = self.records.next_match(index)?;
+        if next > index {
+            offset = self.field.start;
+        }
+        next.checked_mul(self.layout.size)?
+            .checked_add(self.layout.header + offset)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::pattern::{swap_slice, Pattern, Predicate};
+    use crate::plan::SwapPlan;
+    use crate::record::RecordLayout;
+    use crate::rule::Target;
+    use crate::PositionPredicate;
+
+    #[test]
+    fn test_record_targets() {
+        let layout = RecordLayout::new(7).with_header(5);
+        let targets = [
+            layout.target(Target::Range(3..40), 2..5),
+            layout.target(Target::offsets(vec![0, 9, 10, 200]), 6..9),
+            layout.target(Target::Periodic(Predicate::new().with_periodicity(3)), 0..1),
+        ];
+        for target in &targets {
+            let positions: Vec<usize> = (0..1_000).filter(|&p| target.eval(p)).collect();
+            let expected: Vec<usize> = (0..1_000)
+                .filter(|&p| {
+                    p >= 5 && {
+                        let (index, offset) = ((p - 5) / 7, (p - 5) % 7);
+                        target.field.contains(&offset) && target.records.eval(index)
+                    }
+                })
+                .collect();
+            assert_eq!(positions, expected);
+            for position in 0..1_000 {
+                let next = positions.iter().copied().find(|&p| p >= position);
+                let matched = target.next_match(position).filter(|&p| p < 1_000);
+                assert_eq!(matched, next, "position: {}", position);
+            }
+        }
+        let periodic = targets[2].as_periodic().unwrap();
+        assert_eq!((periodic.offset, periodic.periodicity), (5, 21));
+
+        let source: Vec<u8> = (0..1_000).map(|i| i as u8).collect();
+        let swaps = &[(Pattern::new(0x00), targets[2].clone())];
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        let mut planned = source.clone();
+        SwapPlan::compile(swaps).unwrap().apply_slice(&mut planned);
+        assert_eq!(planned, expected);
+    }
+}
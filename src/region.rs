@@ -0,0 +1,195 @@
+//! Named regions (pure)
+//!
+//! A `RegionMap` names ranges of a file (sections, tables, palettes...) so that rules can
+//! be declared relative to a region and resolved once the region is located in a file:
+//! the same rules apply to files whose regions move around.
+
+use crate::rule::{Op, SwapRule, Target};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// Error returned when a rule cannot be resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RegionError {
+    /// the rule refers to a region missing from the map
+    UnknownRegion(String),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::UnknownRegion(name) => write!(f, "unknown region {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+/// Operation applied at positions relative to the start of a named region.
+///
+/// Positions past the end of the region are never targeted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionRule {
+    /// operation applied
+    pub op: Op,
+    /// name of the region
+    pub region: String,
+    /// positions, `0` being the first byte of the region
+    pub target: Target,
+}
+
+impl RegionRule {
+    /// Creates a rule applying `op` at `target` positions of `region`.
+    pub fn new(op: Op, region: impl Into<String>, target: impl Into<Target>) -> Self {
+        RegionRule {
+            op,
+            region: region.into(),
+            target: target.into(),
+        }
+    }
+}
+
+/// Region names mapped to their positions in a file.
+///
+/// ```
+/// use bswp::pattern::{swap_slice, Predicate};
+/// use bswp::region::{RegionMap, RegionRule};
+/// use bswp::rule::Op;
+///
+/// // every 4th byte of the palette
+/// let rules = [RegionRule::new(Op::Xor(0xFF), "palette", Predicate::new().with_periodicity(4))];
+///
+/// let regions = RegionMap::new().with_region("palette", 2, 8);
+/// let swaps = regions.resolve_all(&rules).unwrap();
+/// let mut buffer = [0x00; 12];
+/// swap_slice(&mut buffer, &swaps);
+/// assert_eq!(buffer, [0, 0, 0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0, 0]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegionMap {
+    regions: BTreeMap<String, Range<usize>>,
+}
+
+impl RegionMap {
+    /// Creates an empty `RegionMap`.
+    pub fn new() -> Self {
+        RegionMap::default()
+    }
+
+    /// Adds the region `name` of `len` bytes starting at `offset`, replacing any region with this name.
+    pub fn with_region(mut self, name: impl Into<String>, offset: usize, len: usize) -> Self {
+        self.insert(name, offset, len);
+        self
+    }
+
+    /// Adds the region `name` of `len` bytes starting at `offset`.
+    ///
+    /// Returns the positions of the region replaced, if any.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        offset: usize,
+        len: usize,
+    ) -> Option<Range<usize>> {
+        self.regions.insert(name.into(), offset..offset + len)
+    }
+
+    /// Returns the positions of the region `name`.
+    pub fn get(&self, name: &str) -> Option<Range<usize>> {
+        self.regions.get(name).cloned()
+    }
+
+    /// Returns an iterator on the regions, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Range<usize>)> + '_ {
+        self.regions
+            .iter()
+            .map(|(name, range)| (name.as_str(), range.clone()))
+    }
+
+    /// Returns `rule` with absolute positions.
+    pub fn resolve(&self, rule: &RegionRule) -> Result<SwapRule, RegionError> {
+        let region = self
+            .get(&rule.region)
+            .ok_or_else(|| RegionError::UnknownRegion(rule.region.clone()))?;
+        Ok((rule.op, relative(&rule.target, region)))
+    }
+
+    /// Returns `rules` with absolute positions, in the same order.
+    pub fn resolve_all(&self, rules: &[RegionRule]) -> Result<Vec<SwapRule>, RegionError> {
+        rules.iter().map(|rule| self.resolve(rule)).collect()
+    }
+}
+
+/// Returns the absolute positions of `target` positions relative to `region`.
+fn relative(target: &Target, region: Range<usize>) -> Target {
+    let len = region.len();
+    match target {
+        Target::Periodic(predicate) => {
+            let periodicity = predicate.periodicity.max(1);
+            let fit = len.saturating_sub(predicate.offset).div_ceil(periodicity);
+            let limit = predicate.limit.map_or(fit, |limit| limit.min(fit));
+            Target::Periodic(
+                predicate
+                    .with_offset(region.start + predicate.offset)
+                    .with_limit(limit),
+            )
+        }
+        Target::Range(range) => {
+            let end = range.end.min(len);
+            let start = range.start.min(end);
+            Target::Range(region.start + start..region.start + end)
+        }
+        Target::Offsets(offsets) => Target::Offsets(
+            offsets
+                .iter()
+                .take_while(|&&offset| offset < len)
+                .map(|offset| region.start + offset)
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{swap_slice, Predicate};
+    use crate::region::{RegionError, RegionMap, RegionRule};
+    use crate::rule::{Op, Target};
+
+    #[test]
+    fn test_resolve_moved_regions() {
+        let rules = [
+            RegionRule::new(
+                Op::Not,
+                "table",
+                Predicate::new()
+                    .with_offset(1)
+                    .with_periodicity(3)
+                    .with_limit(100),
+            ),
+            RegionRule::new(Op::Xor(0x0F), "header", Target::Range(2..10)),
+            RegionRule::new(Op::Or(0x80), "table", Target::offsets(vec![0, 5, 6])),
+        ];
+        for start in [0, 7, 100] {
+            let regions = RegionMap::new()
+                .with_region("header", start, 4)
+                .with_region("table", start + 10, 6);
+            let swaps = regions.resolve_all(&rules).unwrap();
+            let mut buffer = vec![0x00; 120];
+            swap_slice(&mut buffer, &swaps);
+            let mut expected = vec![0x00; 120];
+            expected[start + 2..start + 4].fill(0x0F);
+            expected[start + 10] = 0x80;
+            expected[start + 11] = 0xFF;
+            expected[start + 14] = 0xFF;
+            expected[start + 15] = 0x80;
+            assert_eq!(buffer, expected, "start: {}", start);
+        }
+        let regions = RegionMap::new().with_region("header", 0, 4);
+        assert_eq!(
+            regions.resolve_all(&rules),
+            Err(RegionError::UnknownRegion("table".to_string()))
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Byte sequence search and replace (pure)
+//!
+//! A `Replacement` swaps every occurrence of a needle for a replacement of the same
+//! length, wherever the data is: occurrences are searched instead of located by position.
+//! A `Replacer` finds occurrences spanning several fed chunks.
+
+use crate::pattern::Predicate;
+use crate::PositionPredicate;
+
+/// Replaces occurrences of `needle` starting at positions matching a predicate.
+///
+/// ```
+/// use bswp::pattern::Predicate;
+/// use bswp::replace::{replace_slice, Replacement};
+///
+/// let replacement = Replacement::new(b"cat", b"dog").unwrap();
+/// let mut buffer = *b"cat catalog";
+/// assert_eq!(replace_slice(&mut buffer, &[replacement]), 2);
+/// assert_eq!(&buffer, b"dog dogalog");
+///
+/// // only occurrences starting at even positions
+/// let replacement = Replacement::new(b"ab", b"AB").unwrap().with_predicate(Predicate::new().with_periodicity(2));
+/// let mut buffer = *b"ab-ab-";
+/// replace_slice(&mut buffer, &[replacement]);
+/// assert_eq!(&buffer, b"AB-ab-");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replacement<Q = Predicate> {
+    needle: Vec<u8>,
+    replacement: Vec<u8>,
+    predicate: Q,
+}
+
+impl Replacement {
+    /// Creates a `Replacement` matching at every position.
+    ///
+    /// Returns `None` if `needle` is empty or if `replacement` has a different length.
+    pub fn new(needle: impl Into<Vec<u8>>, replacement: impl Into<Vec<u8>>) -> Option<Self> {
+        let (needle, replacement) = (needle.into(), replacement.into());
+        if needle.is_empty() || needle.len() != replacement.len() {
+            return None;
+        }
+        Some(Replacement {
+            needle,
+            replacement,
+            predicate: Predicate::new(),
+        })
+    }
+}
+
+impl<Q: PositionPredicate> Replacement<Q> {
+    /// Sets the predicate occurrences must start at.
+    ///
+    /// **Default**: every position
+    pub fn with_predicate<T: PositionPredicate>(self, predicate: T) -> Replacement<T> {
+        Replacement {
+            needle: self.needle,
+            replacement: self.replacement,
+            predicate,
+        }
+    }
+
+    /// Returns the searched byte sequence.
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    /// Returns the bytes written over occurrences.
+    pub fn replacement(&self) -> &[u8] {
+        &self.replacement
+    }
+
+    /// Returns `true` if an occurrence starts at `haystack[index]`, at `position`.
+    fn matches(&self, haystack: &[u8], index: usize, position: usize) -> bool {
+        haystack[index..].starts_with(&self.needle) && self.predicate.eval(position)
+    }
+}
+
+/// Replaces occurrences from `buffer` in place, returns the number of occurrences replaced.
+///
+/// `buffer` is scanned once from the start, at each position the first matching
+/// replacement is applied and scanning resumes after the occurrence: occurrences
+/// do not overlap and replaced bytes are not searched again.
+pub fn replace_slice<Q: PositionPredicate>(
+    buffer: &mut [u8],
+    replacements: &[Replacement<Q>],
+) -> usize {
+    let mut replacer = Replacer::new(replacements);
+    replacer.scan(buffer, buffer.len());
+    replacer.matches
+}
+
+/// Search and replace state over a stream of chunks.
+///
+/// Occurrences spanning several chunks are found: the end of a chunk that could
+/// start an occurrence is held back until the next chunk (or `finish`).
+///
+/// ```
+/// use bswp::replace::{Replacement, Replacer};
+///
+/// let replacements = [Replacement::new(b"cat", b"dog").unwrap()];
+/// let mut replacer = Replacer::new(&replacements);
+/// let mut output: Vec<u8> = Vec::new();
+/// replacer.feed(b"a c", &mut output);
+/// assert_eq!(output, b"a"); // the last bytes are held back until more data arrives
+/// replacer.feed(b"at", &mut output);
+/// replacer.finish(&mut output);
+/// assert_eq!(output, b"a dog");
+/// assert_eq!(replacer.matches(), 1);
+/// ```
+pub struct Replacer<'a, Q> {
+    replacements: &'a [Replacement<Q>],
+    /// bytes held back, starting at `position`
+    pending: Vec<u8>,
+    position: usize,
+    matches: usize,
+}
+
+impl<'a, Q: PositionPredicate> Replacer<'a, Q> {
+    /// Creates a new `Replacer` starting at position `0`.
+    pub fn new(replacements: &'a [Replacement<Q>]) -> Self {
+        Replacer {
+            replacements,
+            pending: Vec::new(),
+            position: 0,
+            matches: 0,
+        }
+    }
+
+    /// Returns the number of occurrences replaced so far.
+    pub fn matches(&self) -> usize {
+        self.matches
+    }
+
+    /// Returns the position of the next byte to output.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Searches `chunk`, appending bytes that can no longer be part of an occurrence to `output`.
+    pub fn feed(&mut self, chunk: &[u8], output: &mut Vec<u8>) {
+        let longest = self
+            .replacements
+            .iter()
+            .map(|replacement| replacement.needle.len())
+            .max()
+            .unwrap_or(1);
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(chunk);
+        // positions followed by fewer than `longest` bytes could start a longer occurrence
+        let end = (pending.len() + 1).saturating_sub(longest);
+        let done = self.scan(&mut pending, end);
+        output.extend_from_slice(&pending[..done]);
+        pending.drain(..done);
+        self.pending = pending;
+    }
+
+    /// Searches bytes held back and appends them to `output`.
+    pub fn finish(&mut self, output: &mut Vec<u8>) {
+        let mut pending = std::mem::take(&mut self.pending);
+        let end = pending.len();
+        self.scan(&mut pending, end);
+        output.extend_from_slice(&pending);
+    }
+
+    /// Replaces occurrences starting before `buffer[end]`, `buffer[0]` being at `self.position`.
+    ///
+    /// Returns the index following the last byte scanned, advancing position to it.
+    fn scan(&mut self, buffer: &mut [u8], end: usize) -> usize {
+        let mut index = 0;
+        while index < end {
+            let position = self.position + index;
+            let matching = self
+                .replacements
+                .iter()
+                .find(|replacement| replacement.matches(buffer, index, position));
+            match matching {
+                Some(replacement) => {
+                    let len = replacement.needle.len();
+                    buffer[index..index + len].copy_from_slice(&replacement.replacement);
+                    self.matches += 1;
+                    index += len;
+                }
+                None => index += 1,
+            }
+        }
+        self.position += index;
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::Predicate;
+    use crate::replace::{replace_slice, Replacement, Replacer};
+
+    #[test]
+    fn test_replace_chunks() {
+        let replacements = [
+            Replacement::new(b"abcd", b"ABCD").unwrap(),
+            Replacement::new(b"ab", b"xy").unwrap(),
+            Replacement::new(b"d", b"D")
+                .unwrap()
+                .with_predicate(Predicate::new().with_offset(10)),
+        ];
+        let source = b"abcdab-dabcabcd-d-ab".to_vec();
+        let mut expected = source.clone();
+        assert_eq!(replace_slice(&mut expected, &replacements), 6);
+        assert_eq!(&expected, b"ABCDxy-dxycABCD-D-xy");
+        for chunk_size in 1..source.len() {
+            let mut replacer = Replacer::new(&replacements);
+            let mut output: Vec<u8> = Vec::new();
+            for chunk in source.chunks(chunk_size) {
+                replacer.feed(chunk, &mut output);
+            }
+            replacer.finish(&mut output);
+            assert_eq!(output, expected, "chunk size: {}", chunk_size);
+            assert_eq!(replacer.position(), source.len());
+            assert_eq!(replacer.matches(), 6);
+        }
+        assert_eq!(Replacement::new(b"ab", b"a"), None);
+        assert_eq!(Replacement::new(b"", b""), None);
+    }
+}
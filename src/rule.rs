@@ -0,0 +1,347 @@
+//! General swap rules (pure)
+//!
+//! `Op` and `Target` cover the byte operations and position sets beyond `Pattern`
+//! and `Predicate`, a `SwapRule` pairs them and is usable wherever swaps are.
+
+use crate::pattern::{Pattern, Predicate};
+use crate::{BytePattern, PositionPredicate};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::Range;
+
+/// Byte operation.
+///
+/// ```
+/// use bswp::BytePattern;
+/// use bswp::pattern::Pattern;
+/// use bswp::rule::Op;
+///
+/// assert_eq!(Op::Set(Pattern::new(0x0F).with_mask(0x0F)).eval(0xA0), 0xAF);
+/// assert_eq!(Op::Xor(0xFF).eval(0x0F), 0xF0);
+/// assert_eq!(Op::And(0x0F).eval(0xAB), 0x0B);
+/// assert_eq!(Op::Or(0x0F).eval(0xA0), 0xAF);
+/// assert_eq!(Op::Not.eval(0x0F), 0xF0);
+/// assert_eq!(Op::Reverse(4).source_lane(0), 3);
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// sets bits from `value` according to `mask`
+    Set(Pattern),
+    /// xors with the given key
+    Xor(u8),
+    /// ands with the given mask
+    And(u8),
+    /// ors with the given mask
+    Or(u8),
+    /// inverts every bit
+    Not,
+    /// reverses the byte order within every group of the given size
+    Reverse(u8),
+}
+
+impl BytePattern for Op {
+    fn eval(&self, value: u8) -> u8 {
+        match self {
+            Op::Set(pattern) => pattern.eval(value),
+            Op::Xor(key) => value ^ key,
+            Op::And(mask) => value & mask,
+            Op::Or(mask) => value | mask,
+            Op::Not => !value,
+            Op::Reverse(_) => value,
+        }
+    }
+
+    fn constant(&self) -> Option<u8> {
+        match self {
+            Op::Set(pattern) => pattern.constant(),
+            Op::And(0x00) => Some(0x00),
+            Op::Or(0xFF) => Some(0xFF),
+            _ => None,
+        }
+    }
+
+    fn group_size(&self) -> usize {
+        match self {
+            Op::Reverse(size) => usize::from(*size).max(1),
+            _ => 1,
+        }
+    }
+
+    fn source_lane(&self, lane: usize) -> usize {
+        self.group_size() - 1 - lane
+    }
+}
+
+impl From<Pattern> for Op {
+    fn from(pattern: Pattern) -> Self {
+        Op::Set(pattern)
+    }
+}
+
+/// Set of byte positions.
+///
+/// ```
+/// use bswp::PositionPredicate;
+/// use bswp::pattern::Predicate;
+/// use bswp::rule::Target;
+///
+/// assert!(Target::Periodic(Predicate::new().with_periodicity(2)).eval(4));
+/// assert!(Target::Range(2..4).eval(3) && !Target::Range(2..4).eval(4));
+/// assert!(Target::Offsets(vec![1, 5]).eval(5) && !Target::Offsets(vec![1, 5]).eval(3));
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// positions matching a periodic predicate
+    Periodic(Predicate),
+    /// every position in a range
+    Range(Range<usize>),
+    /// explicit positions, in increasing order
+    Offsets(Vec<usize>),
+}
+
+impl Target {
+    /// Creates a `Target::Offsets`, sorting and deduplicating `offsets`.
+    pub fn offsets(mut offsets: Vec<usize>) -> Self {
+        offsets.sort_unstable();
+        offsets.dedup();
+        Target::Offsets(offsets)
+    }
+}
+
+impl PositionPredicate for Target {
+    fn eval(&self, position: usize) -> bool {
+        match self {
+            Target::Periodic(predicate) => predicate.eval(position),
+            Target::Range(range) => range.contains(&position),
+            Target::Offsets(offsets) => offsets.binary_search(&position).is_ok(),
+        }
+    }
+
+    fn as_periodic(&self) -> Option<Predicate> {
+        match self {
+            Target::Periodic(predicate) => predicate.as_periodic(),
+            Target::Range(range) => Some(
+                Predicate::new()
+                    .with_offset(range.start)
+                    .with_limit(range.len()),
+            ),
+            Target::Offsets(_) => None,
+        }
+    }
+
+    fn next_match(&self, position: usize) -> Option<usize> {
+        match self {
+            Target::Periodic(predicate) => predicate.next_match(position),
+            Target::Range(range) => Some(position.max(range.start)).filter(|&p| p < range.end),
+            Target::Offsets(offsets) => {
+                let index = offsets.partition_point(|&offset| offset < position);
+                offsets.get(index).copied()
+            }
+        }
+    }
+}
+
+impl From<Predicate> for Target {
+    fn from(predicate: Predicate) -> Self {
+        Target::Periodic(predicate)
+    }
+}
+
+/// Byte operation applied at a set of positions, usable wherever swaps are.
+///
+/// ```
+/// use bswp::pattern::swap_slice;
+/// use bswp::rule::{Op, SwapRule, Target};
+///
+/// let rules: Vec<SwapRule> = vec![
+///     (Op::Xor(0xFF), Target::Range(1..3)),
+///     (Op::Not, Target::offsets(vec![3])),
+/// ];
+/// let mut buffer = [0x0F; 4];
+/// swap_slice(&mut buffer, &rules);
+/// assert_eq!(buffer, [0x0F, 0xF0, 0xF0, 0xF0]);
+/// ```
+pub type SwapRule = (Op, Target);
+
+/// Byte operation deciding the replacement of every matching byte with a closure,
+/// for transforms no built-in pattern covers.
+///
+/// The closure is called with the position and the value of the byte, returning `None`
+/// leaves the byte untouched. Swaps only call it at positions matching their predicate.
+///
+/// ```
+/// use bswp::pattern::{swap_slice, Predicate};
+/// use bswp::rule::Callback;
+///
+/// // rolling key, one more per position
+/// let callback = Callback::new(|position, value| Some(value.wrapping_add(position as u8)));
+/// let mut buffer = [0x10; 4];
+/// swap_slice(&mut buffer, &[(callback, Predicate::new().with_offset(1))]);
+/// assert_eq!(buffer, [0x10, 0x11, 0x12, 0x13]);
+///
+/// // only even values
+/// let mut halved = 0;
+/// let callback = Callback::new(|_, value: u8| {
+///     halved += 1;
+///     (value % 2 == 0).then(|| value / 2)
+/// });
+/// let mut buffer = [0x10, 0x11, 0x12];
+/// swap_slice(&mut buffer, &[(callback, Predicate::new())]);
+/// assert_eq!(buffer, [0x08, 0x11, 0x09]);
+/// assert_eq!(halved, 3);
+/// ```
+///
+/// `SwapPlan::compile` rejects callbacks since they cannot be tabulated.
+pub struct Callback<F> {
+    callback: RefCell<F>,
+}
+
+impl<F: FnMut(usize, u8) -> Option<u8>> Callback<F> {
+    /// Creates a `Callback` calling `callback(position, value)`.
+    pub fn new(callback: F) -> Self {
+        Callback {
+            callback: RefCell::new(callback),
+        }
+    }
+
+    /// Returns the underlying closure.
+    pub fn into_inner(self) -> F {
+        self.callback.into_inner()
+    }
+}
+
+impl<F: FnMut(usize, u8) -> Option<u8>> BytePattern for Callback<F> {
+    /// Returns `value` untouched, the closure needs the position (see `eval_at`).
+    fn eval(&self, value: u8) -> u8 {
+        value
+    }
+
+    fn eval_at(&self, position: usize, value: u8) -> u8 {
+        (self.callback.borrow_mut())(position, value).unwrap_or(value)
+    }
+
+    fn is_positional(&self) -> bool {
+        true
+    }
+}
+
+impl<F> fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callback").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::io::swap_io;
+    use crate::pattern::{diff_iter, swap_iter, swap_iter_chained, swap_iter_from, swap_slice};
+    use crate::pattern::{swap_slice_from, Predicate};
+    use crate::plan::SwapPlan;
+    use crate::rule::{Callback, Op, SwapRule, Target};
+    use crate::PositionPredicate;
+
+    #[test]
+    fn test_targets_fast_path() {
+        let rules: Vec<SwapRule> = vec![
+            (Op::Xor(0x55), Target::Range(3..17)),
+            (Op::And(0x00), Target::Range(40..41)),
+            (
+                Op::Or(0x80),
+                Target::Periodic(Predicate::new().with_periodicity(3)),
+            ),
+            (Op::Not, Target::offsets(vec![30, 2, 2, 63])),
+        ];
+        let source: Vec<u8> = (0..64).collect();
+        let expected: Vec<u8> = swap_iter(&source, &rules).collect();
+        let mut buffer = source.clone();
+        swap_slice(&mut buffer, &rules);
+        assert_eq!(buffer, expected);
+
+        let offsets = Target::offsets(vec![30, 2, 63]);
+        let matches: Vec<Option<usize>> = [0, 3, 31, 63, 64]
+            .iter()
+            .map(|&position| offsets.next_match(position))
+            .collect();
+        assert_eq!(matches, vec![Some(2), Some(30), Some(63), Some(63), None]);
+        assert_eq!(Target::Range(3..5).next_match(5), None);
+    }
+
+    #[test]
+    fn test_reverse_groups() {
+        let rules: Vec<SwapRule> = vec![
+            (Op::Xor(0xF0), Target::Range(0..2)),
+            (Op::Reverse(4), Target::Periodic(Predicate::new())),
+            (Op::Reverse(2), Target::Range(8..11)),
+        ];
+        let source: Vec<u8> = (0..14).collect();
+        let mut expected = source.clone();
+        expected[..2].iter_mut().for_each(|byte| *byte ^= 0xF0);
+        for group in expected[..12].chunks_mut(4) {
+            group.reverse();
+        }
+        expected.swap(8, 9); // only the range part of group 10..12 moves
+        expected[10] = expected[11];
+
+        let mut buffer = source.clone();
+        swap_slice(&mut buffer, &rules);
+        assert_eq!(buffer, expected);
+        assert_eq!(swap_iter(&source, &rules).collect::<Vec<u8>>(), expected);
+        let segments: Vec<&[u8]> = source.chunks(3).collect();
+        let chained: Vec<u8> = swap_iter_chained(&segments, &rules).collect();
+        assert_eq!(chained, expected);
+        let changed: Vec<usize> = diff_iter(&source, &rules).map(|c| c.offset).collect();
+        assert_eq!(changed, (0..12).collect::<Vec<usize>>());
+
+        // partial groups at chunk boundaries are left untouched
+        let mut buffer = source.clone();
+        swap_slice_from(&mut buffer[2..9], &rules, 2);
+        assert_eq!(&buffer[..10], &[0, 1, 2, 3, 7, 6, 5, 4, 8, 9][..]);
+        let swapped: Vec<u8> = swap_iter_from(&source[2..9], &rules, 2).collect();
+        assert_eq!(swapped, &buffer[2..9]);
+    }
+
+    #[test]
+    fn test_callback_positions() {
+        let source: Vec<u8> = (0..50_000).map(|i| (i % 251) as u8).collect();
+        let expected: Vec<u8> = source
+            .iter()
+            .enumerate()
+            .map(|(position, &value)| {
+                if position >= 7 && (position - 7) % 3 == 0 && value < 100 {
+                    value ^ (position as u8)
+                } else {
+                    value
+                }
+            })
+            .collect();
+        let callback = || {
+            Callback::new(|position: usize, value: u8| {
+                (value < 100).then_some(value ^ (position as u8))
+            })
+        };
+        let predicate = Predicate::new().with_periodicity(3).with_offset(7);
+        let swaps = &[(callback(), predicate)];
+
+        let mut buffer = source.clone();
+        swap_slice(&mut buffer, swaps);
+        assert_eq!(buffer, expected);
+        let swapped: Vec<u8> = swap_iter(&source, swaps).collect();
+        assert_eq!(swapped, expected);
+        let xor = Callback::new(|position: usize, value: u8| Some(value ^ (position as u8)));
+        let targets = &[(xor, Target::Range(0..usize::MAX))];
+        let mut buffer = source.clone();
+        for (index, chunk) in buffer.chunks_mut(11).enumerate() {
+            swap_slice_from(chunk, targets, index * 11);
+        }
+        let mut writer: Vec<u8> = Vec::new();
+        swap_io(&mut &buffer[..], &mut writer, targets).unwrap();
+        assert_eq!(writer, source); // xor undone
+        let mut writer: Vec<u8> = Vec::new();
+        swap_io(&mut &source[..], &mut writer, swaps).unwrap();
+        assert_eq!(writer, expected);
+        assert!(SwapPlan::compile(swaps).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! Executable sections (pure), requires the `object` feature.
+//!
+//! Locates the sections of ELF, PE and Mach-O files so that rules target a section
+//! (`.text`, `.rodata`...) instead of raw file offsets, which move from one build to the next.
+
+use crate::pattern::Predicate;
+use crate::region::RegionMap;
+use object::{Object, ObjectSection};
+use std::fmt;
+
+/// Error returned when sections cannot be located.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SectionError {
+    /// the file is not a supported executable or object file
+    Object(object::Error),
+    /// the file has no section with this name, or the section has no data in the file
+    UnknownSection(String),
+}
+
+impl fmt::Display for SectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SectionError::Object(error) => write!(f, "invalid executable: {}", error),
+            SectionError::UnknownSection(name) => write!(f, "unknown section {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for SectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SectionError::Object(error) => Some(error),
+            SectionError::UnknownSection(_) => None,
+        }
+    }
+}
+
+impl From<object::Error> for SectionError {
+    fn from(error: object::Error) -> Self {
+        SectionError::Object(error)
+    }
+}
+
+/// Returns the file positions of the sections of the executable `data`, by section name.
+///
+/// Sections without data in the file (e.g. `.bss`) are left out. Rules declared relative
+/// to a section are resolved with `RegionMap::resolve_all` (see `region::RegionRule`).
+pub fn section_map(data: &[u8]) -> Result<RegionMap, SectionError> {
+    let file = object::File::parse(data)?;
+    let mut regions = RegionMap::new();
+    for section in file.sections() {
+        if let (Ok(name), Some((offset, len))) = (section.name(), section.file_range()) {
+            regions.insert(name, offset as usize, len as usize);
+        }
+    }
+    Ok(regions)
+}
+
+/// Returns a predicate matching every byte of section `name` of the executable `data`.
+///
+/// ```no_run
+/// use bswp::pattern::{swap_slice, Pattern};
+/// use bswp::section::section_predicate;
+///
+/// let mut data = std::fs::read("firmware.elf").unwrap();
+/// let rodata = section_predicate(&data, ".rodata").unwrap();
+/// swap_slice(&mut data, &[(Pattern::new(0x00).with_mask(0x80), rodata)]);
+/// ```
+pub fn section_predicate(data: &[u8], name: &str) -> Result<Predicate, SectionError> {
+    let region = section_map(data)?
+        .get(name)
+        .ok_or_else(|| SectionError::UnknownSection(name.to_string()))?;
+    Ok(Predicate::new()
+        .with_offset(region.start)
+        .with_limit(region.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{swap_slice, Pattern, Predicate};
+    use crate::region::RegionRule;
+    use crate::rule::Op;
+    use crate::section::{section_map, section_predicate, SectionError};
+
+    /// Returns a minimal 64-bit little-endian ELF file with `.text`, `.shstrtab` and `.bss`.
+    fn elf(text: &[u8; 16]) -> Vec<u8> {
+        let names = b"\0.text\0.shstrtab\0.bss\0";
+        let shoff = 104u64;
+        let mut file = Vec::new();
+        file.extend_from_slice(b"\x7FELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+        for half in [2u16, 0x3E] {
+            file.extend_from_slice(&half.to_le_bytes()); // type, machine
+        }
+        file.extend_from_slice(&1u32.to_le_bytes()); // version
+        for word in [0u64, 0, shoff] {
+            file.extend_from_slice(&word.to_le_bytes()); // entry, phoff, shoff
+        }
+        file.extend_from_slice(&0u32.to_le_bytes()); // flags
+        for half in [64u16, 56, 0, 64, 4, 2] {
+            file.extend_from_slice(&half.to_le_bytes()); // sizes, counts, shstrndx
+        }
+        file.extend_from_slice(text); // 64..80
+        file.extend_from_slice(names); // 80..102
+        file.resize(shoff as usize, 0);
+        // name, type, flags, address, offset, size, alignment
+        let sections: [(u32, u32, u64, u64, u64, u64, u64); 4] = [
+            (0, 0, 0, 0, 0, 0, 0),
+            (1, 1, 6, 0x1000, 64, 16, 16),
+            (7, 3, 0, 0, 80, names.len() as u64, 1),
+            (17, 8, 3, 0x2000, 96, 64, 8),
+        ];
+        for (name, kind, flags, address, offset, size, align) in sections {
+            file.extend_from_slice(&name.to_le_bytes());
+            file.extend_from_slice(&kind.to_le_bytes());
+            for word in [flags, address, offset, size] {
+                file.extend_from_slice(&word.to_le_bytes());
+            }
+            file.extend_from_slice(&[0; 8]); // link, info
+            file.extend_from_slice(&align.to_le_bytes());
+            file.extend_from_slice(&0u64.to_le_bytes()); // entry size
+        }
+        file
+    }
+
+    #[test]
+    fn test_elf_sections() {
+        let mut data = elf(&[0x90; 16]);
+        let regions = section_map(&data).unwrap();
+        assert_eq!(regions.get(".text"), Some(64..80));
+        assert_eq!(regions.get(".bss"), None);
+
+        let text = section_predicate(&data, ".text").unwrap();
+        swap_slice(&mut data, &[(Pattern::new(0xCC), text)]);
+        assert_eq!(data[63..81], elf(&[0xCC; 16])[63..81]);
+
+        let rules = [RegionRule::new(
+            Op::Xor(0x01),
+            ".text",
+            Predicate::new().with_periodicity(4),
+        )];
+        let swaps = regions.resolve_all(&rules).unwrap();
+        swap_slice(&mut data, &swaps);
+        assert_eq!(data[64..69], [0xCD, 0xCC, 0xCC, 0xCC, 0xCD]);
+
+        assert!(matches!(
+            section_predicate(&data, ".rodata"),
+            Err(SectionError::UnknownSection(_))
+        ));
+        assert!(matches!(
+            section_map(b"not an executable"),
+            Err(SectionError::Object(_))
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! Rule set with explicit conflict policy (pure)
+
+use crate::{BytePattern, PositionPredicate};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// What to do when several rules match the same position.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Apply every matching rule in order, each rule seeing the previous rule output
+    /// (same as `swap_iter`, `swap_slice` and `swap_io`).
+    Chain,
+    /// Only apply the first matching rule.
+    FirstWins,
+    /// Only apply the last matching rule.
+    LastWins,
+    /// Fail if several rules match the same position.
+    ErrorOnOverlap,
+}
+
+impl Default for ConflictPolicy {
+    /// `ConflictPolicy::Chain`
+    fn default() -> Self {
+        ConflictPolicy::Chain
+    }
+}
+
+/// Several rules matching the same position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// byte position
+    pub position: usize,
+    /// indices of the matching rules, in order
+    pub rules: Vec<usize>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rules {:?} overlap at position {}",
+            self.rules, self.position
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Conflict {}
+
+/// Ordered rules with a `ConflictPolicy`.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::set::{ConflictPolicy, SwapSet};
+///
+/// let set = SwapSet::new()
+///     .with_swap(Pattern::new(0x0F).with_mask(0x0F), Predicate::new().with_periodicity(2))
+///     .with_swap(Pattern::new(0xF0).with_mask(0xF0), Predicate::new().with_limit(2));
+///
+/// let mut buffer = [0x00; 4];
+/// set.apply_slice(&mut buffer).unwrap();
+/// assert_eq!(buffer, [0xFF, 0xF0, 0x0F, 0x00]);
+///
+/// let set = set.with_policy(ConflictPolicy::FirstWins);
+/// let mut buffer = [0x00; 4];
+/// set.apply_slice(&mut buffer).unwrap();
+/// assert_eq!(buffer, [0x0F, 0xF0, 0x0F, 0x00]);
+///
+/// let set = set.with_policy(ConflictPolicy::ErrorOnOverlap);
+/// let mut buffer = [0x00; 4];
+/// let conflict = set.apply_slice(&mut buffer).unwrap_err();
+/// assert_eq!(conflict.position, 0);
+/// assert_eq!(buffer, [0x00; 4]); // untouched
+/// assert_eq!(set.check_conflicts(4), vec![conflict]);
+/// ```
+pub struct SwapSet<P, Q> {
+    swaps: Vec<(P, Q)>,
+    policy: ConflictPolicy,
+}
+
+impl<P, Q> Default for SwapSet<P, Q> {
+    fn default() -> Self {
+        SwapSet {
+            swaps: Vec::new(),
+            policy: ConflictPolicy::default(),
+        }
+    }
+}
+
+impl<P: BytePattern, Q: PositionPredicate> SwapSet<P, Q> {
+    /// Creates an empty `SwapSet` with the default `ConflictPolicy`.
+    pub fn new() -> Self {
+        SwapSet::default()
+    }
+
+    /// Sets the `ConflictPolicy`.
+    ///
+    /// **Default**: `ConflictPolicy::Chain`
+    pub fn with_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Appends a rule, rules are considered in insertion order.
+    pub fn with_swap(mut self, pattern: P, predicate: Q) -> Self {
+        self.push(pattern, predicate);
+        self
+    }
+
+    /// Appends a rule, rules are considered in insertion order.
+    pub fn push(&mut self, pattern: P, predicate: Q) {
+        self.swaps.push((pattern, predicate));
+    }
+
+    /// Returns the `ConflictPolicy`.
+    pub fn policy(&self) -> ConflictPolicy {
+        self.policy
+    }
+
+    /// Returns the rules in order.
+    pub fn swaps(&self) -> &[(P, Q)] {
+        &self.swaps
+    }
+
+    /// Returns the indices of the rules matching `position`, in order.
+    fn matching(&self, position: usize) -> impl Iterator<Item = usize> + '_ {
+        self.swaps
+            .iter()
+            .enumerate()
+            .filter(move |(_, (_, predicate))| predicate.eval(position))
+            .map(|(index, _)| index)
+    }
+
+    fn conflict(&self, position: usize) -> Option<Conflict> {
+        let mut matching = self.matching(position);
+        match (matching.next(), matching.next()) {
+            (Some(first), Some(second)) => {
+                let mut rules = vec![first, second];
+                rules.extend(matching);
+                Some(Conflict { position, rules })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns every position in `0..len` matched by several rules.
+    pub fn check_conflicts(&self, len: usize) -> Vec<Conflict> {
+        (0..len)
+            .filter_map(|position| self.conflict(position))
+            .collect()
+    }
+
+    /// Returns `value` swapped according to the policy, `value` being at `position`.
+    pub fn eval(&self, position: usize, value: u8) -> Result<u8, Conflict> {
+        let rule = match self.policy {
+            ConflictPolicy::Chain => {
+                return Ok(self.matching(position).fold(value, |value, index| {
+                    self.swaps[index].0.eval_at(position, value)
+                }))
+            }
+            ConflictPolicy::FirstWins => self.matching(position).next(),
+            ConflictPolicy::LastWins => self.matching(position).last(),
+            ConflictPolicy::ErrorOnOverlap => {
+                if let Some(conflict) = self.conflict(position) {
+                    return Err(conflict);
+                }
+                self.matching(position).next()
+            }
+        };
+        Ok(rule.map_or(value, |index| self.swaps[index].0.eval_at(position, value)))
+    }
+
+    /// Swaps bytes from `buffer` in place.
+    ///
+    /// With `ConflictPolicy::ErrorOnOverlap`, `buffer` is left untouched on conflict.
+    pub fn apply_slice(&self, buffer: &mut [u8]) -> Result<(), Conflict> {
+        self.apply_slice_from(buffer, 0)
+    }
+
+    /// Swaps bytes from `buffer` in place, `buffer[0]` being at position `start`.
+    ///
+    /// With `ConflictPolicy::ErrorOnOverlap`, `buffer` is left untouched on conflict.
+    pub fn apply_slice_from(&self, buffer: &mut [u8], start: usize) -> Result<(), Conflict> {
+        if self.policy == ConflictPolicy::ErrorOnOverlap {
+            if let Some(conflict) =
+                (start..start + buffer.len()).find_map(|position| self.conflict(position))
+            {
+                return Err(conflict);
+            }
+        }
+        for (index, item) in buffer.iter_mut().enumerate() {
+            *item = self.eval(start + index, *item)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{swap_iter_from, Pattern, Predicate};
+    use crate::set::{ConflictPolicy, SwapSet};
+
+    #[test]
+    fn test_policies() {
+        let set = SwapSet::new()
+            .with_swap(Pattern::new(0x01), Predicate::new().with_periodicity(2))
+            .with_swap(Pattern::new(0x02), Predicate::new().with_periodicity(3))
+            .with_swap(Pattern::new(0x03), Predicate::new().with_offset(4));
+
+        let source = [0u8; 8];
+        let chained: Vec<u8> = swap_iter_from(&source, set.swaps(), 0).collect();
+        let mut buffer = source;
+        set.apply_slice(&mut buffer).unwrap();
+        assert_eq!(buffer.to_vec(), chained);
+
+        let set = set.with_policy(ConflictPolicy::FirstWins);
+        let mut buffer = source;
+        set.apply_slice(&mut buffer).unwrap();
+        assert_eq!(buffer, [1, 0, 1, 2, 1, 3, 1, 3]);
+
+        let set = set.with_policy(ConflictPolicy::LastWins);
+        let mut buffer = source;
+        set.apply_slice(&mut buffer).unwrap();
+        assert_eq!(buffer, [2, 0, 1, 2, 3, 3, 3, 3]);
+
+        let conflicts: Vec<usize> = set
+            .check_conflicts(8)
+            .iter()
+            .map(|conflict| conflict.position)
+            .collect();
+        assert_eq!(conflicts, vec![0, 4, 6]);
+        assert_eq!(set.check_conflicts(8)[2].rules, vec![0, 1, 2]);
+
+        let set = set.with_policy(ConflictPolicy::ErrorOnOverlap);
+        let mut buffer = source;
+        assert!(set.apply_slice_from(&mut buffer[1..4], 1).is_ok());
+        assert_eq!(buffer, [0, 0, 1, 2, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,248 @@
+//! Binary signatures with wildcards (pure)
+//!
+//! A signature is a sequence of hexadecimal bytes where `??` matches any byte and
+//! `?` any nibble, the usual way to describe patch locations:
+//!
+//! ```text
+//! DE AD ?? BE EF
+//! 48 8B 0? ?? E8
+//! ```
+
+use crate::dsl::ParseError;
+use crate::rule::Target;
+use std::fmt;
+use std::str::FromStr;
+
+/// Byte sequence with wildcards: each byte matches if its bits under `mask` equal `value`.
+///
+/// ```
+/// use bswp::signature::Signature;
+///
+/// let signature: Signature = "DE AD ?? BE EF".parse().unwrap();
+/// let haystack = [0x00, 0xDE, 0xAD, 0x42, 0xBE, 0xEF, 0xDE, 0xAD, 0x00, 0xBE, 0xEF];
+/// assert_eq!(signature.find_iter(&haystack).collect::<Vec<usize>>(), vec![1, 6]);
+/// assert_eq!(signature.to_string(), "DE AD ?? BE EF");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Signature {
+    /// `(value, mask)` for every byte
+    bytes: Vec<(u8, u8)>,
+}
+
+impl Signature {
+    /// Creates a signature matching exactly `bytes`.
+    pub fn exact(bytes: &[u8]) -> Self {
+        Signature {
+            bytes: bytes.iter().map(|&value| (value, 0xFF)).collect(),
+        }
+    }
+
+    /// Creates a signature from `(value, mask)` pairs, bits outside `mask` match anything.
+    pub fn from_masked(bytes: Vec<(u8, u8)>) -> Self {
+        let bytes = bytes
+            .into_iter()
+            .map(|(value, mask)| (value & mask, mask))
+            .collect();
+        Signature { bytes }
+    }
+
+    /// Parses space separated hexadecimal bytes, `?` standing for any nibble.
+    ///
+    /// ```
+    /// use bswp::signature::Signature;
+    ///
+    /// let signature = Signature::parse("0? ??").unwrap();
+    /// assert!(signature.matches(&[0x0A, 0xFF]) && !signature.matches(&[0x1A, 0xFF]));
+    ///
+    /// let error = Signature::parse("DE AD BEEF").unwrap_err();
+    /// assert_eq!(error.column(), 7);
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut bytes = Vec::new();
+        let mut column = 1;
+        for word in text.split(' ') {
+            if !word.is_empty() {
+                bytes.push(parse_byte(word, column)?);
+            }
+            column += word.len() + 1;
+        }
+        if bytes.is_empty() {
+            return Err(ParseError::new(1, "expected at least one byte"));
+        }
+        Ok(Signature { bytes })
+    }
+
+    /// Returns the number of bytes matched.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns `true` if the signature matches nothing but empty sequences.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns `true` if `window` starts with the signature.
+    pub fn matches(&self, window: &[u8]) -> bool {
+        window.len() >= self.bytes.len()
+            && self
+                .bytes
+                .iter()
+                .zip(window)
+                .all(|(&(value, mask), &byte)| byte & mask == value)
+    }
+
+    /// Returns an iterator on the offsets of every match in `haystack`, overlapping matches included.
+    pub fn find_iter<'a>(&'a self, haystack: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let len = self.bytes.len().max(1);
+        haystack
+            .windows(len)
+            .enumerate()
+            .filter(move |(_, window)| self.matches(window))
+            .map(|(offset, _)| offset)
+    }
+
+    /// Returns the positions `match + offset .. match + offset + len` for every match in `haystack`.
+    ///
+    /// ```
+    /// use bswp::pattern::swap_slice;
+    /// use bswp::rule::Op;
+    /// use bswp::signature::Signature;
+    ///
+    /// // turn every short jne followed by a nop into a jmp
+    /// let signature = Signature::parse("75 ?? 90").unwrap();
+    /// let mut code = [0x90, 0x75, 0x04, 0x90, 0x75, 0x08];
+    /// let target = signature.target(&code, 0, 1);
+    /// swap_slice(&mut code, &[(Op::Xor(0x75 ^ 0xEB), target)]);
+    /// assert_eq!(code, [0x90, 0xEB, 0x04, 0x90, 0x75, 0x08]);
+    /// ```
+    pub fn target(&self, haystack: &[u8], offset: usize, len: usize) -> Target {
+        let positions = self
+            .find_iter(haystack)
+            .flat_map(|start| (start + offset..start + offset + len).collect::<Vec<usize>>())
+            .collect();
+        Target::offsets(positions)
+    }
+}
+
+impl FromStr for Signature {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Signature::parse(text)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, &(value, mask)) in self.bytes.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            for shift in [4, 0] {
+                match (mask >> shift) & 0x0F {
+                    0x0F => write!(f, "{:X}", (value >> shift) & 0x0F)?,
+                    _ => write!(f, "?")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses `XY`, each digit being hexadecimal or `?`.
+fn parse_byte(word: &str, column: usize) -> Result<(u8, u8), ParseError> {
+    let invalid = || {
+        ParseError::new(
+            column,
+            format!("expected byte like 4F or ??, found {:?}", word),
+        )
+    };
+    if word.len() != 2 {
+        return Err(invalid());
+    }
+    let mut byte = (0, 0);
+    for digit in word.chars() {
+        let (value, mask) = match digit {
+            '?' => (0, 0),
+            digit => (digit.to_digit(16).ok_or_else(invalid)? as u8, 0x0F),
+        };
+        byte = ((byte.0 << 4) | value, (byte.1 << 4) | mask);
+    }
+    Ok(byte)
+}
+
+/// Finds signature matches across fed chunks.
+///
+/// ```
+/// use bswp::signature::{Signature, SignatureScanner};
+///
+/// let signature = Signature::parse("BE ?? EF").unwrap();
+/// let mut scanner = SignatureScanner::new(&signature);
+/// let mut matches = Vec::new();
+/// scanner.feed(&[0x00, 0xBE], &mut matches);
+/// scanner.feed(&[0x42, 0xEF], &mut matches);
+/// assert_eq!(matches, vec![1]);
+/// ```
+pub struct SignatureScanner<'a> {
+    signature: &'a Signature,
+    /// last bytes fed, possibly starting a match
+    tail: Vec<u8>,
+    /// position of the next byte fed
+    position: usize,
+}
+
+impl<'a> SignatureScanner<'a> {
+    /// Creates a new `SignatureScanner` starting at position `0`.
+    pub fn new(signature: &'a Signature) -> Self {
+        SignatureScanner {
+            signature,
+            tail: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Returns the position of the next byte fed.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Searches `chunk`, appending the offsets of matches ending in `chunk` to `matches`.
+    pub fn feed(&mut self, chunk: &[u8], matches: &mut Vec<usize>) {
+        let start = self.position - self.tail.len();
+        self.tail.extend_from_slice(chunk);
+        matches.extend(
+            self.signature
+                .find_iter(&self.tail)
+                .map(|offset| start + offset),
+        );
+        let keep = self.signature.len().saturating_sub(1).min(self.tail.len());
+        self.tail.drain(..self.tail.len() - keep);
+        self.position += chunk.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signature::{Signature, SignatureScanner};
+
+    #[test]
+    fn test_scan_chunks() {
+        let signature: Signature = "?1 ?? 3?".parse().unwrap();
+        let haystack: Vec<u8> = (0..64).map(|i| (i % 5) as u8 * 0x11).collect();
+        let expected: Vec<usize> = signature.find_iter(&haystack).collect();
+        assert_eq!(expected, (0..62).filter(|i| i % 5 == 1).collect::<Vec<_>>());
+        for chunk_size in 1..haystack.len() {
+            let mut scanner = SignatureScanner::new(&signature);
+            let mut matches = Vec::new();
+            for chunk in haystack.chunks(chunk_size) {
+                scanner.feed(chunk, &mut matches);
+            }
+            assert_eq!(matches, expected, "chunk size: {}", chunk_size);
+        }
+        assert_eq!(signature.to_string(), "?1 ?? 3?");
+        assert_eq!(Signature::exact(&[0xAB]).to_string(), "AB");
+        assert!(Signature::parse("  ").is_err());
+        assert_eq!(Signature::parse("DE G0").unwrap_err().column(), 4);
+    }
+}
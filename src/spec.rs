@@ -0,0 +1,1054 @@
+//! Swap specifications (pure), requires the `toml`, `json` or `yaml` feature.
+//!
+//! A specification lists rules, each rule being an operation and a set of positions:
+//!
+//! ```toml
+//! [[rule]]
+//! value = 0x42
+//! mask = "0xFF"     # optional, default: 0xFF
+//! periodicity = 2   # optional, default: 1
+//! offset = 1        # optional, default: 0
+//! limit = 4         # optional, default: no limit
+//!
+//! [[rule]]
+//! op = "xor"        # optional, one of set (default), xor, and, or, not
+//! value = 0x55      # operand, unused by not
+//! range = [16, 32]  # positions 16..32, instead of periodicity, offset and limit
+//!
+//! [[rule]]
+//! op = "not"
+//! offsets = [3, 7]  # explicit positions, instead of periodicity, offset and limit
+//!
+//! [[rule]]
+//! preset = "zero-fill"  # named operation instead of op, value and mask
+//! range = [0, 4]
+//! ```
+//!
+//! Numbers are either integers or strings, strings accepting hexadecimal (`"0xF0"`),
+//! binary (`"0b1111"`) and decimal (`"240"`) literals.
+
+use crate::dsl::{closest, out_of_range, ParseError};
+use crate::pattern::{Pattern, Predicate};
+use crate::presets::PresetRegistry;
+use crate::rule::{Op, SwapRule, Target};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "toml")]
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+/// Kind of `SpecError`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpecErrorKind {
+    /// malformed document or rule expression
+    Syntax,
+    /// well-formed rule that cannot be applied
+    Invalid,
+}
+
+/// Error returned when a specification cannot be loaded.
+///
+/// Locates the error with a field path (e.g. `rule[1].mask`) and, when known,
+/// a line and column in the source, and may suggest a fix.
+///
+/// ```
+/// use bswp::spec::{SpecErrorKind, SwapSpec};
+///
+/// # #[cfg(feature = "toml")] {
+/// let error = SwapSpec::from_toml("
+/// [[rule]]
+/// value = 0x42
+///
+/// [[rule]]
+/// value = 0x42
+/// mask = \"0x1FF\"
+/// ").unwrap_err();
+/// assert_eq!(error.kind(), SpecErrorKind::Syntax);
+/// assert_eq!(error.path(), Some("rule[1].mask"));
+/// assert_eq!((error.line(), error.column()), (Some(7), Some(8)));
+/// assert_eq!(error.message(), "0x1FF does not fit in a byte");
+/// assert_eq!(error.suggestion(), Some("use a value up to 0xFF"));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SpecError {
+    details: Box<Details>,
+}
+
+#[derive(Debug)]
+struct Details {
+    kind: SpecErrorKind,
+    rule: Option<usize>,
+    path: Option<String>,
+    location: Option<(usize, usize)>,
+    message: String,
+    suggestion: Option<String>,
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+impl SpecError {
+    fn new(kind: SpecErrorKind, message: impl Into<String>) -> Self {
+        SpecError {
+            details: Box::new(Details {
+                kind,
+                rule: None,
+                path: None,
+                location: None,
+                message: message.into(),
+                suggestion: None,
+                source: None,
+            }),
+        }
+    }
+
+    /// Invalid `field` of a rule.
+    fn invalid(field: &str, message: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        let mut error = SpecError::new(SpecErrorKind::Invalid, message);
+        error.details.path = Some(field.to_string());
+        error.details.suggestion = Some(suggestion.into());
+        error
+    }
+
+    /// Malformed document, `source` knowing the path to the malformed value.
+    #[cfg(any(feature = "toml", feature = "json", feature = "yaml"))]
+    fn syntax<E>(source: serde_path_to_error::Error<E>, message: String) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut error = SpecError::new(SpecErrorKind::Syntax, message);
+        let path = source.path().to_string();
+        if path != "." {
+            error.details.rule = path
+                .strip_prefix("rule[")
+                .and_then(|rest| rest.split(']').next())
+                .and_then(|index| index.parse().ok());
+            error.details.path = Some(path);
+        }
+        error.details.suggestion = suggest(&error.details.message);
+        error.details.source = Some(Box::new(source.into_inner()));
+        error
+    }
+
+    /// Prefixes the path with rule `index`.
+    fn in_rule(mut self, index: usize) -> Self {
+        self.details.path = Some(match self.details.path {
+            Some(field) => format!("rule[{}].{}", index, field),
+            None => format!("rule[{}]", index),
+        });
+        self.details.rule = Some(index);
+        self
+    }
+
+    fn at(mut self, location: Option<(usize, usize)>) -> Self {
+        self.details.location = location;
+        self
+    }
+
+    /// Returns the kind of error.
+    pub fn kind(&self) -> SpecErrorKind {
+        self.details.kind
+    }
+
+    /// Returns the index of the faulty rule, if known.
+    pub fn rule(&self) -> Option<usize> {
+        self.details.rule
+    }
+
+    /// Returns the path of the faulty field, e.g. `rule[1].mask`, if known.
+    pub fn path(&self) -> Option<&str> {
+        self.details.path.as_deref()
+    }
+
+    /// Returns the line of the error in the source, starting at `1`, if known.
+    pub fn line(&self) -> Option<usize> {
+        self.details.location.map(|(line, _)| line)
+    }
+
+    /// Returns the column of the error in the source, starting at `1`, if known.
+    pub fn column(&self) -> Option<usize> {
+        self.details.location.map(|(_, column)| column)
+    }
+
+    /// Returns what is wrong, without location.
+    pub fn message(&self) -> &str {
+        &self.details.message
+    }
+
+    /// Returns a hint on how to fix the specification, if any.
+    pub fn suggestion(&self) -> Option<&str> {
+        self.details.suggestion.as_deref()
+    }
+}
+
+impl fmt::Display for SpecError {
+    /// Formats as `line:column: path: message (help: suggestion)`, omitting unknown parts.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((line, column)) = self.details.location {
+            write!(f, "{}:{}: ", line, column)?;
+        }
+        if let Some(path) = &self.details.path {
+            write!(f, "{}: ", path)?;
+        }
+        write!(f, "{}", self.details.message)?;
+        if let Some(suggestion) = &self.details.suggestion {
+            write!(f, " (help: {})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SpecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.details.source {
+            Some(source) => Some(source.as_ref()),
+            None => None,
+        }
+    }
+}
+
+impl From<ParseError> for SpecError {
+    /// Surfaces a rule expression error, located in the expression.
+    fn from(error: ParseError) -> Self {
+        let mut spec_error = SpecError::new(SpecErrorKind::Syntax, error.message());
+        spec_error.details.location = Some((error.line(), error.column()));
+        spec_error.details.suggestion = error.suggestion().map(String::from);
+        spec_error.details.source = Some(Box::new(error));
+        spec_error
+    }
+}
+
+/// Suggests a fix for a deserialization error `message`.
+#[cfg(any(feature = "toml", feature = "json", feature = "yaml"))]
+fn suggest(message: &str) -> Option<String> {
+    if message.ends_with("does not fit in a byte") {
+        return Some("use a value up to 0xFF".to_string());
+    }
+    // serde messages: unknown field `x`, expected one of `a`, `b`
+    let rest = message
+        .strip_prefix("unknown field `")
+        .or_else(|| message.strip_prefix("unknown variant `"))?;
+    let (word, expected) = rest.split_once('`')?;
+    let candidates: Vec<&str> = expected.split('`').skip(1).step_by(2).collect();
+    closest(word, &candidates).map(|candidate| format!("did you mean {:?}?", candidate))
+}
+
+/// Returns the location of the field of a rule in TOML `source` that `error` is about.
+#[cfg(feature = "toml")]
+fn locate_toml(source: &str, error: &SpecError) -> Option<(usize, usize)> {
+    #[derive(Deserialize)]
+    struct SpannedSpec {
+        #[serde(default, rename = "rule")]
+        rules: Vec<BTreeMap<String, toml::Spanned<toml::Value>>>,
+    }
+    let spanned: SpannedSpec = toml::from_str(source).ok()?;
+    let fields = spanned.rules.get(error.rule()?)?;
+    let field = error.path()?.rsplit('.').next()?;
+    let span = match fields.get(field) {
+        Some(value) => value.span(),
+        None => fields
+            .values()
+            .map(toml::Spanned::span)
+            .min_by_key(|span| span.start)?,
+    };
+    Some(line_column(source, span.start))
+}
+
+/// Returns the line and column of byte `offset` in `source`, both starting at `1`.
+#[cfg(feature = "toml")]
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    (line, column)
+}
+
+/// Number written either as an integer or as a string literal.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Integer(u64),
+    Literal(String),
+}
+
+impl Number {
+    fn parse<T: TryFrom<u64>>(self) -> Result<T, String> {
+        let (value, text) = match self {
+            Number::Integer(value) => (value, value.to_string()),
+            Number::Literal(literal) => {
+                let literal = literal.trim().replace('_', "");
+                let (digits, radix) = match literal.get(..2) {
+                    Some("0x") | Some("0X") => (&literal[2..], 16),
+                    Some("0b") | Some("0B") => (&literal[2..], 2),
+                    _ => (&literal[..], 10),
+                };
+                let value = u64::from_str_radix(digits, radix)
+                    .map_err(|e| format!("invalid number {:?}: {}", literal, e))?;
+                (value, literal)
+            }
+        };
+        T::try_from(value).map_err(|_| out_of_range::<T>(&text).0)
+    }
+}
+
+fn number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    Number::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn optional_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    match Option::<Number>::deserialize(deserializer)? {
+        Some(number) => number.parse().map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn optional_numbers<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    match Option::<Vec<Number>>::deserialize(deserializer)? {
+        Some(numbers) => numbers
+            .into_iter()
+            .map(Number::parse)
+            .collect::<Result<_, _>>()
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn optional_range<'de, D>(deserializer: D) -> Result<Option<Range<usize>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<(Number, Number)>::deserialize(deserializer)? {
+        Some((start, end)) => {
+            let start = start.parse().map_err(serde::de::Error::custom)?;
+            let end = end.parse().map_err(serde::de::Error::custom)?;
+            Ok(Some(start..end))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Serializes `range` as `[start, end]`.
+fn serialize_range<S: Serializer>(
+    range: &Option<Range<usize>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    range
+        .as_ref()
+        .map(|range| (range.start, range.end))
+        .serialize(serializer)
+}
+
+fn is_zero(value: &u8) -> bool {
+    *value == 0
+}
+
+/// Operation of a rule, see `rule::Op`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpKind {
+    /// sets bits from `value` according to `mask`
+    #[default]
+    Set,
+    /// xors with `value`
+    Xor,
+    /// ands with `value`
+    And,
+    /// ors with `value`
+    Or,
+    /// inverts every bit
+    Not,
+    /// reverses the byte order within groups of `value` bytes
+    Reverse,
+}
+
+impl OpKind {
+    fn is_set(&self) -> bool {
+        *self == OpKind::Set
+    }
+}
+
+/// Rule as written in a specification.
+///
+/// Missing fields take the `Pattern` and `Predicate` defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSpec {
+    /// name of a preset operation, exclusive with `op`, `value` and `mask`,
+    /// see `presets::PresetRegistry`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// operation
+    #[serde(default, skip_serializing_if = "OpKind::is_set")]
+    pub op: OpKind,
+    /// see `Pattern::value`, operand of `xor`, `and` and `or`, group size of `reverse`
+    #[serde(default, deserialize_with = "number", skip_serializing_if = "is_zero")]
+    pub value: u8,
+    /// see `Pattern::mask`, only used by `set`
+    #[serde(
+        default,
+        deserialize_with = "optional_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mask: Option<u8>,
+    /// see `Predicate::periodicity`
+    #[serde(
+        default,
+        deserialize_with = "optional_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub periodicity: Option<usize>,
+    /// see `Predicate::offset`
+    #[serde(
+        default,
+        deserialize_with = "optional_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub offset: Option<usize>,
+    /// see `Predicate::limit`
+    #[serde(
+        default,
+        deserialize_with = "optional_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub limit: Option<usize>,
+    /// positions `start..end`, written `[start, end]`, see `Target::Range`
+    #[serde(
+        default,
+        deserialize_with = "optional_range",
+        serialize_with = "serialize_range",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub range: Option<Range<usize>>,
+    /// explicit positions, see `Target::Offsets`
+    #[serde(
+        default,
+        deserialize_with = "optional_numbers",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub offsets: Option<Vec<usize>>,
+}
+
+impl RuleSpec {
+    /// Returns the swap rule described by this rule.
+    ///
+    /// Errors have a path relative to the rule, e.g. `mask`.
+    pub fn to_rule(&self) -> Result<SwapRule, SpecError> {
+        self.to_rule_with(&PresetRegistry::new())
+    }
+
+    /// Same as `to_rule`, `preset` referencing `presets`.
+    pub fn to_rule_with(&self, presets: &PresetRegistry) -> Result<SwapRule, SpecError> {
+        let op = match &self.preset {
+            Some(_) if !self.op.is_set() || self.value != 0 || self.mask.is_some() => {
+                return Err(SpecError::invalid(
+                    "preset",
+                    "preset is exclusive with op, value and mask",
+                    "remove op, value and mask",
+                ))
+            }
+            Some(name) => presets.get(name).ok_or_else(|| {
+                let names: Vec<&str> = presets.names().collect();
+                let suggestion = match closest(name, &names) {
+                    Some(candidate) => format!("did you mean {:?}?", candidate),
+                    None => format!("use one of {}", names.join(", ")),
+                };
+                SpecError::invalid("preset", format!("unknown preset {:?}", name), suggestion)
+            })?,
+            None => self.to_op()?,
+        };
+        let periodic = self.periodicity.is_some() || self.offset.is_some() || self.limit.is_some();
+        let target = match (&self.range, &self.offsets) {
+            (Some(_), Some(_)) => {
+                return Err(SpecError::invalid(
+                    "offsets",
+                    "range and offsets are exclusive",
+                    "split the rule in two",
+                ))
+            }
+            (Some(_), None) | (None, Some(_)) if periodic => {
+                let field = if self.range.is_some() {
+                    "range"
+                } else {
+                    "offsets"
+                };
+                return Err(SpecError::invalid(
+                    field,
+                    format!("{} is exclusive with periodicity, offset and limit", field),
+                    "split the rule in two",
+                ));
+            }
+            (Some(range), None) if range.start > range.end => {
+                return Err(SpecError::invalid(
+                    "range",
+                    format!("range start {} is after end {}", range.start, range.end),
+                    format!("use [{}, {}]", range.end, range.start),
+                ))
+            }
+            (Some(range), None) => Target::Range(range.clone()),
+            (None, Some(offsets)) => Target::offsets(offsets.clone()),
+            (None, None) => Target::Periodic(self.to_predicate()?),
+        };
+        Ok((op, target))
+    }
+
+    /// Returns the swap described by this rule, if it sets bits at periodic positions.
+    pub fn to_swap(&self) -> Result<(Pattern, Predicate), SpecError> {
+        match self.to_rule()? {
+            (Op::Set(pattern), Target::Periodic(predicate)) => Ok((pattern, predicate)),
+            _ => Err(SpecError::new(
+                SpecErrorKind::Invalid,
+                "not a set rule at periodic positions",
+            )),
+        }
+    }
+
+    fn to_op(&self) -> Result<Op, SpecError> {
+        let op = match self.op {
+            OpKind::Set => Op::Set(Pattern::new(self.value).with_mask(self.mask.unwrap_or(0xFF))),
+            _ if self.mask.is_some() => {
+                return Err(SpecError::invalid(
+                    "mask",
+                    "mask is only used by set",
+                    "remove mask or use op = \"set\"",
+                ))
+            }
+            OpKind::Xor => Op::Xor(self.value),
+            OpKind::And => Op::And(self.value),
+            OpKind::Or => Op::Or(self.value),
+            OpKind::Not if self.value != 0 => {
+                return Err(SpecError::invalid(
+                    "value",
+                    "value is not used by not",
+                    "remove value",
+                ))
+            }
+            OpKind::Not => Op::Not,
+            OpKind::Reverse if self.value < 2 => {
+                return Err(SpecError::invalid(
+                    "value",
+                    "reverse needs a group size of at least 2",
+                    "use value = 2, 4 or 8",
+                ))
+            }
+            OpKind::Reverse => Op::Reverse(self.value),
+        };
+        Ok(op)
+    }
+
+    fn to_predicate(&self) -> Result<Predicate, SpecError> {
+        let mut predicate = Predicate::new();
+        if let Some(periodicity) = self.periodicity {
+            if periodicity == 0 {
+                return Err(SpecError::invalid(
+                    "periodicity",
+                    "periodicity must not be 0",
+                    "use 1 to match every position",
+                ));
+            }
+            predicate = predicate.with_periodicity(periodicity);
+        }
+        if let Some(offset) = self.offset {
+            predicate = predicate.with_offset(offset);
+        }
+        if let Some(limit) = self.limit {
+            predicate = predicate.with_limit(limit);
+        }
+        Ok(predicate)
+    }
+}
+
+impl From<&SwapRule> for RuleSpec {
+    /// Writes `rule` back, omitting default fields.
+    fn from((op, target): &SwapRule) -> Self {
+        let mut rule = RuleSpec::default();
+        match *op {
+            Op::Set(pattern) => {
+                rule.value = pattern.value;
+                rule.mask = Some(pattern.mask).filter(|&mask| mask != 0xFF);
+            }
+            Op::Xor(key) => {
+                rule.op = OpKind::Xor;
+                rule.value = key;
+            }
+            Op::And(mask) => {
+                rule.op = OpKind::And;
+                rule.value = mask;
+            }
+            Op::Or(mask) => {
+                rule.op = OpKind::Or;
+                rule.value = mask;
+            }
+            Op::Not => rule.op = OpKind::Not,
+            Op::Reverse(size) => {
+                rule.op = OpKind::Reverse;
+                rule.value = size;
+            }
+        }
+        match target {
+            Target::Periodic(predicate) => {
+                rule.periodicity = Some(predicate.periodicity).filter(|&p| p != 1);
+                rule.offset = Some(predicate.offset).filter(|&offset| offset != 0);
+                rule.limit = predicate.limit;
+            }
+            Target::Range(range) => rule.range = Some(range.clone()),
+            Target::Offsets(offsets) => rule.offsets = Some(offsets.clone()),
+        }
+        rule
+    }
+}
+
+/// Specification as written.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSpec {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleSpec>,
+}
+
+/// Ready-to-use swap rules loaded from a specification.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::rule::{Op, Target};
+/// use bswp::spec::SwapSpec;
+///
+/// # #[cfg(feature = "toml")] {
+/// let spec = SwapSpec::from_toml(r#"
+///     [[rule]]
+///     value = 0x42
+///     periodicity = 2
+///     offset = 1
+///
+///     [[rule]]
+///     value = "0xF0"
+///     mask = "0xF0"
+///     limit = 4
+/// "#).unwrap();
+/// assert_eq!(spec.swaps(), &[
+///     (
+///         Op::Set(Pattern::new(0x42)),
+///         Target::Periodic(Predicate::new().with_periodicity(2).with_offset(1)),
+///     ),
+///     (
+///         Op::Set(Pattern::new(0xF0).with_mask(0xF0)),
+///         Target::Periodic(Predicate::new().with_limit(4)),
+///     ),
+/// ]);
+///
+/// let mut buffer = [0x00; 4];
+/// bswp::pattern::swap_slice(&mut buffer, spec.swaps());
+/// assert_eq!(buffer, [0xF0, 0xF2, 0xF0, 0xF2]);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SwapSpec {
+    rules: Vec<RuleSpec>,
+    swaps: Vec<SwapRule>,
+}
+
+impl SwapSpec {
+    /// Creates a specification from `rules`.
+    pub fn from_rules(rules: Vec<RuleSpec>) -> Result<Self, SpecError> {
+        SwapSpec::from_rules_with(rules, &PresetRegistry::new())
+    }
+
+    /// Same as `from_rules`, rules referencing `presets`.
+    pub fn from_rules_with(
+        rules: Vec<RuleSpec>,
+        presets: &PresetRegistry,
+    ) -> Result<Self, SpecError> {
+        let swaps = rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                rule.to_rule_with(presets)
+                    .map_err(|error| error.in_rule(index))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(SwapSpec { rules, swaps })
+    }
+
+    /// Creates a specification from in-memory `swaps`, e.g. to save them with `to_toml`.
+    ///
+    /// ```
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::rule::{Op, Target};
+    /// use bswp::spec::SwapSpec;
+    ///
+    /// let spec = SwapSpec::from_swaps(vec![
+    ///     (Op::Set(Pattern::new(0x42)), Target::Periodic(Predicate::new().with_limit(1))),
+    ///     (Op::Xor(0x55), Target::Range(16..32)),
+    ///     (Op::Not, Target::offsets(vec![7, 3])),
+    /// ]);
+    /// # #[cfg(feature = "toml")] {
+    /// let toml = spec.to_toml().unwrap();
+    /// assert_eq!(toml, "\
+    /// [[rule]]
+    /// value = 66
+    /// limit = 1
+    ///
+    /// [[rule]]
+    /// op = \"xor\"
+    /// value = 85
+    /// range = [16, 32]
+    ///
+    /// [[rule]]
+    /// op = \"not\"
+    /// offsets = [3, 7]
+    /// ");
+    /// assert_eq!(SwapSpec::from_toml(&toml).unwrap(), spec);
+    /// # }
+    /// ```
+    pub fn from_swaps(swaps: Vec<SwapRule>) -> Self {
+        let rules = swaps.iter().map(RuleSpec::from).collect();
+        SwapSpec { rules, swaps }
+    }
+
+    /// Parses a TOML specification.
+    ///
+    /// Requires the `toml` feature.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(spec: &str) -> Result<Self, SpecError> {
+        SwapSpec::from_toml_with(spec, &PresetRegistry::new())
+    }
+
+    /// Same as `from_toml`, rules referencing `presets`.
+    ///
+    /// ```
+    /// use bswp::presets::PresetRegistry;
+    /// use bswp::rule::{Op, Target};
+    /// use bswp::spec::SwapSpec;
+    ///
+    /// let presets = PresetRegistry::new().with_preset("clear-high", Op::And(0x0F));
+    /// let spec = SwapSpec::from_toml_with("
+    /// [[rule]]
+    /// preset = \"clear-high\"
+    /// range = [0, 4]
+    /// ", &presets).unwrap();
+    /// assert_eq!(spec.swaps(), &[(Op::And(0x0F), Target::Range(0..4))]);
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_toml_with(spec: &str, presets: &PresetRegistry) -> Result<Self, SpecError> {
+        let raw: RawSpec = serde_path_to_error::deserialize(toml::Deserializer::new(spec))
+            .map_err(|error| {
+                let location = error
+                    .inner()
+                    .span()
+                    .map(|span| line_column(spec, span.start));
+                let message = error.inner().message().to_string();
+                SpecError::syntax(error, message).at(location)
+            })?;
+        SwapSpec::from_rules_with(raw.rules, presets).map_err(|error| {
+            let location = locate_toml(spec, &error);
+            error.at(location)
+        })
+    }
+
+    /// Parses a JSON specification, e.g. `{"rule": [{"value": "0x42", "periodicity": 2}]}`.
+    ///
+    /// Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn from_json(spec: &str) -> Result<Self, SpecError> {
+        SwapSpec::from_json_with(spec, &PresetRegistry::new())
+    }
+
+    /// Same as `from_json`, rules referencing `presets`.
+    #[cfg(feature = "json")]
+    pub fn from_json_with(spec: &str, presets: &PresetRegistry) -> Result<Self, SpecError> {
+        let mut deserializer = serde_json::Deserializer::from_str(spec);
+        let raw: RawSpec =
+            serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+                let inner = error.inner();
+                let location = Some((inner.line(), inner.column())).filter(|&(line, _)| line > 0);
+                let message = inner.to_string();
+                let suffix = format!(" at line {} column {}", inner.line(), inner.column());
+                let message = message
+                    .strip_suffix(&suffix)
+                    .unwrap_or(&message)
+                    .to_string();
+                SpecError::syntax(error, message).at(location)
+            })?;
+        SwapSpec::from_rules_with(raw.rules, presets)
+    }
+
+    /// Parses a YAML specification.
+    ///
+    /// Requires the `yaml` feature.
+    ///
+    /// ```
+    /// use bswp::pattern::{Pattern, Predicate};
+    /// use bswp::spec::SwapSpec;
+    ///
+    /// let spec = SwapSpec::from_yaml("
+    /// rule:
+    ///   - value: 0x42
+    ///     limit: 2
+    /// ").unwrap();
+    /// let swap = (Pattern::new(0x42), Predicate::new().with_limit(2));
+    /// assert_eq!(spec.rules()[0].to_swap().unwrap(), swap);
+    /// ```
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(spec: &str) -> Result<Self, SpecError> {
+        SwapSpec::from_yaml_with(spec, &PresetRegistry::new())
+    }
+
+    /// Same as `from_yaml`, rules referencing `presets`.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_with(spec: &str, presets: &PresetRegistry) -> Result<Self, SpecError> {
+        let deserializer = serde_yaml::Deserializer::from_str(spec);
+        let raw: RawSpec = serde_path_to_error::deserialize(deserializer).map_err(|error| {
+            let location = error
+                .inner()
+                .location()
+                .map(|location| (location.line(), location.column()));
+            // serde_yaml messages read `path: message at line L column C`
+            let mut message = error.inner().to_string();
+            if let Some((line, column)) = location {
+                let suffix = format!(" at line {} column {}", line, column);
+                message.truncate(message.strip_suffix(&suffix).unwrap_or(&message).len());
+            }
+            if let Some((path, rest)) = message.split_once(": ") {
+                if !path.contains(' ') {
+                    message = rest.to_string();
+                }
+            }
+            SpecError::syntax(error, message).at(location)
+        })?;
+        SwapSpec::from_rules_with(raw.rules, presets)
+    }
+
+    /// Writes this specification as TOML.
+    ///
+    /// Requires the `toml` feature.
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Writes this specification as pretty-printed JSON.
+    ///
+    /// Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Writes this specification as YAML.
+    ///
+    /// Requires the `yaml` feature.
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    /// Returns the rules as written, in the specification order.
+    pub fn rules(&self) -> &[RuleSpec] {
+        &self.rules
+    }
+
+    /// Returns the swap rules, in the specification order.
+    pub fn swaps(&self) -> &[SwapRule] {
+        &self.swaps
+    }
+
+    /// Unwraps this `SwapSpec`, returning the swap rules.
+    pub fn into_swaps(self) -> Vec<SwapRule> {
+        self.swaps
+    }
+}
+
+impl Serialize for SwapSpec {
+    /// Serializes the rules as written, e.g. `{"rule": [{"value": 66}]}`.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let raw = RawSpec {
+            rules: self.rules.clone(),
+        };
+        raw.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SwapSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawSpec::deserialize(deserializer)?;
+        SwapSpec::from_rules(raw.rules).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{Pattern, Predicate};
+    use crate::rule::{Op, Target};
+    use crate::spec::{OpKind, RuleSpec, SpecError, SpecErrorKind, SwapSpec};
+    use std::ops::Range;
+
+    #[test]
+    fn test_invalid_rules() {
+        let rule = RuleSpec {
+            periodicity: Some(0),
+            ..RuleSpec::default()
+        };
+        let error = SwapSpec::from_rules(vec![RuleSpec::default(), rule]).unwrap_err();
+        assert_eq!(error.kind(), SpecErrorKind::Invalid);
+        assert_eq!(error.rule(), Some(1));
+        assert_eq!(error.path(), Some("rule[1].periodicity"));
+        assert_eq!(error.line(), None);
+
+        let invalid = [
+            RuleSpec {
+                op: OpKind::Xor,
+                mask: Some(0x0F),
+                ..RuleSpec::default()
+            },
+            RuleSpec {
+                op: OpKind::Not,
+                value: 1,
+                ..RuleSpec::default()
+            },
+            RuleSpec {
+                op: OpKind::Reverse,
+                value: 1,
+                ..RuleSpec::default()
+            },
+            RuleSpec {
+                range: Some(0..1),
+                offsets: Some(vec![2]),
+                ..RuleSpec::default()
+            },
+            RuleSpec {
+                offsets: Some(vec![2]),
+                limit: Some(1),
+                ..RuleSpec::default()
+            },
+            RuleSpec {
+                range: Some(Range { start: 2, end: 1 }),
+                ..RuleSpec::default()
+            },
+        ];
+        for rule in invalid.iter() {
+            assert!(rule.to_rule().is_err(), "{:?}", rule);
+        }
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let swaps = vec![
+            (
+                Op::Set(Pattern::new(0x0A).with_mask(0x0F)),
+                Target::Periodic(Predicate::new().with_periodicity(4).with_offset(2)),
+            ),
+            (Op::And(0xF0), Target::Range(0..8)),
+            (Op::Or(0x01), Target::offsets(vec![9, 1, 9])),
+            (Op::Not, Target::Periodic(Predicate::new())),
+            (Op::Reverse(4), Target::Range(8..16)),
+        ];
+        let spec = SwapSpec::from_swaps(swaps.clone());
+        assert_eq!(spec.swaps(), swaps.as_slice());
+        assert_eq!(SwapSpec::from_rules(spec.rules().to_vec()).unwrap(), spec);
+        #[cfg(feature = "json")]
+        assert_eq!(SwapSpec::from_json(&spec.to_json().unwrap()).unwrap(), spec);
+        #[cfg(feature = "yaml")]
+        assert_eq!(SwapSpec::from_yaml(&spec.to_yaml().unwrap()).unwrap(), spec);
+        #[cfg(feature = "toml")]
+        assert_eq!(SwapSpec::from_toml(&spec.to_toml().unwrap()).unwrap(), spec);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_invalid_toml_specs() {
+        assert_eq!(SwapSpec::from_toml("").unwrap().swaps(), &[]);
+        let error = SwapSpec::from_toml("[[rule]]\nvalue = 1\nperiodicity = 0").unwrap_err();
+        assert_eq!(error.kind(), SpecErrorKind::Invalid);
+        assert_eq!(error.rule(), Some(0));
+        assert_eq!((error.line(), error.column()), (Some(3), Some(15)));
+        let error = SwapSpec::from_toml("[[rule]]\nvalue = 256").unwrap_err();
+        assert_eq!(error.kind(), SpecErrorKind::Syntax);
+        let error = SwapSpec::from_toml("[[rule]]\nvalue = \"0x100\"").unwrap_err();
+        assert_eq!(error.kind(), SpecErrorKind::Syntax);
+        let error = SwapSpec::from_toml("[[rule]]\nvalue = 1\nperiod = 2").unwrap_err();
+        assert_eq!(error.kind(), SpecErrorKind::Syntax);
+        assert_eq!(error.suggestion(), Some("did you mean \"periodicity\"?"));
+        assert_eq!(error.line(), Some(3));
+        let error = SwapSpec::from_toml("[[rule]]\npreset = \"zero-fil\"").unwrap_err();
+        assert_eq!(error.path(), Some("rule[0].preset"));
+        assert_eq!(error.suggestion(), Some("did you mean \"zero-fill\"?"));
+        let error = SwapSpec::from_toml("[[rule]]\npreset = \"invert\"\nop = \"xor\"").unwrap_err();
+        assert_eq!(error.kind(), SpecErrorKind::Invalid);
+        let error = SwapSpec::from_toml("[[rule]]\nop = \"xro\"").unwrap_err();
+        assert_eq!(error.path(), Some("rule[0].op"));
+        assert_eq!(error.suggestion(), Some("did you mean \"xor\"?"));
+        let error = SwapSpec::from_toml("[[rule]]\nop = \"not\"\n\nrange = [8, 4]").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "4:9: rule[0].range: range start 8 is after end 4 (help: use [4, 8])"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_diagnostics() {
+        let error =
+            SwapSpec::from_json("{\"rule\": [{\"value\": 1},\n {\"value\": 512}]}").unwrap_err();
+        assert_eq!(error.path(), Some("rule[1].value"));
+        assert_eq!(error.rule(), Some(1));
+        assert_eq!(error.line(), Some(2));
+        assert_eq!(error.message(), "512 does not fit in a byte");
+        let error = SwapSpec::from_json("{\"rule\": [").unwrap_err();
+        assert_eq!(error.path(), Some("rule"));
+        assert_eq!(error.line(), Some(1));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_diagnostics() {
+        let error = SwapSpec::from_yaml("rule:\n  - value: 1\n    mask: 0x100\n").unwrap_err();
+        assert_eq!(error.path(), Some("rule[0].mask"));
+        assert_eq!(error.line(), Some(2)); // start of the rule
+        assert_eq!(error.message(), "256 does not fit in a byte");
+        assert_eq!(error.suggestion(), Some("use a value up to 0xFF"));
+        let error = SwapSpec::from_yaml("rule:\n  - value: 1\n    limt: 1\n").unwrap_err();
+        assert_eq!(error.suggestion(), Some("did you mean \"limit\"?"));
+        assert_eq!((error.line(), error.column()), (Some(3), Some(5)));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = SpecError::from(crate::dsl::parse_rule("not limt 1").unwrap_err());
+        assert_eq!(error.kind(), SpecErrorKind::Syntax);
+        assert_eq!((error.line(), error.column()), (Some(1), Some(5)));
+        assert_eq!(error.suggestion(), Some("did you mean \"limit\"?"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_round_trip() {
+        let spec = SwapSpec::from_json(
+            r#"{"rule": [{"value": "0b1010", "mask": "0x0F", "offset": "16"}, {"value": 1}]}"#,
+        )
+        .unwrap();
+        assert_eq!(spec.rules()[0].value, 0b1010);
+        assert_eq!(spec.rules()[0].mask, Some(0x0F));
+        assert_eq!(spec.rules()[0].offset, Some(16));
+        let json = serde_json::to_string(&spec).unwrap();
+        assert_eq!(
+            json,
+            r#"{"rule":[{"value":10,"mask":15,"offset":16},{"value":1}]}"#
+        );
+        assert_eq!(SwapSpec::from_json(&json).unwrap(), spec);
+    }
+}
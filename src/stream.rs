@@ -0,0 +1,328 @@
+//! Stateful push-style swapping (pure)
+
+use crate::pattern::{group_size, swap_iter_from, swap_slice_from};
+use crate::{BytePattern, PositionPredicate};
+
+/// Holds swap rules and the position of the next byte to swap.
+///
+/// Chunks are fed one after the other, predicates are evaluated against the
+/// position in the whole stream.
+///
+/// ```
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::stream::Swapper;
+///
+/// let mut swapper = Swapper::new(vec![(Pattern::new(0x42), Predicate::new().with_periodicity(2).with_offset(1))]);
+///
+/// let mut chunk = [0x41, 0x41, 0x41];
+/// swapper.feed(&mut chunk);
+/// assert_eq!(chunk, [0x41, 0x42, 0x41]);
+///
+/// let swapped: Vec<u8> = swapper.feed_iter(&[0x41, 0x41]).collect();
+/// assert_eq!(swapped, vec![0x42, 0x41]);
+/// assert_eq!(swapper.position(), 5);
+/// ```
+pub struct Swapper<P, Q> {
+    swaps: Vec<(P, Q)>,
+    position: usize,
+    /// bytes moved by patterns are swapped in groups of `group` bytes
+    group: usize,
+}
+
+impl<P: BytePattern, Q: PositionPredicate> Swapper<P, Q> {
+    /// Creates a new `Swapper` starting at position `0`.
+    pub fn new(swaps: Vec<(P, Q)>) -> Self {
+        let group = group_size(&swaps);
+        Swapper {
+            swaps,
+            position: 0,
+            group,
+        }
+    }
+
+    /// Sets the position of the next byte to swap.
+    ///
+    /// **Default**: `0`
+    pub fn with_position(mut self, position: usize) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Returns the position of the next byte to swap.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the swap rules.
+    pub fn swaps(&self) -> &[(P, Q)] {
+        &self.swaps
+    }
+
+    /// Sets the position of the next byte to swap back to `0`.
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    /// Returns the size of the groups patterns move bytes within, groups starting at
+    /// position `0` (see `BytePattern::group_size`).
+    pub fn group_size(&self) -> usize {
+        self.group
+    }
+
+    /// Swaps `chunk` in place and advances position by `chunk.len()`.
+    ///
+    /// Patterns moving bytes only swap groups lying wholly in `chunk`, see `pattern::swap_slice_from`:
+    /// use `feed_groups` unless chunks end on group boundaries or at the end of the stream.
+    pub fn feed(&mut self, chunk: &mut [u8]) {
+        swap_slice_from(chunk, &self.swaps, self.position);
+        self.position += chunk.len();
+    }
+
+    /// Swaps the longest prefix of `chunk` ending on a group boundary in place and
+    /// advances position by its length, which is returned.
+    ///
+    /// The bytes after the prefix are left untouched, to be fed again followed by the next
+    /// bytes of the stream, or with `feed` at the end of the stream.
+    ///
+    /// ```
+    /// use bswp::pattern::{PermutePattern, Predicate};
+    /// use bswp::stream::Swapper;
+    ///
+    /// let mut swapper = Swapper::new(vec![(PermutePattern::new(vec![1, 0]).unwrap(), Predicate::new())]);
+    ///
+    /// let mut chunk = [0, 1, 2];
+    /// assert_eq!(swapper.feed_groups(&mut chunk), 2);
+    /// assert_eq!(chunk, [1, 0, 2]);
+    /// let mut chunk = [2, 3];
+    /// assert_eq!(swapper.feed_groups(&mut chunk), 2);
+    /// assert_eq!(chunk, [3, 2]);
+    /// ```
+    pub fn feed_groups(&mut self, chunk: &mut [u8]) -> usize {
+        let end = self.position + chunk.len();
+        let len = chunk.len().saturating_sub(end % self.group);
+        self.feed(&mut chunk[..len]);
+        len
+    }
+
+    /// Returns an iterator on swapped bytes from `chunk` and advances position by `chunk.len()`.
+    ///
+    /// Position is advanced right away, whether the iterator is consumed or not.
+    pub fn feed_iter<'a>(&'a mut self, chunk: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+        let start = self.position;
+        self.position += chunk.len();
+        swap_iter_from(chunk, &self.swaps, start)
+    }
+}
+
+/// Chunk of bytes swappable by a `SwapStream`.
+#[cfg(feature = "stream")]
+pub trait SwapChunk: Sized {
+    /// Returns the bytes of the chunk to swap, or the chunk itself if it is forwarded untouched.
+    fn into_bytes(self) -> Result<bytes::Bytes, Self>;
+
+    /// Returns a chunk holding swapped `bytes`.
+    fn from_bytes(bytes: bytes::Bytes) -> Self;
+}
+
+#[cfg(feature = "stream")]
+impl SwapChunk for bytes::Bytes {
+    fn into_bytes(self) -> Result<bytes::Bytes, Self> {
+        Ok(self)
+    }
+
+    fn from_bytes(bytes: bytes::Bytes) -> Self {
+        bytes
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<E> SwapChunk for Result<bytes::Bytes, E> {
+    /// Errors are forwarded untouched.
+    fn into_bytes(self) -> Result<bytes::Bytes, Self> {
+        match self {
+            Ok(bytes) => Ok(bytes),
+            error => Err(error),
+        }
+    }
+
+    fn from_bytes(bytes: bytes::Bytes) -> Self {
+        Ok(bytes)
+    }
+}
+
+/// Stream adapter swapping `Bytes` chunks (or `Result<Bytes, E>` chunks) from the inner stream.
+///
+/// Chunks are swapped in place if not shared, else copied. Bytes of groups moved by patterns
+/// (see `BytePattern::group_size`) are held back until their group is received whole, a
+/// trailing partial group being emitted once the inner stream ends.
+///
+/// Requires the `stream` feature.
+///
+/// ```
+/// use bytes::Bytes;
+/// use bswp::pattern::{Pattern, Predicate};
+/// use bswp::stream::SwapStream;
+/// use futures_core::Stream;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll};
+///
+/// # struct Chunks(Vec<Bytes>);
+/// # impl Stream for Chunks {
+/// #     type Item = Bytes;
+/// #     fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Bytes>> {
+/// #         Poll::Ready(if self.0.is_empty() { None } else { Some(self.0.remove(0)) })
+/// #     }
+/// # }
+/// # fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+/// #     struct Noop;
+/// #     impl std::task::Wake for Noop { fn wake(self: std::sync::Arc<Self>) {} }
+/// #     let waker = std::task::Waker::from(std::sync::Arc::new(Noop));
+/// #     let mut cx = Context::from_waker(&waker);
+/// #     match Pin::new(stream).poll_next(&mut cx) { Poll::Ready(item) => item, Poll::Pending => None }
+/// # }
+/// let chunks = Chunks(vec![Bytes::from_static(&[0x41, 0x41, 0x41]), Bytes::from_static(&[0x41])]);
+/// let swaps = vec![(Pattern::new(0x42), Predicate::new().with_periodicity(2).with_offset(1))];
+/// let mut stream = SwapStream::new(chunks, swaps);
+/// assert_eq!(next(&mut stream).unwrap(), Bytes::from_static(&[0x41, 0x42, 0x41]));
+/// assert_eq!(next(&mut stream).unwrap(), Bytes::from_static(&[0x42]));
+/// assert!(next(&mut stream).is_none());
+/// ```
+#[cfg(feature = "stream")]
+pub struct SwapStream<S, P, Q> {
+    inner: S,
+    swapper: Swapper<P, Q>,
+    /// bytes of a partial group, not swapped yet
+    held: bytes::BytesMut,
+    finished: bool,
+}
+
+#[cfg(feature = "stream")]
+impl<S, P: BytePattern, Q: PositionPredicate> SwapStream<S, P, Q> {
+    /// Creates a new `SwapStream` wrapping `inner`.
+    pub fn new(inner: S, swaps: Vec<(P, Q)>) -> Self {
+        SwapStream {
+            inner,
+            swapper: Swapper::new(swaps),
+            held: bytes::BytesMut::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the position of the next byte.
+    pub fn position(&self) -> usize {
+        self.swapper.position()
+    }
+
+    /// Unwraps this `SwapStream`, returning the inner stream.
+    ///
+    /// Bytes held back are lost.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S, P, Q> futures_core::Stream for SwapStream<S, P, Q>
+where
+    S: futures_core::Stream + Unpin,
+    S::Item: SwapChunk,
+    P: BytePattern + Unpin,
+    Q: PositionPredicate + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.finished {
+                return std::task::Poll::Ready(None);
+            }
+            let bytes = match std::task::ready!(std::pin::Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(item) => match item.into_bytes() {
+                    Ok(bytes) => bytes,
+                    Err(item) => return std::task::Poll::Ready(Some(item)),
+                },
+                None => {
+                    this.finished = true;
+                    if this.held.is_empty() {
+                        return std::task::Poll::Ready(None);
+                    }
+                    // partial group at the end of the stream
+                    let mut chunk = core::mem::take(&mut this.held);
+                    this.swapper.feed(&mut chunk);
+                    return std::task::Poll::Ready(Some(S::Item::from_bytes(chunk.freeze())));
+                }
+            };
+            let mut chunk = if this.held.is_empty() {
+                bytes
+                    .try_into_mut()
+                    .unwrap_or_else(|shared| bytes::BytesMut::from(&shared[..]))
+            } else {
+                this.held.extend_from_slice(&bytes);
+                core::mem::take(&mut this.held)
+            };
+            let len = this.swapper.feed_groups(&mut chunk);
+            this.held = chunk.split_off(len);
+            if len > 0 || this.held.is_empty() {
+                return std::task::Poll::Ready(Some(S::Item::from_bytes(chunk.freeze())));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "stream"))]
+mod tests {
+    use crate::pattern::{PermutePattern, Predicate};
+    use crate::stream::SwapStream;
+    use bytes::Bytes;
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    struct Chunks(Vec<Result<Bytes, ()>>);
+
+    impl Stream for Chunks {
+        type Item = Result<Bytes, ()>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(if self.0.is_empty() {
+                None
+            } else {
+                Some(self.0.remove(0))
+            })
+        }
+    }
+
+    fn collect<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+        struct Noop;
+        impl std::task::Wake for Noop {
+            fn wake(self: std::sync::Arc<Self>) {}
+        }
+        let waker = std::task::Waker::from(std::sync::Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn test_swap_stream_unaligned_chunks() {
+        let source: Vec<u8> = (0..11).collect();
+        let mut chunks: Vec<Result<Bytes, ()>> = source
+            .chunks(3)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        chunks.insert(2, Err(()));
+        let swaps = vec![(PermutePattern::new(vec![1, 0]).unwrap(), Predicate::new())];
+        let items = collect(SwapStream::new(Chunks(chunks), swaps));
+        assert_eq!(items[2], Err(())); // errors are forwarded in order
+        let swapped: Vec<u8> = items.into_iter().flatten().flatten().collect();
+        // trailing partial group is left unswapped
+        assert_eq!(swapped, vec![1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 10]);
+    }
+}
@@ -0,0 +1,218 @@
+//! VCDIFF deltas (pure)
+//!
+//! VCDIFF (RFC 3284) is the standard delta format understood by `xdelta3`, `open-vcdiff`
+//! and most delta-distribution tools. Since swaps never change the stream length, every
+//! window targets a segment of the original stream: unchanged bytes are copied from the
+//! source, changed bytes are added.
+//!
+//! Deltas use the default code table, without secondary compression nor checksums.
+
+use crate::pattern::Change;
+
+/// Magic bytes and version every delta starts with, followed by an empty header indicator.
+pub const HEADER: &[u8] = &[0xD6, 0xC3, 0xC4, 0x00, 0x00];
+
+/// `Win_Indicator` of windows copying from the source.
+const VCD_SOURCE: u8 = 0x01;
+/// Default code table index of `ADD` with its size following.
+const ADD: u8 = 1;
+/// Default code table index of `COPY` in `VCD_SELF` mode with its size following.
+const COPY: u8 = 19;
+/// Shortest run of unchanged bytes copied, shorter ones being added.
+const MIN_COPY: usize = 4;
+
+/// Returns a delta turning `source` into `source` with `changes` applied.
+///
+/// `changes` must be in increasing offset order, e.g. from `pattern::diff_iter`.
+///
+/// ```
+/// use bswp::pattern::{diff_iter, Pattern, Predicate};
+/// use bswp::vcdiff::{to_vcdiff, HEADER};
+///
+/// let source = [0x41; 8];
+/// let swaps = &[(Pattern::new(0x42), Predicate::new().with_offset(7))];
+/// let changes: Vec<_> = diff_iter(&source, swaps).collect();
+/// let delta = to_vcdiff(&source, &changes);
+/// assert!(delta.starts_with(HEADER));
+/// // instructions: COPY 7 bytes, ADD 1 byte, then the COPY address: source 0
+/// assert!(delta.ends_with(&[19, 7, 1, 1, 0]));
+/// ```
+pub fn to_vcdiff(source: &[u8], changes: &[Change]) -> Vec<u8> {
+    let mut delta = HEADER.to_vec();
+    delta.extend(encode_window(source, 0, changes));
+    delta
+}
+
+/// Returns a window turning `segment`, the bytes of the source at `position`, into
+/// `segment` with `changes` applied.
+///
+/// Deltas are `HEADER` followed by windows, e.g. one per chunk of a stream.
+/// `changes` must lie in the segment, in increasing offset order.
+pub fn encode_window(segment: &[u8], position: usize, changes: &[Change]) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    let mut instructions: Vec<u8> = Vec::new();
+    let mut addresses: Vec<u8> = Vec::new();
+    let mut target = segment.to_vec();
+    for change in changes {
+        target[change.offset - position] = change.new;
+    }
+
+    let mut index = 0; // first byte of the segment not yet encoded
+    let mut changes = changes
+        .iter()
+        .map(|change| change.offset - position)
+        .peekable();
+    while index < segment.len() {
+        let next = changes.peek().copied().unwrap_or(segment.len());
+        if next - index >= MIN_COPY || next == segment.len() {
+            instructions.push(COPY);
+            write_varint(&mut instructions, next - index);
+            write_varint(&mut addresses, index);
+            index = next;
+            continue;
+        }
+        // changed bytes along with short unchanged gaps
+        let mut end = next;
+        while let Some(&offset) = changes.peek() {
+            if offset - end >= MIN_COPY && end > next {
+                break;
+            }
+            end = offset + 1;
+            changes.next();
+        }
+        instructions.push(ADD);
+        write_varint(&mut instructions, end - index);
+        data.extend_from_slice(&target[index..end]);
+        index = end;
+    }
+
+    let mut encoding: Vec<u8> = Vec::new();
+    write_varint(&mut encoding, segment.len()); // target window length
+    encoding.push(0); // Delta_Indicator, no compression
+    write_varint(&mut encoding, data.len());
+    write_varint(&mut encoding, instructions.len());
+    write_varint(&mut encoding, addresses.len());
+    encoding.extend(data);
+    encoding.extend(instructions);
+    encoding.extend(addresses);
+
+    let mut window = vec![VCD_SOURCE];
+    write_varint(&mut window, segment.len());
+    write_varint(&mut window, position);
+    write_varint(&mut window, encoding.len());
+    window.extend(encoding);
+    window
+}
+
+/// Appends `value` as a VCDIFF integer: base 128, most significant digit first, every
+/// digit but the last having its high bit set.
+fn write_varint(buffer: &mut Vec<u8>, value: usize) {
+    let mut digits = [0u8; 10];
+    let mut len = 0;
+    let mut value = value;
+    loop {
+        digits[len] = (value & 0x7F) as u8;
+        len += 1;
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    for (index, digit) in digits[..len].iter().enumerate().rev() {
+        buffer.push(if index > 0 { digit | 0x80 } else { *digit });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{diff_iter, swap_slice, Change, Pattern, Predicate};
+    use crate::vcdiff::{encode_window, to_vcdiff, write_varint, HEADER};
+
+    fn read_varint(bytes: &mut &[u8]) -> usize {
+        let mut value = 0;
+        loop {
+            let (&digit, rest) = bytes.split_first().unwrap();
+            *bytes = rest;
+            value = (value << 7) | (digit & 0x7F) as usize;
+            if digit & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    /// Decodes windows using `VCD_SOURCE` and single `ADD`/`COPY` instructions with
+    /// explicit sizes from the default code table.
+    fn decode(source: &[u8], mut delta: &[u8]) -> Vec<u8> {
+        delta = delta.strip_prefix(HEADER).unwrap();
+        let mut target = Vec::new();
+        while !delta.is_empty() {
+            assert_eq!(delta[0], 0x01);
+            delta = &delta[1..];
+            let size = read_varint(&mut delta);
+            let position = read_varint(&mut delta);
+            let segment = &source[position..position + size];
+            let len = read_varint(&mut delta);
+            let (mut encoding, rest) = delta.split_at(len);
+            delta = rest;
+            let window_len = read_varint(&mut encoding);
+            assert_eq!(encoding[0], 0);
+            encoding = &encoding[1..];
+            let data_len = read_varint(&mut encoding);
+            let instructions_len = read_varint(&mut encoding);
+            let addresses_len = read_varint(&mut encoding);
+            let (mut data, rest) = encoding.split_at(data_len);
+            let (mut instructions, mut addresses) = rest.split_at(instructions_len);
+            assert_eq!(addresses.len(), addresses_len);
+            let start = target.len();
+            while !instructions.is_empty() {
+                let code = instructions[0];
+                instructions = &instructions[1..];
+                let size = read_varint(&mut instructions);
+                match code {
+                    1 => {
+                        target.extend_from_slice(&data[..size]);
+                        data = &data[size..];
+                    }
+                    19 => {
+                        let address = read_varint(&mut addresses);
+                        target.extend_from_slice(&segment[address..address + size]);
+                    }
+                    _ => panic!("unexpected instruction {}", code),
+                }
+            }
+            assert_eq!(target.len() - start, window_len);
+        }
+        target
+    }
+
+    #[test]
+    fn test_vcdiff_windows() {
+        let mut varint = Vec::new();
+        write_varint(&mut varint, 123_456_789);
+        assert_eq!(varint, [0xBA, 0xEF, 0x9A, 0x15]); // RFC 3284 example
+
+        let source: Vec<u8> = (0..10_000).map(|i| (i % 11) as u8).collect();
+        let swaps = &[
+            (Pattern::new(0xFF), Predicate::new().with_periodicity(97)),
+            (
+                Pattern::new(0x00),
+                Predicate::new().with_periodicity(3).with_limit(40),
+            ),
+            (Pattern::new(0xEE), Predicate::new().with_offset(9_998)),
+        ];
+        let mut expected = source.clone();
+        swap_slice(&mut expected, swaps);
+        let changes: Vec<Change> = diff_iter(&source, swaps).collect();
+        assert_eq!(decode(&source, &to_vcdiff(&source, &changes)), expected);
+
+        let mut delta = HEADER.to_vec();
+        for (index, chunk) in source.chunks(1_000).enumerate() {
+            let range = index * 1_000..index * 1_000 + chunk.len();
+            let first = changes.partition_point(|change| change.offset < range.start);
+            let last = changes.partition_point(|change| change.offset < range.end);
+            delta.extend(encode_window(chunk, range.start, &changes[first..last]));
+        }
+        assert_eq!(decode(&source, &delta), expected);
+        assert_eq!(decode(&[], &to_vcdiff(&[], &[])), Vec::<u8>::new());
+    }
+}
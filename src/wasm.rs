@@ -0,0 +1,104 @@
+//! WebAssembly bindings (mut), requires the `wasm` feature.
+//!
+//! Exposes slice swapping to JavaScript through `wasm-bindgen`: rules are written in the
+//! `dsl` syntax and applied to `Uint8Array`s, e.g. from a browser ROM patcher:
+//!
+//! ```text
+//! import { Rules } from "bswp";
+//!
+//! const rules = new Rules("set 0x42 at 2n+1 limit 3; xor 0xFF in 0x100..0x200");
+//! rules.swap(rom);                  // in place
+//! const patched = rules.swapped(rom); // copy
+//! ```
+
+use crate::dsl::parse_rules;
+use crate::pattern::swap_slice_from;
+use crate::rule::SwapRule;
+use wasm_bindgen::prelude::*;
+
+/// Rules parsed from `dsl` expressions, `Rules` in JavaScript.
+///
+/// ```
+/// use bswp::wasm::Rules;
+///
+/// let rules = Rules::new("set 0x42 at 2n+1; not at 0").unwrap();
+/// let mut rom = [0x0F; 4];
+/// rules.swap(&mut rom);
+/// assert_eq!(rom, [0xF0, 0x42, 0x0F, 0x42]);
+/// assert_eq!(rules.swapped_from(&[0x0F; 2], 1), vec![0x42, 0x0F]);
+/// ```
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Rules {
+    rules: Vec<SwapRule>,
+}
+
+#[wasm_bindgen]
+impl Rules {
+    /// Parses `expressions`, see `dsl::parse_rules`.
+    ///
+    /// Throws an `Error` with the line, column and message of the first invalid rule.
+    #[wasm_bindgen(constructor)]
+    pub fn new(expressions: &str) -> Result<Rules, JsError> {
+        let rules = parse_rules(expressions).map_err(|error| JsError::new(&error.to_string()))?;
+        Ok(Rules { rules })
+    }
+
+    /// Returns the number of rules, `count` in JavaScript.
+    #[wasm_bindgen(getter)]
+    pub fn count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Swaps `bytes` in place.
+    pub fn swap(&self, bytes: &mut [u8]) {
+        swap_slice_from(bytes, &self.rules, 0);
+    }
+
+    /// Swaps `bytes` in place, `bytes[0]` being at `start`, `swapFrom` in JavaScript.
+    ///
+    /// Used to swap a large file chunk by chunk.
+    #[wasm_bindgen(js_name = swapFrom)]
+    pub fn swap_from(&self, bytes: &mut [u8], start: usize) {
+        swap_slice_from(bytes, &self.rules, start);
+    }
+
+    /// Returns a swapped copy of `bytes`.
+    pub fn swapped(&self, bytes: &[u8]) -> Vec<u8> {
+        self.swapped_from(bytes, 0)
+    }
+
+    /// Returns a swapped copy of `bytes`, `bytes[0]` being at `start`, `swappedFrom` in
+    /// JavaScript.
+    #[wasm_bindgen(js_name = swappedFrom)]
+    pub fn swapped_from(&self, bytes: &[u8], start: usize) -> Vec<u8> {
+        let mut swapped = bytes.to_vec();
+        self.swap_from(&mut swapped, start);
+        swapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dsl::parse_rules;
+    use crate::pattern::swap_slice;
+    use crate::wasm::Rules;
+
+    #[test]
+    fn test_rules_chunks() {
+        let expressions = "xor 0x55 in 3..40 # obfuscated\nreverse 4 in 8..24; set 0 at 7n";
+        let rules = Rules::new(expressions).unwrap();
+        assert_eq!(rules.count(), 3);
+
+        let source: Vec<u8> = (0..64).collect();
+        let mut expected = source.clone();
+        swap_slice(&mut expected, &parse_rules(expressions).unwrap());
+        assert_eq!(rules.swapped(&source), expected);
+
+        let mut chunked = source.clone();
+        for (index, chunk) in chunked.chunks_mut(12).enumerate() {
+            rules.swap_from(chunk, index * 12);
+        }
+        assert_eq!(chunked, expected);
+    }
+}
@@ -0,0 +1,206 @@
+//! Word patterns and iterator utils (pure)
+//!
+//! Groups bytes into `u16`/`u32`/`u64` words, applies word patterns and emits bytes again.
+//! Word predicates are regular `PositionPredicate`s evaluated on the word index.
+
+use crate::PositionPredicate;
+
+/// Byte order of words in a byte stream.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Unsigned integer word.
+pub trait Word: Copy {
+    /// Size in bytes.
+    const SIZE: usize;
+
+    /// Truncates `value` to a word.
+    fn from_u64(value: u64) -> Self;
+
+    /// Returns the word as a `u64`.
+    fn to_u64(self) -> u64;
+
+    /// Reverses the byte order of the word.
+    fn swap_bytes(self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($word:ty),*) => {
+        $(
+            impl Word for $word {
+                const SIZE: usize = core::mem::size_of::<$word>();
+
+                fn from_u64(value: u64) -> Self {
+                    value as $word
+                }
+
+                fn to_u64(self) -> u64 {
+                    u64::from(self)
+                }
+
+                fn swap_bytes(self) -> Self {
+                    <$word>::swap_bytes(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(u16, u32, u64);
+
+impl Endianness {
+    /// Reads a `W` from `bytes` (`bytes.len()` must be `W::SIZE`).
+    pub fn read<W: Word>(self, bytes: &[u8]) -> W {
+        let value = match self {
+            Endianness::Little => bytes
+                .iter()
+                .rev()
+                .fold(0u64, |acc, &b| (acc << 8) | u64::from(b)),
+            Endianness::Big => bytes.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b)),
+        };
+        W::from_u64(value)
+    }
+
+    /// Writes `word` to `bytes` (`bytes.len()` must be `W::SIZE`).
+    pub fn write<W: Word>(self, word: W, bytes: &mut [u8]) {
+        let value = word.to_u64();
+        let size = bytes.len();
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            let shift = match self {
+                Endianness::Little => 8 * index,
+                Endianness::Big => 8 * (size - 1 - index),
+            };
+            *byte = (value >> shift) as u8;
+        }
+    }
+}
+
+/// Pattern on word.
+pub trait WordPattern<W: Word> {
+    /// Returns the word with current pattern applied.
+    fn eval(&self, word: W) -> W;
+}
+
+/// Given a target word, the pattern will set bits to corresponding bits from `value`
+/// according to `mask`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WordMask<W> {
+    pub value: W,
+    pub mask: W,
+}
+
+impl<W: Word> WordMask<W> {
+    /// Creates a new `WordMask` with provided `value`.
+    ///
+    /// `mask`: all bits set
+    pub fn new(value: W) -> Self {
+        WordMask {
+            value,
+            mask: W::from_u64(u64::MAX),
+        }
+    }
+
+    /// Sets the `mask`.
+    pub fn with_mask(mut self, mask: W) -> Self {
+        self.mask = mask;
+        self
+    }
+}
+
+impl<W: Word> WordPattern<W> for WordMask<W> {
+    fn eval(&self, word: W) -> W {
+        let (value, mask) = (self.value.to_u64(), self.mask.to_u64());
+        W::from_u64((mask & value) | (!mask & word.to_u64()))
+    }
+}
+
+/// Reverses the byte order of every matching word.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReverseBytes;
+
+impl<W: Word> WordPattern<W> for ReverseBytes {
+    fn eval(&self, word: W) -> W {
+        word.swap_bytes()
+    }
+}
+
+/// Returns an iterator on bytes from `source` swapped word by word.
+///
+/// `source` is split in `W::SIZE` words read with `endianness`, predicates are evaluated
+/// on the word index. Trailing bytes not forming a whole word are left untouched.
+///
+/// ```
+/// use bswp::pattern::Predicate;
+/// use bswp::word::{swap_words, Endianness, ReverseBytes, WordMask};
+///
+/// // byte-swap every 32-bit word
+/// let source: [u8; 9] = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+/// let swaps = &[(ReverseBytes, Predicate::new())];
+/// let swapped: Vec<u8> = swap_words::<u32, _, _>(&source, Endianness::Little, swaps).collect();
+/// assert_eq!(swapped, vec![3, 2, 1, 0, 7, 6, 5, 4, 8]);
+///
+/// // set the low 12 bits of every odd big endian u16
+/// let swaps = &[(WordMask::new(0x0ABCu16).with_mask(0x0FFF), Predicate::new().with_periodicity(2).with_offset(1))];
+/// let source: [u8; 4] = [0xFF; 4];
+/// let swapped: Vec<u8> = swap_words(&source, Endianness::Big, swaps).collect();
+/// assert_eq!(swapped, vec![0xFF, 0xFF, 0xFA, 0xBC]);
+/// ```
+pub fn swap_words<'a, W, P, Q>(
+    source: &'a [u8],
+    endianness: Endianness,
+    swaps: &'a [(P, Q)],
+) -> impl Iterator<Item = u8> + 'a
+where
+    W: Word + 'a,
+    P: WordPattern<W>,
+    Q: PositionPredicate,
+{
+    source
+        .chunks(W::SIZE)
+        .enumerate()
+        .flat_map(move |(index, chunk)| {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            if chunk.len() == W::SIZE {
+                let word: W = endianness.read(chunk);
+                let word = swaps.iter().fold(word, |word, (pattern, predicate)| {
+                    if predicate.eval(index) {
+                        pattern.eval(word)
+                    } else {
+                        word
+                    }
+                });
+                endianness.write(word, &mut bytes[..W::SIZE]);
+            }
+            IntoIterator::into_iter(bytes).take(chunk.len())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::word::{Endianness, Word};
+
+    fn round_trip<W: Word + PartialEq + std::fmt::Debug>(word: W) {
+        for &endianness in &[Endianness::Little, Endianness::Big] {
+            let mut bytes = vec![0u8; W::SIZE];
+            endianness.write(word, &mut bytes);
+            assert_eq!(endianness.read::<W>(&bytes), word);
+        }
+    }
+
+    #[test]
+    fn test_endianness() {
+        assert_eq!(Endianness::Little.read::<u32>(&[1, 2, 3, 4]), 0x0403_0201);
+        assert_eq!(Endianness::Big.read::<u32>(&[1, 2, 3, 4]), 0x0102_0304);
+        round_trip(0xBEEFu16);
+        round_trip(0xDEAD_BEEFu32);
+        round_trip(0x0123_4567_89AB_CDEFu64);
+    }
+}
@@ -0,0 +1,151 @@
+//! Repeating XOR keys (pure)
+//!
+//! XOR with a short repeating key is how countless save files and configuration files
+//! are obfuscated. Since XOR is its own inverse, the same rules obfuscate and deobfuscate.
+
+use crate::pattern::Predicate;
+use crate::rule::{Op, SwapRule, Target};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Multi-byte key XORed over a range of positions, repeating.
+///
+/// A key schedule, i.e. different keys for different regions, is a list of keys with
+/// disjoint ranges (see `schedule`).
+///
+/// ```
+/// use bswp::pattern::swap_slice;
+/// use bswp::xor::XorKey;
+///
+/// let key = XorKey::new(*b"key").unwrap();
+/// let mut buffer = *b"secret";
+/// swap_slice(&mut buffer, &key.rules());
+/// assert_eq!(buffer, [0x18, 0x00, 0x1A, 0x19, 0x00, 0x0D]);
+/// swap_slice(&mut buffer, &key.rules());
+/// assert_eq!(&buffer, b"secret");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorKey {
+    key: Vec<u8>,
+    phase: usize,
+    range: Range<usize>,
+}
+
+impl XorKey {
+    /// Creates a key XORed over the whole stream, `key[0]` being applied at position `0`.
+    ///
+    /// Returns `None` if `key` is empty.
+    pub fn new(key: impl Into<Vec<u8>>) -> Option<Self> {
+        let key = key.into();
+        if key.is_empty() {
+            return None;
+        }
+        Some(XorKey {
+            key,
+            phase: 0,
+            range: 0..usize::MAX,
+        })
+    }
+
+    /// Sets the index of the key byte applied at the start of the range.
+    ///
+    /// **Default**: `0`
+    ///
+    /// ```
+    /// use bswp::pattern::swap_slice;
+    /// use bswp::xor::XorKey;
+    ///
+    /// let key = XorKey::new([0x01, 0x02, 0x03]).unwrap().with_phase(2);
+    /// let mut buffer = [0x00; 4];
+    /// swap_slice(&mut buffer, &key.rules());
+    /// assert_eq!(buffer, [0x03, 0x01, 0x02, 0x03]);
+    /// ```
+    pub fn with_phase(mut self, phase: usize) -> Self {
+        self.phase = phase % self.key.len();
+        self
+    }
+
+    /// Only XORs positions in `range`, the key restarting at `range.start`.
+    ///
+    /// **Default**: every position
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Returns the key bytes.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Returns swap rules XORing the key, one per non-zero key byte.
+    pub fn rules(&self) -> Vec<SwapRule> {
+        let period = self.key.len();
+        let (start, end) = (self.range.start, self.range.end);
+        self.key
+            .iter()
+            .enumerate()
+            .filter(|(_, &byte)| byte != 0)
+            .map(|(index, &byte)| {
+                // first position of the range where `key[index]` applies
+                let offset = start.saturating_add((index + period - self.phase) % period);
+                let limit = end.saturating_sub(offset).div_ceil(period);
+                let predicate = Predicate::new()
+                    .with_periodicity(period)
+                    .with_offset(offset)
+                    .with_limit(limit);
+                (Op::Xor(byte), Target::Periodic(predicate))
+            })
+            .collect()
+    }
+}
+
+/// Returns swap rules XORing every key of a key schedule.
+///
+/// ```
+/// use bswp::pattern::swap_slice;
+/// use bswp::xor::{schedule, XorKey};
+///
+/// let keys = [
+///     XorKey::new([0xFF]).unwrap().with_range(0..2),
+///     XorKey::new([0x0F, 0xF0]).unwrap().with_range(2..usize::MAX),
+/// ];
+/// let mut buffer = [0x00; 5];
+/// swap_slice(&mut buffer, &schedule(&keys));
+/// assert_eq!(buffer, [0xFF, 0xFF, 0x0F, 0xF0, 0x0F]);
+/// ```
+pub fn schedule(keys: &[XorKey]) -> Vec<SwapRule> {
+    keys.iter().flat_map(XorKey::rules).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{swap_slice, swap_slice_from};
+    use crate::xor::XorKey;
+
+    #[test]
+    fn test_rolling_key() {
+        let key = [0x10, 0x00, 0x32, 0x43, 0x54];
+        let source: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        for phase in 0..7 {
+            let xor = XorKey::new(key)
+                .unwrap()
+                .with_phase(phase)
+                .with_range(13..150);
+            let mut expected = source.clone();
+            for (index, position) in (13..150).enumerate() {
+                expected[position] ^= key[(index + phase) % key.len()];
+            }
+            let rules = xor.rules();
+            assert_eq!(rules.len(), 4);
+            let mut buffer = source.clone();
+            swap_slice(&mut buffer, &rules);
+            assert_eq!(buffer, expected, "phase: {}", phase);
+            for (index, chunk) in buffer.chunks_mut(9).enumerate() {
+                swap_slice_from(chunk, &rules, index * 9);
+            }
+            assert_eq!(buffer, source, "phase: {}", phase);
+        }
+        assert_eq!(XorKey::new(Vec::new()), None);
+    }
+}
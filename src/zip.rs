@@ -0,0 +1,98 @@
+//! Byte-wise combination of two streams (pure)
+//!
+//! Combines the bytes of a stream with the bytes at the same positions of another one,
+//! e.g. XORing a keystream or ANDing a mask file over a dump.
+
+use crate::PositionPredicate;
+
+/// Standard combinations of two bytes.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZipOp {
+    /// xors both bytes
+    Xor,
+    /// ands both bytes
+    And,
+    /// ors both bytes
+    Or,
+}
+
+/// Combination of a byte with the byte at the same position of the other stream.
+///
+/// Implemented by `ZipOp` and by closures `FnMut(u8, u8) -> u8`.
+pub trait Combine {
+    /// Returns `value` combined with `other`.
+    fn combine(&mut self, value: u8, other: u8) -> u8;
+}
+
+impl Combine for ZipOp {
+    fn combine(&mut self, value: u8, other: u8) -> u8 {
+        match self {
+            ZipOp::Xor => value ^ other,
+            ZipOp::And => value & other,
+            ZipOp::Or => value | other,
+        }
+    }
+}
+
+impl<F: FnMut(u8, u8) -> u8> Combine for F {
+    fn combine(&mut self, value: u8, other: u8) -> u8 {
+        self(value, other)
+    }
+}
+
+/// Combines bytes of `buffer` at positions matching `predicate` with the bytes of `other`
+/// at the same index, `buffer[0]` being at position `start`.
+///
+/// Bytes past the end of `other` are left untouched.
+///
+/// ```
+/// use bswp::pattern::Predicate;
+/// use bswp::zip::{zip_slice_from, ZipOp};
+///
+/// let mut buffer = [0x0F; 4];
+/// zip_slice_from(&mut buffer, &[0xFF, 0xFF, 0xFF], Predicate::new(), 0, ZipOp::Xor);
+/// assert_eq!(buffer, [0xF0, 0xF0, 0xF0, 0x0F]);
+///
+/// let odd = Predicate::new().with_periodicity(2).with_offset(1);
+/// zip_slice_from(&mut buffer, &[0x01; 4], odd, 0, |a: u8, b: u8| a.wrapping_add(b));
+/// assert_eq!(buffer, [0xF0, 0xF1, 0xF0, 0x10]);
+/// ```
+pub fn zip_slice_from<Q, C>(buffer: &mut [u8], other: &[u8], predicate: Q, start: usize, mut op: C)
+where
+    Q: PositionPredicate,
+    C: Combine,
+{
+    for (index, (item, &other)) in buffer.iter_mut().zip(other).enumerate() {
+        if predicate.eval(start + index) {
+            *item = op.combine(*item, other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rule::Target;
+    use crate::zip::{zip_slice_from, Combine, ZipOp};
+
+    #[test]
+    fn test_zip_ops() {
+        let source: Vec<u8> = (0..=255).collect();
+        let mask: Vec<u8> = source.iter().rev().copied().collect();
+        for (op, expected) in [(ZipOp::Xor, 0xFF), (ZipOp::And, 0x00), (ZipOp::Or, 0xFF)] {
+            let mut buffer = source.clone();
+            zip_slice_from(&mut buffer, &mask, Target::Range(0..256), 0, op);
+            assert!(buffer.iter().all(|&byte| byte == expected), "{:?}", op);
+        }
+        let mut buffer = source.clone();
+        let mut calls = 0;
+        let count = |value: u8, _: u8| {
+            calls += 1;
+            value
+        };
+        zip_slice_from(&mut buffer[10..], &mask, Target::Range(100..120), 10, count);
+        assert_eq!(calls, 20);
+        assert_eq!(buffer, source);
+        assert_eq!(ZipOp::And.combine(0x3C, 0x0F), 0x0C);
+    }
+}